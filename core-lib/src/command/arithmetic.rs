@@ -0,0 +1,456 @@
+use std::iter::Peekable;
+use std::num::IntErrorKind;
+use std::str::Chars;
+
+use crate::command::context::UserContext;
+use crate::command::lexer::LexerError;
+
+/// A number in an arithmetic expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Number {
+    Integer(i64),
+    Float(f64),
+}
+
+/// A token of an arithmetic expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Number),
+    Variable(String),
+    Operator(&'static str),
+}
+
+const OPERATORS: [&str; 17] = [
+    "**", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!", "(", ")"];
+
+/// Evaluates an arithmetic expression, `$(( expr ))`, and returns the result as a string.
+///
+/// Expressions are made up of integers, floats, variables (with or without a `$`), parentheses,
+/// and the operators, from lowest to highest precedence:
+/// - `||`, `&&`: logical or and and, 1 if true and 0 if false, where the right operand is only
+///   evaluated if the left operand does not decide the result
+/// - `==`, `!=`, `<`, `<=`, `>`, `>=`: comparisons, 1 if true and 0 if false
+/// - `+`, `-`: addition and subtraction
+/// - `*`, `/`, `%`: multiplication, division, and remainder
+/// - `**`: exponentiation
+/// - `-`, `+`, `!`: unary minus, plus, and logical not
+///
+/// Integer arithmetic stays an integer, while any float operand makes the result a float.
+/// A float result always has a decimal point (e.g., `4.0 / 2` is `2.0`), so it stays a float when
+/// it is used in another expression.
+/// Variables are only read when they are evaluated, so an unknown variable in an operand that is
+/// not evaluated (e.g., `0 && $unknown`) is not an error.
+pub(crate) fn evaluate(context: &UserContext, expression: &str) -> Result<String, LexerError> {
+    let tokens = tokenize(expression)?;
+    let mut pos = 0;
+    let result = parse_or(context, &tokens, &mut pos, false)?;
+    if pos != tokens.len() {
+        return Err(invalid(format!("unexpected '{}'", describe(&tokens[pos]))));
+    }
+    Ok(match result {
+        Number::Integer(i) => i.to_string(),
+        Number::Float(f) => format_float(f),
+    })
+}
+
+/// Formats a float with at least one decimal, e.g., `2.0` rather than `2`.
+fn format_float(value: f64) -> String {
+    let formatted = value.to_string();
+    if formatted.contains('.') {
+        formatted
+    } else {
+        format!("{}.0", formatted)
+    }
+}
+
+fn invalid(reason: String) -> LexerError {
+    LexerError::InvalidArithmeticExpression(reason)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(Number::Integer(i)) => i.to_string(),
+        Token::Number(Number::Float(f)) => format_float(*f),
+        Token::Variable(var) => var.to_owned(),
+        Token::Operator(op) => op.to_string(),
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, LexerError> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if !c.is_ascii_digit() && c != '.' {
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Number(parse_number(&number)?));
+        } else if c.is_alphabetic() || c == '$' || c == '_' {
+            chars.next();
+            let mut var = if c == '$' { String::new() } else { c.to_string() };
+            while let Some(&c) = chars.peek() {
+                if !c.is_alphanumeric() && c != '_' {
+                    break;
+                }
+                var.push(c);
+                chars.next();
+            }
+            if var.is_empty() {
+                return Err(invalid("'$' is not followed by a variable".to_owned()));
+            }
+            tokens.push(Token::Variable(var));
+        } else {
+            tokens.push(Token::Operator(operator(&mut chars)?));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Returns the value of a variable as a number.
+fn variable(context: &UserContext, var: &str) -> Result<Number, LexerError> {
+    match context.get_value(var) {
+        Some(value) => parse_number(value.trim())
+            .map_err(|_| invalid(format!("{} is not a number: {}", var, value))),
+        None => Err(LexerError::UnknownVariable(var.to_owned())),
+    }
+}
+
+fn operator(chars: &mut Peekable<Chars>) -> Result<&'static str, LexerError> {
+    let c = chars.next().unwrap();
+    if let Some(&next) = chars.peek() {
+        let two: String = [c, next].iter().collect();
+        if let Some(op) = OPERATORS.iter().find(|op| **op == two) {
+            chars.next();
+            return Ok(*op);
+        }
+    }
+    let one = c.to_string();
+    OPERATORS.iter()
+        .find(|op| **op == one)
+        .copied()
+        .ok_or_else(|| invalid(format!("unknown operator '{}'", c)))
+}
+
+fn parse_number(number: &str) -> Result<Number, LexerError> {
+    if number.contains('.') {
+        number.parse::<f64>()
+            .map(Number::Float)
+            .map_err(|_| invalid(format!("invalid number '{}'", number)))
+    } else {
+        number.parse::<i64>().map(Number::Integer).map_err(|e| match e.kind() {
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => LexerError::ArithmeticOverflow,
+            _ => invalid(format!("invalid number '{}'", number))
+        })
+    }
+}
+
+fn is_operator(tokens: &[Token], pos: usize, ops: &[&str]) -> Option<&'static str> {
+    match tokens.get(pos) {
+        Some(Token::Operator(op)) if ops.contains(op) => Some(op),
+        _ => None
+    }
+}
+
+fn parse_or(context: &UserContext, tokens: &[Token], pos: &mut usize, skip: bool)
+        -> Result<Number, LexerError> {
+    let mut lhs = parse_and(context, tokens, pos, skip)?;
+    while is_operator(tokens, *pos, &["||"]).is_some() {
+        *pos += 1;
+        // the right operand is not evaluated if the left operand is true
+        let rhs = parse_and(context, tokens, pos, skip || is_true(lhs))?;
+        lhs = boolean(is_true(lhs) || is_true(rhs));
+    }
+    Ok(lhs)
+}
+
+fn parse_and(context: &UserContext, tokens: &[Token], pos: &mut usize, skip: bool)
+        -> Result<Number, LexerError> {
+    let mut lhs = parse_comparison(context, tokens, pos, skip)?;
+    while is_operator(tokens, *pos, &["&&"]).is_some() {
+        *pos += 1;
+        // the right operand is not evaluated if the left operand is false
+        let rhs = parse_comparison(context, tokens, pos, skip || !is_true(lhs))?;
+        lhs = boolean(is_true(lhs) && is_true(rhs));
+    }
+    Ok(lhs)
+}
+
+fn parse_comparison(context: &UserContext, tokens: &[Token], pos: &mut usize, skip: bool)
+        -> Result<Number, LexerError> {
+    let mut lhs = parse_sum(context, tokens, pos, skip)?;
+    while let Some(op) = is_operator(tokens, *pos, &["==", "!=", "<", "<=", ">", ">="]) {
+        *pos += 1;
+        let rhs = parse_sum(context, tokens, pos, skip)?;
+        // integers are only compared as floats with a float, which would lose their precision
+        lhs = boolean(match (lhs, rhs) {
+            (Number::Integer(l), Number::Integer(r)) => compare(op, l, r),
+            _ => compare(op, as_float(lhs), as_float(rhs)),
+        });
+    }
+    Ok(lhs)
+}
+
+fn parse_sum(context: &UserContext, tokens: &[Token], pos: &mut usize, skip: bool)
+        -> Result<Number, LexerError> {
+    let mut lhs = parse_product(context, tokens, pos, skip)?;
+    while let Some(op) = is_operator(tokens, *pos, &["+", "-"]) {
+        *pos += 1;
+        let rhs = parse_product(context, tokens, pos, skip)?;
+        lhs = evaluated(skip, if op == "+" {
+            apply(lhs, rhs, i64::checked_add, |l, r| l + r)
+        } else {
+            apply(lhs, rhs, i64::checked_sub, |l, r| l - r)
+        })?;
+    }
+    Ok(lhs)
+}
+
+fn parse_product(context: &UserContext, tokens: &[Token], pos: &mut usize, skip: bool)
+        -> Result<Number, LexerError> {
+    let mut lhs = parse_power(context, tokens, pos, skip)?;
+    while let Some(op) = is_operator(tokens, *pos, &["*", "/", "%"]) {
+        *pos += 1;
+        let rhs = parse_power(context, tokens, pos, skip)?;
+        let result = if op != "*" && as_float(rhs) == 0.0 {
+            Err(LexerError::DivisionByZero)
+        } else {
+            match op {
+                "*" => apply(lhs, rhs, i64::checked_mul, |l, r| l * r),
+                "/" => apply(lhs, rhs, i64::checked_div, |l, r| l / r),
+                _ => apply(lhs, rhs, i64::checked_rem, |l, r| l % r),
+            }
+        };
+        lhs = evaluated(skip, result)?;
+    }
+    Ok(lhs)
+}
+
+fn parse_power(context: &UserContext, tokens: &[Token], pos: &mut usize, skip: bool)
+        -> Result<Number, LexerError> {
+    let base = parse_unary(context, tokens, pos, skip)?;
+    if is_operator(tokens, *pos, &["**"]).is_some() {
+        *pos += 1;
+        // exponentiation is right associative, 2 ** 3 ** 2 == 2 ** 9
+        let exponent = parse_power(context, tokens, pos, skip)?;
+        return evaluated(skip, match (base, exponent) {
+            (Number::Integer(b), Number::Integer(e)) if e >= 0 => u32::try_from(e).ok()
+                .and_then(|e| b.checked_pow(e))
+                .map(Number::Integer)
+                .ok_or(LexerError::ArithmeticOverflow),
+            _ => finite(as_float(base).powf(as_float(exponent))),
+        });
+    }
+    Ok(base)
+}
+
+fn parse_unary(context: &UserContext, tokens: &[Token], pos: &mut usize, skip: bool)
+        -> Result<Number, LexerError> {
+    if let Some(op) = is_operator(tokens, *pos, &["-", "+", "!"]) {
+        *pos += 1;
+        let value = parse_unary(context, tokens, pos, skip)?;
+        return evaluated(skip, match (op, value) {
+            ("-", Number::Integer(i)) =>
+                i.checked_neg().map(Number::Integer).ok_or(LexerError::ArithmeticOverflow),
+            ("-", Number::Float(f)) => Ok(Number::Float(-f)),
+            ("+", value) => Ok(value),
+            (_, value) => Ok(boolean(!is_true(value))),
+        });
+    }
+    parse_primary(context, tokens, pos, skip)
+}
+
+fn parse_primary(context: &UserContext, tokens: &[Token], pos: &mut usize, skip: bool)
+        -> Result<Number, LexerError> {
+    match tokens.get(*pos) {
+        Some(Token::Number(number)) => {
+            *pos += 1;
+            Ok(*number)
+        }
+        Some(Token::Variable(var)) => {
+            *pos += 1;
+            // a variable in an operand that is skipped is not read, so it may be unknown
+            if skip { Ok(Number::Integer(0)) } else { variable(context, var) }
+        }
+        Some(Token::Operator("(")) => {
+            *pos += 1;
+            let value = parse_or(context, tokens, pos, skip)?;
+            if is_operator(tokens, *pos, &[")"]).is_none() {
+                return Err(invalid("missing closing parenthesis".to_owned()));
+            }
+            *pos += 1;
+            Ok(value)
+        }
+        Some(token) => Err(invalid(format!("unexpected '{}'", describe(token)))),
+        None => Err(invalid("missing operand".to_owned())),
+    }
+}
+
+/// Returns the result of an operation, or 0 if the operation failed in an operand that is skipped,
+/// i.e., the right operand of `&&` or `||` that does not change the result.
+fn evaluated(skip: bool, result: Result<Number, LexerError>) -> Result<Number, LexerError> {
+    match result {
+        Err(LexerError::DivisionByZero | LexerError::ArithmeticOverflow) if skip =>
+            Ok(Number::Integer(0)),
+        result => result
+    }
+}
+
+fn compare<T: PartialOrd>(op: &str, l: T, r: T) -> bool {
+    match op {
+        "==" => l == r,
+        "!=" => l != r,
+        "<" => l < r,
+        "<=" => l <= r,
+        ">" => l > r,
+        _ => l >= r,
+    }
+}
+
+fn apply(lhs: Number,
+         rhs: Number,
+         integer_op: fn(i64, i64) -> Option<i64>,
+         float_op: fn(f64, f64) -> f64) -> Result<Number, LexerError> {
+    match (lhs, rhs) {
+        (Number::Integer(l), Number::Integer(r)) =>
+            integer_op(l, r).map(Number::Integer).ok_or(LexerError::ArithmeticOverflow),
+        _ => finite(float_op(as_float(lhs), as_float(rhs))),
+    }
+}
+
+fn finite(value: f64) -> Result<Number, LexerError> {
+    if value.is_finite() {
+        Ok(Number::Float(value))
+    } else {
+        Err(LexerError::ArithmeticOverflow)
+    }
+}
+
+fn as_float(number: Number) -> f64 {
+    match number {
+        Number::Integer(i) => i as f64,
+        Number::Float(f) => f,
+    }
+}
+
+fn is_true(number: Number) -> bool {
+    as_float(number) != 0.0
+}
+
+fn boolean(value: bool) -> Number {
+    Number::Integer(if value { 1 } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::arithmetic::evaluate;
+    use crate::command::context::UserContext;
+    use crate::command::lexer::LexerError;
+
+    fn eval(expression: &str) -> Result<String, LexerError> {
+        let mut context = UserContext::default();
+        context.set_value("base", "8000");
+        context.set_value("ratio", "0.5");
+        context.set_value("name", "foo");
+        evaluate(&context, expression)
+    }
+
+    #[test]
+    fn integer_arithmetic() {
+        assert_eq!("7", eval("1 + 2 * 3").unwrap());
+        assert_eq!("9", eval("(1 + 2) * 3").unwrap());
+        assert_eq!("3", eval("10 / 3").unwrap());
+        assert_eq!("1", eval("10 % 3").unwrap());
+        assert_eq!("-4", eval("-(2 + 2)").unwrap());
+        assert_eq!("512", eval("2 ** 3 ** 2").unwrap());
+    }
+
+    #[test]
+    fn float_arithmetic() {
+        assert_eq!("2.5", eval("5 / 2.0").unwrap());
+        assert_eq!("0.75", eval("ratio + 0.25").unwrap());
+        assert_eq!("1.5", eval("3 * $ratio").unwrap());
+    }
+
+    #[test]
+    fn float_result_keeps_decimal_point() {
+        assert_eq!("2.0", eval("4.0 / 2").unwrap());
+        assert_eq!("-3.0", eval("-1.5 * 2").unwrap());
+        assert_eq!("100000000000000000000.0", eval("10.0 ** 20").unwrap());
+    }
+
+    #[test]
+    fn comparison_and_logical_operators() {
+        assert_eq!("1", eval("base + 1 > base").unwrap());
+        assert_eq!("0", eval("1 == 2").unwrap());
+        assert_eq!("1", eval("1 != 2 && 2 <= 2").unwrap());
+        assert_eq!("1", eval("0 || 3 >= 4 || !0").unwrap());
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        assert_eq!("0", eval("0 && 1 / 0").unwrap());
+        assert_eq!("1", eval("1 || 2 ** 64").unwrap());
+        assert_eq!("1", eval("(0 && 1 % 0) || 1").unwrap());
+        assert_eq!(LexerError::DivisionByZero, eval("1 && 1 / 0").err().unwrap());
+        assert!(matches!(eval("0 && (1 +").err().unwrap(),
+            LexerError::InvalidArithmeticExpression(_)));
+    }
+
+    #[test]
+    fn variables_are_not_read_in_skipped_operands() {
+        assert_eq!("0", eval("0 && $unknown").unwrap());
+        assert_eq!("1", eval("1 || unknown + name").unwrap());
+        assert_eq!(LexerError::UnknownVariable("unknown".to_owned()),
+                   eval("1 && $unknown").err().unwrap());
+    }
+
+    #[test]
+    fn integers_are_compared_exactly() {
+        assert_eq!("0", eval("9007199254740993 == 9007199254740992").unwrap());
+        assert_eq!("1", eval("9007199254740993 > 9007199254740992").unwrap());
+        assert_eq!("1", eval("2 > 1.5").unwrap());
+    }
+
+    #[test]
+    fn variables_with_and_without_dollar_sign() {
+        assert_eq!("8003", eval("$base + 3").unwrap());
+        assert_eq!("8003", eval("base + 3").unwrap());
+    }
+
+    #[test]
+    fn division_by_zero_is_error() {
+        assert_eq!(LexerError::DivisionByZero, eval("1 / 0").err().unwrap());
+        assert_eq!(LexerError::DivisionByZero, eval("1 % (2 - 2)").err().unwrap());
+        assert_eq!(LexerError::DivisionByZero, eval("1.5 / 0.0").err().unwrap());
+    }
+
+    #[test]
+    fn overflow_is_error() {
+        assert_eq!(LexerError::ArithmeticOverflow,
+                   eval("9223372036854775807 + 1").err().unwrap());
+        assert_eq!(LexerError::ArithmeticOverflow, eval("2 ** 64").err().unwrap());
+        assert_eq!(LexerError::ArithmeticOverflow,
+                   eval("99999999999999999999").err().unwrap());
+        assert_eq!(LexerError::ArithmeticOverflow, eval("10.0 ** 400").err().unwrap());
+    }
+
+    #[test]
+    fn unknown_variable_is_error() {
+        assert_eq!(LexerError::UnknownVariable("port".to_owned()), eval("port + 1").err().unwrap());
+    }
+
+    #[test]
+    fn invalid_expression_is_error() {
+        for expression in ["", "1 +", "(1 + 2", "1 2", "1 & 2", "name + 1", "1.2.3", "$"] {
+            assert!(matches!(eval(expression).err().unwrap(),
+                LexerError::InvalidArithmeticExpression(_)), "{}", expression);
+        }
+    }
+}
\ No newline at end of file
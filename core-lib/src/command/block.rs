@@ -0,0 +1,368 @@
+use std::io;
+use std::io::Cursor;
+
+use crate::command::context::{CommandContext, IoContext, UserContext};
+use crate::command::lexer::{lex_command, LexerError, Tokens};
+use crate::command::shell::{Shell, ShellError};
+use crate::command::{CommandExecutionError, SourceInfo};
+
+/// Keywords that open a nested block.
+const BLOCK_OPENERS: [&str; 5] = ["if", "for", "while", "function", "try"];
+
+/// Keywords that close a nested block.
+const BLOCK_TERMINATORS: [&str; 3] = ["fi", "done", "}"];
+
+/// Statements that close a nested block and open the next section of the same block.
+const BLOCK_CONTINUATIONS: [&str; 1] = ["} catch"];
+
+/// A section of a multi-line block (e.g., the `if`, `elif`, and `else` branches of a conditional
+/// block).
+///
+/// The body of a section is stored unexpanded so that variables are expanded when, and each time,
+/// the section is executed.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Section {
+    /// The raw statement that starts the section.
+    /// This is empty for the first section because its statement has already been lexed.
+    pub(crate) header: String,
+    /// The line of the statement that starts the section.
+    pub(crate) line: usize,
+    /// The raw commands in the section.
+    pub(crate) body: String,
+}
+
+impl Section {
+    /// Returns the keyword of the statement that starts the section.
+    pub(crate) fn keyword(&self) -> &str {
+        first_word(&self.header)
+    }
+}
+
+/// A multi-line block that has been read from the I/O context.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Block {
+    /// The sections of the block.
+    pub(crate) sections: Vec<Section>,
+    /// The raw statement that terminates the block, which may redirect the output of the block
+    /// (e.g., `done > file`).
+    pub(crate) terminator: Section,
+}
+
+/// Reads the lines of a block from the I/O context until the terminator of the block is found.
+///
+/// The block is split into sections at each of the separators (e.g., `elif`, `else`, or
+/// `} catch`), which are matched against the leading words of a line.
+/// Nested blocks are read as part of the enclosing section.
+/// Block keywords are only recognized at the start of a line.
+pub(crate) fn read_block(tokens: &Tokens,
+                         io_context: &mut IoContext,
+                         separators: &[&str],
+                         terminator: &'static str) -> Result<Block, ShellError> {
+    let src = io_context.to_source_info();
+    let mut sections = vec![Section {
+        header: String::new(),
+        line: io_context.line,
+        body: String::new(),
+    }];
+    let mut depth = 0;
+    let mut continued = false;
+
+    loop {
+        let line = match read_line(io_context)
+            .map_err(|e| ShellError::from_lexer_error(io_context, e))? {
+            Some(line) => line,
+            None => return Err(ShellError::CommandExecutionError {
+                src,
+                tokens: tokens.clone(),
+                error: CommandExecutionError::UnterminatedBlock(terminator),
+            })
+        };
+
+        if !continued {
+            let keyword = first_word(&line);
+            if depth == 0 && separators.iter().any(|sep| starts_with_words(&line, sep)) {
+                sections.push(Section {
+                    header: line.trim().to_owned(),
+                    line: io_context.line,
+                    body: String::new(),
+                });
+                continue;
+            } else if BLOCK_CONTINUATIONS.iter().any(|stmt| starts_with_words(&line, stmt)) {
+                if depth == 0 {
+                    return Err(ShellError::CommandExecutionError {
+                        src: io_context.to_source_info(),
+                        tokens: tokens.clone(),
+                        error: CommandExecutionError::UnexpectedBlockStatement(
+                            line.trim().to_owned()),
+                    });
+                }
+            } else if BLOCK_OPENERS.contains(&keyword) {
+                depth += 1;
+            } else if BLOCK_TERMINATORS.contains(&keyword) {
+                if depth == 0 {
+                    if keyword == terminator {
+                        return Ok(Block {
+                            sections,
+                            terminator: Section {
+                                header: line.trim().to_owned(),
+                                line: io_context.line,
+                                body: String::new(),
+                            },
+                        });
+                    }
+                    return Err(ShellError::CommandExecutionError {
+                        src: io_context.to_source_info(),
+                        tokens: tokens.clone(),
+                        error: CommandExecutionError::UnexpectedBlockStatement(
+                            keyword.to_owned()),
+                    });
+                }
+                depth -= 1;
+            }
+        }
+
+        // a backslash continues the command on the next line, which cannot start a block
+        continued = line.ends_with('\\');
+        let section = sections.last_mut().unwrap();
+        section.body.push_str(&line);
+        section.body.push('\n');
+    }
+}
+
+/// Lexes the statement that starts a section with the user's current variables.
+pub(crate) fn lex_header(section: &Section,
+                         user_context: &mut UserContext,
+                         io_context: &IoContext,
+                         command_context: &CommandContext,
+                         shell: &mut Shell) -> Result<(Tokens, SourceInfo), ShellError> {
+    let src = io_context.src;
+    let mut input = Cursor::new(section.header.as_bytes());
+    let mut sink = io::sink();
+    let mut header_io = IoContext::new(src, &mut input, &mut sink);
+    header_io.line = section.line - 1;
+
+    let mut substitute = |command: &str, user_context: &UserContext|
+        shell.substitute(command, user_context, src, command_context);
+    match lex_command(user_context, &mut header_io, &mut substitute) {
+        Some(Ok(tokens)) => Ok((tokens, header_io.to_source_info())),
+        Some(Err(e)) => Err(ShellError::from_lexer_error(&header_io, e)),
+        None => Err(ShellError::CommandExecutionError {
+            src: header_io.to_source_info(),
+            tokens: Tokens::new(vec![]),
+            error: CommandExecutionError::UnexpectedBlockStatement(section.header.to_owned()),
+        })
+    }
+}
+
+/// Executes the commands in a section with the same user context and output as the block.
+pub(crate) fn execute_section(section: &Section,
+                              user_context: &mut UserContext,
+                              io_context: &mut IoContext,
+                              command_context: &CommandContext,
+                              shell: &mut Shell) -> Result<(), ShellError> {
+    let src = io_context.src;
+    let mut input = Cursor::new(section.body.as_bytes());
+    let mut section_io = IoContext::new(src, &mut input, &mut io_context.output);
+    section_io.line = section.line;
+    shell.execute_commands(user_context, &mut section_io, command_context)
+}
+
+/// Executes a block, writing its output to the file of the redirection of the block's
+/// terminator, if any (e.g., `done > file` or `fi >> file`).
+pub(crate) fn execute_block<F>(block: &Block,
+                               user_context: &mut UserContext,
+                               io_context: &mut IoContext,
+                               command_context: &CommandContext,
+                               shell: &mut Shell,
+                               execute: F) -> Result<(), ShellError>
+    where F: FnOnce(&mut UserContext, &mut IoContext, &mut Shell) -> Result<(), ShellError> {
+    let (tokens, src) = lex_header(&block.terminator, user_context, io_context, command_context, shell)?;
+    let (file, append) = match Shell::redirection(&tokens) {
+        Ok(None) if tokens.len() == 1 => return execute(user_context, io_context, shell),
+        Ok(Some((_, file, append))) if tokens.get(1) == ">" || tokens.get(1) == ">>" =>
+            (file, append),
+        // the block has already been read, so this is not a validation error that skips the block
+        _ => return Err(ShellError::CommandExecutionError {
+            src,
+            tokens,
+            error: CommandExecutionError::UnexpectedBlockStatement(block.terminator.header.to_owned()),
+        })
+    };
+
+    let mut output = Shell::open_redirection(&file, append, &tokens, io_context)?;
+    let mut redirected = io_context.with_output(&mut output);
+    let result = execute(user_context, &mut redirected, shell);
+    let (line, col) = (redirected.line, redirected.col);
+    io_context.line = line;
+    io_context.col = col;
+    result
+}
+
+/// Returns the index of the end of a block statement's arguments, excluding the optional trailing
+/// keyword (e.g., `then` or `do`) and the semicolon that precedes it.
+pub(crate) fn statement_end(tokens: &Tokens, keyword: &str) -> usize {
+    let mut end = tokens.len();
+    if end > 1 && tokens.get(end - 1) == keyword {
+        end -= 1;
+    }
+    if end > 1 && tokens.get(end - 1) == ";" {
+        end -= 1;
+    }
+    end
+}
+
+/// Skips the block of a statement that failed before its block was read, so the commands in the
+/// block are not executed when the shell continues after the error.
+pub(crate) fn skip_block(statement: &str, io_context: &mut IoContext) {
+    let (separators, terminator): (&[&str], &'static str) = match first_word(statement) {
+        "if" => (&[], "fi"),
+        "for" | "while" => (&[], "done"),
+        "function" if statement.ends_with('{') => (&[], "}"),
+        "try" => (&["} catch"], "}"),
+        _ => return
+    };
+    // the block is being skipped, so errors reading it are ignored
+    let _ = read_block(&Tokens::new(vec![]), io_context, separators, terminator);
+}
+
+fn read_line(io_context: &mut IoContext) -> Result<Option<String>, LexerError> {
+    let mut line = String::new();
+    let mut empty = true;
+    loop {
+        match io_context.next_char()? {
+            None => {
+                if empty {
+                    return Ok(None);
+                }
+                break;
+            }
+            Some('\n') => break,
+            Some(c) => line.push(c)
+        }
+        empty = false;
+    }
+    io_context.line += 1;
+    Ok(Some(line))
+}
+
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+/// Returns true if the leading words of the line are the words of the statement.
+fn starts_with_words(line: &str, statement: &str) -> bool {
+    let mut words = line.split_whitespace();
+    statement.split_whitespace().all(|word| words.next() == Some(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Cursor;
+    use crate::command::block::{read_block, statement_end};
+    use crate::command::context::IoContext;
+    use crate::command::lexer::Tokens;
+    use crate::command::{CommandExecutionError, ShellError};
+
+    fn tokens(tokens: &[&str]) -> Tokens {
+        Tokens::new(tokens.iter().map(|t| t.to_string()).collect())
+    }
+
+    #[test]
+    fn block_is_split_into_sections() {
+        let text = "echo a
+elif $foo == bar
+  echo b
+else
+  echo c
+fi
+echo d";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+        io_context.line = 1;
+
+        let block = read_block(
+            &tokens(&["if", "true"]), &mut io_context, &["elif", "else"], "fi").unwrap();
+        let sections = &block.sections;
+
+        assert_eq!(3, sections.len());
+        assert_eq!("", sections[0].header);
+        assert_eq!(1, sections[0].line);
+        assert_eq!("echo a\n", sections[0].body);
+        assert_eq!("elif $foo == bar", sections[1].header);
+        assert_eq!("elif", sections[1].keyword());
+        assert_eq!(3, sections[1].line);
+        assert_eq!("  echo b\n", sections[1].body);
+        assert_eq!("else", sections[2].header);
+        assert_eq!(5, sections[2].line);
+        assert_eq!("fi", block.terminator.header);
+        assert_eq!(7, block.terminator.line);
+        assert_eq!(7, io_context.line);
+    }
+
+    #[test]
+    fn nested_blocks_are_part_of_the_section() {
+        let text = "if false
+  echo a
+else
+  echo b
+fi
+else
+fi";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+        let sections = read_block(
+            &tokens(&["if", "true"]), &mut io_context, &["elif", "else"], "fi").unwrap().sections;
+
+        assert_eq!(2, sections.len());
+        assert_eq!("if false\n  echo a\nelse\n  echo b\nfi\n", sections[0].body);
+        assert_eq!("", sections[1].body);
+    }
+
+    #[test]
+    fn keywords_on_continued_lines_are_ignored() {
+        let text = "echo a \\
+fi
+fi";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+        let sections = read_block(
+            &tokens(&["if", "true"]), &mut io_context, &["elif", "else"], "fi").unwrap().sections;
+
+        assert_eq!("echo a \\\nfi\n", sections[0].body);
+    }
+
+    #[test]
+    fn missing_terminator_is_error() {
+        let text = "echo a
+else";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+        io_context.line = 1;
+        let src = io_context.to_source_info();
+        let if_tokens = tokens(&["if", "true"]);
+
+        let result = read_block(
+            &if_tokens, &mut io_context, &["elif", "else"], "fi").err().unwrap();
+
+        assert_eq!(ShellError::CommandExecutionError {
+            src,
+            tokens: if_tokens,
+            error: CommandExecutionError::UnterminatedBlock("fi"),
+        }, result);
+    }
+
+    #[test]
+    fn statement_end_excludes_trailing_keyword() {
+        assert_eq!(4, statement_end(&tokens(&["if", "a", "==", "b", ";", "then"]), "then"));
+        assert_eq!(4, statement_end(&tokens(&["if", "a", "==", "b", "then"]), "then"));
+        assert_eq!(4, statement_end(&tokens(&["if", "a", "==", "b"]), "then"));
+        assert_eq!(1, statement_end(&tokens(&["if", "then"]), "then"));
+    }
+}
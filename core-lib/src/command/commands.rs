@@ -1,12 +1,15 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io;
-use std::io::BufReader;
+use std::io::{BufReader, Cursor};
 use std::ptr::eq;
 use log::{Level, debug};
-use crate::command::context::{UserContext, IoContext, CommandContext};
+use crate::command::block::{execute_block, execute_section, lex_header, read_block, Section, statement_end};
+use crate::command::condition::{ConditionError, evaluate};
+use crate::command::context::{UserContext, IoContext, CommandContext, Function};
 use crate::command::lexer::Tokens;
 use crate::command::oso::PolarValue;
-use crate::command::ShellError;
+use crate::command::{FindFilter, NodeKind, PathSegment, Registry, RegistryError, ShellError, SourceInfo};
 use crate::command::shell::Shell;
 
 use thiserror::Error;
@@ -19,7 +22,11 @@ pub enum CommandValidationError {
         format: &'static str
     },
     #[error("invalid variable name: {0}")]
-    InvalidVariableName(String)
+    InvalidVariableName(String),
+    #[error("invalid redirection, expected: <command> >|>> <file>")]
+    InvalidRedirection,
+    #[error("invalid pipeline, expected: <command> | <command> [| <command> ...]")]
+    InvalidPipeline
 }
 
 /// Errors thrown while executing commands.
@@ -37,6 +44,19 @@ pub enum CommandExecutionError {
     },
     #[error("source command invoked too many times recursively: {0}")]
     MaxSourceCommand(usize),
+    #[error("block is missing its terminating '{0}'")]
+    UnterminatedBlock(&'static str),
+    #[error("unexpected statement in block: {0}")]
+    UnexpectedBlockStatement(String),
+    #[error("invalid condition: {reason}, command={tokens}")]
+    InvalidCondition {
+        tokens: Tokens,
+        reason: String,
+    },
+    #[error("function name is the keyword of a built-in command: {0}")]
+    ReservedFunctionName(String),
+    #[error("function invoked too many times recursively: {0}")]
+    MaxFunctionRecursion(String),
 }
 
 impl PartialEq for CommandExecutionError {
@@ -49,6 +69,18 @@ impl PartialEq for CommandExecutionError {
             => command == command2 && var == var2,
             (CommandExecutionError::MaxSourceCommand(size),
                 CommandExecutionError::MaxSourceCommand(size2)) => size == size2,
+            (CommandExecutionError::UnterminatedBlock(terminator),
+                CommandExecutionError::UnterminatedBlock(terminator2)) => terminator == terminator2,
+            (CommandExecutionError::UnexpectedBlockStatement(statement),
+                CommandExecutionError::UnexpectedBlockStatement(statement2)) =>
+                statement == statement2,
+            (CommandExecutionError::InvalidCondition { tokens, reason },
+                CommandExecutionError::InvalidCondition { tokens: tokens2, reason: reason2 })
+            => tokens == tokens2 && reason == reason2,
+            (CommandExecutionError::ReservedFunctionName(name),
+                CommandExecutionError::ReservedFunctionName(name2)) => name == name2,
+            (CommandExecutionError::MaxFunctionRecursion(name),
+                CommandExecutionError::MaxFunctionRecursion(name2)) => name == name2,
             _ => false
         }
     }
@@ -89,12 +121,76 @@ pub trait Command {
 
 /// Assigns a value to a variable.
 ///
+/// If the value is the absolute path to a method (or the output of a command substitution naming
+/// one), the method is invoked with the remaining arguments and the variable is assigned the result.
+/// A relative word is always assigned as is, even if it names a method in the working directory.
+/// Instances, lists, and maps are kept as typed values, so that a variable holding one (e.g.,
+/// `$config`) can be passed to another method or constructor without being converted to a string.
+///
+/// If the name is the path to a writable attribute (i.e., it contains a `/`, such as
+/// `/feed/throttle` or `./throttle`), the attribute of the instance is set instead of a variable.
+/// The value is parsed into, or must be of, the type of the attribute's setter.
+///
 /// # Example
 /// ```
 /// let (_, context) = rcore::command::Shell::from_string("foo = bar").unwrap();
 ///
 /// assert_eq!("bar", context.get_value("foo").unwrap());
 /// ```
+/// ```
+/// use rcore::command::{CommandContext, IoContext, Shell, UserContext};
+/// use rcore::command::oso::{ClassBuilder, PolarValue, ToPolar};
+///
+/// struct Exchange {}
+/// impl Exchange {
+///     fn symbols(&self) -> Vec<String> { vec!["AAPL".to_owned(), "MSFT".to_owned()] }
+///     fn count(&self, symbols: Vec<String>) -> i32 { symbols.len() as i32 }
+/// }
+///
+/// let mut shell = Shell::default();
+/// shell.cache_class(ClassBuilder::<Exchange>::with_constructor(|| Exchange {}, vec![])
+///     .add_method("symbols", Exchange::symbols, vec![], None)
+///     .add_method("count", Exchange::count, vec!["list"], None)
+///     .build()).unwrap();
+///
+/// let mut commands = std::io::Cursor::new("create /nasdaq Exchange
+///     symbols = /nasdaq/symbols
+///     count = /nasdaq/count $symbols".as_bytes());
+/// let mut output = std::io::sink();
+/// let mut io_context = IoContext::new("test", &mut commands, &mut output);
+/// let mut user_context = UserContext::default();
+/// shell.execute_commands(&mut user_context, &mut io_context, &CommandContext::default()).unwrap();
+///
+/// assert_eq!(Some(&PolarValue::List(vec!["AAPL".to_polar(), "MSFT".to_polar()])),
+///            user_context.get_polar_value("symbols"));
+/// assert_eq!("[\"AAPL\",\"MSFT\"]", user_context.get_value("symbols").unwrap());
+/// assert_eq!("2", user_context.get_value("count").unwrap());
+/// ```
+/// ```
+/// use rcore::command::{CommandContext, IoContext, Shell, UserContext};
+/// use rcore::command::oso::ClassBuilder;
+///
+/// struct Feed { throttle: i32 }
+///
+/// let mut shell = Shell::default();
+/// shell.cache_class(ClassBuilder::<Feed>::with_constructor(|| Feed { throttle: 10 }, vec![])
+///     .name("Feed")
+///     .add_attribute_getter("throttle", |feed: &Feed| feed.throttle)
+///     .add_attribute_setter("throttle", |feed: &mut Feed, throttle: i32| {
+///         feed.throttle = throttle
+///     }, "int")
+///     .build()).unwrap();
+///
+/// let mut commands = std::io::Cursor::new("create /feed Feed
+///     /feed/throttle = 100
+///     /feed/throttle".as_bytes());
+/// let mut output: Vec<u8> = Vec::new();
+/// let mut io_context = IoContext::new("test", &mut commands, &mut output);
+/// let mut user_context = UserContext::default();
+/// shell.execute_commands(&mut user_context, &mut io_context, &CommandContext::default()).unwrap();
+///
+/// assert_eq!("100", String::from_utf8(output).unwrap());
+/// ```
 pub struct AssignCommand {}
 
 /// Changes the current working directory.
@@ -110,6 +206,38 @@ pub struct AssignCommand {}
 /// ```
 pub struct CdCommand {}
 
+/// Copies an instance by creating a new instance with the same class and constructor arguments.
+///
+/// If the destination is an existing directory, the copy is created in it with the same name.
+///
+/// # Example
+/// ```
+/// use rcore::command::{CommandContext, IoContext, Shell, UserContext};
+/// use rcore::command::oso::ClassBuilder;
+///
+/// struct Feed { port: i32 }
+///
+/// let mut shell = Shell::default();
+/// shell.cache_class(ClassBuilder::<Feed>::with_constructor(|port: i32| Feed { port }, vec!["int"])
+///     .name("Feed")
+///     .add_attribute_getter("port", |feed: &Feed| feed.port)
+///     .build()).unwrap();
+///
+/// let mut commands = std::io::Cursor::new("create /feeds/nyse Feed 4000
+///     mkdir /backup
+///     cp /feeds/nyse /feeds/nyse2
+///     cp /feeds/nyse /backup
+///     /feeds/nyse2/port
+///     /backup/nyse/port".as_bytes());
+/// let mut output: Vec<u8> = Vec::new();
+/// let mut io_context = IoContext::new("test", &mut commands, &mut output);
+/// let mut user_context = UserContext::default();
+/// shell.execute_commands(&mut user_context, &mut io_context, &CommandContext::default()).unwrap();
+///
+/// assert_eq!("40004000", String::from_utf8(output).unwrap());
+/// ```
+pub struct CpCommand {}
+
 /// Creates an instance of a struct.
 /// TODO: Example
 pub struct CreateCommand {}
@@ -132,6 +260,38 @@ pub struct CreateCommand {}
 /// ```
 pub struct DefaultAssignCommand {}
 
+/// Writes the commands that re-create a directory, and everything in it, such as the directories,
+/// the instances and the arguments they were created with, and links (see
+/// [Registry::dump](crate::command::Registry::dump)).
+///
+/// The output can be saved with `dump > file` and executed later with `source file`.
+///
+/// # Example
+/// ```
+/// use rcore::command::{CommandContext, IoContext, Shell, UserContext};
+/// use rcore::command::oso::ClassBuilder;
+///
+/// struct Feed { port: i32 }
+///
+/// let mut shell = Shell::default();
+/// shell.cache_class(ClassBuilder::<Feed>::with_constructor(|port: i32| Feed { port }, vec!["int"])
+///     .name("Feed")
+///     .build()).unwrap();
+///
+/// let mut commands = std::io::Cursor::new("create /feeds/nyse Feed 4000
+///     mkdir /sessions
+///     ln -s /feeds/nyse /primary
+///     dump".as_bytes());
+/// let mut output: Vec<u8> = Vec::new();
+/// let mut io_context = IoContext::new("test", &mut commands, &mut output);
+/// let mut user_context = UserContext::default();
+/// shell.execute_commands(&mut user_context, &mut io_context, &CommandContext::default()).unwrap();
+///
+/// assert_eq!("create /feeds/nyse Feed 4000\nmkdir /sessions\nln -s /feeds/nyse /primary\n",
+///            String::from_utf8(output).unwrap());
+/// ```
+pub struct DumpCommand {}
+
 /// Writes the arguments back to the output.
 ///
 /// # Example
@@ -150,6 +310,177 @@ pub struct EchoCommand {}
 /// TODO: Example
 pub struct ExecuteCommand {}
 
+/// Writes the absolute paths in a directory, and the directory itself, that match all of the
+/// specified conditions, one per line.
+///
+/// - `-name <pattern>`: the name matches the pattern, where `*` matches any characters and `?`
+///   matches any single character
+/// - `-class <class>`: the path stores an instance of the class
+/// - `-type d|i|m|a|l`: the path is a directory, instance, method, attribute, or link
+/// - `-maxdepth <depth>`: the path is at most `depth` levels below the directory
+///
+/// # Example
+/// ```
+/// use rcore::command::{CommandContext, IoContext, Shell, UserContext};
+/// use rcore::command::oso::ClassBuilder;
+///
+/// struct OrderGateway { port: i32 }
+///
+/// let mut shell = Shell::default();
+/// shell.cache_class(ClassBuilder::<OrderGateway>::with_constructor(|port: i32| OrderGateway { port }, vec!["int"])
+///     .name("OrderGateway")
+///     .build()).unwrap();
+///
+/// let mut commands = std::io::Cursor::new("create /nyse/orders OrderGateway 4000
+///     create /lse/orders OrderGateway 4001
+///     mkdir /lse/archive/orders
+///     find -class OrderGateway
+///     find / -name 'ord*' -type d".as_bytes());
+/// let mut output: Vec<u8> = Vec::new();
+/// let mut io_context = IoContext::new("test", &mut commands, &mut output);
+/// let mut user_context = UserContext::default();
+/// shell.execute_commands(&mut user_context, &mut io_context, &CommandContext::default()).unwrap();
+///
+/// assert_eq!("/lse/orders\n/nyse/orders\n/lse/archive/orders\n", String::from_utf8(output).unwrap());
+/// ```
+pub struct FindCommand {}
+
+/// Executes commands once for each item in a list, setting a variable to the item.
+///
+/// The items can be:
+/// - words: `for v in a b c; do`
+/// - integer ranges with an optional step: `for v in {1..10..2}; do`
+/// - a method or attribute that returns a list or an iterable instance:
+///   `for v in /feeds/symbols; do`
+/// - an instance whose class can be converted into an iterator: `for v in /feeds; do`
+/// - a variable holding a list or an iterable instance: `for v in $symbols; do`
+///
+/// A method, attribute, or instance must be an absolute path or the output of a command
+/// substitution (e.g., `for v in $(echo /feeds); do`); any other item is a word.
+///
+/// The `for` and `done` keywords must start a line.
+///
+/// # Example
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "for name in feed {1..3}; do
+///          mkdir \"/feeds/$name\"
+///      done
+///      ls /feeds").unwrap();
+///
+/// assert_eq!("1\n2\n3\nfeed\n", result);
+/// ```
+pub struct ForCommand {}
+
+/// Defines a function or, if no function is specified, lists the user-defined functions.
+///
+/// The definition starts with `function <name> [param ...] {` and ends with a `}` line, both of
+/// which must start a line.
+/// When the function is called, its arguments are bound to the positional arguments (`$0`, `$1`,
+/// ...) and to the named parameters.
+/// The positional arguments and parameters are restored when the function returns, while other
+/// variables set by the function remain set.
+///
+/// # Example
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "function make_feed name port {
+///          mkdir \"/feeds/$name\"
+///          echo \"$0:$port \"
+///      }
+///      make_feed nasdaq 4000
+///      make_feed nyse 5000
+///      ls /feeds").unwrap();
+///
+/// assert_eq!("nasdaq:4000 nyse:5000 nasdaq\nnyse\n", result);
+/// ```
+pub struct FunctionCommand {}
+
+/// Writes the values of the attributes of an instance, one `<attr>=<value>` per line.
+///
+/// All of the instance's attributes are written in alphabetical order if no attributes are
+/// specified.
+/// A single attribute can also be read by using its path as a command (e.g., `/feed/port`).
+///
+/// # Example
+/// ```
+/// use rcore::command::{CommandContext, IoContext, Shell, UserContext};
+/// use rcore::command::oso::ClassBuilder;
+///
+/// struct Feed { host: String, port: i32 }
+///
+/// let mut shell = Shell::default();
+/// shell.cache_class(ClassBuilder::<Feed>::with_constructor(
+///         |host: String, port: i32| Feed { host, port }, vec!["string", "int"])
+///     .name("Feed")
+///     .add_attribute_getter("host", |feed: &Feed| feed.host.clone())
+///     .add_attribute_getter("port", |feed: &Feed| feed.port)
+///     .build()).unwrap();
+///
+/// let mut commands = std::io::Cursor::new("create /feed Feed localhost 8080
+///     get /feed
+///     get /feed port
+///     /feed/port".as_bytes());
+/// let mut output: Vec<u8> = Vec::new();
+/// let mut io_context = IoContext::new("test", &mut commands, &mut output);
+/// let mut user_context = UserContext::default();
+/// shell.execute_commands(&mut user_context, &mut io_context, &CommandContext::default()).unwrap();
+///
+/// assert_eq!("host=\"localhost\"\nport=8080\nport=8080\n8080",
+///            String::from_utf8(output).unwrap());
+/// ```
+pub struct GetCommand {}
+
+/// Executes the commands of the first branch of a conditional block whose condition is true.
+///
+/// A condition is made up of one or more tests combined with `!`, `&&`, `||`, and parentheses.
+/// A test is one of:
+/// - `true` or `false`
+/// - `-n <value>` or `-z <value>`: the value is non-empty or empty
+/// - `<value> == <value>` or `<value> != <value>`: string comparison
+/// - `<value> -eq|-ne|-lt|-le|-gt|-ge <value>`: numeric comparison
+/// - `<method> [arg ...]`: invokes a method, or reads an attribute, that returns a boolean
+///
+/// The `if`, `elif`, `else`, and `fi` keywords must start a line.
+///
+/// # Example
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "env = prod
+///      threads = 8
+///      if $env == dev; then
+///          echo development
+///      elif $env == prod && $threads -ge 4; then
+///          echo production
+///      else
+///          echo unknown
+///      fi").unwrap();
+///
+/// assert_eq!("production", result);
+/// ```
+pub struct IfCommand {}
+
+/// Creates a link to a directory, instance, method, or attribute, or, with `-sf`, points an existing
+/// link at a new target.
+///
+/// As with Unix symbolic links, the target is an absolute path or a path relative to the link's
+/// directory, and navigating the link navigates its target instead.
+///
+/// # Example
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "mkdir /md/feeds/nyse1/a
+///      mkdir /md/feeds/nyse2/b
+///      ln -s feeds/nyse1 /md/primary
+///      ls /md/primary
+///      ln -sf /md/feeds/nyse2 /md/primary
+///      ls /md/primary
+///      ls /md").unwrap();
+///
+/// assert_eq!("a\nb\nfeeds/\nprimary -> /md/feeds/nyse2\n", result);
+/// ```
+pub struct LnCommand {}
+
 /// Lists the contents of a directory
 ///
 /// # Example
@@ -163,7 +494,8 @@ pub struct ExecuteCommand {}
 /// ```
 pub struct LsCommand {}
 
-/// Prints out all commands or information on how a command is formatted.
+/// Prints out all commands and user-defined functions or information on how a command or function
+/// is formatted.
 ///
 /// # Examples
 /// ```
@@ -172,15 +504,30 @@ pub struct LsCommand {}
 ///
 /// assert_eq!("=
 /// cd
+/// cp
 /// create
 /// :=
+/// dump
 /// echo
+/// find
+/// for
+/// function
+/// get
 /// help
+/// if
+/// ln
 /// ls
 /// mkdir
+/// mv
 /// pwd
+/// rm
+/// rmdir
+/// set
 /// source
+/// tree
+/// try
 /// unset
+/// while
 /// ", result);
 /// ```
 /// ```
@@ -188,6 +535,15 @@ pub struct LsCommand {}
 ///
 /// assert_eq!("ls [dir]", result);
 /// ```
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "function greet name {
+///          echo \"Hello, $name\"
+///      }
+///      help greet").unwrap();
+///
+/// assert_eq!("greet name", result);
+/// ```
 pub struct HelpCommand {}
 
 /// Creates a new directory.
@@ -205,6 +561,23 @@ pub struct HelpCommand {}
 /// ```
 pub struct MkDirCommand {}
 
+/// Moves or renames a directory or instance, along with everything in it.
+///
+/// If the destination is an existing directory, the directory or instance is moved into it.
+///
+/// # Example
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "mkdir /feeds/nyse
+///      mkdir /md
+///      mv /feeds/nyse /feeds/nyse1
+///      mv /feeds /md
+///      ls /md/feeds").unwrap();
+///
+/// assert_eq!("nyse1\n", result);
+/// ```
+pub struct MvCommand {}
+
 /// Returns the current working directory.
 ///
 /// # Example
@@ -219,6 +592,58 @@ pub struct MkDirCommand {}
 /// ```
 pub struct PwdCommand {}
 
+/// Removes instances and empty directories, or, with `-r`, directories and everything in them.
+///
+/// The methods and attributes of a removed instance are removed with it.
+///
+/// # Example
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "mkdir /feeds/nyse
+///      mkdir /feeds/nasdaq
+///      mkdir /tmp/a/b
+///      rm /feeds/nyse
+///      rm -r /tmp
+///      ls /").unwrap();
+///
+/// assert_eq!("feeds/\n", result);
+/// ```
+pub struct RmCommand {}
+
+/// Removes an empty directory.
+///
+/// # Example
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "mkdir /foo/bar
+///      rmdir /foo/bar
+///      ls /foo").unwrap();
+///
+/// assert_eq!("", result);
+/// ```
+pub struct RmDirCommand {}
+
+/// Sets how the shell handles failing commands.
+///
+/// - `set -e`: stops at the first failing command and returns its error (the default)
+/// - `set +e`: logs and counts failing commands and continues with the next command
+///
+/// The exit status of the last command, 0 if it succeeded and 1 if it failed, is available as
+/// `$?`.
+///
+/// # Example
+/// ```
+/// let (result, context) = rcore::command::Shell::from_string(
+///     "set +e
+///      cd /missing
+///      echo \"status=$? \"
+///      echo \"status=$?\"").unwrap();
+///
+/// assert_eq!("status=1 status=0", result);
+/// assert!(!context.errexit());
+/// ```
+pub struct SetCommand {}
+
 /// Returns the current working directory.
 ///
 /// # Examples
@@ -255,6 +680,89 @@ pub struct PwdCommand {}
 /// ```
 pub struct SourceCommand {}
 
+/// Writes the contents of a directory, and everything in it, as a tree.
+///
+/// Each path is annotated like it is listed by `ls`, and links are not followed.
+///
+/// - `-L <depth>`: only writes the paths at most `depth` levels below the directory
+/// - `-M`: hides the methods of instances
+/// - `-A`: hides the attributes of instances
+/// - `-v`: writes the value of each attribute after it
+///
+/// # Example
+/// ```
+/// use rcore::command::{CommandContext, IoContext, Shell, UserContext};
+/// use rcore::command::oso::ClassBuilder;
+///
+/// struct Feed { port: i32 }
+///
+/// let mut shell = Shell::default();
+/// shell.cache_class(ClassBuilder::<Feed>::with_constructor(|port: i32| Feed { port }, vec!["int"])
+///     .name("Feed")
+///     .add_attribute_getter("port", |feed: &Feed| feed.port)
+///     .build()).unwrap();
+///
+/// let mut commands = std::io::Cursor::new("create /md/feeds/nyse Feed 4000
+///     mkdir /md/sessions
+///     tree /md -v".as_bytes());
+/// let mut output: Vec<u8> = Vec::new();
+/// let mut io_context = IoContext::new("test", &mut commands, &mut output);
+/// let mut user_context = UserContext::default();
+/// shell.execute_commands(&mut user_context, &mut io_context, &CommandContext::default()).unwrap();
+///
+/// assert_eq!("/md
+/// ├── feeds/
+/// │   └── nyse/ Feed
+/// │       └── port+Feed.port = 4000
+/// └── sessions
+/// ", String::from_utf8(output).unwrap());
+/// ```
+pub struct TreeCommand {}
+
+/// Executes commands and, if one of them fails, executes the commands of the catch block.
+///
+/// The commands of the try block stop at the first failing command, even with `set +e`.
+/// The optional variable of the catch block is set to the error, which is written as the error's
+/// source, tokens, and message.
+/// When the variable is an argument by itself, it is passed to methods as a map of the error's
+/// `message`, `src`, `line`, `col`, and `tokens`.
+/// Without a catch block, the error is ignored.
+/// The `try` and `}` keywords must start a line.
+///
+/// # Example
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "try {
+///          echo a
+///          cd /missing
+///          echo b
+///      } catch err {
+///          echo \" caught: ${err#*, }\"
+///      }").unwrap();
+///
+/// assert_eq!("a caught: cannot navigate to path: pwd=/, cd=/missing, reason=unknown path", result);
+/// ```
+pub struct TryCommand {}
+
+/// Repeatedly executes commands while a condition is true.
+///
+/// The condition is expanded and evaluated before each iteration and takes the same format as the
+/// conditions of the [IfCommand].
+/// The `while` and `done` keywords must start a line.
+///
+/// # Example
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "v = a
+///      while $v != aaa; do
+///          v = \"${v}a\"
+///      done
+///      echo $v").unwrap();
+///
+/// assert_eq!("aaa", result);
+/// ```
+pub struct WhileCommand {}
+
 /// Removes a variable.
 ///
 /// # Example
@@ -279,12 +787,12 @@ impl Command for AssignCommand {
     }
 
     fn help_text(&self) -> &'static str {
-        "<var> = <value>"
+        "<var>|<attr> = <value>|<method> [arg ...]"
     }
 
     fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
-        if tokens.len() == 3 {
-            if validate_variable(tokens.get(0)) {
+        if tokens.len() >= 3 {
+            if validate_variable(tokens.get(0)) || is_attr_path(tokens.get(0)) {
                 Ok(())
             } else {
                 Err(CommandValidationError::InvalidVariableName(tokens.get(0).to_owned()))
@@ -297,17 +805,84 @@ impl Command for AssignCommand {
     fn execute(&self,
                tokens: &Tokens,
                user_context: &mut UserContext,
-               _io_context: &mut IoContext,
+               io_context: &mut IoContext,
                _command_context: &CommandContext,
-               _shell: &mut Shell) -> Result<(), ShellError> {
+               shell: &mut Shell) -> Result<(), ShellError> {
         let var = &tokens.get(0);
         let value = &tokens.get(2);
-        debug!("[Assign] setting variable {} = {}", var, value);
-        user_context.set_value(var, value);
+        // only an absolute path or the output of a command substitution is invoked, so a word
+        // is never reinterpreted as a method relative to the working directory
+        let is_path = value.starts_with('/') || tokens.is_substitution(2);
+        let is_method = is_path && shell.registry.read().cd(user_context.pwd(), value)
+            .map(|path| path.method.is_some() || path.attr.is_some())
+            .unwrap_or(false);
+
+        if tokens.len() > 3 && !is_method {
+            // only a method or attribute is followed by arguments
+            return Err(ShellError::CommandValidationError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: CommandValidationError::InvalidCommandFormat { format: self.help_text() },
+            });
+        }
+        if is_attr_path(var) {
+            return AssignCommand::set_attr(tokens, user_context, io_context, shell, is_method);
+        }
+
+        if tokens.len() == 3 && !is_method {
+            debug!("[Assign] setting variable {} = {}", var, value);
+            match tokens.value(2) {
+                Some(value) => set_variable(user_context, shell, var, value.clone()),
+                None => user_context.set_value(var, value)
+            }
+            return Ok(());
+        }
+
+        let args: Vec<&str> = tokens.all()[3..].iter().map(|t| t.as_str()).collect();
+        debug!("[Assign] setting variable {} to result of method {}, args={}",
+            var, value, args.join(", "));
+        let result = shell.registry.parsed_invoke_with_values(
+            user_context.pwd(), value, &args, &arg_values(tokens, 3)
+        ).map_err(|e| ShellError::RegistryError {
+            src: io_context.to_source_info(),
+            tokens: tokens.clone(),
+            error: e,
+        })?;
+        set_variable(user_context, shell, var, result);
         Ok(())
     }
 }
 
+impl AssignCommand {
+    /// Sets the attribute at the path of the first token to the value, or to the result of the
+    /// method, of the remaining tokens.
+    fn set_attr(tokens: &Tokens,
+                user_context: &mut UserContext,
+                io_context: &mut IoContext,
+                shell: &mut Shell,
+                is_method: bool) -> Result<(), ShellError> {
+        let attr = tokens.get(0);
+        let value = tokens.get(2);
+        let result = if tokens.len() == 3 && !is_method {
+            debug!("[Assign] setting attribute {} = {}", attr, value);
+            shell.registry.write().parsed_set_attr_with_values(
+                user_context.pwd(), attr, value, &arg_values(tokens, 2))
+        } else {
+            let args: Vec<&str> = tokens.all()[3..].iter().map(|t| t.as_str()).collect();
+            debug!("[Assign] setting attribute {} to result of method {}, args={}",
+                attr, value, args.join(", "));
+            shell.registry.parsed_invoke_with_values(
+                user_context.pwd(), value, &args, &arg_values(tokens, 3)
+            ).and_then(|result| shell.registry.write().set_attr(user_context.pwd(), attr, result))
+        };
+        result.map_err(|e| ShellError::RegistryError {
+            src: io_context.to_source_info(),
+            tokens: tokens.clone(),
+            error: e,
+        })
+    }
+}
+
 impl Command for CdCommand {
     fn keyword(&self) -> &'static str {
         "cd"
@@ -335,7 +910,7 @@ impl Command for CdCommand {
                io_context: &mut IoContext,
                _command_context: &CommandContext,
                shell: &mut Shell) -> Result<(), ShellError> {
-        match shell.registry.cd(user_context.pwd(), &tokens.get(1)) {
+        match shell.registry.read().cd(user_context.pwd(), &tokens.get(1)) {
             Ok(path) => {
                 debug!("[Cd] setting current working directory = {}", path.abs_path());
                 user_context.set_pwd(path.abs_path());
@@ -350,6 +925,44 @@ impl Command for CdCommand {
     }
 }
 
+impl Command for CpCommand {
+    fn keyword(&self) -> &'static str {
+        "cp"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "cp <instance> <path>"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() == 3 {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        debug!("[Cp] copying instance pwd={}, from={}, to={}",
+            user_context.pwd(), tokens.get(1), tokens.get(2));
+        shell.registry.write().copy(user_context.pwd(), tokens.get(1), tokens.get(2)).map_err(
+            |e| ShellError::RegistryError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e,
+            })
+    }
+}
+
 impl Command for CreateCommand {
     fn keyword(&self) -> &'static str {
         "create"
@@ -384,8 +997,8 @@ impl Command for CreateCommand {
 
         debug!("[Create] creating instance: dir={}, class={}, args=[{}]",
             &tokens.get(1), &tokens.get(2), &args.join(", "));
-        shell.registry.parsed_create_instance(
-            user_context.pwd(), &tokens.get(1), &tokens.get(2), &args
+        shell.registry.write().parsed_create_instance_with_values(
+            user_context.pwd(), &tokens.get(1), &tokens.get(2), &args, &arg_values(tokens, 3)
         ).map_err(|e| ShellError::RegistryError {
             src: io_context.to_source_info(),
             tokens: tokens.clone(),
@@ -442,6 +1055,53 @@ impl Command for DefaultAssignCommand {
     }
 }
 
+impl Command for DumpCommand {
+    fn keyword(&self) -> &'static str {
+        "dump"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "dump [dir]"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() == 1 || tokens.len() == 2 {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        let cd = if tokens.len() == 1 { "." } else { tokens.get(1) };
+        debug!("[Dump] dumping directory pwd={}, cd={}", user_context.pwd(), cd);
+        let commands = shell.registry.read().dump(user_context.pwd(), cd).map_err(
+            |e| ShellError::RegistryError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e,
+            })?;
+
+        for command in commands {
+            io_context.write_string(command + "\n").map_err(|e| ShellError::IoError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e,
+            })?;
+        }
+        Ok(())
+    }
+}
+
 impl Command for EchoCommand {
     fn keyword(&self) -> &'static str {
         "echo"
@@ -511,34 +1171,50 @@ impl Command for ExecuteCommand {
                io_context: &mut IoContext,
                _command_context: &CommandContext,
                shell: &mut Shell) -> Result<(), ShellError> {
-        let mut args: Vec<&str> = vec![];
-        for i in 1..tokens.len() {
-            args.push(&tokens.get(i));
+        let result = ExecuteCommand::invoke(tokens, user_context, io_context, shell)?;
+
+        let written = if shell.substituting {
+            io_context.write_string(value_to_string(shell, &result))
+        } else {
+            write_object(io_context, shell, &result)
+        };
+        written.map_err(|e| ShellError::IoError {
+            src: io_context.to_source_info(),
+            tokens: tokens.clone(),
+            error: e,
+        })?;
+        Ok(())
+    }
+}
+
+impl ExecuteCommand {
+    /// Invokes the method, or retrieves the value of the attribute, and returns its result.
+    pub(crate) fn invoke(tokens: &Tokens,
+                         user_context: &UserContext,
+                         io_context: &IoContext,
+                         shell: &mut Shell) -> Result<PolarValue, ShellError> {
+        let mut args: Vec<&str> = vec![];
+        for i in 1..tokens.len() {
+            args.push(&tokens.get(i));
         }
 
         debug!("[Execute] invoking method pwd={}, cd={}, args={}",
             user_context.pwd(), &tokens.get(0), args.join(", "));
-        let result = shell.registry.parsed_invoke_method(user_context.pwd(),
-                                                         &tokens.get(0),
-                                                         &args)
+        shell.registry.parsed_invoke_with_values(user_context.pwd(),
+                                                 &tokens.get(0),
+                                                 &args,
+                                                 &arg_values(tokens, 1))
             .map_err(|e| ShellError::RegistryError {
                 src: io_context.to_source_info(),
                 tokens: tokens.clone(),
                 error: e,
-            })?;
-
-        write_object(io_context, shell, &result).map_err(|e| ShellError::IoError {
-            src: io_context.to_source_info(),
-            tokens: tokens.clone(),
-            error: e,
-        })?;
-        Ok(())
+            })
     }
 }
 
-impl Command for HelpCommand {
+impl Command for FindCommand {
     fn keyword(&self) -> &'static str {
-        "help"
+        "find"
     }
 
     fn keyword_position(&self) -> usize {
@@ -546,49 +1222,81 @@ impl Command for HelpCommand {
     }
 
     fn help_text(&self) -> &'static str {
-        "help [command]"
+        "find [dir] [-name <pattern>] [-class <class>] [-type d|i|m|a|l] [-maxdepth <depth>]"
     }
 
     fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
-        if tokens.len() == 1 || tokens.len() == 2 {
-            Ok(())
-        } else {
-            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
-        }
+        self.parse_filter(tokens).map(|_| ())
     }
 
     fn execute(&self,
                tokens: &Tokens,
-               _user_context: &mut UserContext,
+               user_context: &mut UserContext,
                io_context: &mut IoContext,
-               command_context: &CommandContext,
-               _shell: &mut Shell) -> Result<(), ShellError> {
-        (|| -> Result<(), io::Error> {
-            if tokens.len() == 1 {
-                for cmd in &command_context.builtin_commands {
-                    io_context.write_str(cmd.keyword())?;
-                    io_context.write_str("\n")?;
-                }
-            } else if tokens.len() == 2 {
-                let help_wanted = tokens.get(1);
-                for cmd in &command_context.builtin_commands {
-                    if cmd.keyword() == help_wanted {
-                        io_context.write_str(cmd.help_text())?;
-                    }
-                }
+               _command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        let (cd, filter) = self.parse_filter(tokens).unwrap();
+        debug!("[Find] finding paths pwd={}, cd={}, filter={:?}", user_context.pwd(), cd, filter);
+        let paths = shell.registry.read().find(user_context.pwd(), cd, &filter).map_err(
+            |e| ShellError::RegistryError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e,
+            })?;
+
+        for path in paths {
+            io_context.write_string(path + "\n").map_err(|e| ShellError::IoError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl FindCommand {
+    /// Returns the directory to search and the conditions of the paths to find.
+    fn parse_filter<'a>(&self, tokens: &'a Tokens)
+                        -> Result<(&'a str, FindFilter), CommandValidationError> {
+        let invalid = || CommandValidationError::InvalidCommandFormat { format: self.help_text() };
+        let mut i = 1;
+        let cd = if tokens.len() > 1 && !tokens.get(1).starts_with('-') {
+            i += 1;
+            tokens.get(1)
+        } else {
+            "."
+        };
+
+        let mut filter = FindFilter::default();
+        while i < tokens.len() {
+            if i + 1 == tokens.len() {
+                return Err(invalid());
             }
-            Ok(())
-        })().map_err(|e| ShellError::IoError {
-            src: io_context.to_source_info(),
-            tokens: tokens.clone(),
-            error: e,
-        })
+            let value = tokens.get(i + 1);
+            match tokens.get(i) {
+                "-name" => filter.name = Some(value.to_owned()),
+                "-class" => filter.class = Some(value.to_owned()),
+                "-type" => filter.kind = Some(match value {
+                    "d" => NodeKind::Directory,
+                    "i" => NodeKind::Instance,
+                    "m" => NodeKind::Method,
+                    "a" => NodeKind::Attribute,
+                    "l" => NodeKind::Link,
+                    _ => return Err(invalid())
+                }),
+                "-maxdepth" => filter.max_depth = Some(value.parse().map_err(|_| invalid())?),
+                _ => return Err(invalid())
+            }
+            i += 2;
+        }
+        Ok((cd, filter))
     }
 }
 
-impl Command for LsCommand {
+impl Command for ForCommand {
     fn keyword(&self) -> &'static str {
-        "ls"
+        "for"
     }
 
     fn keyword_position(&self) -> usize {
@@ -596,14 +1304,16 @@ impl Command for LsCommand {
     }
 
     fn help_text(&self) -> &'static str {
-        "ls [dir]"
+        "for <var> in [item ...]; do ... done"
     }
 
     fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
-        if tokens.len() == 1 || tokens.len() == 2 {
-            Ok(())
-        } else {
+        if statement_end(tokens, "do") < 3 || tokens.get(2) != "in" {
             Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        } else if !validate_variable(tokens.get(1)) {
+            Err(CommandValidationError::InvalidVariableName(tokens.get(1).to_owned()))
+        } else {
+            Ok(())
         }
     }
 
@@ -611,137 +1321,241 @@ impl Command for LsCommand {
                tokens: &Tokens,
                user_context: &mut UserContext,
                io_context: &mut IoContext,
-               _command_context: &CommandContext,
+               command_context: &CommandContext,
                shell: &mut Shell) -> Result<(), ShellError> {
-        let registry = &shell.registry;
-        let mut children: Vec<String> = vec![];
+        let src = io_context.to_source_info();
+        let block = read_block(tokens, io_context, &[], "done")?;
 
-        let cd = if tokens.len() == 1 { "." } else { &tokens.get(1) };
-
-        let path = registry.cd(user_context.pwd(), cd)
+        let end = statement_end(tokens, "do");
+        let values = Self::values(tokens, end, user_context, shell)
             .map_err(|e| ShellError::RegistryError {
-                src: io_context.to_source_info(),
+                src,
                 tokens: tokens.clone(),
                 error: e,
             })?;
 
-        for child in path.children(&registry) {
-            let mut child_str = String::new();
-            child_str.push_str(child.name());
-            if child.has_children() {
-                child_str.push('/');
+        let var = tokens.get(1);
+        execute_block(&block, user_context, io_context, command_context, shell,
+                      |user_context, io_context, shell| {
+            for value in values {
+                debug!("[For] setting variable {} = {:?}", var, value);
+                set_variable(user_context, shell, var, value);
+                execute_section(&block.sections[0], user_context, io_context, command_context, shell)?;
             }
+            Ok(())
+        })
+    }
+}
 
-            if child.instance().is_some() {
-                let instance = child.instance().unwrap();
-                let class = registry.class_for_instance(instance);
-
-                child_str.push(' ');
-                child_str.push_str(&class.name);
-            } else if child.method.is_some() {
-                let instance = child.owner_instance(&registry).unwrap();
-                let class = registry.class_for_instance(instance);
-                let method_name = child.method.unwrap();
-                let method = class.instance_methods.get(method_name).unwrap();
-
-                child_str.push_str("! ");
-                child_str.push_str(&class.name);
-                child_str.push_str("::");
-                child_str.push_str(method_name);
-                child_str.push('(');
-                let mut first = true;
-                for pt in method.param_types() {
-                    if !first {
-                        child_str.push_str(", ");
-                    }
-                    first = false;
-                    child_str.push_str(pt);
+impl ForCommand {
+    /// Returns the values of the items, which are the tokens from index 3 to the end index.
+    ///
+    /// Only an absolute path or the output of a command substitution is invoked or iterated, so a
+    /// list of words is never reinterpreted as a path relative to the working directory.
+    fn values(tokens: &Tokens, end: usize, user_context: &UserContext, shell: &mut Shell)
+              -> Result<Vec<PolarValue>, RegistryError> {
+        let items = &tokens.all()[3..end];
+        if let Some(first) = items.first() {
+            let pwd = user_context.pwd();
+            let is_path = first.starts_with('/') || tokens.is_substitution(3);
+            let (is_method, is_instance) = match shell.registry.read().cd(pwd, first) {
+                Ok(path) if is_path => (path.method.is_some() || path.attr.is_some(),
+                                        path.instance().is_some()),
+                _ => (false, false)
+            };
+
+            if is_method {
+                // the remaining items are the method's arguments
+                let args: Vec<&str> = items[1..].iter().map(|i| i.as_str()).collect();
+                let result = shell.registry.parsed_invoke_with_values(
+                    pwd, first, &args, &arg_values(tokens, 4))?;
+                return match result {
+                    PolarValue::List(values) => Ok(values),
+                    PolarValue::Instance(instance) =>
+                        shell.registry.read().iter_values(pwd, first, &instance),
+                    value => Ok(vec![value])
+                };
+            } else if is_instance && items.len() == 1 {
+                return shell.registry.read().instance_iter(pwd, first);
+            } else if items.len() == 1 {
+                // a variable holding a list or an iterable instance
+                match tokens.value(3) {
+                    Some(PolarValue::List(values)) => return Ok(values.clone()),
+                    Some(PolarValue::Instance(instance)) =>
+                        return shell.registry.read().iter_values(pwd, first, instance),
+                    _ => {}
                 }
-                child_str.push(')');
-
-            } else if child.attr.is_some() {
-                let instance = child.owner_instance(&registry).unwrap();
-                let class = registry.class_for_instance(instance);
-                let attr_name = child.attr.unwrap();
+            }
+        }
 
-                child_str.push('+');
-                child_str.push_str(&class.name);
-                child_str.push('.');
-                child_str.push_str(attr_name);
+        let mut values = vec![];
+        for item in items {
+            match Self::range(item) {
+                Some(range) => values.extend(range.into_iter().map(PolarValue::String)),
+                None => values.push(PolarValue::String(item.to_owned()))
             }
+        }
+        Ok(values)
+    }
 
-            child_str.push('\n');
-            children.push(child_str);
+    /// Expands an integer range with an optional step (e.g., `{1..5}` or `{10..0..2}`).
+    fn range(item: &str) -> Option<Vec<String>> {
+        let parts: Vec<&str> = item.strip_prefix('{')?.strip_suffix('}')?.split("..").collect();
+        if parts.len() != 2 && parts.len() != 3 {
+            return None;
         }
 
-        children.sort();
-        for child in children {
-            let e = io_context.write_string(child).err();
-            if e.is_some() {
-                return Err(ShellError::IoError {
-                    src: io_context.to_source_info(),
-                    tokens: tokens.clone(),
-                    error: e.unwrap(),
-                })
+        let start: i64 = parts[0].parse().ok()?;
+        let end: i64 = parts[1].parse().ok()?;
+        let step: i64 = if parts.len() == 3 {
+            parts[2].parse::<i64>().ok()?.checked_abs()?.max(1)
+        } else {
+            1
+        };
+
+        let mut values = vec![];
+        let mut i = start;
+        while (start <= end && i <= end) || (start > end && i >= end) {
+            values.push(i.to_string());
+            i = match if start <= end { i.checked_add(step) } else { i.checked_sub(step) } {
+                Some(next) => next,
+                None => break
+            };
+        }
+        Some(values)
+    }
+}
+
+impl Command for FunctionCommand {
+    fn keyword(&self) -> &'static str {
+        "function"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "function [<name> [param ...] { ... }]"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() == 1 {
+            return Ok(());
+        } else if tokens.len() < 3 || tokens.get(tokens.len() - 1) != "{" {
+            return Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() });
+        }
+
+        for i in 1..tokens.len() - 1 {
+            if !validate_variable(tokens.get(i)) {
+                return Err(CommandValidationError::InvalidVariableName(tokens.get(i).to_owned()));
             }
         }
+        Ok(())
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               command_context: &CommandContext,
+               _shell: &mut Shell) -> Result<(), ShellError> {
+        if tokens.len() == 1 {
+            return (|| -> Result<(), io::Error> {
+                for name in user_context.function_names() {
+                    io_context.write_str(&user_context.function(name).unwrap().signature())?;
+                    io_context.write_str("\n")?;
+                }
+                Ok(())
+            })().map_err(|e| ShellError::IoError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e,
+            });
+        }
+
+        let name = tokens.get(1);
+        // built-in commands take precedence over functions, so the function could never be called
+        if command_context.builtin_commands.iter().any(|cmd| cmd.keyword() == name) {
+            return Err(ShellError::CommandExecutionError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: CommandExecutionError::ReservedFunctionName(name.to_owned()),
+            });
+        }
 
+        let section = read_block(tokens, io_context, &[], "}")?.sections.remove(0);
+        let function = Function {
+            name: name.to_owned(),
+            params: tokens.all()[2..tokens.len() - 1].to_vec(),
+            src: io_context.src.to_owned(),
+            line: section.line,
+            body: section.body,
+        };
+        debug!("[Function] defining function {}", function.signature());
+        user_context.set_function(function);
         Ok(())
     }
 }
 
-fn write_object(io_context: &mut IoContext, shell: &Shell, result: &PolarValue)
-                -> Result<(), io::Error> {
-    match result {
-        PolarValue::Integer(i) => io_context.write_string(format!("{}", i)),
-        PolarValue::Float(f) => io_context.write_string(format!("{}", f)),
-        PolarValue::String(s) => io_context.write_string(format!("\"{}\"", s)),
-        PolarValue::Boolean(b) => io_context.write_string(format!("{}", b)),
-        PolarValue::Map(m) => {
-            io_context.write_str("{{")?;
-            let mut first = true;
-            for (key, val) in m {
-                if !first {
-                    io_context.write_str(",")?;
-                }
-                first = false;
-                io_context.write_string(format!("\"{}\":", key))?;
-                write_object(io_context, shell, val)?;
-            }
-            io_context.write_str("}}")
-        },
-        PolarValue::List(l) => {
-            io_context.write_str("[")?;
-            for i in 0..l.len() {
-                if i != 0 {
-                    io_context.write_str(",")?;
-                }
-                write_object(io_context, shell, &l[i])?;
-            }
-            io_context.write_str("]")
-        },
-        PolarValue::Instance(i) => {
-            let clz = shell.registry.class_for_instance(i);
-            io_context.write_str("{{")?;
-            let mut first = true;
-            for (attr_name, attr) in &clz.attributes {
-                if !first {
-                    io_context.write_str(",")?;
-                }
-                first = false;
-                io_context.write_string(format!("\"{}\":", attr_name))?;
+impl FunctionCommand {
+    /// Calls the user-defined function with the arguments in the tokens.
+    pub(crate) fn call(function: &Function,
+                       tokens: &Tokens,
+                       user_context: &mut UserContext,
+                       io_context: &mut IoContext,
+                       command_context: &CommandContext,
+                       shell: &mut Shell) -> Result<(), ShellError> {
+        if user_context.function_level >= MAX_FUNCTION_RECURSION {
+            return Err(ShellError::CommandExecutionError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: CommandExecutionError::MaxFunctionRecursion(function.name.to_owned()),
+            });
+        }
 
-                let value = shell.registry.instance_attr(i, attr);
-                write_object(io_context, shell, &value)?;
+        debug!("[Function] calling function {}, args={}",
+            function.name, tokens.tokens_substring(1, tokens.len()));
+
+        // bind the arguments, saving the caller's values so they can be restored
+        let args = tokens.all()[1..].to_vec();
+        let mut saved_params = vec![];
+        for (i, param) in function.params.iter().enumerate() {
+            saved_params.push((param,
+                               user_context.get_value(param).cloned(),
+                               user_context.get_polar_value(param).cloned()));
+            match (args.get(i), tokens.value(i + 1)) {
+                (Some(arg), Some(value)) => user_context.set_polar_value(param, value.clone(), arg),
+                (Some(arg), None) => user_context.set_value(param, arg),
+                (None, _) => user_context.remove_value(param)
             }
-            io_context.write_str("}}")
-        },
+        }
+        let saved_args = std::mem::replace(&mut user_context.arguments, args);
+
+        // the function's commands are read from its definition, but written to the same output
+        let mut input = Cursor::new(function.body.as_bytes());
+        let mut function_io = IoContext::new(&function.src, &mut input, &mut io_context.output);
+        function_io.line = function.line;
+
+        user_context.function_level += 1;
+        let result = shell.execute_commands(user_context, &mut function_io, command_context);
+        user_context.function_level -= 1;
+
+        user_context.arguments = saved_args;
+        for (param, text, value) in saved_params {
+            match (text, value) {
+                (Some(text), Some(value)) => user_context.set_polar_value(param, value, &text),
+                (Some(text), None) => user_context.set_value(param, &text),
+                (None, _) => user_context.remove_value(param)
+            }
+        }
+        result
     }
 }
 
-impl Command for MkDirCommand {
+impl Command for GetCommand {
     fn keyword(&self) -> &'static str {
-        "mkdir"
+        "get"
     }
 
     fn keyword_position(&self) -> usize {
@@ -749,11 +1563,11 @@ impl Command for MkDirCommand {
     }
 
     fn help_text(&self) -> &'static str {
-        "mkdir <dir>"
+        "get <instance> [attr ...]"
     }
 
-    fn validate(&self, command: &Tokens) -> Result<(), CommandValidationError> {
-        if command.len() == 2 {
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() >= 2 {
             Ok(())
         } else {
             Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
@@ -766,20 +1580,32 @@ impl Command for MkDirCommand {
                io_context: &mut IoContext,
                _command_context: &CommandContext,
                shell: &mut Shell) -> Result<(), ShellError> {
-        debug!("[MkDir] creating directories pwd={}, cd={}",
-            user_context.pwd(), tokens.get(1));
-        shell.registry.mkdir(user_context.pwd(), tokens.get(1)).map_err(
-            |e| ShellError::RegistryError {
+        let names: Vec<&str> = tokens.all()[2..].iter().map(|t| t.as_str()).collect();
+        let attrs = shell.registry.read().attrs(user_context.pwd(), tokens.get(1), &names)
+            .map_err(|e| ShellError::RegistryError {
                 src: io_context.to_source_info(),
                 tokens: tokens.clone(),
                 error: e,
-            })
+            })?;
+
+        (|| -> Result<(), io::Error> {
+            for (name, value) in &attrs {
+                io_context.write_string(format!("{}=", name))?;
+                write_object(io_context, shell, value)?;
+                io_context.write_str("\n")?;
+            }
+            Ok(())
+        })().map_err(|e| ShellError::IoError {
+            src: io_context.to_source_info(),
+            tokens: tokens.clone(),
+            error: e,
+        })
     }
 }
 
-impl Command for PwdCommand {
+impl Command for HelpCommand {
     fn keyword(&self) -> &'static str {
-        "pwd"
+        "help"
     }
 
     fn keyword_position(&self) -> usize {
@@ -787,11 +1613,11 @@ impl Command for PwdCommand {
     }
 
     fn help_text(&self) -> &'static str {
-        "pwd"
+        "help [command]"
     }
 
     fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
-        if tokens.len() == 1 {
+        if tokens.len() == 1 || tokens.len() == 2 {
             Ok(())
         } else {
             Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
@@ -802,9 +1628,35 @@ impl Command for PwdCommand {
                tokens: &Tokens,
                user_context: &mut UserContext,
                io_context: &mut IoContext,
-               _command_context: &CommandContext,
+               command_context: &CommandContext,
                _shell: &mut Shell) -> Result<(), ShellError> {
-        io_context.write_str(user_context.pwd()).map_err(|e| ShellError::IoError {
+        (|| -> Result<(), io::Error> {
+            if tokens.len() == 1 {
+                for cmd in &command_context.builtin_commands {
+                    io_context.write_str(cmd.keyword())?;
+                    io_context.write_str("\n")?;
+                }
+                for name in user_context.function_names() {
+                    io_context.write_str(name)?;
+                    io_context.write_str("\n")?;
+                }
+            } else if tokens.len() == 2 {
+                let help_wanted = tokens.get(1);
+                let mut found = false;
+                for cmd in &command_context.builtin_commands {
+                    if cmd.keyword() == help_wanted {
+                        io_context.write_str(cmd.help_text())?;
+                        found = true;
+                    }
+                }
+                if !found {
+                    if let Some(function) = user_context.function(help_wanted) {
+                        io_context.write_str(&function.signature())?;
+                    }
+                }
+            }
+            Ok(())
+        })().map_err(|e| ShellError::IoError {
             src: io_context.to_source_info(),
             tokens: tokens.clone(),
             error: e,
@@ -812,11 +1664,9 @@ impl Command for PwdCommand {
     }
 }
 
-const MAX_SOURCE_RECURSION: usize = 10;
-
-impl Command for SourceCommand {
+impl Command for IfCommand {
     fn keyword(&self) -> &'static str {
-        "source"
+        "if"
     }
 
     fn keyword_position(&self) -> usize {
@@ -824,11 +1674,11 @@ impl Command for SourceCommand {
     }
 
     fn help_text(&self) -> &'static str {
-        "source [-s] <file>"
+        "if <condition>; then ... [elif <condition>; then ...] [else ...] fi"
     }
 
-    fn validate(&self, command: &Tokens) -> Result<(), CommandValidationError> {
-        if command.len() == 2 && command.get(1) != "-s" || command.len() >= 3 {
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if statement_end(tokens, "then") > 1 {
             Ok(())
         } else {
             Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
@@ -839,63 +1689,162 @@ impl Command for SourceCommand {
                tokens: &Tokens,
                user_context: &mut UserContext,
                io_context: &mut IoContext,
-               _command_context: &CommandContext,
+               command_context: &CommandContext,
                shell: &mut Shell) -> Result<(), ShellError> {
-        let subshell = tokens.get(1) == "-s";
-        let arg_start = if subshell { 3 } else { 2 };
-        let file_name = tokens.get(arg_start - 1);
+        let src = io_context.to_source_info();
+        let block = read_block(tokens, io_context, &["elif", "else"], "fi")?;
+        let sections = &block.sections;
+
+        // else must be the last branch
+        for (i, section) in sections.iter().enumerate() {
+            if section.keyword() == "else" && i != sections.len() - 1 {
+                return Err(ShellError::CommandExecutionError {
+                    src,
+                    tokens: tokens.clone(),
+                    error: CommandExecutionError::UnexpectedBlockStatement(
+                        sections[i + 1].header.to_owned()),
+                });
+            }
+        }
 
-        debug!("[Source] loading file {}{}, args={}",
-            file_name,
-            if subshell { " (subshell)" } else { "" },
-            tokens.tokens_substring(arg_start, tokens.len()));
+        execute_block(&block, user_context, io_context, command_context, shell,
+                      |user_context, io_context, shell| {
+            for (i, section) in sections.iter().enumerate() {
+                let matched = if i == 0 {
+                    evaluate_condition(tokens, &src, "then", user_context, shell)?
+                } else if section.keyword() == "else" {
+                    true
+                } else {
+                    let (header, header_src) = lex_header(section, user_context, io_context, command_context, shell)?;
+                    evaluate_condition(&header, &header_src, "then", user_context, shell)?
+                };
+
+                if matched {
+                    debug!("[If] executing branch at line {}", section.line);
+                    return execute_section(section, user_context, io_context, command_context, shell);
+                }
+            }
 
-        // make a copy of all variables
-        let mut new_user_context = user_context.clone();
-        // add our arguments to the file
-        new_user_context.clear_arguments();
-        for i in arg_start..tokens.len() {
-            let arg = tokens.get(i);
-            new_user_context.add_argument(arg);
+            Ok(())
+        })
+    }
+}
+
+/// Evaluates the condition of a block statement (e.g., `if <condition>; then`).
+fn evaluate_condition(tokens: &Tokens,
+                      src: &SourceInfo,
+                      keyword: &str,
+                      user_context: &UserContext,
+                      shell: &mut Shell) -> Result<bool, ShellError> {
+    let end = statement_end(tokens, keyword);
+    evaluate(&tokens.slice(1, end), user_context, shell).map_err(|e| match e {
+        ConditionError::Invalid(reason) => ShellError::CommandExecutionError {
+            src: src.clone(),
+            tokens: tokens.clone(),
+            error: CommandExecutionError::InvalidCondition {
+                tokens: tokens.clone(),
+                reason,
+            },
+        },
+        ConditionError::Registry(error) => ShellError::RegistryError {
+            src: src.clone(),
+            tokens: tokens.clone(),
+            error,
         }
+    })
+}
 
-        // load file
-        match File::open(file_name) {
-            Ok(f) => {
-                if user_context.level >= MAX_SOURCE_RECURSION {
-                    return Err(ShellError::CommandExecutionError {
-                        src: io_context.to_source_info(),
-                        tokens: tokens.clone(),
-                        error: CommandExecutionError::MaxSourceCommand(user_context.level)
-                    })
-                }
+impl Command for LnCommand {
+    fn keyword(&self) -> &'static str {
+        "ln"
+    }
 
-                let mut reader = BufReader::new(f);
-                // the new I/O context has the new file, but the same output
-                let mut new_io_context = IoContext::new(
-                    file_name, &mut reader, &mut io_context.output);
+    fn keyword_position(&self) -> usize {
+        0
+    }
 
-                user_context.level += 1;
-                shell.execute_commands(
-                    &mut new_user_context, &mut new_io_context, _command_context)?;
-                user_context.level -= 1;
-            }
-            Err(error) => return Err(ShellError::CommandExecutionError {
+    fn help_text(&self) -> &'static str {
+        "ln -s|-sf <target> <link>"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() == 4 && matches!(tokens.get(1), "-s" | "-sf" | "-fs") {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        let replace = tokens.get(1) != "-s";
+        debug!("[Ln] linking pwd={}, cd={}, target={}, replace={}",
+            user_context.pwd(), tokens.get(3), tokens.get(2), replace);
+        shell.registry.write().link(user_context.pwd(), tokens.get(3), tokens.get(2), replace).map_err(
+            |e| ShellError::RegistryError {
                 src: io_context.to_source_info(),
                 tokens: tokens.clone(),
-                error: CommandExecutionError::UnableToOpenFile {
-                    file: file_name.to_owned(),
-                    error,
-                }
+                error: e,
             })
+    }
+}
+
+impl Command for LsCommand {
+    fn keyword(&self) -> &'static str {
+        "ls"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "ls [dir]"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() == 1 || tokens.len() == 2 {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        let registry = shell.registry.read();
+        let mut children: Vec<String> = vec![];
+
+        let cd = if tokens.len() == 1 { "." } else { &tokens.get(1) };
+
+        let path = registry.cd(user_context.pwd(), cd)
+            .map_err(|e| ShellError::RegistryError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e,
+            })?;
+
+        for child in path.children(&registry) {
+            children.push(describe_path(&registry, child) + "\n");
         }
 
-        // update variables if we're not a subshell
-        if !subshell {
-            user_context.set_pwd(new_user_context.pwd());
-            user_context.clear_variables();
-            for (key, value) in &new_user_context.variables {
-                user_context.set_value(key, value);
+        children.sort();
+        for child in children {
+            let e = io_context.write_string(child).err();
+            if e.is_some() {
+                return Err(ShellError::IoError {
+                    src: io_context.to_source_info(),
+                    tokens: tokens.clone(),
+                    error: e.unwrap(),
+                })
             }
         }
 
@@ -903,9 +1852,153 @@ impl Command for SourceCommand {
     }
 }
 
-impl Command for UnsetCommand {
+/// Returns the name of a path as it is listed by `ls`, followed by `/` if it has children, and the
+/// link target, class, method signature, or attribute it stores.
+fn describe_path(registry: &Registry, child: &PathSegment) -> String {
+    let mut child_str = String::new();
+    child_str.push_str(child.name());
+    if child.has_children() {
+        child_str.push('/');
+    }
+
+    if let Some(target) = child.link() {
+        child_str.push_str(" -> ");
+        child_str.push_str(target);
+    } else if child.instance().is_some() {
+        let instance = child.instance().unwrap();
+        let class = registry.class_for_instance(instance);
+
+        child_str.push(' ');
+        child_str.push_str(&class.name);
+    } else if child.method.is_some() {
+        let instance = child.owner_instance(registry).unwrap();
+        let class = registry.class_for_instance(instance);
+        let method_name = child.method.unwrap();
+        let method = class.instance_methods.get(method_name).unwrap();
+
+        child_str.push_str("! ");
+        child_str.push_str(&class.name);
+        child_str.push_str("::");
+        child_str.push_str(method_name);
+        child_str.push('(');
+        let mut first = true;
+        for pt in method.param_types() {
+            if !first {
+                child_str.push_str(", ");
+            }
+            first = false;
+            child_str.push_str(pt);
+        }
+        child_str.push(')');
+
+    } else if child.attr.is_some() {
+        let instance = child.owner_instance(registry).unwrap();
+        let class = registry.class_for_instance(instance);
+        let attr_name = child.attr.unwrap();
+
+        child_str.push('+');
+        child_str.push_str(&class.name);
+        child_str.push('.');
+        child_str.push_str(attr_name);
+    }
+
+    child_str
+}
+
+fn write_object(io_context: &mut IoContext, shell: &Shell, result: &PolarValue)
+                -> Result<(), io::Error> {
+    match result {
+        PolarValue::Integer(i) => io_context.write_string(format!("{}", i)),
+        PolarValue::Float(f) => io_context.write_string(format!("{}", f)),
+        PolarValue::String(s) => io_context.write_string(format!("\"{}\"", s)),
+        PolarValue::Boolean(b) => io_context.write_string(format!("{}", b)),
+        PolarValue::Map(m) => {
+            io_context.write_str("{")?;
+            let mut first = true;
+            for (key, val) in m {
+                if !first {
+                    io_context.write_str(",")?;
+                }
+                first = false;
+                io_context.write_string(format!("\"{}\":", key))?;
+                write_object(io_context, shell, val)?;
+            }
+            io_context.write_str("}")
+        },
+        PolarValue::List(l) => {
+            io_context.write_str("[")?;
+            for i in 0..l.len() {
+                if i != 0 {
+                    io_context.write_str(",")?;
+                }
+                write_object(io_context, shell, &l[i])?;
+            }
+            io_context.write_str("]")
+        },
+        PolarValue::Instance(i) => {
+            // the registry is not locked while the attribute values are written
+            let attrs: Vec<(String, PolarValue)> = {
+                let registry = shell.registry.read();
+                let clz = registry.class_for_instance(i);
+                clz.attributes.iter()
+                    .map(|(attr_name, attr)| (attr_name.to_string(), registry.instance_attr(i, attr)))
+                    .collect()
+            };
+            io_context.write_str("{")?;
+            let mut first = true;
+            for (attr_name, value) in attrs {
+                if !first {
+                    io_context.write_str(",")?;
+                }
+                first = false;
+                io_context.write_string(format!("\"{}\":", attr_name))?;
+
+                write_object(io_context, shell, &value)?;
+            }
+            io_context.write_str("}")
+        },
+    }
+}
+
+/// Returns the value as a string, the way it would be written by [write_object] but without quotes
+/// around strings.
+pub(crate) fn value_to_string(shell: &Shell, value: &PolarValue) -> String {
+    match value {
+        PolarValue::String(s) => s.to_owned(),
+        _ => {
+            let mut input = io::empty();
+            let mut vec: Vec<u8> = Vec::new();
+            let mut io_context = IoContext::new("", &mut input, &mut vec);
+            // writing to a vector cannot fail
+            write_object(&mut io_context, shell, value).unwrap();
+            String::from_utf8_lossy(&vec).into_owned()
+        }
+    }
+}
+
+/// Sets a variable to a value.
+/// Instances, lists, and maps are kept as typed values, while other values are set as strings.
+fn set_variable(user_context: &mut UserContext, shell: &Shell, var: &str, value: PolarValue) {
+    match value {
+        PolarValue::Instance(_) | PolarValue::List(_) | PolarValue::Map(_) => {
+            let text = value_to_string(shell, &value);
+            user_context.set_polar_value(var, value, &text);
+        }
+        _ => user_context.set_value(var, &value_to_string(shell, &value))
+    }
+}
+
+/// Returns the typed values of the tokens starting at the specified index, keyed by the index of
+/// the argument (i.e., the index of the token minus the start index).
+fn arg_values(tokens: &Tokens, start: usize) -> HashMap<usize, PolarValue> {
+    (start..tokens.len())
+        .filter_map(|i| tokens.value(i).map(|value| (i - start, value.clone())))
+        .collect()
+}
+
+impl Command for MkDirCommand {
     fn keyword(&self) -> &'static str {
-        "unset"
+        "mkdir"
     }
 
     fn keyword_position(&self) -> usize {
@@ -913,633 +2006,3063 @@ impl Command for UnsetCommand {
     }
 
     fn help_text(&self) -> &'static str {
-        "unset [var ...]"
+        "mkdir <dir>"
     }
 
     fn validate(&self, command: &Tokens) -> Result<(), CommandValidationError> {
-        if command.len() == 1 {
-            return Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() });
-        }
-        for i in 1..command.len() {
-            if !validate_variable(&command.get(i)) {
-                return Err(CommandValidationError::InvalidVariableName(
-                    command.get(i).to_owned()));
-            }
+        if command.len() == 2 {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
         }
-        Ok(())
     }
 
     fn execute(&self,
-               command: &Tokens,
+               tokens: &Tokens,
                user_context: &mut UserContext,
-               _io_context: &mut IoContext,
+               io_context: &mut IoContext,
                _command_context: &CommandContext,
-               _shell: &mut Shell) -> Result<(), ShellError> {
-        debug!("[Unset] removing variables {}",
-            command.tokens_substring(1, command.len()));
-        for i in 1..command.len() {
-            user_context.remove_value(&command.get(i));
-        }
-        Ok(())
+               shell: &mut Shell) -> Result<(), ShellError> {
+        debug!("[MkDir] creating directories pwd={}, cd={}",
+            user_context.pwd(), tokens.get(1));
+        shell.registry.write().mkdir(user_context.pwd(), tokens.get(1)).map_err(
+            |e| ShellError::RegistryError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e,
+            })
     }
 }
 
-fn validate_variable(variable: &str) -> bool {
-    let mut first = true;
+impl Command for MvCommand {
+    fn keyword(&self) -> &'static str {
+        "mv"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "mv <path> <path>"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() == 3 {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        debug!("[Mv] moving path pwd={}, from={}, to={}",
+            user_context.pwd(), tokens.get(1), tokens.get(2));
+        shell.registry.write().move_path(user_context.pwd(), tokens.get(1), tokens.get(2)).map_err(
+            |e| ShellError::RegistryError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e,
+            })
+    }
+}
+
+impl Command for PwdCommand {
+    fn keyword(&self) -> &'static str {
+        "pwd"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "pwd"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() == 1 {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               _shell: &mut Shell) -> Result<(), ShellError> {
+        io_context.write_str(user_context.pwd()).map_err(|e| ShellError::IoError {
+            src: io_context.to_source_info(),
+            tokens: tokens.clone(),
+            error: e,
+        })
+    }
+}
+
+impl Command for RmCommand {
+    fn keyword(&self) -> &'static str {
+        "rm"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "rm [-r] <path> [path ...]"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        let start = if tokens.len() > 1 && tokens.get(1) == "-r" { 2 } else { 1 };
+        if tokens.len() > start {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        let recursive = tokens.get(1) == "-r";
+        let start = if recursive { 2 } else { 1 };
+        for i in start..tokens.len() {
+            debug!("[Rm] removing path pwd={}, cd={}, recursive={}",
+                user_context.pwd(), tokens.get(i), recursive);
+            shell.registry.write().remove(user_context.pwd(), tokens.get(i), recursive).map_err(
+                |e| ShellError::RegistryError {
+                    src: io_context.to_source_info(),
+                    tokens: tokens.clone(),
+                    error: e,
+                })?;
+        }
+        Ok(())
+    }
+}
+
+impl Command for RmDirCommand {
+    fn keyword(&self) -> &'static str {
+        "rmdir"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "rmdir <dir>"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() == 2 {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        debug!("[RmDir] removing directory pwd={}, cd={}", user_context.pwd(), tokens.get(1));
+        let to_shell_error = |e| ShellError::RegistryError {
+            src: io_context.to_source_info(),
+            tokens: tokens.clone(),
+            error: e,
+        };
+
+        // the path is checked and removed under the same lock, so it cannot change in between
+        let mut registry = shell.registry.write();
+        let instance_path = match registry.cd(user_context.pwd(), tokens.get(1)) {
+            Ok(path) => path.instance().map(|_| path.abs_path().to_owned()),
+            Err(e) => return Err(to_shell_error(e))
+        };
+        if let Some(path) = instance_path {
+            return Err(to_shell_error(RegistryError::IllegalRemove {
+                path,
+                reason: "not a directory",
+            }));
+        }
+        registry.remove(user_context.pwd(), tokens.get(1), false).map_err(to_shell_error)
+    }
+}
+
+impl Command for SetCommand {
+    fn keyword(&self) -> &'static str {
+        "set"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "set -e|+e"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() == 2 && (tokens.get(1) == "-e" || tokens.get(1) == "+e") {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               _io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               _shell: &mut Shell) -> Result<(), ShellError> {
+        user_context.set_errexit(tokens.get(1) == "-e");
+        Ok(())
+    }
+}
+
+const MAX_SOURCE_RECURSION: usize = 10;
+const MAX_FUNCTION_RECURSION: usize = 64;
+
+impl Command for SourceCommand {
+    fn keyword(&self) -> &'static str {
+        "source"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "source [-s] <file>"
+    }
+
+    fn validate(&self, command: &Tokens) -> Result<(), CommandValidationError> {
+        if command.len() == 2 && command.get(1) != "-s" || command.len() >= 3 {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        let subshell = tokens.get(1) == "-s";
+        let arg_start = if subshell { 3 } else { 2 };
+        let file_name = tokens.get(arg_start - 1);
+
+        debug!("[Source] loading file {}{}, args={}",
+            file_name,
+            if subshell { " (subshell)" } else { "" },
+            tokens.tokens_substring(arg_start, tokens.len()));
+
+        // make a copy of all variables
+        let mut new_user_context = user_context.clone();
+        // add our arguments to the file
+        new_user_context.clear_arguments();
+        for i in arg_start..tokens.len() {
+            let arg = tokens.get(i);
+            new_user_context.add_argument(arg);
+        }
+
+        // load file
+        match File::open(file_name) {
+            Ok(f) => {
+                if user_context.level >= MAX_SOURCE_RECURSION {
+                    return Err(ShellError::CommandExecutionError {
+                        src: io_context.to_source_info(),
+                        tokens: tokens.clone(),
+                        error: CommandExecutionError::MaxSourceCommand(user_context.level)
+                    })
+                }
+
+                let mut reader = BufReader::new(f);
+                // the new I/O context has the new file, but the same output
+                let mut new_io_context = IoContext::new(
+                    file_name, &mut reader, &mut io_context.output);
+
+                user_context.level += 1;
+                let result = shell.execute_commands(
+                    &mut new_user_context, &mut new_io_context, _command_context);
+                user_context.level -= 1;
+                result?;
+            }
+            Err(error) => return Err(ShellError::CommandExecutionError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: CommandExecutionError::UnableToOpenFile {
+                    file: file_name.to_owned(),
+                    error,
+                }
+            })
+        }
+
+        // update variables if we're not a subshell
+        if !subshell {
+            user_context.set_pwd(new_user_context.pwd());
+            user_context.clear_variables();
+            for (key, value) in &new_user_context.variables {
+                user_context.set_value(key, value);
+            }
+            user_context.set_errexit(new_user_context.errexit());
+            user_context.set_exit_status(new_user_context.exit_status());
+            user_context.values = new_user_context.values;
+            user_context.functions = new_user_context.functions;
+        }
+
+        Ok(())
+    }
+}
+
+impl Command for TreeCommand {
+    fn keyword(&self) -> &'static str {
+        "tree"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "tree [dir] [-L <depth>] [-M] [-A] [-v]"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        self.parse_options(tokens).map(|_| ())
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        let options = self.parse_options(tokens).unwrap();
+        debug!("[Tree] writing tree pwd={}, cd={}", user_context.pwd(), options.cd);
+        let lines = {
+            let registry = shell.registry.read();
+            let path = registry.cd(user_context.pwd(), options.cd)
+                .map_err(|e| ShellError::RegistryError {
+                    src: io_context.to_source_info(),
+                    tokens: tokens.clone(),
+                    error: e,
+                })?;
+
+            let mut lines = vec![(path.abs_path().to_owned(), None)];
+            self.tree_lines(&registry, path, "", 1, &options, &mut lines);
+            lines
+        };
+
+        // the values are written once the registry is unlocked, as writing instances reads it
+        for (line, value) in lines {
+            let line = match value {
+                Some(value) => format!("{} = {}\n", line, value_to_string(shell, &value)),
+                None => line + "\n"
+            };
+            io_context.write_string(line).map_err(|e| ShellError::IoError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// The options of the `tree` command.
+struct TreeOptions<'a> {
+    cd: &'a str,
+    depth: Option<usize>,
+    methods: bool,
+    attributes: bool,
+    values: bool,
+}
+
+impl TreeCommand {
+    fn parse_options<'a>(&self, tokens: &'a Tokens)
+                         -> Result<TreeOptions<'a>, CommandValidationError> {
+        let invalid = || CommandValidationError::InvalidCommandFormat { format: self.help_text() };
+        let mut options = TreeOptions {
+            cd: ".",
+            depth: None,
+            methods: true,
+            attributes: true,
+            values: false,
+        };
+
+        let mut i = 1;
+        if tokens.len() > 1 && !tokens.get(1).starts_with('-') {
+            options.cd = tokens.get(1);
+            i += 1;
+        }
+        while i < tokens.len() {
+            match tokens.get(i) {
+                "-L" if i + 1 < tokens.len() => {
+                    options.depth = Some(tokens.get(i + 1).parse().map_err(|_| invalid())?);
+                    i += 1;
+                }
+                "-M" => options.methods = false,
+                "-A" => options.attributes = false,
+                "-v" => options.values = true,
+                _ => return Err(invalid())
+            }
+            i += 1;
+        }
+        Ok(options)
+    }
+
+    /// Adds the lines of the children of a path, which are prefixed by the branches of the
+    /// path's ancestors, and the values of the attributes if they are written.
+    fn tree_lines(&self,
+                  registry: &Registry,
+                  path: &PathSegment,
+                  prefix: &str,
+                  depth: usize,
+                  options: &TreeOptions,
+                  lines: &mut Vec<(String, Option<PolarValue>)>) {
+        if path.link().is_some() || options.depth.is_some_and(|max_depth| depth > max_depth) {
+            return;
+        }
+
+        let children: Vec<&PathSegment> = path.children(registry)
+            .filter(|child| match child.kind() {
+                NodeKind::Method => options.methods,
+                NodeKind::Attribute => options.attributes,
+                _ => true
+            })
+            .collect();
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let branch = if last { "└── " } else { "├── " };
+            let value = if options.values && child.kind() == NodeKind::Attribute {
+                registry.attr(child.abs_path(), ".").ok()
+            } else {
+                None
+            };
+            lines.push((prefix.to_owned() + branch + &describe_path(registry, child), value));
+
+            let child_prefix = prefix.to_owned() + if last { "    " } else { "│   " };
+            self.tree_lines(registry, child, &child_prefix, depth + 1, options, lines);
+        }
+    }
+}
+
+impl Command for TryCommand {
+    fn keyword(&self) -> &'static str {
+        "try"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "try { ... } [catch [var] { ... }]"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if tokens.len() == 2 && tokens.get(1) == "{" {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        let src = io_context.to_source_info();
+        let sections = read_block(tokens, io_context, &["} catch"], "}")?.sections;
+        if sections.len() > 2 {
+            return Err(ShellError::CommandExecutionError {
+                src,
+                tokens: tokens.clone(),
+                error: CommandExecutionError::UnexpectedBlockStatement(
+                    sections[2].header.to_owned()),
+            });
+        }
+
+        // the catch statement is checked before the commands are executed
+        let catch = match sections.get(1) {
+            Some(section) => {
+                let (header, header_src) = lex_header(
+                    section, user_context, io_context, command_context, shell)?;
+                let var = self.catch_variable(&header).map_err(|e|
+                    ShellError::CommandValidationError {
+                        src: header_src,
+                        tokens: header.clone(),
+                        error: e,
+                    })?;
+                Some((section, var))
+            }
+            None => None
+        };
+
+        // the try block stops at the first failing command
+        let errexit = user_context.errexit();
+        user_context.set_errexit(true);
+        let result = execute_section(&sections[0], user_context, io_context, command_context, shell);
+        user_context.set_errexit(errexit);
+
+        let error = match result {
+            Ok(()) => return Ok(()),
+            Err(e) => e
+        };
+        debug!("[Try] caught error: {}", error);
+
+        match catch {
+            Some((section, var)) => {
+                if let Some(var) = var {
+                    user_context.set_polar_value(&var, error.to_value(), &error.to_string());
+                }
+                execute_section(section, user_context, io_context, command_context, shell)
+            }
+            None => Ok(())
+        }
+    }
+}
+
+impl TryCommand {
+    /// Returns the variable of a `} catch [var] {` statement.
+    fn catch_variable(&self, tokens: &Tokens) -> Result<Option<String>, CommandValidationError> {
+        if tokens.len() < 3 || tokens.len() > 4 || tokens.get(0) != "}" || tokens.get(1) != "catch"
+            || tokens.get(tokens.len() - 1) != "{" {
+            return Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() });
+        }
+        if tokens.len() == 3 {
+            return Ok(None);
+        }
+        let var = tokens.get(2);
+        if !validate_variable(var) {
+            return Err(CommandValidationError::InvalidVariableName(var.to_owned()));
+        }
+        Ok(Some(var.to_owned()))
+    }
+}
+
+impl Command for WhileCommand {
+    fn keyword(&self) -> &'static str {
+        "while"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "while <condition>; do ... done"
+    }
+
+    fn validate(&self, tokens: &Tokens) -> Result<(), CommandValidationError> {
+        if statement_end(tokens, "do") > 1 {
+            Ok(())
+        } else {
+            Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() })
+        }
+    }
+
+    fn execute(&self,
+               tokens: &Tokens,
+               user_context: &mut UserContext,
+               io_context: &mut IoContext,
+               command_context: &CommandContext,
+               shell: &mut Shell) -> Result<(), ShellError> {
+        // the condition is re-expanded before each iteration, so keep the unexpanded statement
+        let raw = io_context.raw_command();
+        let statement = Section {
+            line: io_context.line - raw.matches('\n').count(),
+            header: raw,
+            body: String::new(),
+        };
+        let mut condition = (tokens.clone(), io_context.to_source_info());
+        let block = read_block(tokens, io_context, &[], "done")?;
+
+        execute_block(&block, user_context, io_context, command_context, shell,
+                      |user_context, io_context, shell| {
+            while evaluate_condition(&condition.0, &condition.1, "do", user_context, shell)? {
+                execute_section(&block.sections[0], user_context, io_context, command_context, shell)?;
+                condition = lex_header(&statement, user_context, io_context, command_context, shell)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Command for UnsetCommand {
+    fn keyword(&self) -> &'static str {
+        "unset"
+    }
+
+    fn keyword_position(&self) -> usize {
+        0
+    }
+
+    fn help_text(&self) -> &'static str {
+        "unset [var ...]"
+    }
+
+    fn validate(&self, command: &Tokens) -> Result<(), CommandValidationError> {
+        if command.len() == 1 {
+            return Err(CommandValidationError::InvalidCommandFormat { format: self.help_text() });
+        }
+        for i in 1..command.len() {
+            if !validate_variable(&command.get(i)) {
+                return Err(CommandValidationError::InvalidVariableName(
+                    command.get(i).to_owned()));
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&self,
+               command: &Tokens,
+               user_context: &mut UserContext,
+               _io_context: &mut IoContext,
+               _command_context: &CommandContext,
+               _shell: &mut Shell) -> Result<(), ShellError> {
+        debug!("[Unset] removing variables {}",
+            command.tokens_substring(1, command.len()));
+        for i in 1..command.len() {
+            user_context.remove_value(&command.get(i));
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if the assigned name is the path to an attribute (e.g., `/feed/throttle` or
+/// `./throttle`) rather than a variable.
+fn is_attr_path(name: &str) -> bool {
+    name.contains('/')
+}
+
+fn validate_variable(variable: &str) -> bool {
+    let mut first = true;
+
+    for c in variable.chars() {
+        if first && !c.is_alphabetic() && c != '_' {
+            return false;
+        } else if !c.is_alphanumeric() && c != '_' {
+            return false;
+        }
+        first = false;
+    }
+
+    return true;
+}
+
+/// Fixtures and helpers shared by the tests of the commands.
+#[cfg(test)]
+mod test_support {
+    use std::io::Cursor;
+    use crate::command::oso::{Class, PolarClass};
+    use crate::command::shell::Shell;
+    use crate::command::{CommandContext, IoContext, RegistryError, ShellError, UserContext};
+
+    #[derive(Clone, PolarClass)]
+    struct Feed {
+        #[polar(attribute)]
+        port: i32,
+        #[polar(attribute, writable)]
+        name: String,
+    }
+
+    impl Feed {
+        fn reconnect(&self, delay: i32, force: bool) -> bool {
+            delay > 0 || force
+        }
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Gateway {
+        #[polar(attribute)]
+        port: i32,
+    }
+
+    /// Returns a shell with the `Feed` class, which is constructed from a port, and the `Gateway`
+    /// class, which is constructed from a feed.
+    pub(crate) fn feed_shell() -> Shell {
+        shell_with_classes(vec![
+            Feed::get_polar_class_builder()
+                .set_constructor(|port: i32| Feed { port, name: "feed".to_owned() }, vec!["int"])
+                .add_method("reconnect", Feed::reconnect, vec!["int", "bool"], None)
+                .build(),
+            Gateway::get_polar_class_builder()
+                .set_constructor(|feed: Feed| Gateway { port: feed.port }, vec!["Feed"])
+                .build(),
+        ])
+    }
+
+    /// Returns a shell with the classes cached.
+    pub(crate) fn shell_with_classes(classes: Vec<Class>) -> Shell {
+        let mut shell = Shell::default();
+        for class in classes {
+            shell.cache_class(class).unwrap();
+        }
+        shell
+    }
+
+    /// Executes the commands with a new user context and returns the output.
+    pub(crate) fn execute(shell: &mut Shell, commands: &str) -> Result<String, ShellError> {
+        execute_with_context(shell, &mut UserContext::default(), commands)
+    }
+
+    /// Executes the commands with the user context and returns the output.
+    pub(crate) fn execute_with_context(shell: &mut Shell,
+                                       user_context: &mut UserContext,
+                                       commands: &str) -> Result<String, ShellError> {
+        let mut input = Cursor::new(commands.as_bytes());
+        let mut vec: Vec<u8> = Vec::new();
+        let mut io_context = IoContext::new("test", &mut input, &mut vec);
+        shell.execute_commands(user_context, &mut io_context, &CommandContext::default())?;
+        Ok(String::from_utf8(vec).unwrap())
+    }
+
+    /// Returns the registry error of a failed command.
+    pub(crate) fn registry_error(err: ShellError) -> RegistryError {
+        match err {
+            ShellError::RegistryError { error, .. } => error,
+            _ => panic!("unexpected error: {}", err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod assign_tests {
+    use std::io;
+    use crate::command::commands::{AssignCommand, Command, CommandValidationError};
+    use crate::command::commands::test_support::{execute_with_context, feed_shell, shell_with_classes};
+    use crate::command::context::{UserContext, IoContext, CommandContext};
+    use crate::command::lexer::Tokens;
+    use crate::command::shell::Shell;
+    use crate::command::oso::{PolarClass, PolarValue};
+    use crate::command::{RegistryError, ShellError};
+
+    #[test]
+    fn validate_valid_assignment_command_returns_true() {
+        let command = AssignCommand {};
+
+        let result = command.validate(&Tokens::new(
+            vec!["foo".to_owned(), "=".to_owned(), "bar".to_owned()])).unwrap();
+
+        assert_eq!((), result);
+    }
+
+    #[test]
+    fn validate_invalid_variable_name_returns_error() {
+        let command = AssignCommand {};
+        let tokens = Tokens::new(vec!["12foo".to_owned(), "=".to_owned(), "bar".to_owned()]);
+
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidVariableName("12foo".to_owned()), result);
+    }
+
+    #[test]
+    fn execute_assignment_command() {
+        let mut context = UserContext::default();
+        let command = AssignCommand {};
+        let mut shell = Shell::default();
+        let mut input = io::stdin();
+        let mut output = io::sink();
+        let command_context = CommandContext::default();
+        let mut source = IoContext::new("test", &mut input, &mut output);
+        let tokens = Tokens::new(vec!["foo".to_owned(), "=".to_owned(), "bar".to_owned()]);
+        command.validate(&tokens).unwrap();
+
+        command.execute(&tokens, &mut context, &mut source, &command_context, &mut shell).unwrap();
+
+        assert_eq!("bar", context.get_value("foo").unwrap());
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Config {
+        #[polar(attribute)]
+        port: i32,
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Factory {}
+
+    impl Factory {
+        fn make_config(&self, port: i32) -> Config {
+            Config { port }
+        }
+
+        fn name(&self) -> String {
+            "factory".to_owned()
+        }
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Feed {
+        config: Config,
+    }
+
+    impl Feed {
+        fn new(config: Config) -> Feed {
+            Feed { config }
+        }
+
+        fn port(&self, config: Config) -> i32 {
+            config.port + self.config.port
+        }
+    }
+
+    fn shell_with_factory() -> Shell {
+        let shell = shell_with_classes(vec![
+            Config::get_polar_class_builder().build(),
+            Factory::get_polar_class_builder()
+                .set_constructor(|| Factory {}, vec![])
+                .add_method("make_config", Factory::make_config, vec!["int"], None)
+                .add_method("name", Factory::name, vec![], None)
+                .build(),
+            Feed::get_polar_class_builder()
+                .set_constructor(Feed::new, vec!["Config"])
+                .add_method("port", Feed::port, vec!["Config"], None)
+                .build(),
+        ]);
+        shell.registry.write().parsed_create_instance("/factory", ".", "Factory", &vec![]).unwrap();
+        shell
+    }
+
+    #[test]
+    fn method_result_instance_is_typed_variable() {
+        let mut shell = shell_with_factory();
+        let mut context = UserContext::default();
+
+        let result = execute_with_context(&mut shell, &mut context, "config = /factory/make_config 10
+create /feed Feed $config
+/feed/port $config").unwrap();
+
+        assert_eq!("20", result);
+        assert!(matches!(context.get_polar_value("config"), Some(PolarValue::Instance(_))));
+    }
+
+    #[test]
+    fn method_result_string_is_string_variable() {
+        let mut shell = shell_with_factory();
+        let mut context = UserContext::default();
+
+        execute_with_context(&mut shell, &mut context, "name = /factory/name").unwrap();
+
+        assert_eq!("factory", context.get_value("name").unwrap());
+        assert_eq!(None, context.get_polar_value("name"));
+    }
+
+    #[test]
+    fn typed_variable_is_copied() {
+        let mut shell = shell_with_factory();
+        let mut context = UserContext::default();
+
+        execute_with_context(&mut shell, &mut context, "config = /factory/make_config 10
+copy = $config
+text = \"$config\"").unwrap();
+
+        assert!(matches!(context.get_polar_value("copy"), Some(PolarValue::Instance(_))));
+        assert_eq!(None, context.get_polar_value("text"));
+        assert_eq!(context.get_value("config"), context.get_value("text"));
+        assert!(context.get_value("text").unwrap().contains("\"port\":10"));
+    }
+
+    #[test]
+    fn string_assignment_replaces_typed_variable() {
+        let mut shell = shell_with_factory();
+        let mut context = UserContext::default();
+
+        execute_with_context(&mut shell, &mut context, "config = /factory/make_config 10
+config = foo").unwrap();
+
+        assert_eq!(None, context.get_polar_value("config"));
+        assert_eq!("foo", context.get_value("config").unwrap());
+    }
+
+    #[test]
+    fn typed_variable_of_wrong_type_is_error() {
+        let mut shell = shell_with_factory();
+        let mut context = UserContext::default();
+
+        let result = execute_with_context(&mut shell, &mut context, "config = /factory/make_config 10
+/factory/make_config $config").err().unwrap();
+
+        match result {
+            ShellError::RegistryError {
+                error: RegistryError::InvalidMethodParameter { param_index: 0, .. }, ..
+            } => {}
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
+
+    #[test]
+    fn assigning_unknown_method_with_arguments_is_error() {
+        let mut shell = shell_with_factory();
+        let mut context = UserContext::default();
+
+        let result = execute_with_context(&mut shell, &mut context, "foo = /factory/unknown 10").err().unwrap();
+
+        match result {
+            ShellError::CommandValidationError {
+                error: CommandValidationError::InvalidCommandFormat { .. }, ..
+            } => {}
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
+
+    #[test]
+    fn assigning_several_words_is_error() {
+        let mut shell = shell_with_factory();
+        let mut context = UserContext::default();
+
+        let result = execute_with_context(&mut shell, &mut context, "foo = hello world").err().unwrap();
+
+        match result {
+            ShellError::CommandValidationError {
+                error: CommandValidationError::InvalidCommandFormat { .. }, ..
+            } => {}
+            _ => panic!("unexpected error: {}", result)
+        }
+        assert_eq!(None, context.get_value("foo"));
+    }
+
+    #[test]
+    fn relative_word_matching_method_is_assigned_as_is() {
+        let mut shell = shell_with_factory();
+        let mut context = UserContext::default();
+
+        execute_with_context(&mut shell, &mut context, "cd /factory
+x = name
+y = $(echo /factory/name)").unwrap();
+
+        assert_eq!("name", context.get_value("x").unwrap());
+        assert_eq!("factory", context.get_value("y").unwrap());
+    }
+
+    #[test]
+    fn relative_word_matching_attribute_is_assigned_as_is() {
+        let mut context = UserContext::default();
+
+        execute_with_context(&mut feed_shell(), &mut context, "create /feed Feed 4000
+cd /feed
+x = port").unwrap();
+
+        assert_eq!("port", context.get_value("x").unwrap());
+    }
+
+    #[test]
+    fn typed_variable_is_passed_to_function_parameter() {
+        let mut shell = shell_with_factory();
+        let mut context = UserContext::default();
+
+        let result = execute_with_context(&mut shell, &mut context, "function start name config {
+  create \"/feeds/$name\" Feed $config
+}
+start a $(echo x)
+config = /factory/make_config 10
+start a $config
+/feeds/a/port $config").err().unwrap();
+
+        // the first call passes a string, which cannot be parsed into a Config
+        match result {
+            ShellError::RegistryError { .. } => {}
+            _ => panic!("unexpected error: {}", result)
+        }
+
+        let result = execute_with_context(&mut shell, &mut context, "function start name config {
+  create \"/feeds/$name\" Feed $config
+}
+config = /factory/make_config 10
+start a $config
+/feeds/a/port $config").unwrap();
+
+        assert_eq!("20", result);
+        assert!(matches!(context.get_polar_value("config"), Some(PolarValue::Instance(_))));
+    }
+}
+
+#[cfg(test)]
+mod default_assign_tests {
+    use std::io;
+    use crate::command::commands::{DefaultAssignCommand, Command, CommandValidationError};
+    use crate::command::context::{UserContext, IoContext, CommandContext};
+    use crate::command::lexer::Tokens;
+    use crate::command::shell::Shell;
+
+    #[test]
+    fn validate_valid_assignment_command_returns_true() {
+        let command = DefaultAssignCommand {};
+
+        let result = command.validate(&Tokens::new(
+            vec!["foo".to_owned(), ":=".to_owned(), "bar".to_owned()])).unwrap();
+
+        assert_eq!((), result);
+    }
+
+    #[test]
+    fn validate_invalid_variable_name_returns_error() {
+        let command = DefaultAssignCommand {};
+        let tokens = Tokens::new(vec!["12foo".to_owned(), ":=".to_owned(), "bar".to_owned()]);
+
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidVariableName("12foo".to_owned()), result);
+    }
+
+    #[test]
+    fn execute_default_assignment_command_sets_new_value() {
+        let mut context = UserContext::default();
+        let mut shell = Shell::default();
+        let mut input = io::stdin();
+        let mut output = io::sink();
+        let mut source = IoContext::new("test", &mut input, &mut output);
+        let command_context = CommandContext::default();
+        let command = DefaultAssignCommand {};
+        let tokens = Tokens::new(vec!["foo".to_owned(), ":=".to_owned(), "bar".to_owned()]);
+        command.validate(&tokens).unwrap();
+
+        command.execute(&tokens, &mut context, &mut source, &command_context, &mut shell).unwrap();
+
+        assert_eq!("bar", context.get_value("foo").unwrap());
+    }
+
+    #[test]
+    fn execute_default_assignment_command_already_there_doesnt_replace() {
+        let mut context = UserContext::default();
+        context.set_value("foo", "soo");
+        let mut shell = Shell::default();
+        let mut input = io::stdin();
+        let mut output = io::sink();
+        let mut source = IoContext::new("test", &mut input, &mut output);
+
+        let command_context = CommandContext::default();
+        let command = DefaultAssignCommand {};
+        let tokens = Tokens::new(vec!["foo".to_owned(), ":=".to_owned(), "bar".to_owned()]);
+        command.validate(&tokens).unwrap();
+
+        command.execute(&tokens, &mut context, &mut source, &command_context, &mut shell).unwrap();
+
+        assert_eq!("soo", context.get_value("foo").unwrap());
+    }
+}
+
+#[cfg(test)]
+mod unset_tests {
+    use std::io;
+    use crate::command::commands::{Command, CommandValidationError, UnsetCommand};
+    use crate::command::context::{UserContext, IoContext, CommandContext};
+    use crate::command::lexer::Tokens;
+    use crate::command::shell::Shell;
+
+    #[test]
+    fn validate_valid_unset_command_with_one_variable_returns_true() {
+        let command = UnsetCommand {};
+
+        let result = command.validate(&Tokens::new(vec!["unset".to_owned(), "foo".to_owned()])).unwrap();
+
+        assert_eq!((), result);
+    }
+
+    #[test]
+    fn validate_valid_unset_command_with_multiple_variables_returns_true() {
+        let command = UnsetCommand {};
+
+        let result = command.validate(&Tokens::new(
+            vec!["unset".to_owned(), "foo".to_owned(), "bar".to_owned()])).unwrap();
+
+        assert_eq!((), result);
+    }
+
+    #[test]
+    fn unset_with_no_variables_is_error() {
+        let command = UnsetCommand {};
+        let tokens = Tokens::new(vec!["unset".to_owned()]);
+
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidCommandFormat {
+            format: "unset [var ...]",
+        }, result);
+    }
+
+    #[test]
+    fn unset_invalid_variable_name_returns_error() {
+        let command = UnsetCommand {};
+        let tokens = Tokens::new(vec!["unset".to_owned(), "foo".to_owned(), "12foo".to_owned()]);
+
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidVariableName("12foo".to_owned()), result);
+    }
+
+    #[test]
+    fn execute_unset_command() {
+        let mut context = UserContext::default();
+        context.set_value("foo", "bar");
+        context.set_value("soo", "doo");
+        context.set_value("goo", "boo");
+        let mut shell = Shell::default();
+        let mut input = io::stdin();
+        let mut output = io::sink();
+        let mut io_context = IoContext::new("test", &mut input, &mut output);
+        let command_context = CommandContext::default();
+        let command = UnsetCommand {};
+        let tokens = Tokens::new(vec!["unset".to_owned(), "soo".to_owned(), "goo".to_owned()]);
+
+        command.execute(
+            &tokens, &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+
+        assert_eq!("bar", context.get_value("foo").unwrap());
+        assert!(context.get_value("soo").is_none());
+        assert!(context.get_value("goo").is_none());
+    }
+}
+
+#[cfg(test)]
+mod mkdir_tests {
+    use std::io;
+    use crate::command::commands::{Command, CommandValidationError, MkDirCommand};
+    use crate::command::context::{UserContext, IoContext, CommandContext};
+    use crate::command::lexer::Tokens;
+    use crate::command::shell::Shell;
+
+    #[test]
+    fn validate_valid_mkdir_command_returns_true() {
+        let command = MkDirCommand {};
+
+        let result = command.validate(&Tokens::new(vec!["mkdir".to_owned(), "foo".to_owned()])).unwrap();
+
+        assert_eq!((), result);
+    }
+
+    #[test]
+    fn validate_mkdir_command_without_arg_returns_error() {
+        let command = MkDirCommand {};
+        let tokens = Tokens::new(vec!["mkdir".to_owned()]);
+
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidCommandFormat { format: "mkdir <dir>" }, result);
+    }
+
+    #[test]
+    fn validate_mkdir_command_with_multiple_args_returns_error() {
+        let command = MkDirCommand {};
+        let tokens = Tokens::new(vec!["mkdir".to_owned(), "foo".to_owned(), "bar".to_owned()]);
+
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidCommandFormat { format: "mkdir <dir>" }, result);
+    }
+
+    #[test]
+    fn mkdir_creates_new_directories() {
+        let mut context = UserContext::default();
+        let mut shell = Shell::default();
+        let mut input = io::stdin();
+        let mut output = io::sink();
+        let mut io_context = IoContext::new("test", &mut input, &mut output);
+        let command_context = CommandContext::default();
+        let command = MkDirCommand {};
+        let tokens = Tokens::new(vec!["mkdir".to_owned(), "foo/bar".to_owned()]);
+
+        command.execute(
+            &tokens, &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+
+        let registry = shell.registry.read();
+        let path = registry.path("/foo/bar").unwrap();
+        assert_eq!("/foo/bar", path.abs_path());
+    }
+}
+
+#[cfg(test)]
+mod rm_tests {
+    use crate::command::commands::{Command, CommandValidationError, RmCommand};
+    use crate::command::commands::test_support::{execute, feed_shell, registry_error};
+    use crate::command::lexer::Tokens;
+    use crate::command::RegistryError;
+
+    #[test]
+    fn validate_rm_command_without_path_returns_error() {
+        let command = RmCommand {};
+
+        let result = command.validate(
+            &Tokens::new(vec!["rm".to_owned(), "-r".to_owned()])).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidCommandFormat {
+            format: "rm [-r] <path> [path ...]"
+        }, result);
+    }
+
+    #[test]
+    fn rm_instance_and_recreate() {
+        let mut shell = feed_shell();
+        let result = execute(&mut shell, "create /feeds/nyse Feed 4000
+create /feeds/nasdaq Feed 5000
+rm /feeds/nyse /feeds/nasdaq
+create /feeds/nyse Feed 4001
+/feeds/nyse/port").unwrap();
+
+        assert_eq!("4001", result);
+        assert!(shell.registry.read().path("/feeds/nasdaq").is_err());
+    }
+
+    #[test]
+    fn rm_directory_with_instance_requires_recursive() {
+        let err = execute(&mut feed_shell(), "create /feeds/nyse Feed 4000
+rm /feeds").err().unwrap();
+
+        assert_eq!(RegistryError::IllegalRemove {
+            path: "/feeds".to_owned(),
+            reason: "directory is not empty",
+        }, registry_error(err));
+
+        let mut shell = feed_shell();
+        execute(&mut shell, "create /feeds/nyse Feed 4000
+rm -r /feeds").unwrap();
+
+        assert!(shell.registry.read().path("/feeds").is_err());
+    }
+
+    #[test]
+    fn rm_attribute_is_error() {
+        let err = execute(&mut feed_shell(), "create /feed Feed 4000
+rm /feed/port").err().unwrap();
+
+        assert_eq!(RegistryError::IllegalRemove {
+            path: "/feed/port".to_owned(),
+            reason: "cannot remove a method or attribute of an instance",
+        }, registry_error(err));
+    }
+
+    #[test]
+    fn rmdir_instance_is_error() {
+        let err = execute(&mut feed_shell(), "create /feed Feed 4000
+rmdir /feed").err().unwrap();
+
+        assert_eq!(RegistryError::IllegalRemove {
+            path: "/feed".to_owned(),
+            reason: "not a directory",
+        }, registry_error(err));
+    }
+
+    #[test]
+    fn rmdir_root_is_error() {
+        let err = execute(&mut feed_shell(), "rmdir /").err().unwrap();
+
+        assert_eq!(RegistryError::IllegalRemove {
+            path: "/".to_owned(),
+            reason: "cannot remove the root directory",
+        }, registry_error(err));
+    }
+}
+
+#[cfg(test)]
+mod mv_tests {
+    use crate::command::commands::test_support::{execute, feed_shell, registry_error};
+    use crate::command::RegistryError;
+
+    #[test]
+    fn mv_instance_keeps_methods_and_attributes() {
+        let mut shell = feed_shell();
+        let result = execute(&mut shell, "create /feeds/nyse Feed 4000
+mkdir /md
+mv /feeds /md/all
+cd /md/all
+nyse/port").unwrap();
+
+        assert_eq!("4000", result);
+        assert_eq!("/md/all/nyse/port", shell.registry.read().path("/md/all/nyse/port").unwrap().abs_path());
+        assert!(shell.registry.read().path("/feeds").is_err());
+    }
+
+    #[test]
+    fn mv_into_own_subtree_is_error() {
+        let err = execute(&mut feed_shell(), "mkdir /feeds/nyse
+mv /feeds /feeds/nyse").err().unwrap();
+
+        assert_eq!(RegistryError::IllegalDestination {
+            path: "/feeds/nyse".to_owned(),
+            reason: "cannot move a path into itself",
+        }, registry_error(err));
+    }
+
+    #[test]
+    fn mv_to_missing_parent_is_error() {
+        let err = execute(&mut feed_shell(), "mkdir /feeds
+mv /feeds /md/feeds").err().unwrap();
+
+        assert!(matches!(registry_error(err), RegistryError::IllegalPathNavigation { .. }));
+    }
+
+    #[test]
+    fn mv_method_is_error() {
+        let err = execute(&mut feed_shell(), "create /feed Feed 4000
+mv /feed/port /port").err().unwrap();
+
+        assert_eq!(RegistryError::IllegalMove {
+            path: "/feed/port".to_owned(),
+            reason: "cannot move a method or attribute of an instance",
+        }, registry_error(err));
+    }
+
+    #[test]
+    fn cp_instance_reparses_constructor_arguments() {
+        let result = execute(&mut feed_shell(), "create /feed Feed 4000
+create /gw Gateway /feed
+rm /feed
+create /feed Feed 5000
+cp /gw /gw2
+/gw/port
+echo \" \"
+/gw2/port").unwrap();
+
+        assert_eq!("4000 5000", result);
+    }
+
+    #[test]
+    fn cp_to_existing_instance_is_error() {
+        let err = execute(&mut feed_shell(), "create /feed Feed 4000
+create /feeds/feed Feed 5000
+cp /feed /feeds").err().unwrap();
+
+        assert_eq!(RegistryError::DuplicatePath("/feeds/feed".to_owned()), registry_error(err));
+    }
+
+    #[test]
+    fn cp_directory_is_error() {
+        let err = execute(&mut feed_shell(), "mkdir /feeds
+cp /feeds /feeds2").err().unwrap();
+
+        assert_eq!(RegistryError::MissingAtPath {
+            path: "/feeds".to_owned(),
+            expected: "instance",
+        }, registry_error(err));
+    }
+}
+
+#[cfg(test)]
+mod dump_tests {
+    use crate::command::commands::test_support::{execute, feed_shell};
+    use crate::command::{RegistryError, ShellError};
+
+    #[test]
+    fn dump_recreates_registry() {
+        let dump = execute(&mut feed_shell(), "create /md/feeds/nyse Feed 4000
+/md/feeds/nyse/name = \"NYSE feed\"
+create /gateways/gw Gateway /md/feeds/nyse
+mv /md/feeds /feeds
+mkdir /md/empty
+ln -s /feeds/nyse /primary
+dump").unwrap();
+
+        assert_eq!("create /feeds/nyse Feed 4000
+/feeds/nyse/name = 'NYSE feed'
+create /gateways/gw Gateway /feeds/nyse
+mkdir /md/empty
+ln -s /feeds/nyse /primary
+", dump);
+        assert_eq!(dump, execute(&mut feed_shell(), &(dump.to_owned() + "dump")).unwrap());
+    }
+
+    #[test]
+    fn dump_directory() {
+        let dump = execute(&mut feed_shell(), "create /feeds/nyse Feed 4000
+create /feeds/nasdaq Feed 5000
+create /gw Gateway /feeds/nyse
+cd /feeds
+dump nasdaq").unwrap();
+
+        assert_eq!("create /feeds/nasdaq Feed 5000\n/feeds/nasdaq/name = feed\n", dump);
+    }
+
+    #[test]
+    fn dump_unknown_directory_is_error() {
+        let err = execute(&mut feed_shell(), "dump /feeds").err().unwrap();
+
+        assert!(matches!(err, ShellError::RegistryError {
+            error: RegistryError::IllegalPathNavigation { .. }, ..
+        }));
+    }
+}
+
+#[cfg(test)]
+mod find_tests {
+    use crate::command::commands::test_support::{execute, feed_shell};
+    use crate::command::{CommandValidationError, ShellError};
+
+    #[test]
+    fn find_instances_of_class() {
+        let result = execute(&mut feed_shell(), "create /nyse/orders Feed 4000
+create /nyse/backup/orders Feed 4001
+mkdir /lse/orders
+cd /nyse
+find . -class Feed -maxdepth 1").unwrap();
+
+        assert_eq!("/nyse/orders\n", result);
+    }
+
+    #[test]
+    fn find_attributes_by_name() {
+        let result = execute(&mut feed_shell(), "create /nyse/orders Feed 4000
+mkdir /ports
+find / -type a -name 'po?t'").unwrap();
+
+        assert_eq!("/nyse/orders/port\n", result);
+    }
+
+    #[test]
+    fn validate_invalid_options_returns_error() {
+        for command in ["find -type x", "find -maxdepth one", "find / -name", "find / -size 1"] {
+            let err = execute(&mut feed_shell(), command).err().unwrap();
+
+            assert!(matches!(err, ShellError::CommandValidationError {
+                error: CommandValidationError::InvalidCommandFormat { .. }, ..
+            }), "{}", command);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tree_tests {
+    use crate::command::commands::test_support::{execute, feed_shell};
+    use crate::command::{CommandValidationError, ShellError};
+
+    #[test]
+    fn tree_writes_annotations_of_ls() {
+        let result = execute(&mut feed_shell(), "create /md/feeds/nyse Feed 4000
+ln -s /md/feeds /md/primary
+cd /md
+tree").unwrap();
+
+        assert_eq!("/md
+├── feeds/
+│   └── nyse/ Feed
+│       ├── name+Feed.name
+│       ├── port+Feed.port
+│       └── reconnect! Feed::reconnect(int, bool)
+└── primary -> /md/feeds
+", result);
+    }
+
+    #[test]
+    fn tree_with_depth() {
+        let result = execute(&mut feed_shell(), "create /md/feeds/nyse Feed 4000
+mkdir /md/sessions/a
+tree /md -L 1").unwrap();
+
+        assert_eq!("/md\n├── feeds/\n└── sessions/\n", result);
+    }
+
+    #[test]
+    fn tree_hides_methods_and_attributes() {
+        let result = execute(&mut feed_shell(), "create /md/feeds/nyse Feed 4000
+tree / -M -A").unwrap();
+
+        assert_eq!("/\n└── md/\n    └── feeds/\n        └── nyse/ Feed\n", result);
+    }
+
+    #[test]
+    fn tree_writes_attribute_values() {
+        let result = execute(&mut feed_shell(), "create /nyse Feed 4000
+/nyse/name = \"nyse feed\"
+tree /nyse -M -v").unwrap();
+
+        assert_eq!("/nyse\n├── name+Feed.name = nyse feed\n└── port+Feed.port = 4000\n", result);
+    }
+
+    #[test]
+    fn validate_invalid_options_returns_error() {
+        for command in ["tree -L", "tree -L deep", "tree / -x", "tree / /md"] {
+            let err = execute(&mut feed_shell(), command).err().unwrap();
+
+            assert!(matches!(err, ShellError::CommandValidationError {
+                error: CommandValidationError::InvalidCommandFormat { .. }, ..
+            }), "{}", command);
+        }
+    }
+}
+
+#[cfg(test)]
+mod ln_tests {
+    use crate::command::commands::{Command, CommandValidationError, LnCommand};
+    use crate::command::commands::test_support::{execute, feed_shell, registry_error};
+    use crate::command::lexer::Tokens;
+    use crate::command::RegistryError;
+
+    #[test]
+    fn validate_ln_command_without_symbolic_flag_returns_error() {
+        let command = LnCommand {};
+        let tokens = Tokens::new(vec!["ln".to_owned(), "/foo".to_owned(), "/bar".to_owned()]);
+
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidCommandFormat {
+            format: "ln -s|-sf <target> <link>"
+        }, result);
+    }
+
+    #[test]
+    fn invoke_method_through_link() {
+        let result = execute(&mut feed_shell(), "create /md/feeds/nyse1 Feed 4000
+create /md/feeds/nyse2 Feed 5000
+ln -s feeds/nyse1 /md/primary
+/md/primary/port
+echo \" \"
+ln -sf feeds/nyse2 /md/primary
+/md/primary/port").unwrap();
+
+        assert_eq!("4000 5000", result);
+    }
+
+    #[test]
+    fn instance_parameter_resolves_through_link() {
+        let result = execute(&mut feed_shell(), "create /md/feeds/nyse1 Feed 4000
+ln -s /md/feeds/nyse1 /md/primary
+create /gw Gateway /md/primary
+/gw/port").unwrap();
+
+        assert_eq!("4000", result);
+    }
+
+    #[test]
+    fn rm_link_keeps_target() {
+        let mut shell = feed_shell();
+        execute(&mut shell, "mkdir /md/feeds/nyse1
+ln -s /md/feeds/nyse1 /md/primary
+rm /md/primary").unwrap();
+
+        assert!(shell.registry.read().path("/md/primary").is_err());
+        assert!(shell.registry.read().path("/md/feeds/nyse1").is_ok());
+    }
+
+    #[test]
+    fn link_to_existing_path_is_error() {
+        let err = execute(&mut feed_shell(), "mkdir /md/primary
+ln -s /md /md/primary").err().unwrap();
+
+        assert_eq!(RegistryError::DuplicatePath("/md/primary".to_owned()), registry_error(err));
+
+        let err = execute(&mut feed_shell(), "mkdir /md/primary
+ln -sf /md /md/primary").err().unwrap();
+
+        assert_eq!(RegistryError::DuplicatePath("/md/primary".to_owned()), registry_error(err));
+    }
+
+    #[test]
+    fn link_loop_is_error() {
+        let err = execute(&mut feed_shell(), "ln -s b /a
+ln -s a /b
+cd /a").err().unwrap();
+
+        assert!(matches!(registry_error(err), RegistryError::LinkLoop(_)));
+    }
+}
+
+#[cfg(test)]
+mod cd_tests {
+    use std::io;
+    use crate::command::commands::{Command, CdCommand, MkDirCommand, CommandValidationError};
+    use crate::command::context::{UserContext, IoContext, CommandContext};
+    use crate::command::lexer::Tokens;
+    use crate::command::shell::Shell;
+
+    #[test]
+    fn validate_valid_cd_command_returns_error() {
+        let command = CdCommand {};
+        let tokens = Tokens::new(vec!["cd".to_owned(), "foo".to_owned(), "bar".to_owned()]);
+
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidCommandFormat { format: "cd <dir>" }, result);
+    }
+
+    #[test]
+    fn validate_cd_command_without_arg_returns_error() {
+        let command = CdCommand {};
+        let tokens = Tokens::new(vec!["cd".to_owned()]);
+
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidCommandFormat { format: "cd <dir>" }, result);
+    }
+
+    #[test]
+    fn validate_cd_command_with_one_arg_returns_true() {
+        let command = CdCommand {};
+        let tokens = Tokens::new(vec!["cd".to_owned(), "foo/bar".to_owned()]);
+
+        let result = command.validate(&tokens).unwrap();
+
+        assert_eq!((), result);
+    }
+
+    #[test]
+    fn cd_navigates_to_new_directory() {
+        let mut context = UserContext::default();
+        let mut shell = Shell::default();
+        let mut input = io::stdin();
+        let mut output = io::sink();
+        let mut io_context = IoContext::new("test", &mut input, &mut output);
+        let command_context = CommandContext::default();
+        MkDirCommand {}.execute(
+            &Tokens::new(vec!["mkdir".to_owned(), "foo/bar/soo".to_owned()]),
+            &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+
+        CdCommand {}.execute(
+            &Tokens::new(vec!["cd".to_owned(), "foo/bar".to_owned()]),
+            &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+
+        assert_eq!("/foo/bar", context.pwd());
+    }
+}
+
+#[cfg(test)]
+mod echo_tests {
+    use std::io;
+    use std::io::Cursor;
+    use crate::command::commands::{Command, EchoCommand};
+    use crate::command::context::{UserContext, IoContext, CommandContext};
+    use crate::command::lexer::Tokens;
+    use crate::command::shell::Shell;
+
+    #[test]
+    fn validate_valid_echo_command_returns_true() {
+        let command = EchoCommand {};
+
+        let result = command.validate(&Tokens::new(
+            vec!["echo".to_owned(), "foo".to_owned(), "bar".to_owned()])).unwrap();
+
+        assert_eq!((), result);
+    }
+
+    #[test]
+    fn validate_echo_command_without_args_returns_true() {
+        let command = EchoCommand {};
+
+        let result = command.validate(&Tokens::new(vec!["echo".to_owned()])).unwrap();
+
+        assert_eq!((), result);
+    }
+
+    #[test]
+    fn echo_command_writes_tokens_to_output() {
+        let mut context = UserContext::default();
+        let mut shell = Shell::default();
+        let mut input = io::stdin();
+        let mut vec: Vec<u8> = Vec::new();
+        let mut output = Cursor::new(&mut vec);
+        let mut io_context = IoContext::new("test", &mut input, &mut output);
+        let command_context = CommandContext::default();
+        let command = EchoCommand {};
+        let tokens = Tokens::new(vec!["echo".to_owned(), "foo".to_owned(), "me".to_owned()]);
+
+        command.execute(
+            &tokens, &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+
+        let str = String::from_utf8(vec).unwrap();
+        assert_eq!("foo me", str);
+    }
+}
+
+#[cfg(test)]
+mod create_tests {
+    use std::io;
+    use std::io::Cursor;
+    use crate::command::commands::{Command, CommandValidationError, CreateCommand};
+    use crate::command::context::{UserContext, IoContext, CommandContext};
+    use crate::command::lexer::Tokens;
+    use crate::command::shell::Shell;
+    use crate::command::oso::PolarClass;
+    use crate::command::{RegistryError, ShellError};
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        #[polar(attribute)]
+        pub username: String,
+        #[polar(attribute)]
+        pub user_id: i32,
+    }
+
+    impl User {
+        fn new(username: String, user_id: i32) -> User {
+            User { username, user_id }
+        }
+    }
+
+    #[test]
+    fn validate_valid_create_command_returns_true() {
+        let command = CreateCommand {};
+
+        let result = command.validate(&Tokens::new(vec![
+                "create".to_owned(),
+                "/foo/bar".to_owned(),
+                "core::command::commands::create_tests::User".to_owned(),
+                "jgreco".to_owned(),
+                "42".to_owned()
+            ])).unwrap();
+
+        assert_eq!((), result);
+    }
+
+    #[test]
+    fn validate_create_command_with_1_param_is_error() {
+        let command = CreateCommand {};
+        let token = Tokens::new(vec![
+                "create".to_owned()
+            ]);
+
+        let result = command.validate(&token).err().unwrap();
+
+        assert_eq!(result, CommandValidationError::InvalidCommandFormat {
+            format: "create <dir> <struct> [args ...]"
+        });
+    }
+
+    #[test]
+    fn validate_create_command_with_2_params_returns_false() {
+        let command = CreateCommand {};
+        let token = Tokens::new(vec![
+                "create".to_owned(),
+                "/foo/bar".to_owned(),
+            ]);
+
+        let result = command.validate(&token).err().unwrap();
+
+        assert_eq!(result, CommandValidationError::InvalidCommandFormat {
+            format: "create <dir> <struct> [args ...]"
+        });
+    }
+
+    #[test]
+    fn execute_create_command() {
+        let mut context = UserContext::default();
+        let mut shell = Shell::default();
+        shell.cache_class(User::get_polar_class_builder()
+            .set_constructor(User::new, vec!["string", "int"])
+            .build()).unwrap();
+        let mut input = io::stdin();
+        let mut vec: Vec<u8> = Vec::new();
+        let mut output = Cursor::new(&mut vec);
+        let mut io_context = IoContext::new("test", &mut input, &mut output);
+        let command_context = CommandContext::default();
+        let command = CreateCommand {};
+        let tokens = Tokens::new(vec![
+                "create".to_owned(),
+                "/foo/bar".to_owned(),
+                "rcore::command::commands::create_tests::User".to_owned(),
+                "jgreco".to_owned(),
+                "42".to_owned()
+            ]);
+
+        command.execute(
+            &tokens, &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+
+        let registry = shell.registry.read();
+        let user = registry.instance_value::<User>("/foo/bar", ".").unwrap();
+        assert_eq!(42, user.user_id);
+        assert_eq!("jgreco", user.username);
+    }
+
+    #[test]
+    fn execute_create_command_with_wrong_data_type_is_an_era() {
+        let mut context = UserContext::default();
+        let mut shell = Shell::default();
+        shell.cache_class(User::get_polar_class_builder()
+            .set_constructor(User::new, vec!["int", "string"])
+            .build()).unwrap();
+        let mut input = io::stdin();
+        let mut vec: Vec<u8> = Vec::new();
+        let mut output = Cursor::new(&mut vec);
+        let mut io_context = IoContext::new("test", &mut input, &mut output);
+        let command_context = CommandContext::default();
+        let command = CreateCommand {};
+        let tokens = Tokens::new(vec![
+                "create".to_owned(),
+                "/foo/bar".to_owned(),
+                "rcore::command::commands::create_tests::User".to_owned(),
+                "jgreco".to_owned(),
+                "42".to_owned()
+            ]);
+
+        let result = command.execute(
+            &tokens, &mut context, &mut io_context, &command_context, &mut shell).err().unwrap();
+
+        assert_eq!(result, ShellError::RegistryError {
+            src: io_context.to_source_info(),
+            tokens,
+            error: RegistryError::InvalidMethodParameter {
+                class: "rcore::command::commands::create_tests::User".to_owned(),
+                method: "<constructor>".to_owned(),
+                param_index: 0,
+                param_type: "int",
+                reason: "",
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod execute_tests {
+    use std::collections::HashMap;
+    use std::io;
+    use std::io::Cursor;
+    use crate::command::commands::{Command, CreateCommand, ExecuteCommand};
+    use crate::command::commands::test_support::{execute_with_context, shell_with_classes};
+    use crate::command::context::{UserContext, IoContext, CommandContext};
+    use crate::command::lexer::Tokens;
+    use crate::command::shell::Shell;
+    use crate::command::oso::PolarClass;
+    use crate::command::{RegistryError, ShellError};
+
+    #[derive(Clone, PolarClass)]
+    struct User {
+        #[polar(attribute)]
+        pub username: String,
+        #[polar(attribute, writable)]
+        pub user_id: i32,
+    }
+
+    impl User {
+        fn new(username: String, user_id: i32) -> User {
+            User { username, user_id }
+        }
+
+        pub fn add_one(&self, id: i32) -> i32 {
+            id + 1
+        }
+
+        pub fn set_user_id(&mut self, user_id: i32) -> i32 {
+            self.user_id = user_id;
+            user_id
+        }
+
+        pub fn copy(&self) -> User {
+            self.clone()
+        }
+
+        pub fn ids(&self) -> HashMap<String, i32> {
+            HashMap::from([(self.username.clone(), self.user_id)])
+        }
+    }
+
+    #[test]
+    fn execute_method() {
+        let mut context = UserContext::default();
+        let mut shell = Shell::default();
+        shell.cache_class(User::get_polar_class_builder()
+            .set_constructor(User::new, vec!["string", "int"])
+            .add_method("add_one", User::add_one, vec!["int"], Some("add"))
+            .build()).unwrap();
+        let mut input = io::stdin();
+        let mut vec: Vec<u8> = Vec::new();
+        let mut output = Cursor::new(&mut vec);
+        let mut io_context = IoContext::new("test", &mut input, &mut output);
+        let command_context = CommandContext::default();
+        CreateCommand {}.execute(
+            &Tokens::new(vec![
+                    "create".to_owned(),
+                    "/foo/bar".to_owned(),
+                    "rcore::command::commands::execute_tests::User".to_owned(),
+                    "jgreco".to_owned(),
+                    "42".to_owned()
+                ]), &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+
+        ExecuteCommand {}.execute(
+            &Tokens::new(vec![
+                    "/foo/bar/add".to_owned(),
+                    "41".to_owned()
+                ]), &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+
+        let string = String::from_utf8(vec).unwrap();
+        assert_eq!("42", string);
+    }
+
+    #[test]
+    fn execute_method_with_incorrect_data_type_is_error() {
+        let mut context = UserContext::default();
+        let mut shell = Shell::default();
+        shell.cache_class(User::get_polar_class_builder()
+            .set_constructor(User::new, vec!["string", "int"])
+            .add_method("add_one", User::add_one, vec!["int"], Some("add"))
+            .build()).unwrap();
+        let mut input = io::stdin();
+        let mut vec: Vec<u8> = Vec::new();
+        let mut output = Cursor::new(&mut vec);
+        let mut io_context = IoContext::new("test", &mut input, &mut output);
+        let command_context = CommandContext::default();
+        CreateCommand {}.execute(
+            &Tokens::new(vec![
+                    "create".to_owned(),
+                    "/foo/bar".to_owned(),
+                    "rcore::command::commands::execute_tests::User".to_owned(),
+                    "jgreco".to_owned(),
+                    "42".to_owned()
+                ]), &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+        let tokens = Tokens::new(vec![
+                "/foo/bar/add".to_owned(),
+                "foo".to_owned()
+            ]);
+
+        let err = ExecuteCommand {}.execute(
+            &tokens, &mut context, &mut io_context, &command_context, &mut shell).err().unwrap();
+
+        assert_eq!(ShellError::RegistryError {
+            src: io_context.to_source_info(),
+            error: RegistryError::InvalidMethodParameter {
+                class: "User".to_owned(),
+                method: "add_one".to_string(),
+                param_index: 0,
+                param_type: "int",
+                reason: "",
+            },
+            tokens: tokens.clone(),
+        }, err);
+    }
+
+    fn execute(commands: &str) -> Result<(String, UserContext), ShellError> {
+        let mut shell = shell_with_classes(vec![User::get_polar_class_builder()
+            .set_constructor(User::new, vec!["string", "int"])
+            .add_method("add_one", User::add_one, vec!["int"], Some("add"))
+            .add_mut_method("set_user_id", User::set_user_id, vec!["int"], None)
+            .add_method("copy", User::copy, vec![], None)
+            .add_method("ids", User::ids, vec![], None)
+            .build()]);
+        let mut user_context = UserContext::default();
+        let result = execute_with_context(&mut shell, &mut user_context, commands)?;
+        Ok((result, user_context))
+    }
+
+    #[test]
+    fn execute_attribute() {
+        let (result, _) = execute("create /foo User jgreco 42
+/foo/user_id
+cd /foo
+username").unwrap();
+
+        assert_eq!("42\"jgreco\"", result);
+    }
+
+    #[test]
+    fn execute_method_writes_instance_and_map_in_braces() {
+        let (map, _) = execute("create /foo User jgreco 42
+/foo/ids").unwrap();
+        let (instance, _) = execute("create /foo User jgreco 42
+/foo/copy").unwrap();
+
+        assert_eq!("{\"jgreco\":42}", map);
+        // the attributes are not ordered
+        assert!(["{\"user_id\":42,\"username\":\"jgreco\"}", "{\"username\":\"jgreco\",\"user_id\":42}"]
+            .contains(&instance.as_str()));
+    }
+
+    #[test]
+    fn execute_attribute_with_arguments_is_error() {
+        let err = execute("create /foo User jgreco 42
+/foo/user_id 1").err().unwrap();
+
+        assert_eq!(ShellError::RegistryError {
+            src: err.src().clone(),
+            error: RegistryError::InvalidNumberOfMethodParameters {
+                class: "User".to_owned(),
+                method: "user_id".to_owned(),
+                expected: 0,
+                received: 1,
+            },
+            tokens: Tokens::new(vec!["/foo/user_id".to_owned(), "1".to_owned()]),
+        }, err);
+    }
+
+    #[test]
+    fn assign_and_compare_attribute() {
+        let (result, context) = execute("create /foo User jgreco 42
+id = /foo/user_id
+for name in /foo/username; do
+  echo $name
+done").unwrap();
+
+        assert_eq!("jgreco", result);
+        assert_eq!("42", context.get_value("id").unwrap());
+    }
+
+    #[test]
+    fn get_all_attributes() {
+        let (result, _) = execute("create /foo User jgreco 42
+get /foo").unwrap();
+
+        assert_eq!("user_id=42\nusername=\"jgreco\"\n", result);
+    }
+
+    #[test]
+    fn get_named_attributes_in_order() {
+        let (result, _) = execute("create /foo User jgreco 42
+cd /foo
+get . username user_id").unwrap();
+
+        assert_eq!("username=\"jgreco\"\nuser_id=42\n", result);
+    }
+
+    #[test]
+    fn get_unknown_attribute_is_error() {
+        let err = execute("create /foo User jgreco 42
+get /foo add").err().unwrap();
+
+        match err {
+            ShellError::RegistryError { error, .. } => assert_eq!(RegistryError::MissingAtPath {
+                path: "/foo/add".to_owned(),
+                expected: "attribute",
+            }, error),
+            _ => panic!("unexpected error: {}", err)
+        }
+    }
+
+    #[test]
+    fn execute_mut_method_changes_instance() {
+        let (result, _) = execute("create /foo User jgreco 42
+/foo/set_user_id 43
+echo \" \"
+get /foo user_id").unwrap();
+
+        assert_eq!("43 user_id=43\n", result);
+    }
+
+    #[test]
+    fn assign_attribute_changes_instance() {
+        let (result, _) = execute("create /foo User jgreco 42
+/foo/user_id = 43
+cd /foo
+./user_id = /foo/add 43
+get . user_id").unwrap();
+
+        assert_eq!("user_id=44\n", result);
+    }
+
+    #[test]
+    fn assign_attribute_of_wrong_type_is_error() {
+        let err = execute("create /foo User jgreco 42
+/foo/user_id = abc").err().unwrap();
+
+        match err {
+            ShellError::RegistryError { error, .. } => assert_eq!(
+                RegistryError::InvalidMethodParameter {
+                    class: "User".to_owned(),
+                    method: "user_id".to_owned(),
+                    param_index: 0,
+                    param_type: "int",
+                    reason: "",
+                }, error),
+            _ => panic!("unexpected error: {}", err)
+        }
+    }
+
+    #[test]
+    fn assign_read_only_attribute_is_error() {
+        let err = execute("create /foo User jgreco 42
+/foo/username = burns").err().unwrap();
+
+        match err {
+            ShellError::RegistryError { error, .. } => assert_eq!(
+                RegistryError::ReadOnlyAttribute {
+                    class: "User".to_owned(),
+                    attr: "username".to_owned(),
+                }, error),
+            _ => panic!("unexpected error: {}", err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod if_tests {
+    use std::fs::File;
+    use std::io::Write;
+    use crate::command::commands::{Command, CommandExecutionError, CommandValidationError, IfCommand};
+    use crate::command::commands::test_support::execute;
+    use crate::command::lexer::Tokens;
+    use crate::command::shell::Shell;
+    use crate::command::oso::PolarClass;
+    use crate::command::ShellError;
+
+    #[derive(Clone, PolarClass)]
+    struct Service {}
+
+    impl Service {
+        fn new() -> Service {
+            Service {}
+        }
+
+        fn is_ready(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn validate_if_command_without_condition_returns_error() {
+        let command = IfCommand {};
+        let tokens = Tokens::new(vec!["if".to_owned(), ";".to_owned(), "then".to_owned()]);
+
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidCommandFormat {
+            format: "if <condition>; then ... [elif <condition>; then ...] [else ...] fi"
+        }, result);
+    }
+
+    #[test]
+    fn validate_if_command_with_condition_returns_true() {
+        let command = IfCommand {};
+        let tokens = Tokens::new(vec!["if".to_owned(), "true".to_owned()]);
+
+        let result = command.validate(&tokens).unwrap();
+
+        assert_eq!((), result);
+    }
+
+    #[test]
+    fn if_branch_is_executed() {
+        let (result, context) = Shell::from_string(
+            "if 1 -lt 2; then
+                echo a
+                v1 = b
+             else
+                echo c
+             fi
+             echo d").unwrap();
+
+        assert_eq!("ad", result);
+        assert_eq!("b", context.get_value("v1").unwrap());
+    }
+
+    #[test]
+    fn else_branch_is_executed() {
+        let (result, _) = Shell::from_string(
+            "if false
+                echo a
+             elif false
+                echo b
+             else
+                echo c
+             fi").unwrap();
+
+        assert_eq!("c", result);
+    }
+
+    #[test]
+    fn elif_is_expanded_when_evaluated() {
+        let (result, _) = Shell::from_string(
+            "v1 = a
+             if $v1 == a; then
+                v1 = b
+             elif $v1 == b; then
+                echo wrong
+             fi
+             echo $v1").unwrap();
+
+        assert_eq!("b", result);
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let (result, _) = Shell::from_string(
+            "if true; then
+                if false; then
+                   echo a
+                else
+                   if true; then
+                      echo b
+                   fi
+                fi
+             else
+                echo c
+             fi").unwrap();
+
+        assert_eq!("b", result);
+    }
+
+    #[test]
+    fn nested_blocks_in_source_file() {
+        let mut file = File::create("/tmp/if_test.commands").unwrap();
+        file.write_all("if $0 == prod; then
+                            if $1 -gt 4; then
+                                echo big
+                            else
+                                echo small
+                            fi
+                        fi".as_bytes()).unwrap();
+
+        let (result, _) = Shell::from_string(
+            "source /tmp/if_test.commands prod 8
+             source /tmp/if_test.commands prod 2
+             source /tmp/if_test.commands dev 8").unwrap();
+
+        assert_eq!("bigsmall", result);
+    }
+
+    #[test]
+    fn method_result_is_condition() {
+        let mut shell = Shell::default();
+        shell.cache_class(Service::get_polar_class_builder()
+            .set_constructor(Service::new, vec![])
+            .add_method("is_ready", Service::is_ready, vec![], None)
+            .build()).unwrap();
+
+        let result = execute(&mut shell,
+            "create /svc Service
+             if /svc/is_ready; then
+                echo ready
+             fi").unwrap();
+
+        assert_eq!("ready", result);
+    }
+
+    #[test]
+    fn unterminated_block_is_error() {
+        let result = Shell::from_string(
+            "if true; then
+                echo a").err().unwrap();
+
+        match result {
+            ShellError::CommandExecutionError { src, error, .. } => {
+                assert!(src.to_string().starts_with("test:1:"));
+                assert_eq!(CommandExecutionError::UnterminatedBlock("fi"), error);
+            }
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
+
+    #[test]
+    fn else_must_be_last_branch() {
+        let result = Shell::from_string(
+            "if true; then
+             else
+             elif true
+             fi").err().unwrap();
+
+        match result {
+            ShellError::CommandExecutionError { error, .. } =>
+                assert_eq!(CommandExecutionError::UnexpectedBlockStatement(
+                    "elif true".to_owned()), error),
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
+
+    #[test]
+    fn errors_in_block_reference_the_line_in_the_source() {
+        let result = Shell::from_string(
+            "if true; then
+
+                cd /unknown
+             fi").err().unwrap();
+
+        match result {
+            ShellError::RegistryError { src, .. } =>
+                assert!(src.to_string().starts_with("test:3:")),
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
+
+    #[test]
+    fn invalid_condition_is_error() {
+        let result = Shell::from_string(
+            "if abc -gt 1; then
+             fi").err().unwrap();
+
+        match result {
+            ShellError::CommandExecutionError { error, .. } =>
+                assert_eq!(CommandExecutionError::InvalidCondition {
+                    tokens: Tokens::new(vec![
+                        "if".to_owned(), "abc".to_owned(), "-gt".to_owned(), "1".to_owned(),
+                        ";".to_owned(), "then".to_owned()]),
+                    reason: "not a number: abc".to_owned(),
+                }, error),
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
+
+    #[test]
+    fn quoted_operators_and_empty_variables_are_values() {
+        let result = execute(&mut Shell::default(), "v = \"!\"
+e = \"\"
+if \"&&\" == \"&&\"; then
+  echo a
+fi
+if $v == \"!\"; then
+  echo b
+fi
+if -z $e; then
+  echo c
+fi").unwrap();
+
+        assert_eq!("abc", result);
+    }
+
+    #[test]
+    fn lines_after_block_have_correct_line_numbers() {
+        let mut shell = Shell::default();
+
+        let result = execute(&mut shell,
+            "if false; then
+                echo a
+             fi
+             cd /unknown").err().unwrap();
 
-    for c in variable.chars() {
-        if first && !c.is_alphabetic() && c != '_' {
-            return false;
-        } else if !c.is_alphanumeric() && c != '_' {
-            return false;
+        match result {
+            ShellError::RegistryError { src, .. } =>
+                assert!(src.to_string().starts_with("test:4:")),
+            _ => panic!("unexpected error: {}", result)
         }
-        first = false;
     }
-
-    return true;
 }
 
 #[cfg(test)]
-mod assign_tests {
-    use std::io;
-    use crate::command::commands::{AssignCommand, Command, CommandValidationError};
-    use crate::command::context::{UserContext, IoContext, CommandContext};
+mod for_tests {
+    use crate::command::commands::{Command, CommandValidationError, ForCommand};
+    use crate::command::commands::test_support::{execute, shell_with_classes};
     use crate::command::lexer::Tokens;
     use crate::command::shell::Shell;
+    use crate::command::oso::PolarClass;
+    use crate::command::{RegistryError, ShellError};
 
-    #[test]
-    fn validate_valid_assignment_command_returns_true() {
-        let command = AssignCommand {};
+    #[derive(Clone, PolarClass)]
+    struct Symbols {
+        symbols: Vec<String>,
+    }
 
-        let result = command.validate(&Tokens::new(
-            vec!["foo".to_owned(), "=".to_owned(), "bar".to_owned()])).unwrap();
+    impl Symbols {
+        fn new() -> Symbols {
+            Symbols { symbols: vec!["AAPL".to_owned(), "MSFT".to_owned()] }
+        }
 
-        assert_eq!((), result);
+        fn list(&self, prefix: String) -> Vec<String> {
+            self.symbols.iter().map(|s| format!("{}{}", prefix, s)).collect()
+        }
+
+        fn copy(&self) -> Symbols {
+            self.clone()
+        }
+
+        fn count(&self) -> i32 {
+            self.symbols.len() as i32
+        }
+    }
+
+    fn shell_with_symbols() -> Shell {
+        let shell = shell_with_classes(vec![Symbols::get_polar_class_builder()
+            .set_constructor(Symbols::new, vec![])
+            .set_into_iter(|s: &Symbols| s.symbols.clone().into_iter())
+            .add_method("list", Symbols::list, vec!["string"], None)
+            .add_method("copy", Symbols::copy, vec![], None)
+            .add_method("count", Symbols::count, vec![], None)
+            .build()]);
+        shell.registry.write().parsed_create_instance("/syms", ".", "Symbols", &vec![]).unwrap();
+        shell
+    }
+
+    fn tokens(tokens: &[&str]) -> Tokens {
+        Tokens::new(tokens.iter().map(|t| t.to_string()).collect())
     }
 
     #[test]
-    fn validate_invalid_variable_name_returns_error() {
-        let command = AssignCommand {};
-        let tokens = Tokens::new(vec!["12foo".to_owned(), "=".to_owned(), "bar".to_owned()]);
+    fn validate_for_command_without_in_returns_error() {
+        let command = ForCommand {};
 
-        let result = command.validate(&tokens).err().unwrap();
+        let result = command.validate(&tokens(&["for", "i", "a", "b"])).err().unwrap();
 
-        assert_eq!(CommandValidationError::InvalidVariableName("12foo".to_owned()), result);
+        assert_eq!(CommandValidationError::InvalidCommandFormat {
+            format: "for <var> in [item ...]; do ... done"
+        }, result);
     }
 
     #[test]
-    fn execute_assignment_command() {
-        let mut context = UserContext::default();
-        let command = AssignCommand {};
-        let mut shell = Shell::default();
-        let mut input = io::stdin();
-        let mut output = io::sink();
-        let command_context = CommandContext::default();
-        let mut source = IoContext::new("test", &mut input, &mut output);
-        let tokens = Tokens::new(vec!["foo".to_owned(), "=".to_owned(), "bar".to_owned()]);
-        command.validate(&tokens).unwrap();
+    fn validate_for_command_with_invalid_variable_returns_error() {
+        let command = ForCommand {};
 
-        command.execute(&tokens, &mut context, &mut source, &command_context, &mut shell).unwrap();
+        let result = command.validate(&tokens(&["for", "1i", "in", "a", ";", "do"])).err().unwrap();
 
-        assert_eq!("bar", context.get_value("foo").unwrap());
+        assert_eq!(CommandValidationError::InvalidVariableName("1i".to_owned()), result);
     }
-}
 
-#[cfg(test)]
-mod default_assign_tests {
-    use std::io;
-    use crate::command::commands::{DefaultAssignCommand, Command, CommandValidationError};
-    use crate::command::context::{UserContext, IoContext, CommandContext};
-    use crate::command::lexer::Tokens;
-    use crate::command::shell::Shell;
+    #[test]
+    fn for_loop_over_words() {
+        let result = execute(&mut Shell::default(), "for i in a b c; do
+  echo \"$i \"
+done").unwrap();
+
+        assert_eq!("a b c ", result);
+    }
 
     #[test]
-    fn validate_valid_assignment_command_returns_true() {
-        let command = DefaultAssignCommand {};
+    fn for_loop_over_no_items_executes_nothing() {
+        let result = execute(&mut Shell::default(), "for i in; do
+  echo $i
+done
+echo end").unwrap();
 
-        let result = command.validate(&Tokens::new(
-            vec!["foo".to_owned(), ":=".to_owned(), "bar".to_owned()])).unwrap();
+        assert_eq!("end", result);
+    }
 
-        assert_eq!((), result);
+    #[test]
+    fn for_loop_over_ranges() {
+        let result = execute(&mut Shell::default(), "for i in {1..3} {3..1} {0..10..5} {5..0..-5}; do
+  echo $i
+done").unwrap();
+
+        assert_eq!("123321051050", result);
     }
 
     #[test]
-    fn validate_invalid_variable_name_returns_error() {
-        let command = DefaultAssignCommand {};
-        let tokens = Tokens::new(vec!["12foo".to_owned(), ":=".to_owned(), "bar".to_owned()]);
+    fn for_loop_over_method_list_result() {
+        let result = execute(&mut shell_with_symbols(), "for s in /syms/list X; do
+  echo \"$s \"
+done").unwrap();
 
-        let result = command.validate(&tokens).err().unwrap();
+        assert_eq!("XAAPL XMSFT ", result);
+    }
 
-        assert_eq!(CommandValidationError::InvalidVariableName("12foo".to_owned()), result);
+    #[test]
+    fn for_loop_over_method_iterable_instance_result() {
+        let result = execute(&mut shell_with_symbols(), "for s in /syms/copy; do
+  echo $s
+done").unwrap();
+
+        assert_eq!("AAPLMSFT", result);
     }
 
     #[test]
-    fn execute_default_assignment_command_sets_new_value() {
-        let mut context = UserContext::default();
-        let mut shell = Shell::default();
-        let mut input = io::stdin();
-        let mut output = io::sink();
-        let mut source = IoContext::new("test", &mut input, &mut output);
-        let command_context = CommandContext::default();
-        let command = DefaultAssignCommand {};
-        let tokens = Tokens::new(vec!["foo".to_owned(), ":=".to_owned(), "bar".to_owned()]);
-        command.validate(&tokens).unwrap();
+    fn for_loop_over_method_single_result() {
+        let result = execute(&mut shell_with_symbols(), "for s in /syms/count; do
+  echo $s
+done").unwrap();
 
-        command.execute(&tokens, &mut context, &mut source, &command_context, &mut shell).unwrap();
+        assert_eq!("2", result);
+    }
 
-        assert_eq!("bar", context.get_value("foo").unwrap());
+    #[test]
+    fn for_loop_over_typed_list_variable() {
+        let result = execute(&mut shell_with_symbols(), "syms = /syms/list X
+for s in $syms; do
+  echo \"$s \"
+done").unwrap();
+
+        assert_eq!("XAAPL XMSFT ", result);
     }
 
     #[test]
-    fn execute_default_assignment_command_already_there_doesnt_replace() {
-        let mut context = UserContext::default();
-        context.set_value("foo", "soo");
-        let mut shell = Shell::default();
-        let mut input = io::stdin();
-        let mut output = io::sink();
-        let mut source = IoContext::new("test", &mut input, &mut output);
+    fn for_loop_over_iterable_instance() {
+        let result = execute(&mut shell_with_symbols(), "for s in /syms; do
+  mkdir \"/md/$s\"
+done
+ls /md").unwrap();
 
-        let command_context = CommandContext::default();
-        let command = DefaultAssignCommand {};
-        let tokens = Tokens::new(vec!["foo".to_owned(), ":=".to_owned(), "bar".to_owned()]);
-        command.validate(&tokens).unwrap();
+        assert_eq!("AAPL\nMSFT\n", result);
+    }
 
-        command.execute(&tokens, &mut context, &mut source, &command_context, &mut shell).unwrap();
+    #[test]
+    fn for_loop_over_command_substitution_of_path() {
+        let result = execute(&mut shell_with_symbols(), "for s in $(echo /syms); do
+  echo \"$s \"
+done").unwrap();
 
-        assert_eq!("soo", context.get_value("foo").unwrap());
+        assert_eq!("AAPL MSFT ", result);
     }
-}
 
-#[cfg(test)]
-mod unset_tests {
-    use std::io;
-    use crate::command::commands::{Command, CommandValidationError, UnsetCommand};
-    use crate::command::context::{UserContext, IoContext, CommandContext};
-    use crate::command::lexer::Tokens;
-    use crate::command::shell::Shell;
+    #[test]
+    fn for_loop_over_relative_paths_iterates_words() {
+        let result = execute(&mut shell_with_symbols(), "for s in syms; do
+  echo \"$s \"
+done
+cd /syms
+for s in list a b; do
+  echo \"$s \"
+done").unwrap();
+
+        assert_eq!("syms list a b ", result);
+    }
 
     #[test]
-    fn validate_valid_unset_command_with_one_variable_returns_true() {
-        let command = UnsetCommand {};
+    fn for_loop_over_non_iterable_instance_is_error() {
+        let mut shell = Shell::default();
+        shell.cache_class(Symbols::get_polar_class_builder()
+            .set_constructor(Symbols::new, vec![])
+            .build()).unwrap();
+        shell.registry.write().parsed_create_instance("/syms", ".", "Symbols", &vec![]).unwrap();
 
-        let result = command.validate(&Tokens::new(vec!["unset".to_owned(), "foo".to_owned()])).unwrap();
+        let result = execute(&mut shell, "for s in /syms; do
+  echo $s
+done").err().unwrap();
 
-        assert_eq!((), result);
+        match result {
+            ShellError::RegistryError { error: RegistryError::InvocationFailure { .. }, .. } => {}
+            _ => panic!("unexpected error: {}", result)
+        }
     }
 
     #[test]
-    fn validate_valid_unset_command_with_multiple_variables_returns_true() {
-        let command = UnsetCommand {};
+    fn nested_loops() {
+        let result = execute(&mut Shell::default(), "for i in a b; do
+  for j in 1 2; do
+    if $j == 2; then
+      echo \"$i$j \"
+    fi
+  done
+done").unwrap();
+
+        assert_eq!("a2 b2 ", result);
+    }
 
-        let result = command.validate(&Tokens::new(
-            vec!["unset".to_owned(), "foo".to_owned(), "bar".to_owned()])).unwrap();
+    #[test]
+    fn loop_variable_remains_set_after_loop() {
+        let result = execute(&mut Shell::default(), "for i in a b; do
+done
+echo $i").unwrap();
 
-        assert_eq!((), result);
+        assert_eq!("b", result);
     }
+}
+
+#[cfg(test)]
+mod while_tests {
+    use crate::command::commands::{Command, CommandExecutionError, CommandValidationError,
+                                   WhileCommand};
+    use crate::command::commands::test_support::execute;
+    use crate::command::lexer::Tokens;
+    use crate::command::shell::Shell;
+    use crate::command::ShellError;
 
     #[test]
-    fn unset_with_no_variables_is_error() {
-        let command = UnsetCommand {};
-        let tokens = Tokens::new(vec!["unset".to_owned()]);
+    fn validate_while_command_without_condition_returns_error() {
+        let command = WhileCommand {};
+        let tokens = Tokens::new(vec!["while".to_owned(), "do".to_owned()]);
 
         let result = command.validate(&tokens).err().unwrap();
 
         assert_eq!(CommandValidationError::InvalidCommandFormat {
-            format: "unset [var ...]",
+            format: "while <condition>; do ... done"
         }, result);
     }
 
     #[test]
-    fn unset_invalid_variable_name_returns_error() {
-        let command = UnsetCommand {};
-        let tokens = Tokens::new(vec!["unset".to_owned(), "foo".to_owned(), "12foo".to_owned()]);
+    fn condition_is_expanded_before_each_iteration() {
+        let result = execute(&mut Shell::default(), "v = a
+while $v != aaaa; do
+  echo \"$v \"
+  v = \"${v}a\"
+done
+echo end").unwrap();
+
+        assert_eq!("a aa aaa end", result);
+    }
 
-        let result = command.validate(&tokens).err().unwrap();
+    #[test]
+    fn false_condition_skips_body() {
+        let result = execute(&mut Shell::default(), "while false; do
+  echo a
+done").unwrap();
 
-        assert_eq!(CommandValidationError::InvalidVariableName("12foo".to_owned()), result);
+        assert_eq!("", result);
     }
 
     #[test]
-    fn execute_unset_command() {
-        let mut context = UserContext::default();
-        context.set_value("foo", "bar");
-        context.set_value("soo", "doo");
-        context.set_value("goo", "boo");
-        let mut shell = Shell::default();
-        let mut input = io::stdin();
-        let mut output = io::sink();
-        let mut io_context = IoContext::new("test", &mut input, &mut output);
-        let command_context = CommandContext::default();
-        let command = UnsetCommand {};
-        let tokens = Tokens::new(vec!["unset".to_owned(), "soo".to_owned(), "goo".to_owned()]);
+    fn continued_condition_is_re_expanded() {
+        let result = execute(&mut Shell::default(), "v = a
+while $v != aa \\
+  && $v != aaa; do
+  v = \"${v}a\"
+done
+echo $v").unwrap();
+
+        assert_eq!("aa", result);
+    }
 
-        command.execute(
-            &tokens, &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+    #[test]
+    fn invalid_condition_is_error() {
+        let result = execute(&mut Shell::default(), "while a b c d; do
+done").err().unwrap();
+
+        match result {
+            ShellError::RegistryError { .. } => {}
+            ShellError::CommandExecutionError {
+                error: CommandExecutionError::InvalidCondition { .. }, ..
+            } => {}
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
 
-        assert_eq!("bar", context.get_value("foo").unwrap());
-        assert!(context.get_value("soo").is_none());
-        assert!(context.get_value("goo").is_none());
+    #[test]
+    fn missing_done_is_error() {
+        let result = execute(&mut Shell::default(), "while true; do
+  echo a").err().unwrap();
+
+        match result {
+            ShellError::CommandExecutionError {
+                error: CommandExecutionError::UnterminatedBlock("done"), ..
+            } => {}
+            _ => panic!("unexpected error: {}", result)
+        }
     }
 }
 
 #[cfg(test)]
-mod mkdir_tests {
-    use std::io;
-    use crate::command::commands::{Command, CommandValidationError, MkDirCommand};
-    use crate::command::context::{UserContext, IoContext, CommandContext};
+mod function_tests {
+    use std::fs::File;
+    use std::io::Write;
+    use crate::command::commands::{Command, CommandExecutionError, CommandValidationError,
+                                   FunctionCommand};
+    use crate::command::commands::test_support::execute_with_context;
+    use crate::command::context::UserContext;
     use crate::command::lexer::Tokens;
     use crate::command::shell::Shell;
+    use crate::command::ShellError;
+
+    fn tokens(tokens: &[&str]) -> Tokens {
+        Tokens::new(tokens.iter().map(|t| t.to_string()).collect())
+    }
 
     #[test]
-    fn validate_valid_mkdir_command_returns_true() {
-        let command = MkDirCommand {};
+    fn validate_function_without_opening_bracket_returns_error() {
+        let command = FunctionCommand {};
 
-        let result = command.validate(&Tokens::new(vec!["mkdir".to_owned(), "foo".to_owned()])).unwrap();
+        let result = command.validate(&tokens(&["function", "foo", "bar"])).err().unwrap();
 
-        assert_eq!((), result);
+        assert_eq!(CommandValidationError::InvalidCommandFormat {
+            format: "function [<name> [param ...] { ... }]"
+        }, result);
     }
 
     #[test]
-    fn validate_mkdir_command_without_arg_returns_error() {
-        let command = MkDirCommand {};
-        let tokens = Tokens::new(vec!["mkdir".to_owned()]);
+    fn validate_function_with_invalid_parameter_returns_error() {
+        let command = FunctionCommand {};
 
-        let result = command.validate(&tokens).err().unwrap();
+        let result = command.validate(&tokens(&["function", "foo", "1bar", "{"])).err().unwrap();
 
-        assert_eq!(CommandValidationError::InvalidCommandFormat { format: "mkdir <dir>" }, result);
+        assert_eq!(CommandValidationError::InvalidVariableName("1bar".to_owned()), result);
     }
 
     #[test]
-    fn validate_mkdir_command_with_multiple_args_returns_error() {
-        let command = MkDirCommand {};
-        let tokens = Tokens::new(vec!["mkdir".to_owned(), "foo".to_owned(), "bar".to_owned()]);
+    fn function_binds_positional_arguments_and_parameters() {
+        let mut user_context = UserContext::default();
 
-        let result = command.validate(&tokens).err().unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "function foo a b {
+  echo \"$0 $1 $a $b\"
+}
+foo 1 2").unwrap();
 
-        assert_eq!(CommandValidationError::InvalidCommandFormat { format: "mkdir <dir>" }, result);
+        assert_eq!("1 2 1 2", result);
     }
 
     #[test]
-    fn mkdir_creates_new_directories() {
-        let mut context = UserContext::default();
-        let mut shell = Shell::default();
-        let mut input = io::stdin();
-        let mut output = io::sink();
-        let mut io_context = IoContext::new("test", &mut input, &mut output);
-        let command_context = CommandContext::default();
-        let command = MkDirCommand {};
-        let tokens = Tokens::new(vec!["mkdir".to_owned(), "foo/bar".to_owned()]);
+    fn arguments_and_parameters_are_restored_after_call() {
+        let mut user_context = UserContext::default();
+        user_context.add_argument("outer");
 
-        command.execute(
-            &tokens, &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "a = x
+function foo a b {
+  c = \"$a$b\"
+}
+foo 1 2
+echo $0 $a $c").unwrap();
 
-        let path = shell.registry.path("/foo/bar").unwrap();
-        assert_eq!("/foo/bar", path.abs_path());
+        assert_eq!("outer x 12", result);
+        assert_eq!(None, user_context.get_value("b"));
     }
+
+    #[test]
+    fn function_is_redefined() {
+        let mut user_context = UserContext::default();
+
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "function foo {
+  echo a
 }
+function foo {
+  echo b
+}
+foo").unwrap();
 
-#[cfg(test)]
-mod cd_tests {
-    use std::io;
-    use crate::command::commands::{Command, CdCommand, MkDirCommand, CommandValidationError};
-    use crate::command::context::{UserContext, IoContext, CommandContext};
-    use crate::command::lexer::Tokens;
-    use crate::command::shell::Shell;
+        assert_eq!("b", result);
+    }
 
     #[test]
-    fn validate_valid_cd_command_returns_error() {
-        let command = CdCommand {};
-        let tokens = Tokens::new(vec!["cd".to_owned(), "foo".to_owned(), "bar".to_owned()]);
+    fn functions_are_listed() {
+        let mut user_context = UserContext::default();
 
-        let result = command.validate(&tokens).err().unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "function foo a b {
+}
+function bar {
+}
+function").unwrap();
 
-        assert_eq!(CommandValidationError::InvalidCommandFormat { format: "cd <dir>" }, result);
+        assert_eq!("bar\nfoo a b\n", result);
+        assert_eq!(vec!["bar", "foo"], user_context.function_names());
+        assert!(user_context.has_function("foo"));
     }
 
     #[test]
-    fn validate_cd_command_without_arg_returns_error() {
-        let command = CdCommand {};
-        let tokens = Tokens::new(vec!["cd".to_owned()]);
+    fn functions_are_listed_by_help() {
+        let mut user_context = UserContext::default();
 
-        let result = command.validate(&tokens).err().unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "function zzz {
+}
+help").unwrap();
 
-        assert_eq!(CommandValidationError::InvalidCommandFormat { format: "cd <dir>" }, result);
+        assert!(result.ends_with("while\nzzz\n"));
     }
 
     #[test]
-    fn validate_cd_command_with_one_arg_returns_true() {
-        let command = CdCommand {};
-        let tokens = Tokens::new(vec!["cd".to_owned(), "foo/bar".to_owned()]);
+    fn function_calls_function_and_blocks() {
+        let mut user_context = UserContext::default();
 
-        let result = command.validate(&tokens).unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "function inner v {
+  echo $v
+}
+function outer {
+  for i in {1..3}; do
+    if $i != 2; then
+      inner $i
+    fi
+  done
+}
+outer").unwrap();
 
-        assert_eq!((), result);
+        assert_eq!("13", result);
     }
 
     #[test]
-    fn cd_navigates_to_new_directory() {
-        let mut context = UserContext::default();
-        let mut shell = Shell::default();
-        let mut input = io::stdin();
-        let mut output = io::sink();
-        let mut io_context = IoContext::new("test", &mut input, &mut output);
-        let command_context = CommandContext::default();
-        MkDirCommand {}.execute(
-            &Tokens::new(vec!["mkdir".to_owned(), "foo/bar/soo".to_owned()]),
-            &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+    fn function_with_builtin_name_is_error() {
+        let mut user_context = UserContext::default();
 
-        CdCommand {}.execute(
-            &Tokens::new(vec!["cd".to_owned(), "foo/bar".to_owned()]),
-            &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "function echo {
+}").err().unwrap();
 
-        assert_eq!("/foo/bar", context.pwd());
+        match result {
+            ShellError::CommandExecutionError {
+                error: CommandExecutionError::ReservedFunctionName(name), ..
+            } => assert_eq!("echo", name),
+            _ => panic!("unexpected error: {}", result)
+        }
     }
+
+    #[test]
+    fn infinite_recursion_is_error() {
+        let mut user_context = UserContext::default();
+
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "function foo {
+  foo
 }
+foo").err().unwrap();
 
-#[cfg(test)]
-mod echo_tests {
-    use std::io;
-    use std::io::Cursor;
-    use crate::command::commands::{Command, EchoCommand};
-    use crate::command::context::{UserContext, IoContext, CommandContext};
-    use crate::command::lexer::Tokens;
-    use crate::command::shell::Shell;
+        match result {
+            ShellError::CommandExecutionError {
+                error: CommandExecutionError::MaxFunctionRecursion(name), ..
+            } => assert_eq!("foo", name),
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
 
     #[test]
-    fn validate_valid_echo_command_returns_true() {
-        let command = EchoCommand {};
+    fn errors_in_function_refer_to_definition_line() {
+        let mut user_context = UserContext::default();
 
-        let result = command.validate(&Tokens::new(
-            vec!["echo".to_owned(), "foo".to_owned(), "bar".to_owned()])).unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "function foo {
+  echo a
+  /unknown/method
+}
+foo").err().unwrap();
 
-        assert_eq!((), result);
+        match result {
+            ShellError::RegistryError { src, .. } =>
+                assert!(src.to_string().starts_with("test:3:")),
+            _ => panic!("unexpected error: {}", result)
+        }
     }
 
     #[test]
-    fn validate_echo_command_without_args_returns_true() {
-        let command = EchoCommand {};
+    fn functions_defined_in_sourced_file_are_kept() {
+        let path = std::env::temp_dir().join("rcore_function_source_test.txt");
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"function foo {\n  echo sourced\n}\n").unwrap();
+        let mut user_context = UserContext::default();
 
-        let result = command.validate(&Tokens::new(vec!["echo".to_owned()])).unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, &format!("source {}
+foo", path.to_str().unwrap())).unwrap();
 
-        assert_eq!((), result);
+        assert_eq!("sourced", result);
     }
 
     #[test]
-    fn echo_command_writes_tokens_to_output() {
-        let mut context = UserContext::default();
-        let mut shell = Shell::default();
-        let mut input = io::stdin();
-        let mut vec: Vec<u8> = Vec::new();
-        let mut output = Cursor::new(&mut vec);
-        let mut io_context = IoContext::new("test", &mut input, &mut output);
-        let command_context = CommandContext::default();
-        let command = EchoCommand {};
-        let tokens = Tokens::new(vec!["echo".to_owned(), "foo".to_owned(), "me".to_owned()]);
+    fn source_in_nested_functions() {
+        let path = std::env::temp_dir().join("rcore_nested_function_source_test.txt");
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"echo sourced\n").unwrap();
+        let mut commands = format!("function f20 {{\n  source {}\n}}\n", path.to_str().unwrap());
+        for i in 0..20 {
+            commands.push_str(&format!("function f{} {{\n  f{}\n}}\n", i, i + 1));
+        }
+        commands.push_str("f0");
+        let mut user_context = UserContext::default();
 
-        command.execute(
-            &tokens, &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, &commands).unwrap();
 
-        let str = String::from_utf8(vec).unwrap();
-        assert_eq!("foo me", str);
+        assert_eq!("sourced", result);
     }
 }
 
+
 #[cfg(test)]
-mod create_tests {
-    use std::io;
-    use std::io::Cursor;
-    use crate::command::commands::{Command, CommandValidationError, CreateCommand};
-    use crate::command::context::{UserContext, IoContext, CommandContext};
+mod try_tests {
+    use crate::command::commands::{Command, CommandExecutionError, CommandValidationError,
+                                   TryCommand};
+    use crate::command::commands::test_support::execute_with_context;
+    use crate::command::context::UserContext;
     use crate::command::lexer::Tokens;
+    use crate::command::oso::PolarValue;
     use crate::command::shell::Shell;
-    use crate::command::oso::PolarClass;
-    use crate::command::{RegistryError, ShellError};
+    use crate::command::ShellError;
 
-    #[derive(Clone, PolarClass)]
-    struct User {
-        #[polar(attribute)]
-        pub username: String,
-        #[polar(attribute)]
-        pub user_id: i32,
-    }
+    #[test]
+    fn validate_try_without_opening_bracket_returns_error() {
+        let command = TryCommand {};
+        let tokens = Tokens::new(vec!["try".to_owned()]);
 
-    impl User {
-        fn new(username: String, user_id: i32) -> User {
-            User { username, user_id }
-        }
+        let result = command.validate(&tokens).err().unwrap();
+
+        assert_eq!(CommandValidationError::InvalidCommandFormat {
+            format: "try { ... } [catch [var] { ... }]"
+        }, result);
     }
 
     #[test]
-    fn validate_valid_create_command_returns_true() {
-        let command = CreateCommand {};
+    fn catch_is_skipped_without_error() {
+        let mut user_context = UserContext::default();
 
-        let result = command.validate(&Tokens::new(vec![
-                "create".to_owned(),
-                "/foo/bar".to_owned(),
-                "core::command::commands::create_tests::User".to_owned(),
-                "jgreco".to_owned(),
-                "42".to_owned()
-            ])).unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "try {
+  echo a
+} catch err {
+  echo b
+}
+echo c").unwrap();
 
-        assert_eq!((), result);
+        assert_eq!("ac", result);
+        assert_eq!(None, user_context.get_value("err"));
     }
 
     #[test]
-    fn validate_create_command_with_1_param_is_error() {
-        let command = CreateCommand {};
-        let token = Tokens::new(vec![
-                "create".to_owned()
-            ]);
+    fn error_stops_try_and_executes_catch() {
+        let mut user_context = UserContext::default();
+
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "try {
+  echo a
+  cd /missing
+  echo b
+} catch err {
+  echo c
+}
+echo d").unwrap();
 
-        let result = command.validate(&token).err().unwrap();
+        assert_eq!("acd", result);
+        assert_eq!(0, user_context.exit_status());
+    }
 
-        assert_eq!(result, CommandValidationError::InvalidCommandFormat {
-            format: "create <dir> <struct> [args ...]"
-        });
+    #[test]
+    fn caught_error_is_set_to_variable() {
+        let mut user_context = UserContext::default();
+
+        execute_with_context(&mut Shell::default(), &mut user_context, "try {
+  cd /missing
+} catch err {
+}").unwrap();
+
+        let text = user_context.get_value("err").unwrap();
+        assert!(text.starts_with("test:2:"), "{}", text);
+        assert!(text.contains("/missing"), "{}", text);
+        match user_context.get_polar_value("err").unwrap() {
+            PolarValue::Map(map) => {
+                assert!(matches!(map.get("message"),
+                    Some(PolarValue::String(message)) if message.contains("/missing")));
+                assert_eq!(Some(&PolarValue::String("test".to_owned())), map.get("src"));
+                assert_eq!(Some(&PolarValue::Integer(2)), map.get("line"));
+                assert_eq!(Some(&PolarValue::List(vec![
+                    PolarValue::String("cd".to_owned()),
+                    PolarValue::String("/missing".to_owned())])), map.get("tokens"));
+            }
+            value => panic!("unexpected value: {:?}", value)
+        }
     }
 
     #[test]
-    fn validate_create_command_with_2_params_returns_false() {
-        let command = CreateCommand {};
-        let token = Tokens::new(vec![
-                "create".to_owned(),
-                "/foo/bar".to_owned(),
-            ]);
+    fn error_is_ignored_without_catch() {
+        let mut user_context = UserContext::default();
 
-        let result = command.validate(&token).err().unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "try {
+  cd /missing
+  echo a
+}
+echo b").unwrap();
 
-        assert_eq!(result, CommandValidationError::InvalidCommandFormat {
-            format: "create <dir> <struct> [args ...]"
-        });
+        assert_eq!("b", result);
     }
 
     #[test]
-    fn execute_create_command() {
-        let mut context = UserContext::default();
-        let mut shell = Shell::default();
-        shell.cache_class(User::get_polar_class_builder()
-            .set_constructor(User::new, vec!["string", "int"])
-            .build()).unwrap();
-        let mut input = io::stdin();
-        let mut vec: Vec<u8> = Vec::new();
-        let mut output = Cursor::new(&mut vec);
-        let mut io_context = IoContext::new("test", &mut input, &mut output);
-        let command_context = CommandContext::default();
-        let command = CreateCommand {};
-        let tokens = Tokens::new(vec![
-                "create".to_owned(),
-                "/foo/bar".to_owned(),
-                "rcore::command::commands::create_tests::User".to_owned(),
-                "jgreco".to_owned(),
-                "42".to_owned()
-            ]);
+    fn catch_without_variable() {
+        let mut user_context = UserContext::default();
 
-        command.execute(
-            &tokens, &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "try {
+  cd /missing
+} catch {
+  echo caught
+}").unwrap();
 
-        let user = shell.registry.instance_value::<User>("/foo/bar", ".").unwrap();
-        assert_eq!(42, user.user_id);
-        assert_eq!("jgreco", user.username);
+        assert_eq!("caught", result);
     }
 
     #[test]
-    fn execute_create_command_with_wrong_data_type_is_an_era() {
-        let mut context = UserContext::default();
-        let mut shell = Shell::default();
-        shell.cache_class(User::get_polar_class_builder()
-            .set_constructor(User::new, vec!["int", "string"])
-            .build()).unwrap();
-        let mut input = io::stdin();
-        let mut vec: Vec<u8> = Vec::new();
-        let mut output = Cursor::new(&mut vec);
-        let mut io_context = IoContext::new("test", &mut input, &mut output);
-        let command_context = CommandContext::default();
-        let command = CreateCommand {};
-        let tokens = Tokens::new(vec![
-                "create".to_owned(),
-                "/foo/bar".to_owned(),
-                "rcore::command::commands::create_tests::User".to_owned(),
-                "jgreco".to_owned(),
-                "42".to_owned()
-            ]);
+    fn try_stops_at_first_error_when_continuing_after_errors() {
+        let mut user_context = UserContext::default();
+
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "set +e
+try {
+  cd /missing
+  echo a
+} catch {
+  echo b
+}
+cd /missing
+echo c").unwrap();
 
-        let result = command.execute(
-            &tokens, &mut context, &mut io_context, &command_context, &mut shell).err().unwrap();
+        assert_eq!("bc", result);
+        assert!(!user_context.errexit());
+    }
 
-        assert_eq!(result, ShellError::RegistryError {
-            src: io_context.to_source_info(),
-            tokens,
-            error: RegistryError::InvalidMethodParameter {
-                class: "rcore::command::commands::create_tests::User".to_owned(),
-                method: "<constructor>".to_owned(),
-                param_index: 0,
-                param_type: "int",
-                reason: "",
-            }
-        })
+    #[test]
+    fn nested_try_blocks() {
+        let mut user_context = UserContext::default();
+
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "try {
+  try {
+    cd /missing
+  } catch {
+    echo a
+  }
+  cd /missing
+} catch {
+  try {
+    echo b
+  } catch {
+    echo c
+  }
+}").unwrap();
+
+        assert_eq!("ab", result);
     }
+
+    #[test]
+    fn error_in_catch_is_error() {
+        let mut user_context = UserContext::default();
+
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "try {
+  cd /missing
+} catch {
+  echo a
+  /unknown/method
 }
+echo b").err().unwrap();
 
-#[cfg(test)]
-mod execute_tests {
-    use std::io;
-    use std::io::Cursor;
-    use crate::command::commands::{Command, CreateCommand, ExecuteCommand};
-    use crate::command::context::{UserContext, IoContext, CommandContext};
-    use crate::command::lexer::Tokens;
-    use crate::command::shell::Shell;
-    use crate::command::oso::PolarClass;
-    use crate::command::{RegistryError, ShellError};
+        assert!(matches!(result, ShellError::RegistryError { .. }));
+        assert_eq!(5, result.src().line());
+    }
 
-    #[derive(Clone, PolarClass)]
-    struct User {
-        #[polar(attribute)]
-        pub username: String,
-        #[polar(attribute)]
-        pub user_id: i32,
+    #[test]
+    fn invalid_catch_statement_is_error() {
+        let mut user_context = UserContext::default();
+
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "try {
+  echo a
+} catch a b {
+}").err().unwrap();
+
+        assert_eq!(ShellError::CommandValidationError {
+            src: result.src().clone(),
+            tokens: Tokens::new(vec!["}".to_owned(), "catch".to_owned(), "a".to_owned(),
+                                     "b".to_owned(), "{".to_owned()]),
+            error: CommandValidationError::InvalidCommandFormat {
+                format: "try { ... } [catch [var] { ... }]"
+            },
+        }, result);
     }
 
-    impl User {
-        fn new(username: String, user_id: i32) -> User {
-            User { username, user_id }
+    #[test]
+    fn second_catch_is_error() {
+        let mut user_context = UserContext::default();
+
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "try {
+} catch {
+} catch {
+}").err().unwrap();
+
+        match result {
+            ShellError::CommandExecutionError { error, .. } =>
+                assert_eq!(CommandExecutionError::UnexpectedBlockStatement(
+                    "} catch {".to_owned()), error),
+            _ => panic!("unexpected error: {}", result)
         }
+    }
 
-        pub fn add_one(&self, id: i32) -> i32 {
-            id + 1
+    #[test]
+    fn catch_outside_of_try_is_error() {
+        let mut user_context = UserContext::default();
+
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "if true; then
+} catch {
+fi").err().unwrap();
+
+        match result {
+            ShellError::CommandExecutionError { error, .. } =>
+                assert_eq!(CommandExecutionError::UnexpectedBlockStatement(
+                    "} catch {".to_owned()), error),
+            _ => panic!("unexpected error: {}", result)
         }
     }
 
     #[test]
-    fn execute_method() {
-        let mut context = UserContext::default();
-        let mut shell = Shell::default();
-        shell.cache_class(User::get_polar_class_builder()
-            .set_constructor(User::new, vec!["string", "int"])
-            .add_method("add_one", User::add_one, vec!["int"], Some("add"))
-            .build()).unwrap();
-        let mut input = io::stdin();
-        let mut vec: Vec<u8> = Vec::new();
-        let mut output = Cursor::new(&mut vec);
-        let mut io_context = IoContext::new("test", &mut input, &mut output);
-        let command_context = CommandContext::default();
-        CreateCommand {}.execute(
-            &Tokens::new(vec![
-                    "create".to_owned(),
-                    "/foo/bar".to_owned(),
-                    "rcore::command::commands::execute_tests::User".to_owned(),
-                    "jgreco".to_owned(),
-                    "42".to_owned()
-                ]), &mut context, &mut io_context, &command_context, &mut shell).unwrap();
-
-        ExecuteCommand {}.execute(
-            &Tokens::new(vec![
-                    "/foo/bar/add".to_owned(),
-                    "41".to_owned()
-                ]), &mut context, &mut io_context, &command_context, &mut shell).unwrap();
+    fn failed_try_statement_skips_block() {
+        let mut user_context = UserContext::default();
+
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "set +e
+try now {
+  echo a
+} catch {
+  echo b
+}
+echo c").unwrap();
 
-        let string = String::from_utf8(vec).unwrap();
-        assert_eq!("42", string);
+        assert_eq!("c", result);
     }
 
     #[test]
-    fn execute_method_with_incorrect_data_type_is_error() {
-        let mut context = UserContext::default();
-        let mut shell = Shell::default();
-        shell.cache_class(User::get_polar_class_builder()
-            .set_constructor(User::new, vec!["string", "int"])
-            .add_method("add_one", User::add_one, vec!["int"], Some("add"))
-            .build()).unwrap();
-        let mut input = io::stdin();
-        let mut vec: Vec<u8> = Vec::new();
-        let mut output = Cursor::new(&mut vec);
-        let mut io_context = IoContext::new("test", &mut input, &mut output);
-        let command_context = CommandContext::default();
-        CreateCommand {}.execute(
-            &Tokens::new(vec![
-                    "create".to_owned(),
-                    "/foo/bar".to_owned(),
-                    "rcore::command::commands::execute_tests::User".to_owned(),
-                    "jgreco".to_owned(),
-                    "42".to_owned()
-                ]), &mut context, &mut io_context, &command_context, &mut shell).unwrap();
-        let tokens = Tokens::new(vec![
-                "/foo/bar/add".to_owned(),
-                "foo".to_owned()
-            ]);
+    fn missing_bracket_is_error() {
+        let mut user_context = UserContext::default();
 
-        let err = ExecuteCommand {}.execute(
-            &tokens, &mut context, &mut io_context, &command_context, &mut shell).err().unwrap();
+        let result = execute_with_context(&mut Shell::default(), &mut user_context, "try {
+  echo a").err().unwrap();
 
-        assert_eq!(ShellError::RegistryError {
-            src: io_context.to_source_info(),
-            error: RegistryError::InvalidMethodParameter {
-                class: "User".to_owned(),
-                method: "add_one".to_string(),
-                param_index: 0,
-                param_type: "int",
-                reason: "",
-            },
-            tokens: tokens.clone(),
-        }, err);
+        match result {
+            ShellError::CommandExecutionError { error, .. } =>
+                assert_eq!(CommandExecutionError::UnterminatedBlock("}"), error),
+            _ => panic!("unexpected error: {}", result)
+        }
     }
 }
\ No newline at end of file
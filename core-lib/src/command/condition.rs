@@ -0,0 +1,334 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::command::context::UserContext;
+use crate::command::lexer::Tokens;
+use crate::command::oso::PolarValue;
+use crate::command::shell::Shell;
+use crate::command::RegistryError;
+
+/// Errors thrown evaluating a condition.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ConditionError {
+    /// The condition is improperly formatted or compares values of the wrong type.
+    Invalid(String),
+    /// The method used as the condition could not be invoked.
+    Registry(RegistryError),
+}
+
+/// A parsed condition.
+enum Condition {
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    /// The start and end indices of the tokens of a test.
+    Test(usize, usize),
+}
+
+/// Evaluates the condition of a conditional block or loop.
+///
+/// A condition is made up of one or more tests combined with `!`, `&&`, `||`, and parentheses.
+/// A test is one of:
+/// - `true` or `false`
+/// - `-n <value>` or `-z <value>`: the value is non-empty or empty
+/// - `<value> == <value>` or `<value> != <value>`: string comparison
+/// - `<value> -eq|-ne|-lt|-le|-gt|-ge <value>`: numeric comparison
+/// - `<method> [arg ...]`: invokes a method, or reads an attribute, that returns a boolean
+///
+/// Operators are only recognized when they are neither quoted nor expanded, so `"&&"` or a
+/// variable holding `!` is a value.
+pub(crate) fn evaluate(tokens: &Tokens, user_context: &UserContext, shell: &mut Shell)
+                       -> Result<bool, ConditionError> {
+    let mut pos = 0;
+    let condition = parse_or(tokens, &mut pos)?;
+    if pos != tokens.len() {
+        return Err(ConditionError::Invalid(format!("unexpected '{}'", tokens.get(pos))));
+    }
+    eval(&condition, tokens, user_context, shell)
+}
+
+/// Returns true if the token of the specified index is the operator.
+fn is_operator(tokens: &Tokens, pos: usize, operator: &str) -> bool {
+    pos < tokens.len() && (tokens.is_literal(pos) || tokens.is_operator(pos))
+        && tokens.get(pos) == operator
+}
+
+fn parse_or(tokens: &Tokens, pos: &mut usize) -> Result<Condition, ConditionError> {
+    let mut condition = parse_and(tokens, pos)?;
+    while is_operator(tokens, *pos, "||") {
+        *pos += 1;
+        condition = Condition::Or(Box::new(condition), Box::new(parse_and(tokens, pos)?));
+    }
+    Ok(condition)
+}
+
+fn parse_and(tokens: &Tokens, pos: &mut usize) -> Result<Condition, ConditionError> {
+    let mut condition = parse_not(tokens, pos)?;
+    while is_operator(tokens, *pos, "&&") {
+        *pos += 1;
+        condition = Condition::And(Box::new(condition), Box::new(parse_not(tokens, pos)?));
+    }
+    Ok(condition)
+}
+
+fn parse_not(tokens: &Tokens, pos: &mut usize) -> Result<Condition, ConditionError> {
+    if is_operator(tokens, *pos, "!") {
+        *pos += 1;
+        Ok(Condition::Not(Box::new(parse_not(tokens, pos)?)))
+    } else if is_operator(tokens, *pos, "(") {
+        *pos += 1;
+        let condition = parse_or(tokens, pos)?;
+        if !is_operator(tokens, *pos, ")") {
+            return Err(ConditionError::Invalid("missing closing parenthesis".to_owned()));
+        }
+        *pos += 1;
+        Ok(condition)
+    } else {
+        // a test runs until the next logical operator or closing parenthesis
+        let start = *pos;
+        while *pos < tokens.len()
+            && !["&&", "||", ")"].iter().any(|operator| is_operator(tokens, *pos, operator)) {
+            *pos += 1;
+        }
+        if start == *pos {
+            return Err(ConditionError::Invalid("missing test".to_owned()));
+        }
+        Ok(Condition::Test(start, *pos))
+    }
+}
+
+fn eval(condition: &Condition, tokens: &Tokens, user_context: &UserContext, shell: &mut Shell)
+        -> Result<bool, ConditionError> {
+    match condition {
+        Condition::Not(c) => Ok(!eval(c, tokens, user_context, shell)?),
+        Condition::And(lhs, rhs) =>
+            Ok(eval(lhs, tokens, user_context, shell)? && eval(rhs, tokens, user_context, shell)?),
+        Condition::Or(lhs, rhs) =>
+            Ok(eval(lhs, tokens, user_context, shell)? || eval(rhs, tokens, user_context, shell)?),
+        Condition::Test(start, end) => test(&tokens.slice(*start, *end), user_context, shell),
+    }
+}
+
+fn test(tokens: &Tokens, user_context: &UserContext, shell: &mut Shell)
+        -> Result<bool, ConditionError> {
+    let len = tokens.len();
+    if is_operator(tokens, 0, "-n") || is_operator(tokens, 0, "-z") {
+        if len != 2 {
+            return Err(ConditionError::Invalid(
+                format!("'{}' requires a single value", tokens.get(0))));
+        }
+        Ok(tokens.get(1).is_empty() == (tokens.get(0) == "-z"))
+    } else if len == 1 && tokens.get(0) == "true" {
+        Ok(true)
+    } else if len == 1 && tokens.get(0) == "false" {
+        Ok(false)
+    } else if len == 3 && is_operator(tokens, 1, "==") {
+        Ok(tokens.get(0) == tokens.get(2))
+    } else if len == 3 && is_operator(tokens, 1, "!=") {
+        Ok(tokens.get(0) != tokens.get(2))
+    } else if len == 3 && tokens.is_literal(1) && is_numeric_operator(tokens.get(1)) {
+        let ordering = compare_numbers(tokens.get(0), tokens.get(2))?;
+        Ok(match tokens.get(1) {
+            "-eq" => ordering == Ordering::Equal,
+            "-ne" => ordering != Ordering::Equal,
+            "-lt" => ordering == Ordering::Less,
+            "-le" => ordering != Ordering::Greater,
+            "-gt" => ordering == Ordering::Greater,
+            _ => ordering != Ordering::Less,
+        })
+    } else {
+        let args: Vec<&str> = tokens.all()[1..].iter().map(|t| t.as_str()).collect();
+        let result = shell.registry
+            .parsed_invoke_with_values(user_context.pwd(), tokens.get(0), &args, &HashMap::new())
+            .map_err(ConditionError::Registry)?;
+        match result {
+            PolarValue::Boolean(b) => Ok(b),
+            _ => Err(ConditionError::Invalid(
+                format!("method did not return a boolean: {}", tokens.get(0))))
+        }
+    }
+}
+
+fn is_numeric_operator(operator: &str) -> bool {
+    ["-eq", "-ne", "-lt", "-le", "-gt", "-ge"].contains(&operator)
+}
+
+fn compare_numbers(lhs: &str, rhs: &str) -> Result<Ordering, ConditionError> {
+    if let (Ok(lhs), Ok(rhs)) = (lhs.parse::<i64>(), rhs.parse::<i64>()) {
+        return Ok(lhs.cmp(&rhs));
+    }
+
+    let lhs = parse_float(lhs)?;
+    let rhs = parse_float(rhs)?;
+    lhs.partial_cmp(&rhs).ok_or_else(|| ConditionError::Invalid("cannot compare NaN".to_owned()))
+}
+
+fn parse_float(value: &str) -> Result<f64, ConditionError> {
+    value.parse().map_err(|_| ConditionError::Invalid(format!("not a number: {}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Cursor;
+    use crate::command::condition::{ConditionError, evaluate};
+    use crate::command::context::{IoContext, UserContext};
+    use crate::command::lexer::lex_command;
+    use crate::command::oso::PolarClass;
+    use crate::command::shell::Shell;
+
+    fn eval(condition: &str) -> Result<bool, ConditionError> {
+        let mut shell = Shell::default();
+        eval_with_shell(condition, &mut shell)
+    }
+
+    fn eval_with_shell(condition: &str, shell: &mut Shell) -> Result<bool, ConditionError> {
+        eval_with_context(condition, &mut UserContext::default(), shell)
+    }
+
+    fn eval_with_context(condition: &str, context: &mut UserContext, shell: &mut Shell)
+                         -> Result<bool, ConditionError> {
+        let mut input = Cursor::new(condition.as_bytes());
+        let mut output = io::sink();
+        let mut io_context = IoContext::new("test", &mut input, &mut output);
+        let tokens = lex_command(context, &mut io_context, &mut |_, _| unreachable!())
+            .unwrap().unwrap();
+        evaluate(&tokens, context, shell)
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Service {
+        ready: bool,
+    }
+
+    impl Service {
+        fn new(ready: bool) -> Service {
+            Service { ready }
+        }
+
+        fn is_ready(&self) -> bool {
+            self.ready
+        }
+
+        fn name(&self) -> String {
+            "svc".to_owned()
+        }
+    }
+
+    fn shell_with_service(ready: &str) -> Shell {
+        let mut shell = Shell::default();
+        shell.cache_class(Service::get_polar_class_builder()
+            .set_constructor(Service::new, vec!["bool"])
+            .add_method("is_ready", Service::is_ready, vec![], None)
+            .add_method("name", Service::name, vec![], None)
+            .build()).unwrap();
+        shell.registry.write().parsed_create_instance("/svc", ".", "Service", &vec![ready]).unwrap();
+        shell
+    }
+
+    #[test]
+    fn boolean_literals() {
+        assert_eq!(Ok(true), eval("true"));
+        assert_eq!(Ok(false), eval("false"));
+    }
+
+    #[test]
+    fn string_comparison() {
+        assert_eq!(Ok(true), eval("prod == prod"));
+        assert_eq!(Ok(false), eval("prod == dev"));
+        assert_eq!(Ok(true), eval("prod != dev"));
+        assert_eq!(Ok(false), eval("1 == 1.0"));
+    }
+
+    #[test]
+    fn empty_and_non_empty_strings() {
+        assert_eq!(Ok(true), eval("-n abc"));
+        assert_eq!(Ok(false), eval("-z abc"));
+        assert_eq!(Ok(true), eval("-z \"\""));
+    }
+
+    #[test]
+    fn empty_test_without_value_is_error() {
+        assert_eq!(Err(ConditionError::Invalid("'-z' requires a single value".to_owned())),
+                   eval("-z"));
+        assert_eq!(Err(ConditionError::Invalid("'-n' requires a single value".to_owned())),
+                   eval("-n && true"));
+    }
+
+    #[test]
+    fn quoted_operators_are_values() {
+        assert_eq!(Ok(true), eval("\"&&\" == \"&&\""));
+        assert_eq!(Ok(true), eval("\"!\" == '!'"));
+        assert_eq!(Ok(false), eval("\"-z\" == \"-n\""));
+        assert_eq!(Ok(true), eval("\"(\" != \")\""));
+    }
+
+    #[test]
+    fn variables_holding_operators_are_values() {
+        let mut context = UserContext::default();
+        context.set_value("not", "!");
+        context.set_value("and", "&&");
+        context.set_value("empty", "");
+
+        assert_eq!(Ok(true), eval_with_context("$not == \"!\"", &mut context, &mut Shell::default()));
+        assert_eq!(Ok(true), eval_with_context("$and == $and", &mut context, &mut Shell::default()));
+        assert_eq!(Ok(true), eval_with_context("-z $empty", &mut context, &mut Shell::default()));
+        assert_eq!(Ok(false), eval_with_context("-n $empty", &mut context, &mut Shell::default()));
+    }
+
+    #[test]
+    fn numeric_comparison() {
+        assert_eq!(Ok(true), eval("1 -eq 1.0"));
+        assert_eq!(Ok(true), eval("2 -gt 1"));
+        assert_eq!(Ok(false), eval("2 -lt 1"));
+        assert_eq!(Ok(true), eval("-3 -le -3"));
+        assert_eq!(Ok(true), eval("1.5 -ge 1"));
+        assert_eq!(Ok(true), eval("10 -ne 9"));
+    }
+
+    #[test]
+    fn numeric_comparison_of_non_numbers_is_error() {
+        assert_eq!(Err(ConditionError::Invalid("not a number: abc".to_owned())),
+                   eval("abc -lt 1"));
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_eq!(Ok(false), eval("! true"));
+        assert_eq!(Ok(true), eval("true && a == a"));
+        assert_eq!(Ok(false), eval("true && a == b"));
+        assert_eq!(Ok(true), eval("false || true && true"));
+        assert_eq!(Ok(false), eval("( false || true ) && false"));
+        assert_eq!(Ok(true), eval("! ( a == b )"));
+    }
+
+    #[test]
+    fn unbalanced_parentheses_is_error() {
+        assert_eq!(Err(ConditionError::Invalid("missing closing parenthesis".to_owned())),
+                   eval("( true"));
+        assert_eq!(Err(ConditionError::Invalid("unexpected ')'".to_owned())),
+                   eval("true )"));
+    }
+
+    #[test]
+    fn method_result_is_condition() {
+        assert_eq!(Ok(true), eval_with_shell("/svc/is_ready", &mut shell_with_service("true")));
+        assert_eq!(Ok(false), eval_with_shell("/svc/is_ready", &mut shell_with_service("false")));
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        let mut shell = shell_with_service("true");
+
+        assert_eq!(Ok(false), eval_with_shell("false && /svc/unknown", &mut shell));
+        assert_eq!(Ok(true), eval_with_shell("true || /svc/unknown", &mut shell));
+    }
+
+    #[test]
+    fn method_without_boolean_result_is_error() {
+        let mut shell = shell_with_service("true");
+
+        assert_eq!(Err(ConditionError::Invalid("method did not return a boolean: /svc/name".to_owned())),
+                   eval_with_shell("/svc/name", &mut shell));
+    }
+}
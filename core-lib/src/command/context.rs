@@ -2,18 +2,52 @@ use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::io::{Read, Write};
 use std::io;
-use crate::command::commands::{AssignCommand, CdCommand, Command, CreateCommand, DefaultAssignCommand, EchoCommand, ExecuteCommand, HelpCommand, LsCommand, MkDirCommand, PwdCommand, SourceCommand, UnsetCommand};
+use crate::command::lexer::LexerError;
+use crate::command::oso::PolarValue;
+use crate::command::commands::{AssignCommand, CdCommand, Command, CpCommand, CreateCommand, DefaultAssignCommand, DumpCommand, EchoCommand, ExecuteCommand, FindCommand, ForCommand, FunctionCommand, GetCommand, HelpCommand, IfCommand, LnCommand, LsCommand, MkDirCommand, MvCommand, PwdCommand, RmCommand, RmDirCommand, SetCommand, SourceCommand, TreeCommand, TryCommand, UnsetCommand, WhileCommand};
 
 /// The user context contains user-specific information related to executing commands in the
-/// shell including the current working directory and variables.
+/// shell including the current working directory, variables, and user-defined functions.
 ///
 /// The initial working directory for the user context is the root directory.
 #[derive(Clone)]
 pub struct UserContext {
     pwd: String,
     pub(crate) variables: HashMap<String, String>,
-    arguments: Vec<String>,
-    pub(crate) level: usize
+    pub(crate) values: HashMap<String, PolarValue>,
+    pub(crate) arguments: Vec<String>,
+    pub(crate) functions: HashMap<String, Function>,
+    pub(crate) level: usize,
+    pub(crate) function_level: usize,
+    exit_status: i32,
+    errexit: bool,
+}
+
+/// A user-defined function.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Function {
+    /// The name of the function.
+    pub(crate) name: String,
+    /// The names of the function's parameters.
+    pub(crate) params: Vec<String>,
+    /// The command source the function was defined in.
+    pub(crate) src: String,
+    /// The line of the statement that starts the function.
+    pub(crate) line: usize,
+    /// The raw commands in the function.
+    pub(crate) body: String,
+}
+
+impl Function {
+    /// Returns the name of the function followed by its parameters (e.g., `make_feed name port`).
+    pub(crate) fn signature(&self) -> String {
+        let mut signature = self.name.to_owned();
+        for param in &self.params {
+            signature.push(' ');
+            signature.push_str(param);
+        }
+        signature
+    }
 }
 
 impl Default for UserContext {
@@ -21,13 +55,40 @@ impl Default for UserContext {
         UserContext {
             pwd: "/".to_owned(),
             variables: HashMap::default(),
+            values: HashMap::default(),
             arguments: vec![],
-            level: 0
+            functions: HashMap::default(),
+            level: 0,
+            function_level: 0,
+            exit_status: 0,
+            errexit: true,
         }
     }
 }
 
 impl UserContext {
+    /// Returns the exit status of the last command, 0 if it succeeded and 1 if it failed.
+    ///
+    /// The exit status can be accessed in commands with `$?`.
+    pub fn exit_status(&self) -> i32 {
+        self.exit_status
+    }
+
+    pub(crate) fn set_exit_status(&mut self, exit_status: i32) {
+        self.exit_status = exit_status;
+    }
+
+    /// Returns true if the shell stops at the first failing command (`set -e`, the default), or
+    /// false if failing commands are logged and counted and the shell continues (`set +e`).
+    pub fn errexit(&self) -> bool {
+        self.errexit
+    }
+
+    /// Sets whether the shell stops at the first failing command.
+    pub fn set_errexit(&mut self, errexit: bool) {
+        self.errexit = errexit;
+    }
+
     pub(crate) fn set_pwd(&mut self, pwd: &str) {
         self.pwd.clear();
         self.pwd.push_str(pwd);
@@ -54,15 +115,30 @@ impl UserContext {
     }
 
     /// Returns the value for the specified variable.
+    ///
+    /// The value of a variable holding a [PolarValue] is the value as it is written by the shell.
     pub fn get_value(&self, var: &str) -> Option<&String> {
         self.variables.get(var)
     }
 
+    /// Returns the typed value for the specified variable, if the variable holds a [PolarValue].
+    pub fn get_polar_value(&self, var: &str) -> Option<&PolarValue> {
+        self.values.get(var)
+    }
+
     /// Sets the value of the specified variable.
     pub fn set_value(&mut self, var: &str, value: &str) {
+        self.values.remove(var);
         self.variables.insert(var.to_owned(), value.to_owned());
     }
 
+    /// Sets the typed value of the specified variable along with the value as it is written by the
+    /// shell, which is used when the variable is expanded in text.
+    pub(crate) fn set_polar_value(&mut self, var: &str, value: PolarValue, text: &str) {
+        self.values.insert(var.to_owned(), value);
+        self.variables.insert(var.to_owned(), text.to_owned());
+    }
+
     /// Sets the value of the specified variable if it is not already set.
     pub fn set_default_value(&mut self, key: &str, value: &str) {
         if !self.variables.contains_key(key) {
@@ -72,17 +148,39 @@ impl UserContext {
 
     /// Removes the value of the specified variable.
     pub fn remove_value(&mut self, key: &str) {
+        self.values.remove(key);
         self.variables.remove(key);
     }
 
     /// Clears all variables.
     pub fn clear_variables(&mut self) {
+        self.values.clear();
         self.variables.clear();
     }
+
+    /// Returns true if a function with the specified name has been defined.
+    pub fn has_function(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    /// Returns the names of the user-defined functions in alphabetical order.
+    pub fn function_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(|name| name.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    pub(crate) fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    pub(crate) fn set_function(&mut self, function: Function) {
+        self.functions.insert(function.name.to_owned(), function);
+    }
 }
 
 /// Information about the source of the Shell error.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct SourceInfo {
     /// The source name.
     pub src: String,
@@ -92,6 +190,18 @@ pub struct SourceInfo {
     col: usize
 }
 
+impl SourceInfo {
+    /// Returns the line associated with the error.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the column associated with the error.
+    pub fn col(&self) -> usize {
+        self.col
+    }
+}
+
 impl Display for SourceInfo {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}:{}:{}", self.src, self.line, self.col)
@@ -112,6 +222,7 @@ pub struct IoContext<'a> {
     /// The command output.
     pub output: &'a mut dyn Write,
     buffer: [u8; 1],
+    raw: Vec<u8>,
 }
 
 // TODO: we need to support writing multiple formats including text and JSON
@@ -125,19 +236,81 @@ impl<'a> IoContext<'a> {
             input,
             output,
             buffer: [0],
+            raw: vec![],
+        }
+    }
+
+    /// Creates an I/O context that reads from this context's input but writes to the specified
+    /// output.
+    pub(crate) fn with_output<'b>(&'b mut self, output: &'b mut dyn Write) -> IoContext<'b> {
+        IoContext {
+            src: self.src,
+            line: self.line,
+            col: self.col,
+            input: &mut *self.input,
+            output,
+            buffer: [0],
+            raw: self.raw.clone(),
         }
     }
 
-    pub(crate) fn next_byte(&mut self) -> Result<Option<u8>, io::Error> {
-        // TODO: extend to support unicode
+    fn next_byte(&mut self) -> Result<Option<u8>, io::Error> {
         let bytes_read = self.input.read(&mut self.buffer)?;
         if bytes_read == 1 {
+            self.raw.push(self.buffer[0]);
             Ok(Some(self.buffer[0]))
         } else {
             Ok(None)
         }
     }
 
+    /// Reads the next UTF-8 encoded character from the input.
+    pub(crate) fn next_char(&mut self) -> Result<Option<char>, LexerError> {
+        let first = match self.next_byte().map_err(LexerError::IoError)? {
+            Some(byte) => byte,
+            None => return Ok(None)
+        };
+        let len = match first {
+            0x00..=0x7F => return Ok(Some(first as char)),
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return Err(LexerError::InvalidUtf8(vec![first]))
+        };
+
+        let mut bytes = vec![first];
+        while bytes.len() < len {
+            match self.next_byte().map_err(LexerError::IoError)? {
+                Some(byte) => bytes.push(byte),
+                None => return Err(LexerError::InvalidUtf8(bytes))
+            }
+        }
+        match std::str::from_utf8(&bytes) {
+            Ok(str) => Ok(str.chars().next()),
+            Err(_) => Err(LexerError::InvalidUtf8(bytes))
+        }
+    }
+
+    /// Skips the rest of the current line, unless the line has already been read.
+    pub(crate) fn skip_line(&mut self) {
+        while self.raw.last() != Some(&b'\n') {
+            match self.next_byte() {
+                Ok(Some(_)) => {}
+                _ => return
+            }
+        }
+    }
+
+    /// Clears the raw text of the last command.
+    pub(crate) fn clear_raw(&mut self) {
+        self.raw.clear();
+    }
+
+    /// Returns the raw, unexpanded text of the last command that was lexed.
+    pub(crate) fn raw_command(&self) -> String {
+        String::from_utf8_lossy(&self.raw).trim().to_owned()
+    }
+
     /// Writes the specified string to the output.
     pub fn write_str(&mut self, string: &str) -> Result<(), io::Error> {
         self.output.write_all(string.as_bytes())
@@ -162,18 +335,35 @@ impl<'a> IoContext<'a> {
 /// The default implementation adds the following built-in commands:
 /// - assign [AssignCommand]
 /// - cd [CdCommand]
+/// - cp [CpCommand]
 /// - create [CreateCommand]
 /// - := [DefaultAssignCommand]
+/// - dump [DumpCommand]
 /// - echo [EchoCommand]
+/// - find [FindCommand]
+/// - for [ForCommand]
+/// - function [FunctionCommand]
+/// - get [GetCommand]
 /// - ls [LsCommand]
 /// - help [HelpCommand]
+/// - if [IfCommand]
+/// - ln [LnCommand]
 /// - mkdir [MkDirCommand]
+/// - mv [MvCommand]
 /// - pwd [PwdCommand]
+/// - rm [RmCommand]
+/// - rmdir [RmDirCommand]
+/// - set [SetCommand]
 /// - source [SourceCommand]
+/// - tree [TreeCommand]
+/// - try [TryCommand]
 /// - unset [UnsetCommand]
+/// - while [WhileCommand]
 ///
 /// The default implementation can also invoke methods and retrieve attributes using the
-/// [ExecuteCommand]
+/// [ExecuteCommand].
+/// User-defined functions are invoked when the first token of a command is the name of a function
+/// and it is not the keyword of a built-in command.
 pub struct CommandContext {
     pub(crate) builtin_commands: Vec<Box<dyn Command>>,
     pub(crate) execute_command: Box<dyn Command>
@@ -184,15 +374,30 @@ impl Default for CommandContext {
         CommandContext {
             builtin_commands: vec![Box::new(AssignCommand {}),
                                    Box::new(CdCommand {}),
+                                   Box::new(CpCommand {}),
                                    Box::new(CreateCommand {}),
                                    Box::new(DefaultAssignCommand {}),
+                                   Box::new(DumpCommand {}),
                                    Box::new(EchoCommand {}),
+                                   Box::new(FindCommand {}),
+                                   Box::new(ForCommand {}),
+                                   Box::new(FunctionCommand {}),
+                                   Box::new(GetCommand {}),
                                    Box::new(HelpCommand {}),
+                                   Box::new(IfCommand {}),
+                                   Box::new(LnCommand {}),
                                    Box::new(LsCommand {}),
                                    Box::new(MkDirCommand {}),
+                                   Box::new(MvCommand {}),
                                    Box::new(PwdCommand {}),
+                                   Box::new(RmCommand {}),
+                                   Box::new(RmDirCommand {}),
+                                   Box::new(SetCommand {}),
                                    Box::new(SourceCommand {}),
-                                   Box::new(UnsetCommand {})],
+                                   Box::new(TreeCommand {}),
+                                   Box::new(TryCommand {}),
+                                   Box::new(UnsetCommand {}),
+                                   Box::new(WhileCommand {})],
             execute_command: Box::new(ExecuteCommand {}),
         }
     }
@@ -1,8 +1,13 @@
 use std::{fmt, io};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Formatter;
+use std::str::Chars;
 
 use thiserror::Error;
+use crate::command::arithmetic;
 use crate::command::context::{UserContext, IoContext};
+use crate::command::ShellError;
+use crate::command::oso::PolarValue;
 
 /// Errors thrown lexing commands from the command file.
 #[derive(Debug, Error)]
@@ -17,6 +22,23 @@ pub enum LexerError {
     UnknownVariable(String),
     #[error("the variable is in an unknown format")]
     InvalidVariableFormat,
+    #[error("the command substitution is missing its closing parenthesis")]
+    UnterminatedCommandSubstitution,
+    #[error("{var}: {message}")]
+    ParameterNotSet {
+        var: String,
+        message: String,
+    },
+    #[error("command substitution failed: {0}")]
+    CommandSubstitutionFailure(Box<ShellError>),
+    #[error("invalid arithmetic expression: {0}")]
+    InvalidArithmeticExpression(String),
+    #[error("division by zero in arithmetic expression")]
+    DivisionByZero,
+    #[error("overflow in arithmetic expression")]
+    ArithmeticOverflow,
+    #[error("the command contains invalid UTF-8: {0:?}")]
+    InvalidUtf8(Vec<u8>),
     #[error("I/O error: {0}")]
     IoError(io::Error),
 }
@@ -34,6 +56,21 @@ impl PartialEq for LexerError {
                 LexerError::UnknownVariable(var2)) => var == var2,
             (LexerError::InvalidVariableFormat,
                 LexerError::InvalidVariableFormat) => true,
+            (LexerError::UnterminatedCommandSubstitution,
+                LexerError::UnterminatedCommandSubstitution) => true,
+            (LexerError::ParameterNotSet { var, message },
+                LexerError::ParameterNotSet { var: var2, message: message2 }) =>
+                var == var2 && message == message2,
+            (LexerError::CommandSubstitutionFailure(error),
+                LexerError::CommandSubstitutionFailure(error2)) => error == error2,
+            (LexerError::InvalidArithmeticExpression(reason),
+                LexerError::InvalidArithmeticExpression(reason2)) => reason == reason2,
+            (LexerError::DivisionByZero,
+                LexerError::DivisionByZero) => true,
+            (LexerError::ArithmeticOverflow,
+                LexerError::ArithmeticOverflow) => true,
+            (LexerError::InvalidUtf8(bytes),
+                LexerError::InvalidUtf8(bytes2)) => bytes == bytes2,
             (LexerError::IoError(_),
                 LexerError::IoError(_)) => true,
             _ => false
@@ -46,27 +83,100 @@ impl PartialEq for LexerError {
 }
 
 /// The tokens for a single command.
+///
+/// A token that is only a variable holding a [PolarValue] (e.g., `$config`) also keeps the typed
+/// value, so it can be passed to methods and constructors without being converted to a string.
+/// Operators (i.e., the `>` and `>>` redirections, and the `|` pipe) are tokens that were not in
+/// quotes.
+/// A token that is only a command substitution (e.g., `$(/feeds/latest)`) is marked as well.
+/// Tokens that were in quotes or expanded are marked too, so a keyword of a condition (e.g., `&&` or
+/// `-z`) is not confused with a quoted string or a variable that has the same value.
 #[derive(Debug, PartialEq, Clone)]
-pub struct Tokens(Vec<String>);
+pub struct Tokens {
+    tokens: Vec<String>,
+    values: HashMap<usize, PolarValue>,
+    operators: HashSet<usize>,
+    substitutions: HashSet<usize>,
+    expanded: HashSet<usize>,
+}
 
 impl Tokens {
     pub(crate) fn all(&self) -> &Vec<String> {
-        &self.0
+        &self.tokens
     }
 
     /// Creates a new [Tokens] instance from the specified vector of strings.
     pub fn new(tokens: Vec<String>) -> Tokens {
-        Tokens(tokens)
+        Tokens {
+            tokens,
+            values: HashMap::new(),
+            operators: HashSet::new(),
+            substitutions: HashSet::new(),
+            expanded: HashSet::new(),
+        }
     }
 
     /// The number of tokens.
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.tokens.len()
     }
 
     /// Returns the token of the specified index.
     pub fn get(&self, index: usize) -> &str {
-        &self.0[index]
+        &self.tokens[index]
+    }
+
+    /// Returns the typed value of the token of the specified index, if the token is a variable
+    /// holding a [PolarValue].
+    pub fn value(&self, index: usize) -> Option<&PolarValue> {
+        self.values.get(&index)
+    }
+
+    /// Returns true if the token of the specified index is an operator.
+    pub fn is_operator(&self, index: usize) -> bool {
+        self.operators.contains(&index)
+    }
+
+    /// Returns true if the token of the specified index is the output of a command substitution
+    /// that was the whole token, e.g., `$(/feeds/latest)`.
+    pub fn is_substitution(&self, index: usize) -> bool {
+        self.substitutions.contains(&index)
+    }
+
+    /// Returns true if the token of the specified index was neither in quotes nor expanded.
+    pub fn is_literal(&self, index: usize) -> bool {
+        !self.expanded.contains(&index)
+    }
+
+    /// Adds a token, with its typed value.
+    pub(crate) fn push(&mut self, token: String, value: Option<PolarValue>) {
+        if let Some(value) = value {
+            self.values.insert(self.tokens.len(), value);
+        }
+        self.tokens.push(token);
+    }
+
+    /// Returns the tokens for the specified indices.
+    pub(crate) fn slice(&self, start: usize, end: usize) -> Tokens {
+        Tokens {
+            tokens: self.tokens[start..end].to_vec(),
+            values: self.values.iter()
+                .filter(|(i, _)| **i >= start && **i < end)
+                .map(|(i, value)| (i - start, value.clone()))
+                .collect(),
+            operators: self.operators.iter()
+                .filter(|i| **i >= start && **i < end)
+                .map(|i| i - start)
+                .collect(),
+            substitutions: self.substitutions.iter()
+                .filter(|i| **i >= start && **i < end)
+                .map(|i| i - start)
+                .collect(),
+            expanded: self.expanded.iter()
+                .filter(|i| **i >= start && **i < end)
+                .map(|i| i - start)
+                .collect(),
+        }
     }
 
     /// Combines all tokens into a string.
@@ -96,7 +206,7 @@ impl Tokens {
     pub fn tokens_substring(&self, start: usize, end: usize) -> String {
         let mut str = "".to_owned();
         let mut first = true;
-        for token in &self.0[start..end] {
+        for token in &self.tokens[start..end] {
             if !first {
                 str.push(' ');
             }
@@ -122,61 +232,97 @@ impl fmt::Display for Tokens {
     }
 }
 
-pub(crate) fn lex_command<'a>(user_context: &UserContext, io_context: &mut IoContext<'a>)
+/// Executes the command of a command substitution, `$(...)`, with the user context and returns its
+/// output.
+pub(crate) type Substitute<'s> = dyn FnMut(&str, &UserContext) -> Result<String, ShellError> + 's;
+
+/// Lexes the next command.
+///
+/// The user context is mutable, as expanding `${var:=default}` sets the variable.
+pub(crate) fn lex_command<'a>(user_context: &mut UserContext,
+                              io_context: &mut IoContext<'a>,
+                              substitute: &mut Substitute)
                               -> Option<Result<Tokens, LexerError>> {
     let mut in_quotes = false;
     let mut in_comment = false;
     let mut in_backslash = false;
+    let mut in_single_quotes = false;
+    let mut escape = String::new();
+    let mut substitution = Substitution::default();
+    let mut after_operator: Option<char> = None;
+    let mut after_semicolon = false;
+    let mut semicolon: Option<Semicolon> = None;
     let mut token = String::new();
-    let mut tokens: Vec<String> = Vec::new();
+    let mut tokens = Tokens::new(vec![]);
     let mut token_cols = 0;
 
     io_context.col = 1;
     io_context.line += 1;
+    io_context.clear_raw();
 
     loop {
-        match io_context.next_byte() {
-            Ok(byte) => {
-                match byte {
+        match io_context.next_char() {
+            Ok(c) => {
+                match c {
                     None => {
                         // end of file
-                        if in_quotes {
+                        if substitution.depth > 0 {
+                            return Some(Err(substitution.unterminated()));
+                        } else if in_quotes || in_single_quotes {
                             return Some(Err(LexerError::UnterminatedQuote));
                         }
 
                         // add the last token
                         if !token.is_empty() {
-                            tokens.push(match expand(
-                                user_context, &token, in_quotes) {
-                                Ok(v) => v,
-                                Err(e) => return Some(Err(e))
-                            });
+                            if let Err(e) = tokens.push_token(
+                                user_context, &token, in_quotes, substitute) {
+                                return Some(Err(e));
+                            }
                             token.clear();
                             io_context.col += token_cols;
                         }
 
                         if tokens.len() > 0 {
-                            let group = Tokens(tokens);
-                            return Some(Ok(group));
+                            tokens.split_semicolon(user_context, semicolon.take());
+                            return Some(Ok(tokens));
                         } else {
                             return None;
                         }
                     }
                     Some(c) => {
                         token_cols += 1;
+                        let follows_operator = after_operator.take();
+                        if std::mem::take(&mut after_semicolon) {
+                            if c.is_ascii_alphabetic() {
+                                // the semicolon may be followed by a keyword, e.g., "b;do"
+                                if let Some(semicolon) = &mut semicolon {
+                                    semicolon.keyword.push(c);
+                                }
+                                after_semicolon = true;
+                            } else if !(c.is_ascii_whitespace() || c == '#' || c == '>' || c == '|') {
+                                // the semicolon does not end the token, e.g., "a;1"
+                                semicolon = None;
+                            }
+                        }
 
-                        if c == b'\n' {
+                        if substitution.depth > 0 && c != '\n' {
+                            // the substituted command is kept as is and lexed when it is executed
+                            substitution.next(c);
+                            token.push(c);
+                        } else if c == '\n' {
                             // end of line
-                            if in_quotes {
+                            if substitution.depth > 0 {
+                                return Some(Err(substitution.unterminated()));
+                            } else if in_quotes || in_single_quotes {
                                 return Some(Err(LexerError::UnterminatedQuote));
                             }
 
                             // add the last token
                             if !token.is_empty() {
-                                tokens.push(match expand(user_context, &token, in_quotes) {
-                                    Ok(v) => v,
-                                    Err(e) => return Some(Err(e))
-                                });
+                                if let Err(e) = tokens.push_token(
+                                    user_context, &token, in_quotes, substitute) {
+                                    return Some(Err(e));
+                                }
                                 token.clear();
                                 io_context.col += token_cols;
                                 token_cols = 0;
@@ -184,8 +330,8 @@ pub(crate) fn lex_command<'a>(user_context: &UserContext, io_context: &mut IoCon
 
                             // continue to the next line if the last character is a backslash
                             if !in_backslash && tokens.len() > 0 {
-                                let group = Tokens(tokens);
-                                return Some(Ok(group));
+                                tokens.split_semicolon(user_context, semicolon.take());
+                                return Some(Ok(tokens));
                             }
 
                             io_context.line += 1;
@@ -194,63 +340,123 @@ pub(crate) fn lex_command<'a>(user_context: &UserContext, io_context: &mut IoCon
                             in_comment = false;
                             in_backslash = false;
                         } else if !in_comment {
-                            if in_backslash {
+                            if in_single_quotes {
+                                if c == '\'' {
+                                    // end single quotes, the token is not expanded
+                                    if let Err(e) = tokens.push_token(
+                                        user_context, &token, true, substitute) {
+                                        return Some(Err(e));
+                                    }
+                                    token.clear();
+                                    io_context.col += token_cols;
+                                    token_cols = 0;
+                                    in_single_quotes = false;
+                                } else {
+                                    push_literal(&mut token, c);
+                                }
+                            } else if !escape.is_empty() {
+                                // hexadecimal or unicode escape, \xNN or \u{NNNN}
+                                escape.push(c);
+                                match escaped_char(&escape) {
+                                    Ok(Some(escaped)) => {
+                                        push_literal(&mut token, escaped);
+                                        escape.clear();
+                                    }
+                                    Ok(None) => {}
+                                    Err(e) => return Some(Err(e))
+                                }
+                            } else if in_backslash {
                                 if !in_quotes {
                                     return Some(Err(invalid_escaped(c)));
                                 }
 
                                 // special characters that are escaped
-                                if c == b'n' {
-                                    token.push('\n');
-                                } else if c == b'\\' {
-                                    token.push('\\');
-                                } else if c == b'"' {
-                                    token.push('"');
-                                } else {
-                                    return Some(Err(invalid_escaped(c)));
+                                match c {
+                                    'n' => token.push('\n'),
+                                    't' => token.push('\t'),
+                                    'r' => token.push('\r'),
+                                    '0' => token.push('\0'),
+                                    '\\' => token.push('\\'),
+                                    '"' => token.push('"'),
+                                    'x' | 'u' => escape.push(c),
+                                    _ => return Some(Err(invalid_escaped(c)))
                                 }
 
                                 in_backslash = false;
-                            } else if c == b'\\' {
+                            } else if c == '\'' && !in_quotes {
+                                // start single quotes, e.g., 'cost: $5'
+                                in_single_quotes = true;
+                            } else if c == '\\' {
                                 // escape the next character or continue to the next line
                                 in_backslash = true;
-                            } else if c == b'"' {
+                            } else if c == '"' {
                                 if in_quotes {
                                     // end quotes
                                     // include zero length tokens
-                                    tokens.push(match expand(user_context, &token, in_quotes) {
-                                        Ok(v) => v,
-                                        Err(e) => return Some(Err(e))
-                                    });
+                                    if let Err(e) = tokens.push_token(
+                                        user_context, &token, in_quotes, substitute) {
+                                        return Some(Err(e));
+                                    }
                                     token.clear();
                                     io_context.col += token_cols;
                                     token_cols = 0;
                                 }
                                 in_quotes = !in_quotes
-                            } else if c == b'#' && !in_quotes {
+                            } else if c == '#' && !in_quotes {
                                 // start of comment
                                 // add the last token
                                 if !token.is_empty() {
-                                    tokens.push(match expand(user_context, &token, in_quotes) {
-                                        Ok(v) => v,
-                                        Err(e) => return Some(Err(e))
-                                    });
+                                    if let Err(e) = tokens.push_token(
+                                        user_context, &token, in_quotes, substitute) {
+                                        return Some(Err(e));
+                                    }
                                     token.clear();
                                     io_context.col += token_cols;
                                     token_cols = 0;
                                 }
 
                                 in_comment = true;
+                            } else if c == ';' && !in_quotes {
+                                // a semicolon is only a separator before "then" or "do", e.g.,
+                                // "if $foo == 1; then", so remember the token it is in
+                                semicolon = Some(Semicolon {
+                                    index: tokens.len(),
+                                    token: token.clone(),
+                                    keyword: String::new(),
+                                });
+                                token.push(c);
+                                after_semicolon = true;
+                            } else if (c == '>' || c == '|') && !in_quotes {
+                                // an operator is always its own token, e.g., "ls>a.txt"
+                                if !token.is_empty() {
+                                    if let Err(e) = tokens.push_token(
+                                        user_context, &token, in_quotes, substitute) {
+                                        return Some(Err(e));
+                                    }
+                                    token.clear();
+                                    io_context.col += token_cols;
+                                    token_cols = 0;
+                                }
+
+                                // two adjacent operators are one operator, e.g., ">>" or "||"
+                                let operator = c.to_string();
+                                if follows_operator == Some(c)
+                                    && *tokens.tokens.last().unwrap() == operator {
+                                    tokens.tokens.last_mut().unwrap().push(c);
+                                } else {
+                                    tokens.push_operator(&operator);
+                                }
+                                after_operator = Some(c);
                             } else if c.is_ascii_whitespace() {
                                 if in_quotes {
                                     // include all whitespace in quotes
-                                    token.push(c as char);
+                                    token.push(c);
                                 } else if !token.is_empty() {
                                     // end the current token
-                                    tokens.push(match expand(user_context, &token, in_quotes) {
-                                        Ok(v) => v,
-                                        Err(e) => return Some(Err(e))
-                                    });
+                                    if let Err(e) = tokens.push_token(
+                                        user_context, &token, in_quotes, substitute) {
+                                        return Some(Err(e));
+                                    }
                                     token.clear();
                                     io_context.col += token_cols;
                                     token_cols = 0;
@@ -258,20 +464,346 @@ pub(crate) fn lex_command<'a>(user_context: &UserContext, io_context: &mut IoCon
                                 // otherwise, ignore whitespace
                             } else {
                                 // add to the current token
-                                token.push(c as char);
+                                if (c == '(' || c == '{') && is_unescaped_dollar(&token) {
+                                    // start of a command substitution, $(...), or parameter
+                                    // expansion, ${...}
+                                    substitution.depth = 1;
+                                    substitution.braces = c == '{';
+                                }
+                                token.push(c);
                             }
                         }
                     }
                 }
             }
             Err(e) => {
-                return Some(Err(LexerError::IoError(e)));
+                return Some(Err(e));
             }
         }
     }
 }
 
-fn expand(context: &UserContext, token: &str, in_quotes: bool) -> Result<String, LexerError> {
+impl Tokens {
+    /// Splits a semicolon into its own token, if it is followed only by "then" or "do", e.g.,
+    /// "if $foo == 1; then" or "for x in a b;do".
+    fn split_semicolon(&mut self, context: &UserContext, semicolon: Option<Semicolon>) {
+        let (index, raw, keyword) = match semicolon {
+            Some(Semicolon { index, token, keyword }) => (index, token, keyword),
+            None => return
+        };
+        if index + 1 == self.len() && matches!(keyword.as_str(), "then" | "do") {
+            let token = &mut self.tokens[index];
+            token.truncate(token.len() - keyword.len());
+            self.tokens.push(keyword);
+        } else if !(keyword.is_empty() && index + 2 == self.len()
+            && matches!(self.get(index + 1), "then" | "do")) {
+            return;
+        }
+
+        let token = &mut self.tokens[index];
+        token.pop();
+        if raw.is_empty() {
+            token.push(';');
+        } else {
+            // the token may expand to an empty string, e.g., "$empty; then"
+            self.tokens.insert(index + 1, ";".to_owned());
+            if self.expanded.remove(&(index + 1)) {
+                self.expanded.insert(index + 2);
+            }
+            // the token may now be only a variable or a command substitution, e.g., "$list; do"
+            self.mark(context, index, &raw);
+        }
+    }
+
+    /// Keeps the typed value of an unquoted token that is only a variable holding a [PolarValue],
+    /// and marks a token that is only a command substitution.
+    fn mark(&mut self, context: &UserContext, index: usize, token: &str) {
+        let var = token.strip_prefix("${").and_then(|t| t.strip_suffix('}'))
+            .or_else(|| token.strip_prefix('$'));
+        if let Some(value) = var.and_then(|var| context.get_polar_value(var)) {
+            self.values.insert(index, value.clone());
+        }
+
+        let command = token.strip_prefix("$(").map(|t| substituted_command(&mut t.chars()));
+        if matches!(command, Some(Ok(command)) if command.len() + 3 == token.len()) {
+            self.substitutions.insert(index);
+        }
+    }
+
+    fn push_operator(&mut self, operator: &str) {
+        self.operators.insert(self.tokens.len());
+        self.tokens.push(operator.to_owned());
+    }
+
+    /// Expands and adds a token.
+    /// The typed value of a token that is only a variable holding a [PolarValue] is kept.
+    fn push_token(&mut self,
+                  context: &mut UserContext,
+                  token: &str,
+                  in_quotes: bool,
+                  substitute: &mut Substitute) -> Result<(), LexerError> {
+        if !in_quotes {
+            self.mark(context, self.tokens.len(), token);
+        }
+        if in_quotes || token.contains('$') {
+            self.expanded.insert(self.tokens.len());
+        }
+        self.tokens.push(expand(context, token, in_quotes, substitute)?);
+        Ok(())
+    }
+}
+
+/// An unquoted semicolon that may separate a block statement from its keyword, e.g.,
+/// "if $foo == 1; then".
+struct Semicolon {
+    /// The index of the token the semicolon is in.
+    index: usize,
+    /// The raw token before the semicolon.
+    token: String,
+    /// The letters that follow the semicolon in the token, e.g., "do" in "b;do".
+    keyword: String,
+}
+
+/// Tracks the nested parentheses and quotes of a command substitution, `$(...)`, so the end of the
+/// substituted command can be found.
+/// The nested curly brackets of a parameter expansion, `${...}`, are tracked the same way.
+#[derive(Default)]
+struct Substitution {
+    depth: usize,
+    in_quotes: bool,
+    in_single_quotes: bool,
+    in_backslash: bool,
+    braces: bool,
+}
+
+impl Substitution {
+    /// Updates the state with the next character of the substituted command.
+    fn next(&mut self, c: char) {
+        if self.in_backslash {
+            self.in_backslash = false;
+        } else if self.in_single_quotes {
+            self.in_single_quotes = c != '\'';
+        } else if c == '\\' {
+            self.in_backslash = true;
+        } else if c == '\'' && !self.in_quotes {
+            self.in_single_quotes = true;
+        } else if c == '"' {
+            self.in_quotes = !self.in_quotes;
+        } else if !self.in_quotes && c == self.open() {
+            self.depth += 1;
+        } else if !self.in_quotes && c == self.close() {
+            self.depth -= 1;
+        }
+    }
+
+    fn open(&self) -> char {
+        if self.braces { '{' } else { '(' }
+    }
+
+    fn close(&self) -> char {
+        if self.braces { '}' } else { ')' }
+    }
+
+    /// The error when the line ends before the substitution.
+    fn unterminated(&self) -> LexerError {
+        if self.braces {
+            LexerError::InvalidVariableFormat
+        } else {
+            LexerError::UnterminatedCommandSubstitution
+        }
+    }
+}
+
+/// Returns true if the token ends with a `$` that is not escaped by another `$`.
+fn is_unescaped_dollar(token: &str) -> bool {
+    token.chars().rev().take_while(|c| *c == '$').count() % 2 == 1
+}
+
+/// Reads the substituted command up to the closing parenthesis of a command substitution.
+fn substituted_command(iterator: &mut Chars) -> Result<String, LexerError> {
+    let mut substitution = Substitution { depth: 1, ..Substitution::default() };
+    let mut command = String::new();
+    for c in iterator {
+        substitution.next(c);
+        if substitution.depth == 0 {
+            return Ok(command);
+        }
+        command.push(c);
+    }
+    Err(LexerError::UnterminatedCommandSubstitution)
+}
+
+/// Returns the expression of an arithmetic expansion, `$((...))`, if the substituted command is
+/// wholly enclosed in parentheses.
+fn arithmetic_expression(command: &str) -> Option<&str> {
+    let inner = command.strip_prefix('(')?.strip_suffix(')')?;
+    let mut depth = 0;
+    for c in inner.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return None,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    Some(inner)
+}
+
+/// Reads the parameter up to the closing curly bracket of a parameter expansion, `${...}`.
+fn braced_parameter(iterator: &mut Chars) -> Result<String, LexerError> {
+    let mut depth = 1;
+    let mut parameter = String::new();
+    for c in iterator {
+        if c == '{' {
+            depth += 1;
+        } else if c == '}' {
+            depth -= 1;
+            if depth == 0 {
+                return Ok(parameter);
+            }
+        }
+        parameter.push(c);
+    }
+    Err(LexerError::InvalidVariableFormat)
+}
+
+/// Expands a parameter, `${...}`, which is a variable or argument followed by an optional modifier:
+/// - `${var:-word}`: the word if the variable is unset or empty
+/// - `${var:=word}`: the word if the variable is unset or empty, which also sets the variable
+/// - `${var:?message}`: an error with the message if the variable is unset or empty
+/// - `${var:+word}`: the word if the variable is set and not empty
+/// - `${#var}`: the number of characters in the value
+/// - `${var#pattern}`, `${var##pattern}`: removes the shortest or longest matching prefix
+/// - `${var%pattern}`, `${var%%pattern}`: removes the shortest or longest matching suffix
+/// - `${var^}`, `${var^^}`: converts the first or all characters to uppercase
+/// - `${var,}`, `${var,,}`: converts the first or all characters to lowercase
+///
+/// Words are expanded only if they are used.
+/// Patterns can include `*` for any characters and `?` for any single character.
+fn expand_parameter(context: &mut UserContext, parameter: &str, substitute: &mut Substitute)
+                    -> Result<String, LexerError> {
+    if let Some(name) = parameter.strip_prefix('#') {
+        if !is_parameter_name(name) {
+            return Err(LexerError::InvalidVariableFormat);
+        }
+        return match parameter_value(context, name) {
+            Some(value) => Ok(value.chars().count().to_string()),
+            None => Err(LexerError::UnknownVariable(name.to_owned()))
+        };
+    }
+
+    let name_len = if parameter.starts_with(|c: char| c.is_ascii_digit()) {
+        parameter.find(|c: char| !c.is_ascii_digit())
+    } else if parameter.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        parameter.find(|c: char| !c.is_alphanumeric() && c != '_')
+    } else {
+        return Err(LexerError::InvalidVariableFormat);
+    }.unwrap_or(parameter.len());
+    let (name, modifier) = parameter.split_at(name_len);
+    let value = parameter_value(context, name).map(|value| value.to_owned());
+    let set = value.as_ref().is_some_and(|value| !value.is_empty());
+
+    let unknown = || LexerError::UnknownVariable(name.to_owned());
+    let mut word = |context: &mut UserContext, start: usize|
+        expand(context, &modifier[start..], true, substitute);
+
+    if modifier.is_empty() {
+        value.ok_or_else(unknown)
+    } else if modifier.starts_with(":-") {
+        if set { Ok(value.unwrap()) } else { word(context, 2) }
+    } else if modifier.starts_with(":=") {
+        if set {
+            Ok(value.unwrap())
+        } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+            // arguments cannot be assigned
+            Err(LexerError::InvalidVariableFormat)
+        } else {
+            let word = word(context, 2)?;
+            context.set_value(name, &word);
+            Ok(word)
+        }
+    } else if modifier.starts_with(":?") {
+        if set {
+            Ok(value.unwrap())
+        } else {
+            let message = word(context, 2)?;
+            Err(LexerError::ParameterNotSet {
+                var: name.to_owned(),
+                message: if message.is_empty() {
+                    "parameter null or not set".to_owned()
+                } else {
+                    message
+                },
+            })
+        }
+    } else if modifier.starts_with(":+") {
+        if set { word(context, 2) } else { Ok(String::new()) }
+    } else if modifier.starts_with('#') || modifier.starts_with('%') {
+        let value = value.ok_or_else(unknown)?;
+        let longest = modifier[1..].starts_with(&modifier[..1]);
+        let pattern: Vec<char> = word(context, if longest { 2 } else { 1 })?.chars().collect();
+        let chars: Vec<char> = value.chars().collect();
+        let mut lengths: Vec<usize> = (0..=chars.len()).collect();
+        if longest {
+            lengths.reverse();
+        }
+        Ok(if modifier.starts_with('#') {
+            lengths.into_iter()
+                .find(|len| glob_matches(&pattern, &chars[..*len]))
+                .map_or(value.clone(), |len| chars[len..].iter().collect())
+        } else {
+            lengths.into_iter()
+                .find(|len| glob_matches(&pattern, &chars[chars.len() - len..]))
+                .map_or(value.clone(), |len| chars[..chars.len() - len].iter().collect())
+        })
+    } else if let Some(case) = ["^^", "^", ",,", ","].iter().find(|case| modifier == **case) {
+        let value = value.ok_or_else(unknown)?;
+        let convert = |s: &str| if case.starts_with('^') {
+            s.to_uppercase()
+        } else {
+            s.to_lowercase()
+        };
+        Ok(if case.len() == 2 {
+            convert(&value)
+        } else {
+            let mut chars = value.chars();
+            chars.next().map_or(String::new(), |first| {
+                convert(&first.to_string()) + chars.as_str()
+            })
+        })
+    } else {
+        Err(LexerError::InvalidVariableFormat)
+    }
+}
+
+/// Returns true if the name is a variable (e.g., `foo`) or an argument (e.g., `1`).
+fn is_parameter_name(name: &str) -> bool {
+    !name.is_empty() && (name.chars().all(|c| c.is_ascii_digit())
+        || (name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_')))
+}
+
+/// Returns the value of a variable or argument.
+fn parameter_value<'c>(context: &'c UserContext, name: &str) -> Option<&'c str> {
+    match name.parse::<usize>() {
+        Ok(argument) => context.get_argument(argument).map(|arg| arg.as_str()),
+        Err(_) => context.get_value(name).map(|value| value.as_str())
+    }
+}
+
+/// Returns true if the text matches the pattern, where `*` matches any characters and `?` matches
+/// any single character.
+pub(crate) fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|i| glob_matches(rest, &text[i..])),
+        Some(('?', rest)) => !text.is_empty() && glob_matches(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_matches(rest, &text[1..]),
+    }
+}
+
+fn expand(context: &mut UserContext, token: &str, in_quotes: bool, substitute: &mut Substitute)
+          -> Result<String, LexerError> {
     let mut first_char = true;
     let mut in_replace = false;
     let mut in_replace_first_char = false;
@@ -279,15 +811,14 @@ fn expand(context: &UserContext, token: &str, in_quotes: bool) -> Result<String,
     let mut variable = String::new();
     let mut expanded = String::new();
     let mut iterator = token.chars();
-    let mut has_curly_brackets = false;
 
     loop {
         match iterator.next() {
             None => {
                 // end of token
                 if in_replace {
-                    if in_replace_first_char || has_curly_brackets {
-                        // $ at the end of a line or missing the closing curly bracket ${foo}
+                    if in_replace_first_char {
+                        // $ at the end of a line
                         return Err(LexerError::InvalidVariableFormat);
                     } else if variable.is_empty() {
                         // end of argument
@@ -312,14 +843,15 @@ fn expand(context: &UserContext, token: &str, in_quotes: bool) -> Result<String,
                     if in_replace_first_char {
                         // first char decides whether we are in an argument or variable
                         if c == '$' {
-                            if has_curly_brackets {
-                                return Err(LexerError::InvalidVariableFormat);
-                            }
-
                             // double dollar sign is just a dollar sign character
                             expanded.push(c);
                             in_replace = false;
                             in_replace_first_char = false;
+                        } else if c == '?' {
+                            // exit status of the last command
+                            expanded.push_str(&context.exit_status().to_string());
+                            in_replace = false;
+                            in_replace_first_char = false;
                         } else if c.is_numeric() {
                             // arguments are all numeric
                             argument = usize::try_from(c.to_digit(10).unwrap()).unwrap();
@@ -328,9 +860,30 @@ fn expand(context: &UserContext, token: &str, in_quotes: bool) -> Result<String,
                             // variables start with an alphabetic character and then alphanumeric
                             variable.push(c);
                             in_replace_first_char = false;
+                        } else if c == '(' {
+                            let command = substituted_command(&mut iterator)?;
+                            match arithmetic_expression(&command) {
+                                Some(expression) => {
+                                    // arithmetic expansion, $((...))
+                                    let expression = expand(context, expression, true, substitute)?;
+                                    expanded.push_str(&arithmetic::evaluate(context, &expression)?);
+                                }
+                                None => {
+                                    // command substitution, $(...)
+                                    let output = substitute(&command, context).map_err(
+                                        |e| LexerError::CommandSubstitutionFailure(Box::new(e)))?;
+                                    expanded.push_str(&output);
+                                }
+                            }
+                            in_replace = false;
+                            in_replace_first_char = false;
                         } else if c == '{' {
-                            // skip over curly bracket, ${foo}
-                            has_curly_brackets = true;
+                            // parameter expansion, ${foo} or ${foo:-default}
+                            let parameter = braced_parameter(&mut iterator)?;
+                            expanded.push_str(
+                                &expand_parameter(context, &parameter, substitute)?);
+                            in_replace = false;
+                            in_replace_first_char = false;
                         } else {
                             return Err(LexerError::InvalidVariableFormat);
                         }
@@ -376,13 +929,7 @@ fn expand(context: &UserContext, token: &str, in_quotes: bool) -> Result<String,
                         }
 
                         if did_expansion {
-                            if has_curly_brackets {
-                                if c != '}' {
-                                    return Err(LexerError::InvalidVariableFormat);
-                                }
-                                in_replace = false;
-                                has_curly_brackets = false;
-                            } else if c == '$' {
+                            if c == '$' {
                                 in_replace_first_char = true;
                             } else {
                                 in_replace = false;
@@ -408,9 +955,47 @@ fn expand(context: &UserContext, token: &str, in_quotes: bool) -> Result<String,
     }
 }
 
-fn invalid_escaped(character: u8) -> LexerError {
+/// Adds a character to a token that is not expanded, e.g., `$` is added as `$$`.
+fn push_literal(token: &mut String, c: char) {
+    if c == '$' {
+        token.push('$');
+    }
+    token.push(c);
+}
+
+/// Returns the character of a hexadecimal (`\xNN`) or unicode (`\u{NNNN}`) escape, or `None` if
+/// more characters of the escape are needed.
+fn escaped_char(escape: &str) -> Result<Option<char>, LexerError> {
+    let invalid = || LexerError::InvalidEscapedCharacterFormat(format!("\\{}", escape));
+    if let Some(hex) = escape.strip_prefix('x') {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        } else if hex.len() < 2 {
+            return Ok(None);
+        }
+        // like Rust, only ASCII characters can be escaped with \xNN
+        match u8::from_str_radix(hex, 16) {
+            Ok(byte) if byte.is_ascii() => Ok(Some(byte as char)),
+            _ => Err(invalid())
+        }
+    } else if escape == "u" {
+        Ok(None)
+    } else {
+        let hex = escape.strip_prefix("u{").ok_or_else(invalid)?;
+        match hex.strip_suffix('}') {
+            None if hex.len() <= 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => Ok(None),
+            Some(hex) if !hex.is_empty() => u32::from_str_radix(hex, 16).ok()
+                .and_then(char::from_u32)
+                .map(Some)
+                .ok_or_else(invalid),
+            _ => Err(invalid())
+        }
+    }
+}
+
+fn invalid_escaped(character: char) -> LexerError {
     let mut str = "\\".to_owned();
-    str.push(character as char);
+    str.push(character);
     LexerError::InvalidEscapedCharacterFormat(str)
 }
 
@@ -420,12 +1005,18 @@ mod tests {
     use std::io::{Cursor};
     use crate::command::context::{UserContext, IoContext};
     use crate::command::lexer::{lex_command, LexerError, Tokens};
+    use crate::command::oso::PolarValue;
+    use crate::command::ShellError;
 
-    fn lex_all_commands(context: &UserContext, source: &mut IoContext)
+    fn substitute(command: &str, _: &UserContext) -> Result<String, ShellError> {
+        Ok(format!("<{}>", command))
+    }
+
+    fn lex_all_commands(context: &mut UserContext, source: &mut IoContext)
                         -> Result<Vec<Tokens>, LexerError> {
         let mut tokens: Vec<Tokens> = vec![];
         loop {
-            match lex_command(context, source) {
+            match lex_command(context, source, &mut substitute) {
                 None => return Ok(tokens),
                 Some(result) => tokens.push(result?)
             }
@@ -449,7 +1040,7 @@ mod tests {
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
 
         loop {
-            match lex_command(&context, &mut io) {
+            match lex_command(&mut context, &mut io, &mut substitute) {
                 Some(result) => println!("{}", result.unwrap()),
                 None => return
             }
@@ -465,9 +1056,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let result = lex_command(&context, &mut io);
+        let result = lex_command(&mut context, &mut io, &mut substitute);
 
         assert_eq!(None, result);
     }
@@ -478,9 +1069,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let result = lex_command(&context, &mut io).unwrap().err().unwrap();
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().err().unwrap();
 
         assert_eq!(LexerError::UnterminatedQuote, result);
     }
@@ -492,9 +1083,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let result = lex_command(&context, &mut io).unwrap().err().unwrap();
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().err().unwrap();
 
         assert_eq!(LexerError::UnterminatedQuote, result);
     }
@@ -505,9 +1096,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let result = lex_command(&context, &mut io).unwrap().err().unwrap();
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().err().unwrap();
 
         assert_eq!(LexerError::InvalidEscapedCharacterFormat("\\^".to_owned()), result);
     }
@@ -518,9 +1109,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let commands = lex_command(&context, &mut io).unwrap();
+        let commands = lex_command(&mut context, &mut io, &mut substitute).unwrap();
 
         assert_eq!("bar \n me \" now \\ abc ", commands.unwrap().get(1));
     }
@@ -531,9 +1122,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let commands: Vec<String> = lex_all_commands(&context, &mut io)
+        let commands: Vec<String> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().join("_/_")).collect();
 
         assert_eq!(1, commands.len());
@@ -548,9 +1139,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let commands: Vec<String> = lex_all_commands(&context, &mut io)
+        let commands: Vec<String> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().join("_/_")).collect();
 
         assert_eq!(3, commands.len());
@@ -571,9 +1162,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let commands: Vec<String> = lex_all_commands(&context, &mut io)
+        let commands: Vec<String> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().join("_/_")).collect();
 
         assert_eq!(3, commands.len());
@@ -588,9 +1179,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let commands: Vec<String> = lex_all_commands(&context, &mut io)
+        let commands: Vec<String> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().join("_/_")).collect();
 
         assert_eq!(1, commands.len());
@@ -605,9 +1196,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let commands: Vec<String> = lex_all_commands(&context, &mut io)
+        let commands: Vec<String> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().join("_/_")).collect();
 
         assert_eq!(2, commands.len());
@@ -622,9 +1213,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         let tokens = &commands[0];
@@ -640,9 +1231,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let result = lex_command(&context, &mut io)
+        let result = lex_command(&mut context, &mut io, &mut substitute)
             .unwrap().err().unwrap();
 
         assert_eq!(LexerError::InvalidEscapedCharacterFormat("\\\"".to_owned()), result);
@@ -656,9 +1247,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let commands: Vec<String> = lex_all_commands(&context, &mut io)
+        let commands: Vec<String> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().join("_/_")).collect();
 
         assert_eq!(2, commands.len());
@@ -674,9 +1265,9 @@ mod tests {
         let mut cursor = Cursor::new(text.as_bytes());
         let mut sink = io::sink();
         let mut io = IoContext::new("test", &mut cursor, &mut sink);
-        let context = UserContext::default();
+        let mut context = UserContext::default();
 
-        let commands: Vec<String> = lex_all_commands(&context, &mut io)
+        let commands: Vec<String> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().join("_/_")).collect();
 
         assert_eq!(3, commands.len());
@@ -685,6 +1276,30 @@ mod tests {
         assert_eq!("Jojo left his home_/_in_/_Tuscon, Arizona", commands[2]);
     }
 
+    #[test]
+    fn semicolon_before_then_or_do_is_a_separate_token() {
+        let text = "if $foo == bar; then
+        for x in a b;do
+        echo a;b;c; done
+        while true ;do
+        echo \"a;b\";";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+        context.set_value("foo", "bar");
+
+        let commands: Vec<String> = lex_all_commands(&mut context, &mut io)
+            .unwrap().iter().map(|r| r.all().join("_/_")).collect();
+
+        assert_eq!(5, commands.len());
+        assert_eq!("if_/_bar_/_==_/_bar_/_;_/_then", commands[0]);
+        assert_eq!("for_/_x_/_in_/_a_/_b_/_;_/_do", commands[1]);
+        assert_eq!("echo_/_a;b;c;_/_done", commands[2]);
+        assert_eq!("while_/_true_/_;_/_do", commands[3]);
+        assert_eq!("echo_/_a;b_/_;", commands[4]);
+    }
+
     #[test]
     fn one_position_argument() {
         let text = "$0";
@@ -694,7 +1309,7 @@ mod tests {
         let mut context = UserContext::default();
         context.add_argument("Jojo left his home");
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         assert_eq!("Jojo left his home", commands[0][0])
@@ -709,7 +1324,7 @@ mod tests {
         let mut context = UserContext::default();
         context.add_argument(" left his home");
 
-        let commands = lex_command(&context, &mut io)
+        let commands = lex_command(&mut context, &mut io, &mut substitute)
             .unwrap().err().unwrap();
 
         assert_eq!(LexerError::EscapedCharacterNotInQuotes, commands);
@@ -725,7 +1340,7 @@ mod tests {
         context.add_argument("Jojo");
         context.add_argument(" left his home");
 
-        let commands = lex_command(&context, &mut io)
+        let commands = lex_command(&mut context, &mut io, &mut substitute)
             .unwrap().err().unwrap();
 
         assert_eq!(LexerError::InvalidVariableFormat, commands);
@@ -741,7 +1356,7 @@ mod tests {
         context.add_argument("Jojo");
         context.add_argument(" left his home");
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         assert_eq!("Jojo left his home", commands[0][0]);
@@ -756,7 +1371,7 @@ mod tests {
         let mut context = UserContext::default();
         context.add_argument(" left his home");
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         assert_eq!("Jojo left his home", commands[0][0])
@@ -771,7 +1386,7 @@ mod tests {
         let mut context = UserContext::default();
         context.add_argument(" left his home");
 
-        let result = lex_command(&context, &mut io)
+        let result = lex_command(&mut context, &mut io, &mut substitute)
             .unwrap().err().unwrap();
 
         assert_eq!(LexerError::UnknownVariable("1".to_owned()), result);
@@ -788,7 +1403,7 @@ mod tests {
         context.add_argument("left");
         context.add_argument("his home");
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         assert_eq!("Jojo left", commands[0][0]);
@@ -804,7 +1419,7 @@ mod tests {
         let mut context = UserContext::default();
         context.add_argument("12");
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         assert_eq!("12345", commands[0][0]);
@@ -819,7 +1434,7 @@ mod tests {
         let mut context = UserContext::default();
         context.set_value("foo", "Jojo left his home");
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         assert_eq!("Jojo left his home", commands[0][0])
@@ -834,7 +1449,7 @@ mod tests {
         let mut context = UserContext::default();
         context.set_value("foo", " left his home");
 
-        let result = lex_command(&context, &mut io)
+        let result = lex_command(&mut context, &mut io, &mut substitute)
             .unwrap().err().unwrap();
 
         assert_eq!(LexerError::EscapedCharacterNotInQuotes, result);
@@ -849,7 +1464,7 @@ mod tests {
         let mut context = UserContext::default();
         context.set_value("foo", " left his home");
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         assert_eq!("Jojo left his home", commands[0][0])
@@ -864,7 +1479,7 @@ mod tests {
         let mut context = UserContext::default();
         context.add_argument(" left his home");
 
-        let result = lex_command(&context, &mut io)
+        let result = lex_command(&mut context, &mut io, &mut substitute)
             .unwrap().err().unwrap();
 
         assert_eq!(LexerError::UnknownVariable("1".to_owned()), result);
@@ -881,7 +1496,7 @@ mod tests {
         context.set_value("bar", "left");
         context.set_value("me", "his home");
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         assert_eq!("Jojo left", commands[0][0]);
@@ -898,7 +1513,7 @@ mod tests {
         context.set_value("foo", "Jojo left");
         context.set_value("bar", " his home");
 
-        let result = lex_command(&context, &mut io)
+        let result = lex_command(&mut context, &mut io, &mut substitute)
             .unwrap().err().unwrap();
 
         assert_eq!(LexerError::EscapedCharacterNotInQuotes, result);
@@ -914,7 +1529,7 @@ mod tests {
         context.set_value("foo", "Jojo left");
         context.set_value("bar", " his home");
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         assert_eq!("Jojo left his home", commands[0][0]);
@@ -929,7 +1544,7 @@ mod tests {
         let mut context = UserContext::default();
         context.set_value("foo", "Jojo left ");
 
-        let commands: Vec<Vec<String>> = lex_all_commands(&context, &mut io)
+        let commands: Vec<Vec<String>> = lex_all_commands(&mut context, &mut io)
             .unwrap().iter().map(|r| r.all().clone()).collect();
 
         assert_eq!("Jojo left his home", commands[0][0]);
@@ -944,7 +1559,7 @@ mod tests {
         let mut context = UserContext::default();
         context.set_value("foo", "Jojo left ");
 
-        let result = lex_command(&context, &mut io)
+        let result = lex_command(&mut context, &mut io, &mut substitute)
             .unwrap().err().unwrap();
 
         assert_eq!(LexerError::InvalidVariableFormat, result);
@@ -959,9 +1574,547 @@ mod tests {
         let mut context = UserContext::default();
         context.set_value("f@oo", "Jojo left ");
 
-        let result = lex_command(&context, &mut io)
+        let result = lex_command(&mut context, &mut io, &mut substitute)
+            .unwrap().err().unwrap();
+
+        assert_eq!(LexerError::InvalidVariableFormat, result);
+    }
+
+    #[test]
+    fn command_substitution_is_one_token() {
+        let text = "echo $(/foo/add 1 2; echo \"a)b\" # (c) \\\" ) after";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let commands: Vec<String> = lex_all_commands(&mut context, &mut io)
+            .unwrap().iter().map(|r| r.all().join("_/_")).collect();
+
+        assert_eq!(1, commands.len());
+        assert_eq!("echo_/_</foo/add 1 2; echo \"a)b\" # (c) \\\" >_/_after", commands[0]);
+    }
+
+    #[test]
+    fn nested_command_substitution_is_one_substitution() {
+        let text = "$(echo $(pwd))";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["<echo $(pwd)>"], *result.all());
+        assert!(result.is_substitution(0));
+    }
+
+    #[test]
+    fn command_substitution_can_be_anywhere_in_string_when_inside_quotes() {
+        let text = "\"$foo: $(echo \"a b\")!\"";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+        context.set_value("foo", "bar");
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(&vec!["bar: <echo \"a b\">!".to_owned()], result.all());
+        assert!(!result.is_literal(0));
+    }
+
+    #[test]
+    fn command_substitution_outside_quotes_is_error() {
+        let text = "foo$(pwd)";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute)
+            .unwrap().err().unwrap();
+
+        assert_eq!(LexerError::EscapedCharacterNotInQuotes, result);
+    }
+
+    #[test]
+    fn unterminated_command_substitution_is_error() {
+        let text = "echo $(pwd
+        echo";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute)
+            .unwrap().err().unwrap();
+
+        assert_eq!(LexerError::UnterminatedCommandSubstitution, result);
+    }
+
+    #[test]
+    fn escaped_dollar_sign_does_not_start_command_substitution() {
+        let text = "\"$$(pwd)\"";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(&vec!["$(pwd)".to_owned()], result.all());
+        assert!(!result.is_literal(0));
+    }
+
+    #[test]
+    fn failed_command_substitution_is_error() {
+        let text = "echo $(pwd)";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+        let error = || ShellError::LexerError {
+            src: IoContext::new("inner", &mut io::empty(), &mut io::sink()).to_source_info(),
+            error: LexerError::UnterminatedQuote,
+        };
+
+        let mut failure = |_: &str, _: &UserContext| Err(error());
+        let result = lex_command(&mut context, &mut io, &mut failure)
             .unwrap().err().unwrap();
 
+        assert_eq!(LexerError::CommandSubstitutionFailure(Box::new(error())), result);
+    }
+
+    #[test]
+    fn variable_holding_polar_value_keeps_typed_value() {
+        let text = "echo $foo ${foo} \"$foo\" $bar";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+        context.set_polar_value("foo", PolarValue::List(vec![PolarValue::Integer(1)]), "[1]");
+        context.set_value("bar", "[1]");
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["echo", "[1]", "[1]", "[1]", "[1]"], *result.all());
+        assert_eq!(None, result.value(0));
+        assert_eq!(Some(&PolarValue::List(vec![PolarValue::Integer(1)])), result.value(1));
+        assert_eq!(Some(&PolarValue::List(vec![PolarValue::Integer(1)])), result.value(2));
+        assert_eq!(None, result.value(3));
+        assert_eq!(None, result.value(4));
+    }
+
+    #[test]
+    fn redirection_is_operator_token() {
+        let text = "echo foo>a.txt \">\" bar >> b.txt";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["echo", "foo", ">", "a.txt", ">", "bar", ">>", "b.txt"], *result.all());
+        assert!(result.is_operator(2));
+        assert!(!result.is_operator(4));
+        assert!(result.is_operator(6));
+    }
+
+    #[test]
+    fn separated_redirections_are_separate_operators() {
+        let text = "echo > >";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["echo", ">", ">"], *result.all());
+    }
+
+    #[test]
+    fn slice_of_tokens_keeps_values_and_operators() {
+        let text = "echo $foo > a.txt";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+        context.set_polar_value("foo", PolarValue::Integer(1), "1");
+
+        let result = lex_command(&mut context, &mut io, &mut substitute)
+            .unwrap().unwrap().slice(1, 3);
+
+        assert_eq!(vec!["1", ">"], *result.all());
+        assert_eq!(Some(&PolarValue::Integer(1)), result.value(0));
+        assert!(result.is_operator(1));
+    }
+
+    #[test]
+    fn quoted_and_expanded_tokens_are_not_literals() {
+        let text = "if ! \"&&\" '-z' $foo == foo; then";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+        context.set_value("foo", "&&");
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["if", "!", "&&", "-z", "&&", "==", "foo", ";", "then"], *result.all());
+        let literals: Vec<bool> = (0..result.len()).map(|i| result.is_literal(i)).collect();
+        assert_eq!(vec![true, true, false, false, false, true, true, true, true], literals);
+    }
+
+    #[test]
+    fn pipe_is_operator_token() {
+        let text = "a|b \"|\" c || d";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["a", "|", "b", "|", "c", "||", "d"], *result.all());
+        assert!(result.is_operator(1));
+        assert!(!result.is_operator(3));
+        assert!(result.is_operator(5));
+    }
+
+    #[test]
+    fn whole_command_substitution_is_marked() {
+        let text = "$(a) \"$(c)\" $(d)x $(f);do";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        let marked: Vec<bool> = (0..result.len()).map(|i| result.is_substitution(i)).collect();
+        assert_eq!(vec![true, false, false, true, false, false], marked);
+    }
+
+    #[test]
+    fn arithmetic_expansion() {
+        let text = "echo $((base + 2 * 3)) \"port: $(( ($base + 1) > 8000 ))\" \
+            \"${port}$((1.5 * 2))\"";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+        context.set_value("base", "8000");
+        context.set_value("port", "80");
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["echo", "8006", "port: 1", "803.0"], *result.all());
+    }
+
+    #[test]
+    fn arithmetic_expansion_is_not_command_substitution() {
+        let text = "echo $((a) + (b)) $( (pwd) )";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["echo", "<(a) + (b)>", "< (pwd) >"], *result.all());
+    }
+
+    #[test]
+    fn arithmetic_expansion_division_by_zero_is_error() {
+        let text = "echo $((10 / (base - 8000)))";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+        context.set_value("base", "8000");
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().err().unwrap();
+
+        assert_eq!(LexerError::DivisionByZero, result);
+    }
+
+    #[test]
+    fn arithmetic_expansion_overflow_is_error() {
+        let text = "echo $((9223372036854775807 * 2))";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().err().unwrap();
+
+        assert_eq!(LexerError::ArithmeticOverflow, result);
+    }
+
+    fn lex_parameters(context: &mut UserContext, text: &str) -> Result<Vec<String>, LexerError> {
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+
+        lex_command(context, &mut io, &mut substitute).unwrap().map(|tokens| tokens.all().clone())
+    }
+
+    #[test]
+    fn parameter_expansion_default_value() {
+        let mut context = UserContext::default();
+        context.set_value("host", "localhost");
+        context.set_value("empty", "");
+        context.add_argument("script");
+
+        let result = lex_parameters(&mut context,
+            "${host:-none} ${port:-80} ${empty:-$host} \"${port:-${host}}:\" ${1:-arg} \
+            ${port:-a b}")
+            .unwrap();
+
+        assert_eq!(vec!["localhost", "80", "localhost", "localhost:", "arg", "a b"], result);
+        assert_eq!(None, context.get_value("port"));
+    }
+
+    #[test]
+    fn parameter_expansion_assign_default_value() {
+        let mut context = UserContext::default();
+        context.set_value("host", "localhost");
+
+        let result = lex_parameters(&mut context, "${host:=none} ${port:=80} $port").unwrap();
+
+        assert_eq!(vec!["localhost", "80", "80"], result);
+        assert_eq!("localhost", context.get_value("host").unwrap());
+        assert_eq!("80", context.get_value("port").unwrap());
+    }
+
+    #[test]
+    fn parameter_expansion_assign_argument_is_error() {
+        let mut context = UserContext::default();
+
+        let result = lex_parameters(&mut context, "${1:=foo}").err().unwrap();
+
         assert_eq!(LexerError::InvalidVariableFormat, result);
     }
+
+    #[test]
+    fn parameter_expansion_error_if_not_set() {
+        let mut context = UserContext::default();
+        context.set_value("host", "localhost");
+
+        assert_eq!(vec!["localhost"], lex_parameters(&mut context, "${host:?no host}").unwrap());
+        assert_eq!(LexerError::ParameterNotSet {
+            var: "port".to_owned(),
+            message: "$port is required".to_owned(),
+        }, lex_parameters(&mut context, "\"${port:?$$port is required}\"").err().unwrap());
+        assert_eq!(LexerError::ParameterNotSet {
+            var: "port".to_owned(),
+            message: "parameter null or not set".to_owned(),
+        }, lex_parameters(&mut context, "${port:?}").err().unwrap());
+    }
+
+    #[test]
+    fn parameter_expansion_alternate_value() {
+        let mut context = UserContext::default();
+        context.set_value("debug", "1");
+        context.set_value("empty", "");
+
+        let result = lex_parameters(&mut context, "${debug:+-v} \"${empty:+-v}\" \"${foo:+-v}\"")
+            .unwrap();
+
+        assert_eq!(vec!["-v", "", ""], result);
+    }
+
+    #[test]
+    fn parameter_expansion_length() {
+        let mut context = UserContext::default();
+        context.set_value("name", "Mr. Burns");
+        context.add_argument("script");
+
+        assert_eq!(vec!["9", "6"], lex_parameters(&mut context, "${#name} ${#0}").unwrap());
+        assert_eq!(LexerError::UnknownVariable("foo".to_owned()),
+                   lex_parameters(&mut context, "${#foo}").err().unwrap());
+        assert_eq!(LexerError::InvalidVariableFormat,
+                   lex_parameters(&mut context, "${#name:-foo}").err().unwrap());
+    }
+
+    #[test]
+    fn parameter_expansion_remove_prefix_and_suffix() {
+        let mut context = UserContext::default();
+        context.set_value("path", "/feeds/nyse/aapl.json");
+
+        let result = lex_parameters(&mut context,
+            "${path#*/} ${path##*/} ${path%.*} ${path%%/n*} ${path#foo} ${path%?????}").unwrap();
+
+        assert_eq!(vec!["feeds/nyse/aapl.json", "aapl.json", "/feeds/nyse/aapl", "/feeds",
+                        "/feeds/nyse/aapl.json", "/feeds/nyse/aapl"], result);
+    }
+
+    #[test]
+    fn parameter_expansion_case_conversion() {
+        let mut context = UserContext::default();
+        context.set_value("name", "mR. bURNS");
+
+        let result = lex_parameters(&mut context, "${name^} ${name^^} ${name,} ${name,,}").unwrap();
+
+        assert_eq!(vec!["MR. bURNS", "MR. BURNS", "mR. bURNS", "mr. burns"], result);
+    }
+
+    #[test]
+    fn parameter_expansion_invalid_modifier_is_error() {
+        let mut context = UserContext::default();
+        context.set_value("name", "foo");
+
+        for text in ["${name:x}", "${name^x}", "${}", "${:-foo}", "${$}"] {
+            assert_eq!(LexerError::InvalidVariableFormat,
+                       lex_parameters(&mut context, text).err().unwrap(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn non_ascii_characters_are_lexed() {
+        let text = "echo \"héllo wörld\" €uro ${naïve:-日本} # コメント\n";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["echo", "héllo wörld", "€uro", "日本"], *result.all());
+    }
+
+    #[test]
+    fn columns_are_counted_by_character() {
+        let mut columns = vec![];
+        for text in ["ab \"cd", "é€ \"日本"] {
+            let mut cursor = Cursor::new(text.as_bytes());
+            let mut sink = io::sink();
+            let mut io = IoContext::new("test", &mut cursor, &mut sink);
+            let mut context = UserContext::default();
+
+            let result = lex_command(&mut context, &mut io, &mut substitute).unwrap();
+
+            assert_eq!(LexerError::UnterminatedQuote, result.err().unwrap());
+            columns.push(io.col);
+        }
+
+        assert_eq!(columns[0], columns[1]);
+    }
+
+    #[test]
+    fn invalid_utf8_is_error() {
+        let inputs: [(&[u8], Vec<u8>); 3] = [
+            (b"echo \xff", vec![0xff]),
+            (b"echo \xe2\x82", vec![0xe2, 0x82]),
+            (b"echo \xe2\x28\xa1", vec![0xe2, 0x28, 0xa1])];
+        for (text, bytes) in inputs {
+            let mut cursor = Cursor::new(text);
+            let mut sink = io::sink();
+            let mut io = IoContext::new("test", &mut cursor, &mut sink);
+            let mut context = UserContext::default();
+
+            let result = lex_command(&mut context, &mut io, &mut substitute).unwrap();
+
+            assert_eq!(LexerError::InvalidUtf8(bytes), result.err().unwrap());
+        }
+    }
+
+    #[test]
+    fn single_quotes_are_not_expanded() {
+        let text = r#"echo '$foo ${bar} $(pwd) $((1 + 2))' '"|>' 'a#b\n' '' "it's" x'$foo'"#;
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["echo", "$foo ${bar} $(pwd) $((1 + 2))", "\"|>", "a#b\\n", "", "it's",
+                        "x$foo"], *result.all());
+        assert!(!result.is_operator(2));
+    }
+
+    #[test]
+    fn single_quotes_in_comments_and_line_continuations() {
+        let text = "echo 'a  b' \\
+        'c' # it's a 'comment\n\
+        echo \"# not a comment\" # \"\\q\"";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let commands = lex_all_commands(&mut context, &mut io).unwrap();
+
+        assert_eq!(2, commands.len());
+        assert_eq!(vec!["echo", "a  b", "c"], *commands[0].all());
+        assert_eq!(vec!["echo", "# not a comment"], *commands[1].all());
+    }
+
+    #[test]
+    fn unterminated_single_quote_is_error() {
+        for text in ["echo 'abc", "echo 'abc\ndef'", "echo 'abc\\\ndef'"] {
+            let mut cursor = Cursor::new(text.as_bytes());
+            let mut sink = io::sink();
+            let mut io = IoContext::new("test", &mut cursor, &mut sink);
+            let mut context = UserContext::default();
+
+            let result = lex_command(&mut context, &mut io, &mut substitute).unwrap();
+
+            assert_eq!(LexerError::UnterminatedQuote, result.err().unwrap(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn single_quotes_in_command_substitution() {
+        let text = "echo $(echo ')(')";
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["echo", "<echo ')('>"], *result.all());
+    }
+
+    #[test]
+    fn escaped_characters_in_quotes() {
+        let text = r#"echo "a\tb\rc\0d" "\x41\x7e\x24foo" "\u{e9}\u{1F600}\u{0024}""#;
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io = IoContext::new("test", &mut cursor, &mut sink);
+        let mut context = UserContext::default();
+
+        let result = lex_command(&mut context, &mut io, &mut substitute).unwrap().unwrap();
+
+        assert_eq!(vec!["echo", "a\tb\rc\0d", "A~$foo", "é😀$"], *result.all());
+    }
+
+    #[test]
+    fn invalid_hexadecimal_and_unicode_escapes_are_errors() {
+        let escapes = [
+            (r#""\x8f""#, "\\x8f"),
+            (r#""\x4g""#, "\\x4g"),
+            (r#""\x4""#, "\\x4\""),
+            (r#""\u1234""#, "\\u1"),
+            (r#""\u{}""#, "\\u{}"),
+            (r#""\u{110000}""#, "\\u{110000}"),
+            (r#""\u{1234567}""#, "\\u{1234567"),
+            (r#""\q""#, "\\q")];
+        for (text, escape) in escapes {
+            let mut cursor = Cursor::new(text.as_bytes());
+            let mut sink = io::sink();
+            let mut io = IoContext::new("test", &mut cursor, &mut sink);
+            let mut context = UserContext::default();
+
+            let result = lex_command(&mut context, &mut io, &mut substitute).unwrap();
+
+            assert_eq!(LexerError::InvalidEscapedCharacterFormat(escape.to_owned()),
+                       result.err().unwrap(), "{}", text);
+        }
+    }
 }
\ No newline at end of file
@@ -1,4 +1,7 @@
+mod arithmetic;
+mod block;
 pub mod commands;
+mod condition;
 mod lexer;
 pub mod oso;
 mod registry;
@@ -13,8 +16,12 @@ pub use self::context::IoContext;
 pub use self::context::SourceInfo;
 pub use self::context::UserContext;
 pub use self::lexer::Tokens;
+pub use self::registry::FindFilter;
+pub use self::registry::NodeKind;
 pub use self::registry::PathSegment;
 pub use self::registry::Registry;
 pub use self::registry::RegistryError;
+pub use self::registry::SharedRegistry;
+pub use self::shell::ErrorReport;
 pub use self::shell::Shell;
 pub use self::shell::ShellError;
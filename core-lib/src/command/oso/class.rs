@@ -5,13 +5,16 @@ use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
 use super::{
-    FromPolarList, InvalidCallError, OsoError, ToPolar, ToPolarResult, PolarValue,
+    FromPolar, FromPolarList, InvalidCallError, OsoError, ToPolar, ToPolarResult, PolarValue,
     PolarIterator, TypeError, Host
 };
-use super::method::{Function, Method};
-use super::class_method::{AttributeGetter, ClassMethod, Constructor, InstanceMethod};
+use super::method::{Function, Method, MutMethod};
+use super::class_method::{
+    AttributeGetter, AttributeSetter, ClassMethod, Constructor, InstanceMethod
+};
 
 type Attributes = HashMap<&'static str, AttributeGetter>;
+type AttributeSetters = HashMap<&'static str, AttributeSetter>;
 type ClassMethods = HashMap<&'static str, ClassMethod>;
 type InstanceMethods = HashMap<&'static str, InstanceMethod>;
 
@@ -52,6 +55,8 @@ pub struct Class {
     pub(crate) constructor: Option<Constructor>,
     /// Methods that return simple attribute lookups on an instance of `T`
     pub(crate) attributes: Attributes,
+    /// Methods that set the value of attributes on an instance of `T`
+    pub(crate) attribute_setters: AttributeSetters,
     /// Instance methods on `T` that expect a list of `PolarValue`s, and an instance of `&T`
     pub(crate) instance_methods: InstanceMethods,
     /// Class methods on `T`
@@ -129,6 +134,7 @@ where
                 fq_name: fq_name.to_owned(),
                 constructor: None,
                 attributes: HashMap::new(),
+                attribute_setters: HashMap::new(),
                 instance_methods: InstanceMethods::new(),
                 class_methods: ClassMethods::new(),
                 equality_check: equality_not_supported(),
@@ -237,6 +243,26 @@ where
         self
     }
 
+    /// Add an attribute setter for statements like `foo.bar = 1`, which makes the attribute
+    /// writable.
+    /// `class.add_attribute_setter("bar", |instance, bar| instance.bar = bar, "int")`
+    ///
+    /// The parameter type describes the type of the value (e.g., "int" or a class name).
+    /// As with methods that take `&mut self`, only an instance that is not shared can be changed.
+    pub fn add_attribute_setter<F, V>(
+            mut self,
+            name: &'static str,
+            f: F,
+            param_type: &'static str) -> Self
+    where
+        F: Fn(&mut T, V) + Send + Sync + 'static,
+        V: FromPolar,
+        T: 'static,
+    {
+        self.class.attribute_setters.insert(name, AttributeSetter::new(f, param_type));
+        self
+    }
+
     /// Set the name of the polar class.
     pub fn name(mut self, name: &str) -> Self {
         self.class.name = name.to_string();
@@ -274,6 +300,28 @@ where
         self
     }
 
+    /// Add a method that takes `&mut self`, which changes the instance, for polar method calls
+    /// like `foo.set_bar(i32)`.
+    ///
+    /// Only an instance that is not shared, such as an instance owned by the
+    /// [Registry](crate::command::Registry), can be changed.
+    pub fn add_mut_method<F, Args, R>(
+            mut self,
+            name: &'static str,
+            f: F,
+            param_types: Vec<&'static str>,
+            path: Option<&'static str>) -> Self
+        where
+            Args: FromPolarList,
+            F: MutMethod<T, Args, Result = R>,
+            R: ToPolarResult + 'static,
+    {
+        self.class
+            .instance_methods
+            .insert(name, InstanceMethod::new_mut(f, param_types, path));
+        self
+    }
+
     /// A method that returns multiple values. Every element in the iterator returned by the method will
     /// be a separate polar return value.
     pub fn add_iterator_method<F, Args, I>(mut self, name: &'static str, f: F) -> Self
@@ -388,6 +436,17 @@ impl Instance {
         attr.invoke(self, host)
     }
 
+    /// Returns true if the instance is shared (i.e., it has been cloned), in which case it cannot
+    /// be changed by methods that take `&mut self`.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.inner) > 1
+    }
+
+    /// Returns true if both instances are the same instance, rather than equal values.
+    pub fn ptr_eq(&self, other: &Instance) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
     /// Call the named method on the instance via the registered `Class`
     ///
     /// Returns: A PolarValue, or an Error if the method cannot be called.
@@ -448,11 +507,38 @@ impl Instance {
             .downcast_ref()
             .ok_or_else(|| TypeError::expected(expected_name).got(name))
     }
+
+    /// Attempt to downcast the inner type of the instance to a mutable reference to the type `T`.
+    /// Fails if the instance is shared (see `Instance::is_shared`).
+    ///
+    /// # Arguments
+    ///
+    /// * `host`: Pass host if possible to improve error handling.
+    pub fn downcast_mut<T: 'static>(
+        &mut self,
+        host: Option<&Host>,
+    ) -> Result<&mut T, TypeError> {
+        let name = host
+            .map(|h| self.name(h).to_owned())
+            .unwrap_or_else(|| self.debug_type_name.to_owned());
+
+        let expected_name = host
+            .and_then(|h| {
+                h.get_class_by_type_id(std::any::TypeId::of::<T>())
+                    .map(|class| class.name.clone())
+                    .ok()
+            })
+            .unwrap_or_else(|| std::any::type_name::<T>().to_owned());
+
+        Arc::get_mut(&mut self.inner)
+            .and_then(|inner| inner.downcast_mut())
+            .ok_or_else(|| TypeError::expected(expected_name).got(name))
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::command::oso::{Class, Instance};
+    use crate::command::oso::{Class, Host, Instance, PolarValue};
 
     #[test]
     fn test_instance_of() {
@@ -466,4 +552,52 @@ mod test {
         assert!(foo_instance.instance_of(&foo_class));
         assert!(!foo_instance.instance_of(&bar_class));
     }
+
+    #[test]
+    fn test_downcast_mut() {
+        struct Foo { id: i32 }
+
+        let mut foo_instance = Instance::new(Foo { id: 1 });
+        foo_instance.downcast_mut::<Foo>(None).unwrap().id = 2;
+
+        assert_eq!(2, foo_instance.downcast::<Foo>(None).unwrap().id);
+        assert!(foo_instance.downcast_mut::<String>(None).is_err());
+    }
+
+    #[test]
+    fn test_shared_instance_cannot_be_changed() {
+        struct Foo {}
+
+        let mut foo_instance = Instance::new(Foo {});
+        let clone = foo_instance.clone();
+
+        assert!(foo_instance.is_shared());
+        assert!(foo_instance.downcast_mut::<Foo>(None).is_err());
+        drop(clone);
+        assert!(!foo_instance.is_shared());
+        assert!(foo_instance.downcast_mut::<Foo>(None).is_ok());
+    }
+
+    #[test]
+    fn test_mut_method_requires_mutable_instance() {
+        struct Foo { id: i32 }
+        impl Foo {
+            fn set_id(&mut self, id: i32) -> bool {
+                self.id = id;
+                true
+            }
+        }
+
+        let mut host = Host::new();
+        host.cache_class(Class::builder::<Foo>()
+            .add_mut_method("set_id", Foo::set_id, vec!["int"], None)
+            .build()).unwrap();
+        let mut foo_instance = Instance::new(Foo { id: 1 });
+        let method = foo_instance.class(&host).unwrap().get_method("set_id").unwrap();
+
+        assert!(method.is_mut());
+        assert!(method.invoke(&foo_instance, vec![PolarValue::Integer(2)], &host).is_err());
+        method.invoke_mut(&mut foo_instance, vec![PolarValue::Integer(3)], &host).unwrap();
+        assert_eq!(3, foo_instance.downcast::<Foo>(None).unwrap().id);
+    }
 }
@@ -1,8 +1,8 @@
 //! Wrapper structs for the generic `Function` and `Method` traits
 use std::sync::Arc;
 use super::{PolarIterator, ToPolar, ToPolarResult, Class, Instance, PolarValue,
-            FromPolarList, Host};
-use super::method::{Function, Method};
+            FromPolar, FromPolarList, Host, OsoError};
+use super::method::{Function, Method, MutMethod};
 
 fn join<A, B>(left: crate::command::oso::Result<A>, right: crate::command::oso::Result<B>) -> super::Result<(A, B)> {
     left.and_then(|l| right.map(|r| (l, r)))
@@ -11,6 +11,8 @@ fn join<A, B>(left: crate::command::oso::Result<A>, right: crate::command::oso::
 type TypeErasedFunction<R> = Arc<dyn Fn(Vec<PolarValue>) -> crate::command::oso::Result<R> + Send + Sync>;
 type TypeErasedMethod<R> =
     Arc<dyn Fn(&Instance, Vec<PolarValue>, &Host) -> super::Result<R> + Send + Sync>;
+type TypeErasedMutMethod<R> =
+    Arc<dyn Fn(&mut Instance, Vec<PolarValue>, &Host) -> super::Result<R> + Send + Sync>;
 
 #[derive(Clone)]
 pub struct Constructor(TypeErasedFunction<Instance>, Vec<&'static str>);
@@ -63,8 +65,55 @@ impl AttributeGetter {
     }
 }
 
+type AttributeSetterMethod =
+    Arc<dyn Fn(&mut Instance, PolarValue, &Host) -> crate::command::oso::Result<()> + Send + Sync>;
+
+#[derive(Clone)]
+pub struct AttributeSetter(AttributeSetterMethod, &'static str);
+
+impl AttributeSetter {
+    pub fn new<T, F, V>(f: F, param_type: &'static str) -> Self
+    where
+        T: 'static,
+        F: Fn(&mut T, V) + Send + Sync + 'static,
+        V: FromPolar,
+    {
+        Self(Arc::new(move |receiver: &mut Instance, value: PolarValue, host: &Host| {
+            if receiver.is_shared() {
+                return Err(OsoError::UnsupportedOperation {
+                    operation: String::from("attribute setter on a shared instance"),
+                    type_name: receiver.name(host).to_owned(),
+                });
+            }
+            let receiver = receiver
+                .downcast_mut(Some(host))
+                .map_err(|e| e.invariant().into());
+            let value = V::from_polar(value);
+
+            join(receiver, value).map(|(receiver, value)| f(receiver, value))
+        }),
+        param_type)
+    }
+
+    pub fn invoke(&self, receiver: &mut Instance, value: PolarValue, host: &Host)
+                  -> crate::command::oso::Result<()> {
+        self.0(receiver, value, host)
+    }
+
+    pub fn param_type(&self) -> &'static str {
+        self.1
+    }
+}
+
+/// The receiver of an instance method, `&self` or `&mut self`.
+#[derive(Clone)]
+enum Receiver {
+    Ref(TypeErasedMethod<PolarValue>),
+    Mut(TypeErasedMutMethod<PolarValue>),
+}
+
 #[derive(Clone)]
-pub struct InstanceMethod(TypeErasedMethod<PolarValue>, Vec<&'static str>, Option<&'static str>);
+pub struct InstanceMethod(Receiver, Vec<&'static str>, Option<&'static str>);
 
 impl InstanceMethod {
     pub fn new<T, F, Args>(f: F, param_types: Vec<&'static str>, path: Option<&'static str>) -> Self
@@ -75,7 +124,7 @@ impl InstanceMethod {
         T: 'static,
     {
         Self(
-            Arc::new(
+            Receiver::Ref(Arc::new(
                 move |receiver: &Instance, args: Vec<PolarValue>, host: &Host| {
                     let receiver = receiver
                         .downcast(Some(host))
@@ -86,7 +135,39 @@ impl InstanceMethod {
                     join(receiver, args)
                         .and_then(|(receiver, args)| f.invoke(receiver, args).to_polar_result())
                 },
-            ),
+            )),
+            param_types,
+            path
+        )
+    }
+
+    pub fn new_mut<T, F, Args>(f: F, param_types: Vec<&'static str>, path: Option<&'static str>)
+                               -> Self
+    where
+        Args: FromPolarList,
+        F: MutMethod<T, Args>,
+        F::Result: ToPolarResult,
+        T: 'static,
+    {
+        Self(
+            Receiver::Mut(Arc::new(
+                move |receiver: &mut Instance, args: Vec<PolarValue>, host: &Host| {
+                    if receiver.is_shared() {
+                        return Err(OsoError::UnsupportedOperation {
+                            operation: String::from("&mut self method on a shared instance"),
+                            type_name: receiver.name(host).to_owned(),
+                        });
+                    }
+                    let receiver = receiver
+                        .downcast_mut(Some(host))
+                        .map_err(|e| e.invariant().into());
+
+                    let args = Args::from_polar_list(&args);
+
+                    join(receiver, args)
+                        .and_then(|(receiver, args)| f.invoke(receiver, args).to_polar_result())
+                },
+            )),
             param_types,
             path
         )
@@ -103,7 +184,7 @@ impl InstanceMethod {
         T: 'static,
     {
         Self(
-            Arc::new(
+            Receiver::Ref(Arc::new(
                 move |receiver: &Instance, args: Vec<PolarValue>, host: &Host| {
                     let receiver = receiver
                         .downcast(Some(host))
@@ -117,7 +198,7 @@ impl InstanceMethod {
                         })
                         .map(|results| results.to_polar())
                 },
-            ),
+            )),
             vec![],
             None
         )
@@ -129,12 +210,36 @@ impl InstanceMethod {
         args: Vec<PolarValue>,
         host: &Host,
     ) -> crate::command::oso::Result<PolarValue> {
-        self.0(receiver, args, host)
+        match &self.0 {
+            Receiver::Ref(method) => method(receiver, args, host),
+            Receiver::Mut(_) => Err(OsoError::UnsupportedOperation {
+                operation: String::from("&mut self method on an immutable instance"),
+                type_name: receiver.name(host).to_owned(),
+            })
+        }
+    }
+
+    /// Invokes the method on an instance that can be changed by methods that take `&mut self`.
+    pub fn invoke_mut(
+        &self,
+        receiver: &mut Instance,
+        args: Vec<PolarValue>,
+        host: &Host,
+    ) -> crate::command::oso::Result<PolarValue> {
+        match &self.0 {
+            Receiver::Ref(method) => method(receiver, args, host),
+            Receiver::Mut(method) => method(receiver, args, host),
+        }
+    }
+
+    /// Returns true if the method takes `&mut self`.
+    pub fn is_mut(&self) -> bool {
+        matches!(self.0, Receiver::Mut(_))
     }
 
     pub fn from_class_method(name: String) -> Self {
         Self (
-            Arc::new(
+            Receiver::Ref(Arc::new(
                 move |receiver: &Instance, args: Vec<PolarValue>, host: &Host| {
                     receiver
                         .downcast::<Class>(Some(host))
@@ -144,7 +249,7 @@ impl InstanceMethod {
                             class.call(&name, args)
                         })
                 },
-            ),
+            )),
             vec![],
             None
         )
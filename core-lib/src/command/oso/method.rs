@@ -17,6 +17,14 @@ pub trait Method<Receiver, Args = ()>: Send + Sync + 'static {
     fn invoke(&self, receiver: &Receiver, args: Args) -> Self::Result;
 }
 
+/// Similar to a `Method` but the explicit `receiver` parameter is mutable
+/// (i.e. the `&mut self` param).
+pub trait MutMethod<Receiver, Args = ()>: Send + Sync + 'static {
+    type Result;
+
+    fn invoke(&self, receiver: &mut Receiver, args: Args) -> Self::Result;
+}
+
 macro_rules! tuple_impls {
     ( $( $name:ident )* ) => {
         impl<Fun, Res, $($name),*> Function<($($name,)*)> for Fun
@@ -44,6 +52,19 @@ macro_rules! tuple_impls {
                 (self)(receiver, $($name,)*)
             }
         }
+
+        impl<Fun, Res, Receiver, $($name),*> MutMethod<Receiver, ($($name,)*)> for Fun
+        where
+            Fun: Fn(&mut Receiver, $($name),*) -> Res + Send + Sync + 'static,
+        {
+            type Result = Res;
+
+            fn invoke(&self, receiver: &mut Receiver, args: ($($name,)*)) -> Self::Result {
+                #[allow(non_snake_case)]
+                let ($($name,)*) = args;
+                (self)(receiver, $($name,)*)
+            }
+        }
     };
 }
 
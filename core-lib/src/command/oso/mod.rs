@@ -30,7 +30,7 @@ mod class;
 mod class_method;
 
 pub use errors::{InvalidCallError, OsoError, Result, TypeError};
-pub use class_method::{Constructor, InstanceMethod, AttributeGetter};
+pub use class_method::{Constructor, InstanceMethod, AttributeGetter, AttributeSetter};
 pub use class::{Class, ClassBuilder, Instance};
 pub use from_polar::{FromPolar, FromPolarList};
 pub use to_polar::{ToPolar, ToPolarList, PolarIterator, ToPolarResult};
@@ -67,7 +67,7 @@ pub trait PolarClass: Sized + 'static {
     /// Returns the partially defined `Class` for this type.
     ///
     /// Can still have methods added to it with `add_method`, and attributes
-    /// with `add_attribute_getter`.
+    /// with `add_attribute_getter` and `add_attribute_setter`.
     /// Use `Class::build` to finish defining the type.
     fn get_polar_class_builder() -> ClassBuilder<Self> {
         Class::builder()
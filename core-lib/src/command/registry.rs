@@ -1,15 +1,19 @@
-use std::collections::{HashMap, HashSet};
-use std::collections::hash_map::Iter;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::btree_map::Iter;
 use std::fmt::Debug;
+use std::ops::{Index, IndexMut};
 use std::ptr::eq;
 use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use rand;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use thiserror::Error;
 
+use super::lexer::glob_matches;
 use super::oso::{
     builtins, Class, Instance, OsoError, PolarValue, FromPolar, Host, ToPolar, Constructor,
-    InstanceMethod, AttributeGetter
+    InstanceMethod, AttributeGetter, AttributeSetter
 };
 
 /// A path segment is a single node in the directory tree.
@@ -18,12 +22,62 @@ pub struct PathSegment {
     id: usize,
     name: String,
     parent: Option<usize>,
-    children: HashMap<String, usize>,
+    children: BTreeMap<String, usize>,
     abs_path: String,
     instance: Option<Instance>,
     owner: Option<usize>,
     pub(crate) attr: Option<&'static str>,
     pub(crate) method: Option<&'static str>,
+    args: Option<ConstructorArgs>,
+    link: Option<String>,
+}
+
+/// The class and arguments an instance was constructed with, which are used to re-create the
+/// instance.
+///
+/// Instances of the registry passed to the constructor are kept as references to their nodes,
+/// rather than values, so they are not shared and can still be changed by `&mut self` methods,
+/// and they are found when the instance is re-created, even if they have been moved.
+#[derive(Clone, Debug)]
+struct ConstructorArgs {
+    class: String,
+    args: Vec<String>,
+    values: HashMap<usize, PolarValue>,
+    instances: HashMap<usize, InstanceArg>,
+}
+
+/// An instance of the registry passed to a constructor.
+#[derive(Clone, Copy, Debug)]
+struct InstanceArg {
+    node: NodeRef,
+    /// True if the instance was passed as a typed value, rather than by its path.
+    typed: bool,
+}
+
+/// The kinds of path segments in the directory tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Directory,
+    Instance,
+    Method,
+    Attribute,
+    Link,
+}
+
+/// The conditions a path must meet to be returned by [Registry::find].
+///
+/// Conditions that are `None` match every path.
+#[derive(Clone, Debug, Default)]
+pub struct FindFilter {
+    /// A pattern the name of the path must match, where `*` matches any characters and `?`
+    /// matches any single character.
+    pub name: Option<String>,
+    /// The name or fully qualified name of the class of the instance stored at the path.
+    pub class: Option<String>,
+    /// The kind of the path.
+    pub kind: Option<NodeKind>,
+    /// The maximum depth of the path below the directory that is searched, which is at depth 0.
+    pub max_depth: Option<usize>,
 }
 
 impl PathSegment {
@@ -47,7 +101,28 @@ impl PathSegment {
         match self.owner {
             None => None,
             // let this explode if we've messed up the registry tree
-            Some(o) => Some(reg.paths.get(&o).unwrap().instance.as_ref().unwrap())
+            Some(o) => Some(reg.paths[o].instance.as_ref().unwrap())
+        }
+    }
+
+    /// Returns the target of the path segment if it is a link, which is an absolute path or a
+    /// path relative to the link's parent directory.
+    pub fn link(&self) -> Option<&str> {
+        self.link.as_deref()
+    }
+
+    /// Returns the kind of the path segment.
+    pub fn kind(&self) -> NodeKind {
+        if self.link.is_some() {
+            NodeKind::Link
+        } else if self.instance.is_some() {
+            NodeKind::Instance
+        } else if self.method.is_some() {
+            NodeKind::Method
+        } else if self.attr.is_some() {
+            NodeKind::Attribute
+        } else {
+            NodeKind::Directory
         }
     }
 
@@ -83,16 +158,112 @@ impl<'a> Iterator for PathSegmentIterator<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         match self.iterator.next() {
             None => None,
-            Some((_, id)) => Some(self.registry.paths.get(&id).unwrap())
+            Some((_, id)) => Some(&self.registry.paths[*id])
+        }
+    }
+}
+
+/// The maximum number of links that are followed when navigating a path, which stops link loops.
+const MAX_LINK_HOPS: usize = 40;
+
+/// The id of the root directory, which is always the first node in the arena.
+const ROOT_ID: usize = 0;
+
+/// A reference to a node that is no longer valid once the node is removed, even if the node's id
+/// is reused.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct NodeRef {
+    id: usize,
+    generation: u64,
+}
+
+/// Stores the nodes of the directory tree in a vector indexed by the node's id.
+///
+/// Ids are allocated deterministically: the slot of a removed node is reused by the next node
+/// created, otherwise the node is appended to the end of the vector.
+/// Each slot has a generation, which changes when its node is removed.
+#[derive(Debug, Default)]
+struct Arena {
+    nodes: Vec<Option<PathSegment>>,
+    generations: Vec<u64>,
+    free: Vec<usize>,
+}
+
+impl Arena {
+    /// Returns the id the next node inserted will have.
+    fn next_id(&self) -> usize {
+        match self.free.last() {
+            None => self.nodes.len(),
+            Some(id) => *id
+        }
+    }
+
+    fn insert(&mut self, node: PathSegment) -> usize {
+        let id = node.id;
+        debug_assert_eq!(id, self.next_id());
+        match self.free.pop() {
+            None => {
+                self.nodes.push(Some(node));
+                self.generations.push(0);
+            }
+            Some(free_id) => self.nodes[free_id] = Some(node)
+        }
+        id
+    }
+
+    fn remove(&mut self, id: usize) -> PathSegment {
+        let node = self.nodes[id].take().expect("node already removed");
+        self.generations[id] += 1;
+        self.free.push(id);
+        node
+    }
+
+    fn node_ref(&self, id: usize) -> NodeRef {
+        NodeRef {
+            id,
+            generation: self.generations[id],
+        }
+    }
+
+    /// Returns the node, or `None` if the node has been removed.
+    fn get(&self, node_ref: NodeRef) -> Option<&PathSegment> {
+        if self.generations[node_ref.id] == node_ref.generation {
+            self.nodes[node_ref.id].as_ref()
+        } else {
+            None
         }
     }
+
+    fn iter(&self) -> impl Iterator<Item = &PathSegment> {
+        self.nodes.iter().flatten()
+    }
+
+    /// Returns the number of nodes in the arena.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+}
+
+impl Index<usize> for Arena {
+    type Output = PathSegment;
+
+    fn index(&self, id: usize) -> &PathSegment {
+        self.nodes[id].as_ref().expect("node removed")
+    }
+}
+
+impl IndexMut<usize> for Arena {
+    fn index_mut(&mut self, id: usize) -> &mut PathSegment {
+        self.nodes[id].as_mut().expect("node removed")
+    }
 }
 
 /// The registry contains the directory tree structure and the "reflection" system.
 pub struct Registry {
     host: Host,
-    paths: HashMap<usize, PathSegment>,
-    root_id: usize,
+    paths: Arena,
+    index: HashMap<String, usize>,
 }
 
 impl Default for Registry {
@@ -104,12 +275,13 @@ impl Default for Registry {
 
         let mut reg = Registry {
             host,
-            paths: HashMap::new(),
-            root_id: rand::random(),
+            paths: Arena::default(),
+            index: HashMap::new(),
         };
-        reg.paths.insert(reg.root_id, PathSegment {
-            children: HashMap::new(),
-            id: reg.root_id,
+        reg.index.insert("/".to_owned(), ROOT_ID);
+        reg.paths.insert(PathSegment {
+            children: BTreeMap::new(),
+            id: ROOT_ID,
             instance: None,
             name: "".to_owned(),
             parent: None,
@@ -117,6 +289,8 @@ impl Default for Registry {
             owner: None,
             method: None,
             attr: None,
+            args: None,
+            link: None,
         });
         reg
     }
@@ -169,6 +343,15 @@ impl Registry {
                 });
             }
         }
+        // setters are only reachable through the path of the attribute's getter
+        for attr in class.attribute_setters.keys() {
+            if !class.attributes.contains_key(attr) {
+                return Err(RegistryError::SetterWithoutGetter {
+                    class: class_name,
+                    attr: attr.to_string(),
+                });
+            }
+        }
 
         self.host.cache_class(class).map_err(|_| RegistryError::DuplicateClass(class_name))
     }
@@ -217,16 +400,19 @@ impl Registry {
     }
 
     fn to_path_str(pwd: &str, cd: &str) -> Result<String, RegistryError> {
-        let segments = Registry::to_path_segments(pwd, cd)?;
+        Ok(Registry::segments_to_path_str(&Registry::to_path_segments(pwd, cd)?))
+    }
+
+    fn segments_to_path_str(segments: &[String]) -> String {
         if segments.is_empty() {
-            Ok("/".to_owned())
+            "/".to_owned()
         } else {
             let mut str = "".to_owned();
             for segment in segments {
-                str.push_str("/");
-                str.push_str(&segment);
+                str.push('/');
+                str.push_str(segment);
             }
-            Ok(str)
+            str
         }
     }
 
@@ -314,7 +500,7 @@ impl Registry {
                    owner: Option<usize>,
                    method: Option<&'static str>,
                    attr: Option<&'static str>) -> Result<(), RegistryError> {
-        let mut pwd_node = self.paths.get(&self.root_id).unwrap();
+        let mut pwd_node = &self.paths[ROOT_ID];
         let mut created = false;
 
         let segments = Registry::to_path_segments(pwd, cd)?;
@@ -325,9 +511,15 @@ impl Registry {
                     let full_path = pwd_node.abs_path.to_owned();
                     let child_id = self.create_child(id, full_path, &segment)?;
                     created = true;
-                    self.paths.get(&child_id).unwrap()
+                    &self.paths[child_id]
+                }
+                Some(child_id) => {
+                    let child = &self.paths[*child_id];
+                    match child.link {
+                        Some(_) => self.follow_link(child, &mut 0)?,
+                        None => child
+                    }
                 }
-                Some(child_id) => self.paths.get(child_id).unwrap()
             };
         }
 
@@ -338,7 +530,7 @@ impl Registry {
                 let attributes = class.attributes.clone();
 
                 let id = pwd_node.id;
-                let path = self.paths.get_mut(&id).unwrap();
+                let path = &mut self.paths[id];
                 path.owner = owner;
                 path.method = method;
                 path.attr = attr;
@@ -372,7 +564,7 @@ impl Registry {
                 }
             } else {
                 let id = pwd_node.id;
-                let path = self.paths.get_mut(&id).unwrap();
+                let path = &mut self.paths[id];
                 path.owner = owner;
                 path.method = method;
                 path.attr = attr;
@@ -386,7 +578,8 @@ impl Registry {
 
     fn create_child(&mut self, node_id: usize, pwd: String, name: &str)
                     -> Result<usize, RegistryError> {
-        let parent = self.paths.get_mut(&node_id).unwrap();
+        let child_id = self.paths.next_id();
+        let parent = &mut self.paths[node_id];
         if name.is_empty() || name == "." || name == ".." {
             Err(RegistryError::InvalidPathChildName {
                 pwd,
@@ -394,9 +587,8 @@ impl Registry {
             })
         } else {
             let path_copy = parent.abs_path.clone();
-            let child_id = rand::random();
             let child = PathSegment {
-                children: HashMap::new(),
+                children: BTreeMap::new(),
                 id: child_id,
                 instance: None,
                 name: name.to_owned(),
@@ -408,174 +600,226 @@ impl Registry {
                 owner: None,
                 method: None,
                 attr: None,
+                args: None,
+                link: None,
             };
             parent.children.insert(name.to_owned(), child_id);
-            self.paths.insert(child_id, child);
+            self.index.insert(child.abs_path.to_owned(), child_id);
+            self.paths.insert(child);
             Ok(child_id)
         }
     }
 
     //
-    // Navigate Paths
+    // Remove paths
     //
 
-    /// Navigates the directory tree with the specified path components and returns the directory.
+    /// Removes the directory or instance at the specified path components, along with the method
+    /// and attribute nodes of an instance.
     ///
-    /// As with most [Registry] methods, the directory to create is specified with two path
-    /// components, the current working directory (pwd) which is an absolute path and the directory
-    /// to change to (cd) which can be an absolute or relative path.
+    /// If `recursive` is false, the path must not contain any other directories or instances.
+    /// The root directory, and the method and attribute nodes of an instance on their own, cannot
+    /// be removed.
+    /// A link is removed without removing its target.
     ///
     /// # Example
     ///
     /// ```
     /// let mut registry = rcore::command::Registry::default();
+    /// registry.mkdir("/", "foo/bar").unwrap();
     ///
-    /// registry.mkdir("/foo/bar", "../soo").unwrap();
+    /// assert!(registry.remove("/", "foo", false).is_err());
+    /// registry.remove("/", "foo", true).unwrap();
     ///
-    /// assert_eq!("/foo/soo", registry.cd("/foo", "soo").unwrap().abs_path());
+    /// assert!(registry.path("/foo").is_err());
     /// ```
-    pub fn cd(&self, pwd: &str, cd: &str) -> Result<&PathSegment, RegistryError> {
-        let mut pwd_node = self.paths.get(&self.root_id).unwrap();
+    pub fn remove(&mut self, pwd: &str, cd: &str, recursive: bool) -> Result<(), RegistryError> {
+        let path = self.cd_no_follow(pwd, cd)?;
+        let id = path.id;
+        let parent_id = match path.parent {
+            None => return Err(RegistryError::IllegalRemove {
+                path: path.abs_path.to_owned(),
+                reason: "cannot remove the root directory",
+            }),
+            Some(parent_id) => parent_id
+        };
+        if self.instance_owner(id).is_some() {
+            return Err(RegistryError::IllegalRemove {
+                path: path.abs_path.to_owned(),
+                reason: "cannot remove a method or attribute of an instance",
+            });
+        }
+        if !recursive && !path.children.values().all(|child| self.is_owned_by(*child, id)) {
+            return Err(RegistryError::IllegalRemove {
+                path: path.abs_path.to_owned(),
+                reason: "directory is not empty",
+            });
+        }
 
-        let segments = Registry::to_path_segments(pwd, cd)?;
-        for segment in segments {
-            pwd_node = match pwd_node.children.get(&segment) {
-                None => return Err(RegistryError::IllegalPathNavigation {
-                    pwd: pwd.to_owned(),
-                    cd: cd.to_owned(),
-                    reason: "unknown path",
-                }),
-                Some(child_id) => self.paths.get(child_id).unwrap()
-            };
+        let name = path.name.to_owned();
+        self.paths[parent_id].children.remove(&name);
+        self.remove_node(id);
+        Ok(())
+    }
+
+    /// Returns true if the node is a method or attribute of the instance with the specified id, or
+    /// a directory that only contains methods or attributes of the instance (i.e., a custom method
+    /// path).
+    fn is_owned_by(&self, node_id: usize, owner_id: usize) -> bool {
+        let node = &self.paths[node_id];
+        node.owner == Some(owner_id)
+            || (node.owner.is_none()
+                && node.instance.is_none()
+                && node.has_children()
+                && node.children.values().all(|child| self.is_owned_by(*child, owner_id)))
+    }
+
+    /// Returns the id of the instance a node belongs to, if the node is a method or attribute of
+    /// the instance, or a directory of the instance's custom method paths.
+    fn instance_owner(&self, node_id: usize) -> Option<usize> {
+        let node = &self.paths[node_id];
+        if node.owner.is_some() {
+            return node.owner;
+        }
+        let mut ancestor = node.parent;
+        while let Some(id) = ancestor {
+            if self.paths[id].instance.is_some() {
+                return Some(id).filter(|id| self.is_owned_by(node_id, *id));
+            }
+            ancestor = self.paths[id].parent;
         }
+        None
+    }
 
-        Ok(pwd_node)
+    fn remove_node(&mut self, node_id: usize) {
+        let node = self.paths.remove(node_id);
+        self.index.remove(&node.abs_path);
+        for child_id in node.children.values() {
+            self.remove_node(*child_id);
+        }
     }
 
-    /// Navigates the directory tree with the specified absolute path and returns the directory.
+    //
+    // Links
+    //
+
+    /// Creates a link at the specified path components to the target path, which is an absolute
+    /// path or a path relative to the link's parent directory.
+    ///
+    /// Navigating the link (e.g., with [Registry::cd]) navigates its target instead, which does not
+    /// need to exist until the link is navigated.
+    /// If `replace` is true, an existing link at the path is pointed at the new target.
     ///
     /// # Example
     ///
     /// ```
     /// let mut registry = rcore::command::Registry::default();
+    /// registry.mkdir("/", "md/feeds/nyse1").unwrap();
+    /// registry.mkdir("/", "md/feeds/nyse2").unwrap();
     ///
-    /// registry.mkdir("/foo/bar", "../soo").unwrap();
+    /// registry.link("/md", "primary", "feeds/nyse1", false).unwrap();
+    /// assert_eq!("/md/feeds/nyse1", registry.cd("/md", "primary").unwrap().abs_path());
     ///
-    /// assert_eq!("/foo/soo", registry.path("/foo/soo").unwrap().abs_path());
+    /// registry.link("/md", "primary", "/md/feeds/nyse2", true).unwrap();
+    /// assert_eq!("/md/feeds/nyse2", registry.cd("/md", "primary").unwrap().abs_path());
     /// ```
-    pub fn path(&self, pwd: &str) -> Result<&PathSegment, RegistryError> {
-        self.cd(pwd, ".")
+    pub fn link(&mut self, pwd: &str, cd: &str, target: &str, replace: bool)
+                -> Result<(), RegistryError> {
+        if let Ok(path) = self.cd_no_follow(pwd, cd) {
+            if !replace || path.link.is_none() {
+                return Err(RegistryError::DuplicatePath(path.abs_path.to_owned()));
+            }
+            let id = path.id;
+            self.paths[id].link = Some(target.to_owned());
+            return Ok(());
+        }
+
+        self.create_path(pwd, cd, true, None, None, None, None)?;
+        let id = self.cd_no_follow(pwd, cd)?.id;
+        self.paths[id].link = Some(target.to_owned());
+        Ok(())
     }
 
     //
-    // Utility methods for dealing with constructor and method params
+    // Move and copy paths
     //
 
-    fn parse_params(&self,
-                    class_name: &str,
-                    method_name: &str,
-                    args: &Vec<&str>,
-                    param_types: &Vec<&'static str>) -> Result<Vec<PolarValue>, RegistryError> {
-        if args.len() != param_types.len() {
-            return Err(RegistryError::InvalidNumberOfMethodParameters {
-                class: class_name.to_owned(),
-                method: method_name.to_owned(),
-                expected: param_types.len(),
-                received: args.len(),
+    /// Moves the directory or instance at `from`, along with everything in it, to `to`.
+    ///
+    /// As with the Unix `mv` command, if `to` is an existing path, the directory or instance is
+    /// moved into it, otherwise it is moved to the new path, whose parent must exist.
+    /// The root directory, and the method and attribute nodes of an instance on their own, cannot
+    /// be moved, and a path cannot be moved into itself.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut registry = rcore::command::Registry::default();
+    /// registry.mkdir("/", "feeds/nyse").unwrap();
+    /// registry.mkdir("/", "md").unwrap();
+    ///
+    /// registry.move_path("/", "feeds", "md").unwrap();
+    /// registry.move_path("/md", "feeds/nyse", "feeds/nyse1").unwrap();
+    ///
+    /// assert_eq!("/md/feeds/nyse1", registry.path("/md/feeds/nyse1").unwrap().abs_path());
+    /// assert!(registry.path("/feeds").is_err());
+    /// ```
+    pub fn move_path(&mut self, pwd: &str, from: &str, to: &str) -> Result<(), RegistryError> {
+        let id = self.movable(pwd, from)?;
+        let name = self.paths[id].name.to_owned();
+        let (parent_id, name) = self.destination(pwd, to, &name)?;
+
+        // the new parent cannot be the moved path or one of its descendants
+        if self.is_within(parent_id, id) {
+            return Err(RegistryError::IllegalDestination {
+                path: Registry::to_path_str(pwd, to)?,
+                reason: "cannot move a path into itself",
             });
         }
 
-        let mut params: Vec<PolarValue> = Vec::new();
-        for i in 0..param_types.len() {
-            let arg = args[i];
-            let pt = param_types[i];
-
-            if pt == "bool" {
-                params.push(Registry::parse::<bool>(
-                    arg, class_name, method_name, i, pt)?.to_polar());
-            } else if pt == "int" {
-                params.push(Registry::parse::<i32>(
-                    arg, class_name, method_name, i, pt)?.to_polar());
-            } else if pt == "float" {
-                params.push(Registry::parse::<f64>(
-                    arg, class_name, method_name, i, pt)?.to_polar());
-            } else if pt == "string" {
-                params.push(PolarValue::String(arg.to_owned()));
-            } else {
-                let instance = self.instance(arg, ".")?;
-                let class = instance.class(&self.host).unwrap();
-                if pt == &class.name || pt == &class.fq_name {
-                    params.push(PolarValue::Instance(instance.to_owned()))
-                } else {
-                    return Err(RegistryError::InvalidCast {
-                        pwd: arg.to_string(),
-                        cd: ".".to_string(),
-                        cast_type: "",
-                        expected: pt.to_string(),
-                        got: class.fq_name.to_string(),
-                    });
-                }
-            }
-        }
-
-        Ok(params)
+        self._move_path(id, parent_id, name);
+        Ok(())
     }
 
-    fn validate_params(&self,
-                       params: &Vec<PolarValue>,
-                       class_name: &str,
-                       method_name: &str,
-                       param_types: &Vec<&'static str>) -> Result<(), RegistryError> {
-        if params.len() != param_types.len() {
-            return Err(RegistryError::InvalidNumberOfMethodParameters {
-                class: class_name.to_owned(),
-                method: method_name.to_owned(),
-                expected: param_types.len(),
-                received: params.len(),
+    /// Renames the directory or instance at the specified path components, keeping it in the same
+    /// parent directory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut registry = rcore::command::Registry::default();
+    /// registry.mkdir("/", "feeds/nyse/a").unwrap();
+    ///
+    /// registry.rename("/feeds", "nyse", "nyse1").unwrap();
+    ///
+    /// assert_eq!("/feeds/nyse1/a", registry.path("/feeds/nyse1/a").unwrap().abs_path());
+    /// ```
+    pub fn rename(&mut self, pwd: &str, cd: &str, name: &str) -> Result<(), RegistryError> {
+        let id = self.movable(pwd, cd)?;
+        let parent_id = self.paths[id].parent.unwrap();
+        let parent = &self.paths[parent_id];
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(RegistryError::InvalidPathChildName {
+                pwd: parent.abs_path.to_owned(),
+                child: name.to_owned(),
             });
         }
-        for i in 0..param_types.len() {
-            let (expected1, expected2) = self.to_type_strings(&params[i]);
-            let pt = param_types[i];
-            if pt != expected1 && pt != expected2 {
-                return Err(RegistryError::InvalidMethodParameter {
-                    class: class_name.to_owned(),
-                    method: method_name.to_owned(),
-                    param_index: i,
-                    param_type: pt,
-                    reason: "param is of the wrong type",
-                });
-            }
+        if let Some(child_id) = parent.children.get(name) {
+            return Err(RegistryError::DuplicatePath(
+                self.paths[*child_id].abs_path.to_owned()));
         }
-        Ok(())
-    }
-
-    //
-    // Class components
-    //
-
-    pub(crate) fn class_for_instance(&self, instance: &Instance) -> &Class {
-        instance.class(&self.host).unwrap()
-    }
-
-    fn class(&self, class_name: &str) -> Result<&Class, RegistryError> {
-        self.host.get_class(class_name).map_err(
-            |_| RegistryError::UnknownClass(class_name.to_owned()))
-    }
 
-    fn constructor(&self, class_name: &str) -> Result<&Constructor, RegistryError> {
-        match &self.class(class_name)?.constructor {
-            Some(c) => Ok(c),
-            None => Err(RegistryError::NoConstructor(class_name.to_owned()))
-        }
+        self._move_path(id, parent_id, name.to_owned());
+        Ok(())
     }
 
-    //
-    // Create and get instances
-    //
-
-    /// Creates the an instance of the specified class with the specified method parameters at the
-    /// specified directory.
+    /// Creates a new instance at `to` with the same class and constructor arguments that the
+    /// instance at `from` was created with.
+    ///
+    /// As with [Registry::move_path], if `to` is an existing path, the instance is created in it
+    /// with the same name.
+    /// Arguments that were parsed from strings are parsed again, so an argument that is the path to
+    /// an instance is the instance at that path when the copy is created.
     ///
     /// # Example
     /// ```
@@ -590,866 +834,3227 @@ impl Registry {
     /// let mut registry = Registry::default();
     /// registry.cache_class(class).unwrap();
     ///
-    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    /// registry.parsed_create_instance("/foo", ".", "Foo", &vec!["42"]).unwrap();
+    /// registry.copy("/", "foo", "bar").unwrap();
     ///
-    /// assert_eq!(registry.instance_value::<Foo>("/foo", ".").unwrap(), &Foo { id: 42 })
+    /// assert_eq!(registry.instance_value::<Foo>("/bar", ".").unwrap(), &Foo { id: 42 })
     /// ```
-    pub fn create_instance(&mut self,
-                           pwd: &str,
-                           cd: &str,
-                           class_name: &str,
-                           params: Vec<PolarValue>) -> Result<(), RegistryError> {
-        let param_types = self.constructor(class_name)?.get_param_types();
-        self.validate_params(&params, class_name, "<constructor>", param_types)?;
-        self._create_instance(pwd, cd, class_name, params)
+    pub fn copy(&mut self, pwd: &str, from: &str, to: &str) -> Result<(), RegistryError> {
+        let path = self.cd(pwd, from)?;
+        let args = match (&path.instance, &path.args) {
+            (Some(_), Some(args)) => args.clone(),
+            _ => return Err(RegistryError::MissingAtPath {
+                path: path.abs_path.to_owned(),
+                expected: "instance",
+            })
+        };
+        let name = path.name.to_owned();
+        let abs_path = path.abs_path.to_owned();
+        let (parent_id, name) = self.destination(pwd, to, &name)?;
+        let parent_path = self.paths[parent_id].abs_path.to_owned();
+
+        // paths are parsed again, while instances passed as typed values are passed again
+        let mut values = args.values.clone();
+        for (i, instance) in args.instances.iter().filter(|(_, instance)| instance.typed) {
+            let id = self.instance_arg(&abs_path, *i, instance.node)?;
+            values.insert(*i, PolarValue::Instance(self.paths[id].instance.clone().unwrap()));
+        }
+        let arg_refs: Vec<&str> = args.args.iter().map(|arg| arg.as_str()).collect();
+        self.parsed_create_instance_with_values(
+            &parent_path, &name, &args.class, &arg_refs, &values)
     }
 
-    /// Creates the an instance of the specified class with the specified method parameters at the
-    /// specified directory.
-    ///
-    /// Each of the arguments are parsed from strings into the parameter type defined on the [Class]
-    /// definition.
+    /// Returns the id of the node that stores the instance that was passed as the specified
+    /// parameter to the constructor of the instance at the specified path.
+    fn instance_arg(&self, path: &str, param_index: usize, node_ref: NodeRef)
+                    -> Result<usize, RegistryError> {
+        match self.paths.get(node_ref) {
+            Some(node) => Ok(node.id),
+            None => Err(RegistryError::MissingConstructorInstance {
+                path: path.to_owned(),
+                param_index,
+            })
+        }
+    }
+
+    /// Returns true if the node is the ancestor node or one of its descendants.
+    fn is_within(&self, node_id: usize, ancestor_id: usize) -> bool {
+        let mut ancestor = Some(node_id);
+        while let Some(id) = ancestor {
+            if id == ancestor_id {
+                return true;
+            }
+            ancestor = self.paths[id].parent;
+        }
+        false
+    }
+
+    //
+    // Dump
+    //
+
+    /// Returns the commands that re-create a directory, and everything in it, when they are
+    /// executed by a [Shell](crate::command::Shell).
     ///
+    /// Empty directories are created with `mkdir`, links with `ln -s`, and instances with `create`
+    /// and the arguments the instance was constructed with, followed by assignments of the current
+    /// values of its writable attributes.
+    /// An instance that was passed to the constructor of another instance is created before it, if
+    /// the instance is in the directory, otherwise the instance is expected to exist already.
     ///
     /// # Example
     /// ```
     /// use rcore::command::Registry;
-    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
+    /// use rcore::command::oso::{ClassBuilder, PolarClass};
     ///
-    /// #[derive(PartialEq, Debug)]
-    /// struct Foo { id: i32 }
-    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
+    /// #[derive(Clone)]
+    /// struct Feed { port: i32 }
+    /// impl PolarClass for Feed {}
+    /// struct Session { port: i32 }
     ///
-    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"]).build();
     /// let mut registry = Registry::default();
-    /// registry.cache_class(class).unwrap();
+    /// registry.cache_class(ClassBuilder::<Feed>::with_constructor(|port: i32| Feed { port }, vec!["int"])
+    ///     .name("Feed").build()).unwrap();
+    /// registry.cache_class(ClassBuilder::<Session>::with_constructor(
+    ///     |feed: Feed| Session { port: feed.port }, vec!["Feed"]).name("Session").build()).unwrap();
     ///
-    /// registry.parsed_create_instance("/foo", ".", "Foo", &vec!["42"]).unwrap();
+    /// registry.mkdir("/", "empty").unwrap();
+    /// registry.parsed_create_instance("/feeds/nyse", ".", "Feed", &vec!["4000"]).unwrap();
+    /// registry.parsed_create_instance("/", "sessions/nyse", "Session", &vec!["/feeds/nyse"]).unwrap();
+    /// registry.move_path("/", "/feeds", "/a").unwrap();
     ///
-    /// assert_eq!(registry.instance_value::<Foo>("/foo", ".").unwrap(), &Foo { id: 42 })
+    /// assert_eq!(vec!["create /a/nyse Feed 4000", "mkdir /empty", "create /sessions/nyse Session /a/nyse"],
+    ///            registry.dump("/", ".").unwrap());
     /// ```
-    pub fn parsed_create_instance(&mut self,
-                                  pwd: &str,
-                                  cd: &str,
-                                  class_name: &str,
-                                  args: &Vec<&str>) -> Result<(), RegistryError> {
-        let param_types = self.constructor(class_name)?.get_param_types();
-        let params = self.parse_params(class_name, "<constructor>", args, param_types)?;
-        self._create_instance(pwd, cd, class_name, params)
+    pub fn dump(&self, pwd: &str, cd: &str) -> Result<Vec<String>, RegistryError> {
+        let root_id = self.cd(pwd, cd)?.id;
+        let mut commands = vec![];
+        for node_id in self.restore_order(root_id)? {
+            let node = &self.paths[node_id];
+            if let Some(target) = &node.link {
+                commands.push(format!("ln -s {} {}", quote(target), quote(&node.abs_path)));
+            } else if node.instance.is_some() {
+                self.dump_instance(node_id, &mut commands)?;
+            } else {
+                commands.push(format!("mkdir {}", quote(&node.abs_path)));
+            }
+        }
+        Ok(commands)
     }
 
-    fn _create_instance(&mut self,
-                        pwd: &str,
-                        cd: &str,
-                        class_name: &str,
-                        params: Vec<PolarValue>) -> Result<(), RegistryError> {
-        let constructor = self.constructor(class_name)?;
-        let instance = constructor.invoke(params)
-            .map_err(|e| RegistryError::InvocationFailure {
-                pwd: pwd.to_owned(),
-                cd: cd.to_owned(),
-                class: class_name.to_owned(),
-                method: "<constructor>".to_owned(),
-                invocation_type: "constructor",
-                reason: "constructor invocation failure",
-                error: e,
-            })?;
-        self.create_path(pwd, cd, false, Some(instance), None, None, None)
+    fn dump_instance(&self, node_id: usize, commands: &mut Vec<String>)
+                     -> Result<(), RegistryError> {
+        let node = &self.paths[node_id];
+        let args = node.args.as_ref().unwrap();
+
+        let mut command = format!("create {} {}", quote(&node.abs_path), quote(&args.class));
+        for (i, arg) in args.args.iter().enumerate() {
+            let arg = if let Some(instance) = args.instances.get(&i) {
+                let id = self.instance_arg(&node.abs_path, i, instance.node)?;
+                quote(&self.paths[id].abs_path)
+            } else if let Some(value) = args.values.get(&i) {
+                dump_value(value).ok_or_else(|| RegistryError::IllegalDump {
+                    path: node.abs_path.to_owned(),
+                    reason: "constructor argument cannot be written as text",
+                })?
+            } else {
+                quote(arg)
+            };
+            command.push(' ');
+            command.push_str(&arg);
+        }
+        commands.push(command);
+
+        let instance = node.instance.as_ref().unwrap();
+        let class = self.class_for_instance(instance);
+        let mut attr_names: Vec<&&str> = class.attribute_setters.keys().collect();
+        attr_names.sort();
+        for attr_name in attr_names {
+            // lists, maps, and instances are only set through the constructor
+            let value = self.instance_attr(instance, class.attributes.get(attr_name).unwrap());
+            if let Some(value) = dump_value(&value) {
+                let attr_path = node.abs_path.to_owned() + "/" + attr_name;
+                commands.push(format!("{} = {}", quote(&attr_path), value));
+            }
+        }
+        Ok(())
     }
 
-    /// Returns the value of the instance stored at the specified path and casts it to the
-    /// specified type.
+    /// Returns the ids of the nodes in a directory that have to be re-created to re-create the
+    /// directory, in the order they are re-created.
     ///
-    /// # Example
-    /// ```
-    /// use rcore::command::Registry;
-    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
-    ///
-    /// #[derive(PartialEq, Debug)]
-    /// struct Foo { id: i32 }
-    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
-    ///
-    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"]).build();
-    /// let mut registry = Registry::default();
-    /// registry.cache_class(class).unwrap();
-    ///
-    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
-    ///
-    /// assert_eq!(registry.instance_value::<Foo>("/foo", ".").unwrap(), &Foo { id: 42 })
-    /// ```
-    pub fn instance_value<T: 'static>(&self, pwd: &str, cd: &str)
-                                      -> Result<&T, RegistryError> {
-        let instance = self.instance(pwd, cd)?;
-        match instance.downcast::<T>(Some(&self.host)) {
-            Ok(value) => Ok(value),
-            Err(e) => Err(RegistryError::InvalidCast {
-                pwd: pwd.to_owned(),
-                cd: cd.to_owned(),
-                cast_type: "instance cast",
-                expected: e.expected,
-                got: e.got.unwrap_or("".to_owned()),
-            })
+    /// Directories are only included if they are empty, as the others are created with their
+    /// children.
+    /// Instances passed to the constructor of another instance are included before it, and links
+    /// are included last, as their targets might not exist before.
+    fn restore_order(&self, root_id: usize) -> Result<Vec<usize>, RegistryError> {
+        let mut order = vec![];
+        let mut links = vec![];
+        let mut visited = HashSet::new();
+        self.restore_node(root_id, root_id, &mut order, &mut links, &mut visited)?;
+        order.append(&mut links);
+        Ok(order)
+    }
+
+    fn restore_node(&self,
+                    root_id: usize,
+                    node_id: usize,
+                    order: &mut Vec<usize>,
+                    links: &mut Vec<usize>,
+                    visited: &mut HashSet<usize>) -> Result<(), RegistryError> {
+        let node = &self.paths[node_id];
+        if node.owner.is_some() {
+            // methods and attributes are created with their instance
+            return Ok(());
+        }
+        if node.link.is_some() {
+            links.push(node_id);
+            return Ok(());
         }
+
+        if node.instance.is_some() {
+            self.restore_instance(root_id, node_id, order, visited)?;
+        } else if !node.has_children() && node.parent.is_some() {
+            order.push(node_id);
+        }
+        for child_id in node.children.values() {
+            self.restore_node(root_id, *child_id, order, links, visited)?;
+        }
+        Ok(())
     }
 
-    pub(crate) fn instance(&self, pwd: &str, cd: &str) -> Result<&Instance, RegistryError> {
-        return match &self.cd(pwd, cd)?.instance {
-            None => Err(RegistryError::MissingAtPath {
-                path: pwd.to_owned(),
-                expected: "instance",
-            }),
-            Some(instance) => Ok(instance)
-        };
+    fn restore_instance(&self,
+                        root_id: usize,
+                        node_id: usize,
+                        order: &mut Vec<usize>,
+                        visited: &mut HashSet<usize>) -> Result<(), RegistryError> {
+        if !visited.insert(node_id) {
+            return Ok(());
+        }
+        let node = &self.paths[node_id];
+        let mut instances: Vec<_> = node.args.as_ref().unwrap().instances.iter().collect();
+        instances.sort_by_key(|(i, _)| **i);
+        for (i, instance) in instances {
+            let id = self.instance_arg(&node.abs_path, *i, instance.node)?;
+            if self.is_within(id, root_id) {
+                self.restore_instance(root_id, id, order, visited)?;
+            }
+        }
+        order.push(node_id);
+        Ok(())
     }
 
     //
-    // Get Attributes
+    // Save and load
     //
 
-    pub(crate) fn instance_attr(&self, instance: &Instance, attr: &AttributeGetter) -> PolarValue {
-        attr.invoke(instance, &self.host).unwrap()
-    }
-
-    /// Returns the value of the attribute stored at the specified path and casts it to the
-    /// specified type.
+    /// Returns a JSON snapshot of the registry, which re-creates its paths when it is loaded by
+    /// [Registry::load].
+    ///
+    /// The snapshot contains the empty directories, the links, and the instances with the class
+    /// and arguments each instance was constructed with, and the current values of its attributes.
+    /// Instances of the registry that were passed to a constructor are saved as the path they are
+    /// currently stored at, like `{"instance": "/feeds/nyse"}`.
     ///
     /// # Example
     /// ```
     /// use rcore::command::Registry;
-    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
+    /// use rcore::command::oso::ClassBuilder;
     ///
-    /// #[derive(PartialEq, Debug)]
-    /// struct Foo { id: i32 }
-    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
+    /// struct Feed { port: i32 }
+    /// let class = ClassBuilder::<Feed>::with_constructor(|port: i32| Feed { port }, vec!["int"])
+    ///     .name("Feed")
+    ///     .add_attribute_getter("port", |feed: &Feed| feed.port)
+    ///     .add_attribute_setter("port", |feed: &mut Feed, port: i32| feed.port = port, "int")
+    ///     .build();
     ///
-    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
-    ///         .add_attribute_getter("id", |foo: &Foo| { foo.id }).build();
     /// let mut registry = Registry::default();
     /// registry.cache_class(class).unwrap();
+    /// registry.parsed_create_instance("/feeds/nyse", ".", "Feed", &vec!["4000"]).unwrap();
+    /// registry.parsed_set_attr("/feeds/nyse/port", ".", "4001").unwrap();
+    /// let snapshot = registry.save().unwrap();
     ///
-    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    /// let mut loaded = Registry::default();
+    /// loaded.cache_class(ClassBuilder::<Feed>::with_constructor(|port: i32| Feed { port }, vec!["int"])
+    ///     .name("Feed")
+    ///     .add_attribute_getter("port", |feed: &Feed| feed.port)
+    ///     .add_attribute_setter("port", |feed: &mut Feed, port: i32| feed.port = port, "int")
+    ///     .build()).unwrap();
+    /// loaded.load(&snapshot).unwrap();
     ///
-    /// assert_eq!(registry.attr_value::<i32>("/foo", "id").unwrap(), 42)
+    /// assert_eq!(loaded.attr_value::<i32>("/feeds/nyse", "port").unwrap(), 4001);
     /// ```
-    pub fn attr_value<T: 'static + FromPolar>(&self, pwd: &str, cd: &str)
-                                              -> Result<T, RegistryError> {
-        let result = self.attr(pwd, cd)?;
-        Self::cast::<T>(pwd, cd, "attribute", result)
+    pub fn save(&self) -> Result<String, RegistryError> {
+        let mut paths = vec![];
+        for node_id in self.restore_order(ROOT_ID)? {
+            let node = &self.paths[node_id];
+            let path = node.abs_path.to_owned();
+            if let Some(target) = &node.link {
+                paths.push(PathSnapshot::Link { path, target: target.to_owned() });
+            } else if node.instance.is_some() {
+                paths.push(self.save_instance(node_id)?);
+            } else {
+                paths.push(PathSnapshot::Dir { path });
+            }
+        }
+
+        let snapshot = Snapshot { version: SNAPSHOT_VERSION, paths };
+        serde_json::to_string_pretty(&snapshot)
+            .map_err(|e| RegistryError::InvalidSnapshot(e.to_string()))
     }
 
-    /// Returns the value of the attribute stored at the specified path.
+    fn save_instance(&self, node_id: usize) -> Result<PathSnapshot, RegistryError> {
+        let node = &self.paths[node_id];
+        let args = node.args.as_ref().unwrap();
+        let param_types = self.constructor(&args.class)?.get_param_types();
+
+        let mut saved_args = vec![];
+        for (i, arg) in args.args.iter().enumerate() {
+            let saved_arg = if let Some(instance) = args.instances.get(&i) {
+                let id = self.instance_arg(&node.abs_path, i, instance.node)?;
+                json!({ "instance": self.paths[id].abs_path })
+            } else if let Some(value) = args.values.get(&i) {
+                self.save_value(value).ok_or_else(|| RegistryError::IllegalDump {
+                    path: node.abs_path.to_owned(),
+                    reason: "constructor argument cannot be saved",
+                })?
+            } else {
+                // the argument was parsed successfully when the instance was created
+                match param_types[i] {
+                    "bool" => json!(arg.parse::<bool>().unwrap()),
+                    "int" => json!(arg.parse::<i32>().unwrap()),
+                    "float" => json!(arg.parse::<f64>().unwrap()),
+                    _ => json!(arg)
+                }
+            };
+            saved_args.push(saved_arg);
+        }
+
+        let instance = node.instance.as_ref().unwrap();
+        let class = self.class_for_instance(instance);
+        let mut attributes = BTreeMap::new();
+        for (attr_name, getter) in &class.attributes {
+            if let Some(value) = self.save_value(&self.instance_attr(instance, getter)) {
+                attributes.insert(attr_name.to_string(), value);
+            }
+        }
+
+        Ok(PathSnapshot::Instance {
+            path: node.abs_path.to_owned(),
+            class: args.class.to_owned(),
+            args: saved_args,
+            attributes,
+        })
+    }
+
+    /// Returns the JSON value of a value, or `None` if the value cannot be saved.
+    fn save_value(&self, value: &PolarValue) -> Option<Value> {
+        match value {
+            PolarValue::Integer(i) => Some(json!(i)),
+            PolarValue::Float(f) => serde_json::Number::from_f64(*f).map(Value::Number),
+            PolarValue::String(s) => Some(json!(s)),
+            PolarValue::Boolean(b) => Some(json!(b)),
+            PolarValue::List(values) => values.iter()
+                .map(|value| self.save_value(value))
+                .collect::<Option<Vec<Value>>>()
+                .map(Value::Array),
+            PolarValue::Map(values) => values.iter()
+                .map(|(key, value)| self.save_value(value).map(|value| (key.to_owned(), value)))
+                .collect::<Option<serde_json::Map<String, Value>>>()
+                .map(Value::Object),
+            PolarValue::Instance(instance) => self.paths.iter()
+                .find(|node| node.instance.as_ref().is_some_and(|i| i.ptr_eq(instance)))
+                .map(|node| json!({ "instance": node.abs_path })),
+        }
+    }
+
+    /// Re-creates the paths of a JSON snapshot returned by [Registry::save].
     ///
-    /// Returns the value of the attribute stored at the specified path and casts it to the
-    /// specified type.
+    /// The classes of the saved instances must already be cached, and the saved paths must not
+    /// exist yet.
+    /// The instances are created with their saved constructor arguments, and then the saved values
+    /// of their writable attributes are set.
+    ///
+    /// Before any path is created, the snapshot is checked to be valid JSON and to only contain
+    /// instances of cached classes.
+    /// Paths created before an error, e.g. a value of the wrong type, are not removed.
     ///
     /// # Example
     /// ```
-    /// use rcore::command::Registry;
-    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
-    ///
-    /// #[derive(PartialEq, Debug)]
-    /// struct Foo { id: i32 }
-    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
-    ///
-    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
-    ///         .add_attribute_getter("id", |foo: &Foo| { foo.id }).build();
-    /// let mut registry = Registry::default();
-    /// registry.cache_class(class).unwrap();
+    /// use rcore::command::{Registry, RegistryError};
     ///
-    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    /// let snapshot = r#"{
+    ///     "version": 1,
+    ///     "paths": [{ "kind": "instance", "path": "/foo", "class": "Foo", "args": [], "attributes": {} }]
+    /// }"#;
     ///
-    /// assert_eq!(registry.attr("/foo", "id").unwrap(), 42.to_polar())
+    /// assert_eq!(Registry::default().load(snapshot), Err(RegistryError::UnknownClass("Foo".to_owned())));
     /// ```
-    pub fn attr(&self, pwd: &str, cd: &str) -> Result<PolarValue, RegistryError> {
-        let attr_path = self.cd(pwd, cd)?;
-        // check that we are an attribute node
-        let attr_name = match &attr_path.attr {
-            Some(name) => name,
-            None => return Err(RegistryError::MissingAtPath {
-                path: attr_path.abs_path.to_owned(),
-                expected: "attribute",
-            })
-        };
-        // lookup the instance node
-        let instance_path = self.paths.get(&attr_path.owner.unwrap()).unwrap();
+    pub fn load(&mut self, json: &str) -> Result<(), RegistryError> {
+        let snapshot: Snapshot = serde_json::from_str(json)
+            .map_err(|e| RegistryError::InvalidSnapshot(e.to_string()))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(RegistryError::InvalidSnapshot(
+                format!("unsupported version: {}", snapshot.version)));
+        }
+        for path in &snapshot.paths {
+            if let PathSnapshot::Instance { class, .. } = path {
+                self.constructor(class)?;
+            }
+        }
 
-        let instance = instance_path.instance.as_ref().unwrap();
-        instance.get_attr(attr_name, &self.host).map_err(|e| {
-            let class_name = &instance.class(&self.host).unwrap().fq_name;
+        for path in snapshot.paths {
+            match path {
+                PathSnapshot::Dir { path } => self.mkdir("/", &path)?,
+                PathSnapshot::Link { path, target } => self.link("/", &path, &target, false)?,
+                PathSnapshot::Instance { path, class, args, attributes } =>
+                    self.load_instance(&path, &class, &args, &attributes)?,
+            }
+        }
+        Ok(())
+    }
 
-            RegistryError::InvocationFailure {
-                pwd: pwd.to_owned(),
-                cd: cd.to_owned(),
-                class: class_name.to_string(),
-                method: attr_name.to_string(),
-                invocation_type: "attribute",
-                reason: "attribute invocation failure",
-                error: e,
+    fn load_instance(&mut self,
+                     path: &str,
+                     class_name: &str,
+                     args: &[Value],
+                     attributes: &BTreeMap<String, Value>) -> Result<(), RegistryError> {
+        let param_types = self.constructor(class_name)?.get_param_types().clone();
+        if args.len() != param_types.len() {
+            return Err(RegistryError::InvalidNumberOfMethodParameters {
+                class: class_name.to_owned(),
+                method: "<constructor>".to_owned(),
+                expected: param_types.len(),
+                received: args.len(),
+            });
+        }
+
+        let mut arg_paths = vec![""; args.len()];
+        let mut values = HashMap::new();
+        for (i, (arg, pt)) in args.iter().zip(param_types).enumerate() {
+            let name = format!("<constructor> parameter {}", i);
+            if let Some(arg_path) = saved_instance_path(arg).filter(|_| is_class_type(pt)) {
+                // passed as a path, so the instance is re-parsed when the instance is copied
+                arg_paths[i] = arg_path;
+            } else {
+                values.insert(i, self.load_value(path, &name, pt, arg)?);
+            }
+        }
+        self.parsed_create_instance_with_values(path, ".", class_name, &arg_paths, &values)?;
+
+        let class = self.class(class_name)?;
+        let mut setters = vec![];
+        for (attr_name, value) in attributes {
+            match class.attribute_setters.get(attr_name.as_str()) {
+                Some(setter) => setters.push((attr_name, setter.param_type(), value)),
+                // read-only attributes are set by the constructor
+                None if class.attributes.contains_key(attr_name.as_str()) => continue,
+                None => return Err(RegistryError::MissingAtPath {
+                    path: path.to_owned() + "/" + attr_name,
+                    expected: "attribute",
+                }),
             }
+        }
+        for (attr_name, pt, value) in setters {
+            let value = self.load_value(path, attr_name, pt, value)?;
+            self.set_attr(path, attr_name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the value of a saved JSON value that is passed as a parameter of the specified type.
+    fn load_value(&self, path: &str, name: &str, param_type: &str, json: &Value)
+                  -> Result<PolarValue, RegistryError> {
+        let value = match (param_type, json) {
+            ("bool" | "boolean", Value::Bool(b)) => Some(PolarValue::Boolean(*b)),
+            ("int" | "integer", Value::Number(n)) => n.as_i64().map(PolarValue::Integer),
+            ("float", Value::Number(n)) => n.as_f64().map(PolarValue::Float),
+            ("string", Value::String(s)) => Some(PolarValue::String(s.to_owned())),
+            ("list" | "vec", Value::Array(_)) | ("map" | "dict", Value::Object(_)) =>
+                Some(self.load_any_value(path, name, json)?),
+            (pt, json) if is_class_type(pt) => match saved_instance_path(json) {
+                Some(arg_path) => Some(PolarValue::Instance(self.instance(arg_path, ".")?.clone())),
+                None => None
+            },
+            _ => None
+        };
+        value.ok_or_else(|| RegistryError::SnapshotTypeMismatch {
+            path: path.to_owned(),
+            name: name.to_owned(),
+            expected: param_type.to_owned(),
+            got: json_type(json),
         })
     }
 
+    /// Returns the value of a saved JSON value of any type, e.g. an element of a list.
+    fn load_any_value(&self, path: &str, name: &str, json: &Value)
+                      -> Result<PolarValue, RegistryError> {
+        if let Some(arg_path) = saved_instance_path(json) {
+            return Ok(PolarValue::Instance(self.instance(arg_path, ".")?.clone()));
+        }
+        Ok(match json {
+            Value::Bool(b) => PolarValue::Boolean(*b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => PolarValue::Integer(i),
+                None => PolarValue::Float(n.as_f64().unwrap()),
+            },
+            Value::String(s) => PolarValue::String(s.to_owned()),
+            Value::Array(values) => PolarValue::List(values.iter()
+                .map(|value| self.load_any_value(path, name, value))
+                .collect::<Result<Vec<_>, _>>()?),
+            Value::Object(values) => PolarValue::Map(values.iter()
+                .map(|(key, value)| Ok((key.to_owned(), self.load_any_value(path, name, value)?)))
+                .collect::<Result<HashMap<_, _>, RegistryError>>()?),
+            Value::Null => return Err(RegistryError::SnapshotTypeMismatch {
+                path: path.to_owned(),
+                name: name.to_owned(),
+                expected: "value".to_owned(),
+                got: json_type(json),
+            }),
+        })
+    }
+
+    /// Returns the id of the node at the specified path components, if it can be moved.
+    fn movable(&self, pwd: &str, cd: &str) -> Result<usize, RegistryError> {
+        let path = self.cd_no_follow(pwd, cd)?;
+        if path.parent.is_none() {
+            Err(RegistryError::IllegalMove {
+                path: path.abs_path.to_owned(),
+                reason: "cannot move the root directory",
+            })
+        } else if self.instance_owner(path.id).is_some() {
+            Err(RegistryError::IllegalMove {
+                path: path.abs_path.to_owned(),
+                reason: "cannot move a method or attribute of an instance",
+            })
+        } else {
+            Ok(path.id)
+        }
+    }
+
+    /// Returns the id of the parent directory and the name of a path that is moved or copied to
+    /// `to`.
+    ///
+    /// If `to` exists, the path is moved into it with the specified name, otherwise the path is
+    /// moved to the parent of `to` with the last segment of `to` as the name.
+    fn destination(&self, pwd: &str, to: &str, name: &str)
+                   -> Result<(usize, String), RegistryError> {
+        let (parent, name) = match self.cd(pwd, to) {
+            Ok(path) => (path, name.to_owned()),
+            Err(_) => {
+                let mut segments = Registry::to_path_segments(pwd, to)?;
+                let name = segments.pop().unwrap();
+                (self.cd("/", &segments.join("/"))?, name)
+            }
+        };
+
+        if self.instance_owner(parent.id).is_some() {
+            return Err(RegistryError::IllegalDestination {
+                path: parent.abs_path.to_owned(),
+                reason: "cannot move or copy into a method or attribute of an instance",
+            });
+        }
+        if let Some(child_id) = parent.children.get(&name) {
+            return Err(RegistryError::DuplicatePath(
+                self.paths[*child_id].abs_path.to_owned()));
+        }
+        Ok((parent.id, name))
+    }
+
+    fn _move_path(&mut self, node_id: usize, parent_id: usize, name: String) {
+        let node = &mut self.paths[node_id];
+        let old_parent_id = node.parent.replace(parent_id).unwrap();
+        let old_name = std::mem::replace(&mut node.name, name.to_owned());
+
+        self.paths[old_parent_id].children.remove(&old_name);
+        self.paths[parent_id].children.insert(name, node_id);
+        self.update_abs_path(node_id);
+    }
+
+    /// Updates the absolute path of the node, and its descendants, from its parent's.
+    fn update_abs_path(&mut self, node_id: usize) {
+        let node = &self.paths[node_id];
+        let parent = &self.paths[node.parent.unwrap()];
+        let abs_path = match parent.parent {
+            None => parent.abs_path.to_owned() + &node.name,
+            Some(_) => parent.abs_path.to_owned() + "/" + &node.name
+        };
+        let children: Vec<usize> = node.children.values().cloned().collect();
+
+        let old_abs_path = std::mem::replace(&mut self.paths[node_id].abs_path, abs_path.to_owned());
+        if self.index.get(&old_abs_path) == Some(&node_id) {
+            self.index.remove(&old_abs_path);
+        }
+        self.index.insert(abs_path, node_id);
+        for child_id in children {
+            self.update_abs_path(child_id);
+        }
+    }
+
     //
-    // Invoke methods
+    // Navigate Paths
     //
 
-    /// Invokes the instance method stored at the specified path and casts the return value of the
-    /// instance method to the specified type.
+    /// Navigates the directory tree with the specified path components and returns the directory.
     ///
-    /// Each of the arguments are parsed from strings into the parameter type defined on the [Class]
-    /// definition.
+    /// As with most [Registry] methods, the directory to create is specified with two path
+    /// components, the current working directory (pwd) which is an absolute path and the directory
+    /// to change to (cd) which can be an absolute or relative path.
     ///
     /// # Example
-    /// ```
-    /// use rcore::command::Registry;
-    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
-    ///
-    /// #[derive(PartialEq, Debug)]
-    /// struct Foo { id: i32 }
-    /// impl Foo {
-    ///     fn new(id: i32) -> Foo { Foo { id } }
-    ///     fn add_to_id(&self, val: i32) -> i32 { self.id + val }
-    /// }
     ///
-    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
-    ///         .add_method("add_to_id", Foo::add_to_id, vec!["int"], Some("add")).build();
-    /// let mut registry = Registry::default();
-    /// registry.cache_class(class).unwrap();
+    /// ```
+    /// let mut registry = rcore::command::Registry::default();
     ///
-    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    /// registry.mkdir("/foo/bar", "../soo").unwrap();
     ///
-    /// assert_eq!(registry.parsed_invoke_method_value::<i32>("/foo", "add", &vec!["15"]).unwrap(),
-    ///            42 + 15)
+    /// assert_eq!("/foo/soo", registry.cd("/foo", "soo").unwrap().abs_path());
     /// ```
-    pub fn parsed_invoke_method_value<T: 'static + FromPolar>(&mut self,
-                                                              pwd: &str,
-                                                              cd: &str,
-                                                              params: &Vec<&str>)
-                                                              -> Result<T, RegistryError> {
-        let result = self.parsed_invoke_method(pwd, cd, params)?;
-        Self::cast::<T>(pwd, cd, "method", result)
+    ///
+    /// Links are followed, so the directory is the target of the link if the path is a link.
+    pub fn cd(&self, pwd: &str, cd: &str) -> Result<&PathSegment, RegistryError> {
+        self.walk(pwd, cd, true, &mut 0)
     }
 
-    /// Invokes the instance method stored at the specified path and returns the return value of the
-    /// instance method.
+    /// Navigates the directory tree like [Registry::cd], except a link at the end of the path is
+    /// not followed, so the link itself is returned.
+    fn cd_no_follow(&self, pwd: &str, cd: &str) -> Result<&PathSegment, RegistryError> {
+        self.walk(pwd, cd, false, &mut 0)
+    }
+
+    fn walk(&self, pwd: &str, cd: &str, follow_last: bool, hops: &mut usize)
+            -> Result<&PathSegment, RegistryError> {
+        let segments = Registry::to_path_segments(pwd, cd)?;
+        // the index only contains the absolute paths of nodes, so a path that navigates through a
+        // link is not found and is walked one segment at a time
+        if let Some(id) = self.index.get(&Registry::segments_to_path_str(&segments)) {
+            return self.resolve(&self.paths[*id], follow_last, hops);
+        }
+
+        let mut pwd_node = &self.paths[ROOT_ID];
+        let last = segments.len();
+        for (i, segment) in segments.iter().enumerate() {
+            pwd_node = match pwd_node.children.get(segment) {
+                None => return Err(RegistryError::IllegalPathNavigation {
+                    pwd: pwd.to_owned(),
+                    cd: cd.to_owned(),
+                    reason: "unknown path",
+                }),
+                Some(child_id) => &self.paths[*child_id]
+            };
+            pwd_node = self.resolve(pwd_node, follow_last || i + 1 < last, hops)?;
+        }
+
+        Ok(pwd_node)
+    }
+
+    /// Returns the target of the node if it is a link and links are to be followed, otherwise the
+    /// node.
+    fn resolve<'a>(&'a self, node: &'a PathSegment, follow: bool, hops: &mut usize)
+                   -> Result<&'a PathSegment, RegistryError> {
+        if follow && node.link.is_some() {
+            self.follow_link(node, hops)
+        } else {
+            Ok(node)
+        }
+    }
+
+    /// Returns the target of the link, following links until the target is not a link.
+    fn follow_link(&self, link: &PathSegment, hops: &mut usize)
+                   -> Result<&PathSegment, RegistryError> {
+        *hops += 1;
+        if *hops > MAX_LINK_HOPS {
+            return Err(RegistryError::LinkLoop(link.abs_path.to_owned()));
+        }
+        let parent = &self.paths[link.parent.unwrap()];
+        self.walk(&parent.abs_path, link.link.as_ref().unwrap(), true, hops)
+    }
+
+    /// Navigates the directory tree with the specified absolute path and returns the directory.
     ///
-    /// Each of the arguments are parsed from strings into the parameter type defined on the [Class]
-    /// definition.
+    /// # Example
+    ///
+    /// ```
+    /// let mut registry = rcore::command::Registry::default();
+    ///
+    /// registry.mkdir("/foo/bar", "../soo").unwrap();
+    ///
+    /// assert_eq!("/foo/soo", registry.path("/foo/soo").unwrap().abs_path());
+    /// ```
+    ///
+    /// The absolute paths of all directories are indexed, so a normalized path is found with a
+    /// single lookup.
+    pub fn path(&self, pwd: &str) -> Result<&PathSegment, RegistryError> {
+        match self.index.get(pwd) {
+            Some(id) => self.resolve(&self.paths[*id], true, &mut 0),
+            None => self.cd(pwd, ".")
+        }
+    }
+
+    //
+    // Find paths
+    //
+
+    /// Returns the absolute paths of the directory, and everything in it, that match the filter,
+    /// in the order they are found by walking the directory depth first.
+    ///
+    /// Links are matched like any other path, but the paths they point to are not searched.
     ///
     /// # Example
     /// ```
-    /// use rcore::command::Registry;
-    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
+    /// use rcore::command::{FindFilter, NodeKind, Registry};
+    /// use rcore::command::oso::ClassBuilder;
     ///
-    /// #[derive(PartialEq, Debug)]
-    /// struct Foo { id: i32 }
-    /// impl Foo {
-    ///     fn new(id: i32) -> Foo { Foo { id } }
-    ///     fn add_to_id(&self, val: i32) -> i32 { self.id + val }
-    /// }
+    /// struct OrderGateway { port: i32 }
+    /// let class = ClassBuilder::<OrderGateway>::with_constructor(|port: i32| OrderGateway { port }, vec!["int"])
+    ///     .name("OrderGateway")
+    ///     .build();
     ///
-    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
-    ///         .add_method("add_to_id", Foo::add_to_id, vec!["int"], Some("add")).build();
     /// let mut registry = Registry::default();
     /// registry.cache_class(class).unwrap();
+    /// registry.parsed_create_instance("/nyse/orders", ".", "OrderGateway", &vec!["4000"]).unwrap();
+    /// registry.parsed_create_instance("/lse/orders", ".", "OrderGateway", &vec!["4001"]).unwrap();
+    /// registry.mkdir("/lse", "orders_old").unwrap();
     ///
-    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    /// let filter = FindFilter { class: Some("OrderGateway".to_owned()), ..FindFilter::default() };
+    /// assert_eq!(vec!["/lse/orders", "/nyse/orders"], registry.find("/", ".", &filter).unwrap());
     ///
-    /// assert_eq!(registry.parsed_invoke_method("/foo", "add", &vec!["15"]).unwrap(),
-    ///            (42 + 15).to_polar())
+    /// let filter = FindFilter {
+    ///     name: Some("orders*".to_owned()),
+    ///     kind: Some(NodeKind::Directory),
+    ///     ..FindFilter::default()
+    /// };
+    /// assert_eq!(vec!["/lse/orders_old"], registry.find("/", ".", &filter).unwrap());
     /// ```
-    pub fn parsed_invoke_method(&mut self, pwd: &str, cd: &str, params: &Vec<&str>)
-                                -> Result<PolarValue, RegistryError> {
-        // check that we are an method node
-        let method_path = self.cd(pwd, cd)?;
-        let method_name = match method_path.method {
-            Some(name) => name,
-            None => return Err(RegistryError::MissingAtPath {
-                path: method_path.abs_path.to_owned(),
-                expected: "method",
+    pub fn find(&self, pwd: &str, cd: &str, filter: &FindFilter)
+                -> Result<Vec<String>, RegistryError> {
+        let path = self.cd(pwd, cd)?;
+        let name: Option<Vec<char>> = filter.name.as_ref().map(|name| name.chars().collect());
+        let mut paths = vec![];
+        self.find_node(path, 0, filter, name.as_deref(), &mut paths);
+        Ok(paths)
+    }
+
+    fn find_node(&self,
+                 path: &PathSegment,
+                 depth: usize,
+                 filter: &FindFilter,
+                 name: Option<&[char]>,
+                 paths: &mut Vec<String>) {
+        let name_matches = name.is_none_or(|name| {
+            glob_matches(name, &path.name.chars().collect::<Vec<char>>())
+        });
+        let class_matches = filter.class.as_ref().is_none_or(|class_name| {
+            path.instance.as_ref().is_some_and(|instance| {
+                let class = self.class_for_instance(instance);
+                &class.name == class_name || &class.fq_name == class_name
             })
-        };
+        });
+        let kind_matches = filter.kind.is_none_or(|kind| path.kind() == kind);
+        if name_matches && class_matches && kind_matches {
+            paths.push(path.abs_path.to_owned());
+        }
 
-        // lookup the instance for the method and then the class
-        let instance_path = self.paths.get(&method_path.owner.unwrap()).unwrap();
-        let instance = instance_path.instance.as_ref().unwrap();
-        let class = instance.class(&self.host).unwrap();
-        let instance_method = class.instance_methods.get(method_name).unwrap();
+        if path.link.is_some() || filter.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return;
+        }
+        for child in path.children(self) {
+            self.find_node(child, depth + 1, filter, name, paths);
+        }
+    }
 
-        // parse the params into PolarValues and invoke method
-        let params = self.parse_params(
-            &class.name, method_name, &params, instance_method.param_types())?;
-        self._invoke_method(pwd, cd, &class.fq_name, method_name, instance, instance_method, params)
+    //
+    // Utility methods for dealing with constructor and method params
+    //
+
+    fn parse_params(&self,
+                    class_name: &str,
+                    method_name: &str,
+                    args: &Vec<&str>,
+                    values: &HashMap<usize, PolarValue>,
+                    param_types: &Vec<&'static str>) -> Result<Vec<PolarValue>, RegistryError> {
+        if args.len() != param_types.len() {
+            return Err(RegistryError::InvalidNumberOfMethodParameters {
+                class: class_name.to_owned(),
+                method: method_name.to_owned(),
+                expected: param_types.len(),
+                received: args.len(),
+            });
+        }
+
+        let mut params: Vec<PolarValue> = Vec::new();
+        for i in 0..param_types.len() {
+            let arg = args[i];
+            let pt = param_types[i];
+
+            if let Some(value) = values.get(&i) {
+                // typed values are not parsed, but must be of the parameter's type
+                let (expected1, expected2) = self.to_type_strings(value);
+                if pt != expected1 && pt != expected2 {
+                    return Err(RegistryError::InvalidMethodParameter {
+                        class: class_name.to_owned(),
+                        method: method_name.to_owned(),
+                        param_index: i,
+                        param_type: pt,
+                        reason: "param is of the wrong type",
+                    });
+                }
+                params.push(value.clone());
+            } else if pt == "bool" {
+                params.push(Registry::parse::<bool>(
+                    arg, class_name, method_name, i, pt)?.to_polar());
+            } else if pt == "int" {
+                params.push(Registry::parse::<i32>(
+                    arg, class_name, method_name, i, pt)?.to_polar());
+            } else if pt == "float" {
+                params.push(Registry::parse::<f64>(
+                    arg, class_name, method_name, i, pt)?.to_polar());
+            } else if pt == "string" {
+                params.push(PolarValue::String(arg.to_owned()));
+            } else {
+                let instance = self.instance(arg, ".")?;
+                let class = instance.class(&self.host).unwrap();
+                if pt == &class.name || pt == &class.fq_name {
+                    params.push(PolarValue::Instance(instance.to_owned()))
+                } else {
+                    return Err(RegistryError::InvalidCast {
+                        pwd: arg.to_string(),
+                        cd: ".".to_string(),
+                        cast_type: "",
+                        expected: pt.to_string(),
+                        got: class.fq_name.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(params)
+    }
+
+    fn validate_params(&self,
+                       params: &Vec<PolarValue>,
+                       class_name: &str,
+                       method_name: &str,
+                       param_types: &Vec<&'static str>) -> Result<(), RegistryError> {
+        if params.len() != param_types.len() {
+            return Err(RegistryError::InvalidNumberOfMethodParameters {
+                class: class_name.to_owned(),
+                method: method_name.to_owned(),
+                expected: param_types.len(),
+                received: params.len(),
+            });
+        }
+        for i in 0..param_types.len() {
+            let (expected1, expected2) = self.to_type_strings(&params[i]);
+            let pt = param_types[i];
+            if pt != expected1 && pt != expected2 {
+                return Err(RegistryError::InvalidMethodParameter {
+                    class: class_name.to_owned(),
+                    method: method_name.to_owned(),
+                    param_index: i,
+                    param_type: pt,
+                    reason: "param is of the wrong type",
+                });
+            }
+        }
+        Ok(())
     }
 
+    //
+    // Class components
+    //
 
-    /// Invokes the instance method stored at the specified path and casts the return value of the
-    /// instance method to the specified type.
+    pub(crate) fn class_for_instance(&self, instance: &Instance) -> &Class {
+        instance.class(&self.host).unwrap()
+    }
+
+    fn class(&self, class_name: &str) -> Result<&Class, RegistryError> {
+        self.host.get_class(class_name).map_err(
+            |_| RegistryError::UnknownClass(class_name.to_owned()))
+    }
+
+    fn constructor(&self, class_name: &str) -> Result<&Constructor, RegistryError> {
+        match &self.class(class_name)?.constructor {
+            Some(c) => Ok(c),
+            None => Err(RegistryError::NoConstructor(class_name.to_owned()))
+        }
+    }
+
+    //
+    // Create and get instances
+    //
+
+    /// Creates the an instance of the specified class with the specified method parameters at the
+    /// specified directory.
     ///
     /// # Example
     /// ```
     /// use rcore::command::Registry;
-    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
     ///
     /// #[derive(PartialEq, Debug)]
     /// struct Foo { id: i32 }
-    /// impl Foo {
-    ///     fn new(id: i32) -> Foo { Foo { id } }
-    ///     fn add_to_id(&self, val: i32) -> i32 { self.id + val }
-    /// }
+    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
     ///
-    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
-    ///         .add_method("add_to_id", Foo::add_to_id, vec!["int"], Some("add")).build();
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"]).build();
     /// let mut registry = Registry::default();
     /// registry.cache_class(class).unwrap();
     ///
     /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
     ///
-    /// assert_eq!(registry.invoke_method_value::<i32>("/foo", "add", vec![15.to_polar()]).unwrap(),
-    ///            42 + 15)
+    /// assert_eq!(registry.instance_value::<Foo>("/foo", ".").unwrap(), &Foo { id: 42 })
     /// ```
-    pub fn invoke_method_value<T: 'static + FromPolar>(&mut self,
-                                                       pwd: &str,
-                                                       cd: &str,
-                                                       params: Vec<PolarValue>)
-                                                       -> Result<T, RegistryError> {
-        let result = self.invoke_method(pwd, cd, params)?;
-        Self::cast::<T>(pwd, cd, "method", result)
+    pub fn create_instance(&mut self,
+                           pwd: &str,
+                           cd: &str,
+                           class_name: &str,
+                           params: Vec<PolarValue>) -> Result<(), RegistryError> {
+        let param_types = self.constructor(class_name)?.get_param_types();
+        self.validate_params(&params, class_name, "<constructor>", param_types)?;
+        let args = ConstructorArgs {
+            class: class_name.to_owned(),
+            args: vec![String::new(); params.len()],
+            values: params.iter().cloned().enumerate().collect(),
+            instances: HashMap::new(),
+        };
+        self._create_instance(pwd, cd, class_name, params, args)
     }
 
-    /// Invokes the instance method stored at the specified path and returns the return value of the
-    /// instance method.
+    /// Creates the an instance of the specified class with the specified method parameters at the
+    /// specified directory.
+    ///
+    /// Each of the arguments are parsed from strings into the parameter type defined on the [Class]
+    /// definition.
+    ///
     ///
     /// # Example
     /// ```
     /// use rcore::command::Registry;
-    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
     ///
     /// #[derive(PartialEq, Debug)]
     /// struct Foo { id: i32 }
-    /// impl Foo {
-    ///     fn new(id: i32) -> Foo { Foo { id } }
-    ///     fn add_to_id(&self, val: i32) -> i32 { self.id + val }
-    /// }
+    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
     ///
-    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
-    ///         .add_method("add_to_id", Foo::add_to_id, vec!["int"], Some("add")).build();
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"]).build();
     /// let mut registry = Registry::default();
     /// registry.cache_class(class).unwrap();
     ///
-    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    /// registry.parsed_create_instance("/foo", ".", "Foo", &vec!["42"]).unwrap();
     ///
-    /// assert_eq!(registry.invoke_method("/foo", "add", vec![15.to_polar()]).unwrap(),
-    ///            (42 + 15).to_polar())
+    /// assert_eq!(registry.instance_value::<Foo>("/foo", ".").unwrap(), &Foo { id: 42 })
     /// ```
-    pub fn invoke_method(&mut self, pwd: &str, cd: &str, params: Vec<PolarValue>)
-                         -> Result<PolarValue, RegistryError> {
-        // check that we are an method node
-        let method_path = self.cd(pwd, cd)?;
-        let method_name = match method_path.method {
-            Some(name) => name,
-            None => return Err(RegistryError::MissingAtPath {
-                path: method_path.abs_path.to_owned(),
-                expected: "method",
-            })
-        };
-
-        // lookup the instance for the method and then the class
-        let instance_path = self.paths.get(&method_path.owner.unwrap()).unwrap();
-        let instance = instance_path.instance.as_ref().unwrap();
-        let class = instance.class(&self.host).unwrap();
-        let instance_method = class.instance_methods.get(method_name).unwrap();
-
+    pub fn parsed_create_instance(&mut self,
+                                  pwd: &str,
+                                  cd: &str,
+                                  class_name: &str,
+                                  args: &Vec<&str>) -> Result<(), RegistryError> {
+        self.parsed_create_instance_with_values(pwd, cd, class_name, args, &HashMap::new())
+    }
+
+    /// Creates an instance like [Registry::parsed_create_instance], except the arguments with an
+    /// index in `values` are replaced by the typed value instead of being parsed.
+    pub(crate) fn parsed_create_instance_with_values(&mut self,
+                                                     pwd: &str,
+                                                     cd: &str,
+                                                     class_name: &str,
+                                                     args: &Vec<&str>,
+                                                     values: &HashMap<usize, PolarValue>)
+                                                     -> Result<(), RegistryError> {
+        let param_types = self.constructor(class_name)?.get_param_types();
+        let params = self.parse_params(class_name, "<constructor>", args, values, param_types)?;
+        let mut instances = HashMap::new();
+        for (i, param) in params.iter().enumerate() {
+            if matches!(param, PolarValue::Instance(_)) && !values.contains_key(&i) {
+                // the argument is the path to an instance of the registry
+                let node = self.paths.node_ref(self.cd(args[i], ".")?.id);
+                instances.insert(i, InstanceArg { node, typed: false });
+            }
+        }
+        let args = ConstructorArgs {
+            class: class_name.to_owned(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            values: values.clone(),
+            instances,
+        };
+        self._create_instance(pwd, cd, class_name, params, args)
+    }
+
+    fn _create_instance(&mut self,
+                        pwd: &str,
+                        cd: &str,
+                        class_name: &str,
+                        params: Vec<PolarValue>,
+                        mut args: ConstructorArgs) -> Result<(), RegistryError> {
+        for (i, param) in params.iter().enumerate() {
+            let instance = match param {
+                PolarValue::Instance(instance) if !args.instances.contains_key(&i) => instance,
+                _ => continue
+            };
+            let node = self.paths.iter().find(|node| {
+                node.instance.as_ref().is_some_and(|i| i.ptr_eq(instance))
+            });
+            // instances that are not in the registry are kept as values
+            if let Some(node) = node {
+                let node = self.paths.node_ref(node.id);
+                args.values.remove(&i);
+                args.instances.insert(i, InstanceArg { node, typed: true });
+            }
+        }
+
+        let constructor = self.constructor(class_name)?;
+        let instance = constructor.invoke(params)
+            .map_err(|e| RegistryError::InvocationFailure {
+                pwd: pwd.to_owned(),
+                cd: cd.to_owned(),
+                class: class_name.to_owned(),
+                method: "<constructor>".to_owned(),
+                invocation_type: "constructor",
+                reason: "constructor invocation failure",
+                error: e,
+            })?;
+        self.create_path(pwd, cd, false, Some(instance), None, None, None)?;
+
+        let id = self.cd(pwd, cd)?.id;
+        self.paths[id].args = Some(args);
+        Ok(())
+    }
+
+    /// Returns the value of the instance stored at the specified path and casts it to the
+    /// specified type.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
+    ///
+    /// #[derive(PartialEq, Debug)]
+    /// struct Foo { id: i32 }
+    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"]).build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    ///
+    /// assert_eq!(registry.instance_value::<Foo>("/foo", ".").unwrap(), &Foo { id: 42 })
+    /// ```
+    pub fn instance_value<T: 'static>(&self, pwd: &str, cd: &str)
+                                      -> Result<&T, RegistryError> {
+        let instance = self.instance(pwd, cd)?;
+        match instance.downcast::<T>(Some(&self.host)) {
+            Ok(value) => Ok(value),
+            Err(e) => Err(RegistryError::InvalidCast {
+                pwd: pwd.to_owned(),
+                cd: cd.to_owned(),
+                cast_type: "instance cast",
+                expected: e.expected,
+                got: e.got.unwrap_or("".to_owned()),
+            })
+        }
+    }
+
+    pub(crate) fn instance(&self, pwd: &str, cd: &str) -> Result<&Instance, RegistryError> {
+        return match &self.cd(pwd, cd)?.instance {
+            None => Err(RegistryError::MissingAtPath {
+                path: pwd.to_owned(),
+                expected: "instance",
+            }),
+            Some(instance) => Ok(instance)
+        };
+    }
+
+    /// Returns the values of the instance stored at the specified path.
+    ///
+    /// The instance's class must define how its instances are converted into iterators with
+    /// [ClassBuilder::set_into_iter](super::oso::ClassBuilder::set_into_iter) or
+    /// [ClassBuilder::with_iter](super::oso::ClassBuilder::with_iter).
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
+    ///
+    /// struct Foo { ids: Vec<i32> }
+    /// impl Foo { fn new(id: i32) -> Foo { Foo { ids: vec![id, id + 1] } } }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .set_into_iter(|foo: &Foo| foo.ids.clone().into_iter()).build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    ///
+    /// assert_eq!(registry.instance_iter("/foo", ".").unwrap(), vec![42.to_polar(), 43.to_polar()])
+    /// ```
+    pub fn instance_iter(&self, pwd: &str, cd: &str) -> Result<Vec<PolarValue>, RegistryError> {
+        let instance = self.instance(pwd, cd)?;
+        self.iter_values(pwd, cd, instance)
+    }
+
+    pub(crate) fn iter_values(&self, pwd: &str, cd: &str, instance: &Instance)
+                              -> Result<Vec<PolarValue>, RegistryError> {
+        let to_registry_error = |e| RegistryError::InvocationFailure {
+            pwd: pwd.to_owned(),
+            cd: cd.to_owned(),
+            class: instance.name(&self.host).to_owned(),
+            method: "<into_iter>".to_owned(),
+            invocation_type: "iterator",
+            reason: "iterator invocation failure",
+            error: e,
+        };
+
+        instance.as_iter(&self.host)
+            .map_err(to_registry_error)?
+            .collect::<Result<Vec<PolarValue>, OsoError>>()
+            .map_err(to_registry_error)
+    }
+
+    //
+    // Get Attributes
+    //
+
+    pub(crate) fn instance_attr(&self, instance: &Instance, attr: &AttributeGetter) -> PolarValue {
+        attr.invoke(instance, &self.host).unwrap()
+    }
+
+    /// Returns the value of the attribute stored at the specified path and casts it to the
+    /// specified type.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
+    ///
+    /// #[derive(PartialEq, Debug)]
+    /// struct Foo { id: i32 }
+    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .add_attribute_getter("id", |foo: &Foo| { foo.id }).build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    ///
+    /// assert_eq!(registry.attr_value::<i32>("/foo", "id").unwrap(), 42)
+    /// ```
+    pub fn attr_value<T: 'static + FromPolar>(&self, pwd: &str, cd: &str)
+                                              -> Result<T, RegistryError> {
+        let result = self.attr(pwd, cd)?;
+        Self::cast::<T>(pwd, cd, "attribute", result)
+    }
+
+    /// Returns the value of the attribute stored at the specified path.
+    ///
+    /// Returns the value of the attribute stored at the specified path and casts it to the
+    /// specified type.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
+    ///
+    /// #[derive(PartialEq, Debug)]
+    /// struct Foo { id: i32 }
+    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .add_attribute_getter("id", |foo: &Foo| { foo.id }).build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    ///
+    /// assert_eq!(registry.attr("/foo", "id").unwrap(), 42.to_polar())
+    /// ```
+    pub fn attr(&self, pwd: &str, cd: &str) -> Result<PolarValue, RegistryError> {
+        let attr_path = self.cd(pwd, cd)?;
+        // check that we are an attribute node
+        let attr_name = match &attr_path.attr {
+            Some(name) => name,
+            None => return Err(RegistryError::MissingAtPath {
+                path: attr_path.abs_path.to_owned(),
+                expected: "attribute",
+            })
+        };
+        // lookup the instance node
+        let instance_path = &self.paths[attr_path.owner.unwrap()];
+
+        let instance = instance_path.instance.as_ref().unwrap();
+        instance.get_attr(attr_name, &self.host).map_err(|e| {
+            let class_name = &instance.class(&self.host).unwrap().fq_name;
+
+            RegistryError::InvocationFailure {
+                pwd: pwd.to_owned(),
+                cd: cd.to_owned(),
+                class: class_name.to_string(),
+                method: attr_name.to_string(),
+                invocation_type: "attribute",
+                reason: "attribute invocation failure",
+                error: e,
+            }
+        })
+    }
+
+    /// Returns the values of the specified attributes of the instance stored at the specified path,
+    /// in the order of the names, or the values of all of its attributes in alphabetical order if
+    /// no names are specified.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
+    ///
+    /// struct Foo { id: i32 }
+    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .add_attribute_getter("id", |foo: &Foo| { foo.id })
+    ///         .add_attribute_getter("next_id", |foo: &Foo| { foo.id + 1 }).build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    ///
+    /// assert_eq!(registry.attrs("/", "foo", &[]).unwrap(),
+    ///            vec![("id".to_owned(), 42.to_polar()), ("next_id".to_owned(), 43.to_polar())]);
+    /// assert_eq!(registry.attrs("/", "foo", &["next_id"]).unwrap(),
+    ///            vec![("next_id".to_owned(), 43.to_polar())]);
+    /// ```
+    pub fn attrs(&self, pwd: &str, cd: &str, names: &[&str])
+                 -> Result<Vec<(String, PolarValue)>, RegistryError> {
+        let instance_path = self.cd(pwd, cd)?;
+        if instance_path.instance.is_none() {
+            return Err(RegistryError::MissingAtPath {
+                path: instance_path.abs_path.to_owned(),
+                expected: "instance",
+            });
+        }
+
+        let mut names = names.to_vec();
+        if names.is_empty() {
+            names = instance_path.children(self)
+                .filter(|child| child.attr.is_some() && child.owner == Some(instance_path.id))
+                .map(|child| child.name())
+                .collect();
+            names.sort_unstable();
+        }
+
+        names.into_iter()
+            .map(|name| Ok((name.to_owned(), self.attr(&instance_path.abs_path, name)?)))
+            .collect()
+    }
+
+    //
+    // Set Attributes
+    //
+
+    /// Sets the value of the attribute stored at the specified path.
+    ///
+    /// The attribute must have a setter, which is added to a class with
+    /// [ClassBuilder::add_attribute_setter](super::oso::ClassBuilder::add_attribute_setter), and
+    /// the value must be of the setter's parameter type.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
+    ///
+    /// struct Foo { id: i32 }
+    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .add_attribute_getter("id", |foo: &Foo| { foo.id })
+    ///         .add_attribute_setter("id", |foo: &mut Foo, id: i32| { foo.id = id }, "int")
+    ///         .build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    /// registry.set_attr("/foo", "id", PolarValue::Integer(43)).unwrap();
+    ///
+    /// assert_eq!(registry.attr_value::<i32>("/foo", "id").unwrap(), 43)
+    /// ```
+    pub fn set_attr(&mut self, pwd: &str, cd: &str, value: PolarValue)
+                    -> Result<(), RegistryError> {
+        let (owner, attr_name, setter) = self.attr_setter(pwd, cd)?;
+        let class_name = self.paths[owner].instance.as_ref().unwrap()
+            .class(&self.host).unwrap().fq_name.to_owned();
+
+        let params = vec![value];
+        self.validate_params(&params, &class_name, attr_name, &vec![setter.param_type()])?;
+        self._set_attr(pwd, cd, &class_name, attr_name, owner, &setter, params)
+    }
+
+    /// Sets the value of the attribute stored at the specified path like [Registry::set_attr].
+    ///
+    /// The value is parsed from a string into the parameter type of the attribute's setter.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
+    ///
+    /// struct Foo { id: i32 }
+    /// impl Foo { fn new(id: i32) -> Foo { Foo { id } } }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .add_attribute_getter("id", |foo: &Foo| { foo.id })
+    ///         .add_attribute_setter("id", |foo: &mut Foo, id: i32| { foo.id = id }, "int")
+    ///         .build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    /// registry.parsed_set_attr("/foo/id", ".", "43").unwrap();
+    ///
+    /// assert_eq!(registry.attr_value::<i32>("/foo", "id").unwrap(), 43)
+    /// ```
+    pub fn parsed_set_attr(&mut self, pwd: &str, cd: &str, arg: &str)
+                           -> Result<(), RegistryError> {
+        self.parsed_set_attr_with_values(pwd, cd, arg, &HashMap::new())
+    }
+
+    /// Sets the value of an attribute like [Registry::parsed_set_attr], except the argument is
+    /// replaced by the typed value at index 0 of `values` instead of being parsed.
+    pub(crate) fn parsed_set_attr_with_values(&mut self,
+                                              pwd: &str,
+                                              cd: &str,
+                                              arg: &str,
+                                              values: &HashMap<usize, PolarValue>)
+                                              -> Result<(), RegistryError> {
+        let (owner, attr_name, setter) = self.attr_setter(pwd, cd)?;
+        let class = self.paths[owner].instance.as_ref().unwrap()
+            .class(&self.host).unwrap();
+        let class_name = class.fq_name.to_owned();
+
+        let params = self.parse_params(
+            &class.name, attr_name, &vec![arg], values, &vec![setter.param_type()])?;
+        self._set_attr(pwd, cd, &class_name, attr_name, owner, &setter, params)
+    }
+
+    /// Returns the instance id, the name, and the setter of the attribute at the specified path.
+    fn attr_setter(&self, pwd: &str, cd: &str)
+                   -> Result<(usize, &'static str, AttributeSetter), RegistryError> {
+        let attr_path = self.cd(pwd, cd)?;
+        let attr_name = match attr_path.attr {
+            Some(name) => name,
+            None => return Err(RegistryError::MissingAtPath {
+                path: attr_path.abs_path.to_owned(),
+                expected: "attribute",
+            })
+        };
+
+        let owner = attr_path.owner.unwrap();
+        let instance = self.paths[owner].instance.as_ref().unwrap();
+        let class = instance.class(&self.host).unwrap();
+        match class.attribute_setters.get(attr_name) {
+            Some(setter) => Ok((owner, attr_name, setter.clone())),
+            None => Err(RegistryError::ReadOnlyAttribute {
+                class: class.name.to_owned(),
+                attr: attr_name.to_owned(),
+            })
+        }
+    }
+
+    /// Sets the attribute on the instance of the specified path, which the registry owns.
+    fn _set_attr(&mut self,
+                 pwd: &str,
+                 cd: &str,
+                 class_name: &str,
+                 attr_name: &str,
+                 instance_id: usize,
+                 setter: &AttributeSetter,
+                 mut params: Vec<PolarValue>) -> Result<(), RegistryError> {
+        let instance = self.paths[instance_id].instance.as_mut().unwrap();
+        setter.invoke(instance, params.remove(0), &self.host).map_err(|e| {
+            RegistryError::InvocationFailure {
+                pwd: pwd.to_owned(),
+                cd: cd.to_owned(),
+                class: class_name.to_owned(),
+                method: attr_name.to_owned(),
+                invocation_type: "attribute setter",
+                reason: "attribute setter invocation failure",
+                error: e,
+            }
+        })
+    }
+
+    //
+    // Invoke methods
+    //
+
+    /// Invokes the instance method, or returns the value of the attribute, stored at the specified
+    /// path.
+    ///
+    /// Each of the arguments are parsed from strings into the parameter type defined on the [Class]
+    /// definition.
+    /// Attributes do not take any arguments.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
+    ///
+    /// struct Foo { id: i32 }
+    /// impl Foo {
+    ///     fn new(id: i32) -> Foo { Foo { id } }
+    ///     fn add_to_id(&self, val: i32) -> i32 { self.id + val }
+    /// }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .add_attribute_getter("id", |foo: &Foo| { foo.id })
+    ///         .add_method("add_to_id", Foo::add_to_id, vec!["int"], Some("add")).build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    ///
+    /// assert_eq!(registry.parsed_invoke("/foo", "add", &vec!["15"]).unwrap(), 57.to_polar());
+    /// assert_eq!(registry.parsed_invoke("/foo", "id", &vec![]).unwrap(), 42.to_polar());
+    /// ```
+    pub fn parsed_invoke(&mut self, pwd: &str, cd: &str, params: &Vec<&str>)
+                         -> Result<PolarValue, RegistryError> {
+        self.parsed_invoke_with_values(pwd, cd, params, &HashMap::new())
+    }
+
+    /// Invokes a method or returns the value of an attribute like [Registry::parsed_invoke],
+    /// except the arguments with an index in `values` are replaced by the typed value instead of
+    /// being parsed.
+    pub(crate) fn parsed_invoke_with_values(&mut self,
+                                            pwd: &str,
+                                            cd: &str,
+                                            params: &Vec<&str>,
+                                            values: &HashMap<usize, PolarValue>)
+                                            -> Result<PolarValue, RegistryError> {
+        if self.is_mut_method(pwd, cd).unwrap_or(false) {
+            return self.parsed_invoke_method_with_values(pwd, cd, params, values);
+        }
+        self.parsed_invoke_ref_with_values(pwd, cd, params, values)
+    }
+
+    /// Invokes a method or returns the value of an attribute like
+    /// [Registry::parsed_invoke_with_values], except the registry is only borrowed, so methods
+    /// that take `&mut self` fail.
+    pub(crate) fn parsed_invoke_ref_with_values(&self,
+                                                pwd: &str,
+                                                cd: &str,
+                                                params: &Vec<&str>,
+                                                values: &HashMap<usize, PolarValue>)
+                                                -> Result<PolarValue, RegistryError> {
+        let path = self.cd(pwd, cd)?;
+        let attr_name = match (path.method, path.attr) {
+            (Some(_), _) => {
+                let (owner, method_name, instance_method, class_name, params) =
+                    self.parsed_method_params(pwd, cd, params, values)?;
+                let instance = self.paths[owner].instance.as_ref().unwrap();
+                return instance_method.invoke(instance, params, &self.host).map_err(|e| {
+                    Registry::invocation_failure(pwd, cd, &class_name, method_name, e)
+                });
+            }
+            (None, Some(name)) => name,
+            (None, None) => return Err(RegistryError::MissingAtPath {
+                path: path.abs_path.to_owned(),
+                expected: "method or attribute",
+            })
+        };
+
+        if !params.is_empty() {
+            let instance = path.owner_instance(self).unwrap();
+            return Err(RegistryError::InvalidNumberOfMethodParameters {
+                class: self.class_for_instance(instance).name.to_owned(),
+                method: attr_name.to_owned(),
+                expected: 0,
+                received: params.len(),
+            });
+        }
+        self.attr(pwd, cd)
+    }
+
+    /// Invokes the instance method stored at the specified path and casts the return value of the
+    /// instance method to the specified type.
+    ///
+    /// Each of the arguments are parsed from strings into the parameter type defined on the [Class]
+    /// definition.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue};
+    ///
+    /// #[derive(PartialEq, Debug)]
+    /// struct Foo { id: i32 }
+    /// impl Foo {
+    ///     fn new(id: i32) -> Foo { Foo { id } }
+    ///     fn add_to_id(&self, val: i32) -> i32 { self.id + val }
+    /// }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .add_method("add_to_id", Foo::add_to_id, vec!["int"], Some("add")).build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    ///
+    /// assert_eq!(registry.parsed_invoke_method_value::<i32>("/foo", "add", &vec!["15"]).unwrap(),
+    ///            42 + 15)
+    /// ```
+    pub fn parsed_invoke_method_value<T: 'static + FromPolar>(&mut self,
+                                                              pwd: &str,
+                                                              cd: &str,
+                                                              params: &Vec<&str>)
+                                                              -> Result<T, RegistryError> {
+        let result = self.parsed_invoke_method(pwd, cd, params)?;
+        Self::cast::<T>(pwd, cd, "method", result)
+    }
+
+    /// Invokes the instance method stored at the specified path and returns the return value of the
+    /// instance method.
+    ///
+    /// Each of the arguments are parsed from strings into the parameter type defined on the [Class]
+    /// definition.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
+    ///
+    /// #[derive(PartialEq, Debug)]
+    /// struct Foo { id: i32 }
+    /// impl Foo {
+    ///     fn new(id: i32) -> Foo { Foo { id } }
+    ///     fn add_to_id(&self, val: i32) -> i32 { self.id + val }
+    /// }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .add_method("add_to_id", Foo::add_to_id, vec!["int"], Some("add")).build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    ///
+    /// assert_eq!(registry.parsed_invoke_method("/foo", "add", &vec!["15"]).unwrap(),
+    ///            (42 + 15).to_polar())
+    /// ```
+    pub fn parsed_invoke_method(&mut self, pwd: &str, cd: &str, params: &Vec<&str>)
+                                -> Result<PolarValue, RegistryError> {
+        self.parsed_invoke_method_with_values(pwd, cd, params, &HashMap::new())
+    }
+
+    /// Invokes a method like [Registry::parsed_invoke_method], except the arguments with an index
+    /// in `values` are replaced by the typed value instead of being parsed.
+    pub(crate) fn parsed_invoke_method_with_values(&mut self,
+                                                   pwd: &str,
+                                                   cd: &str,
+                                                   params: &Vec<&str>,
+                                                   values: &HashMap<usize, PolarValue>)
+                                                   -> Result<PolarValue, RegistryError> {
+        let (owner, method_name, instance_method, class_name, params) =
+            self.parsed_method_params(pwd, cd, params, values)?;
+        self._invoke_method(pwd, cd, &class_name, method_name, owner, &instance_method, params)
+    }
+
+    /// Looks up the instance method stored at the specified path like
+    /// [Registry::instance_method] and parses the params into the method's parameter types.
+    fn parsed_method_params(&self,
+                            pwd: &str,
+                            cd: &str,
+                            params: &Vec<&str>,
+                            values: &HashMap<usize, PolarValue>)
+                            -> Result<(usize, &'static str, InstanceMethod, String, Vec<PolarValue>),
+                                RegistryError> {
+        let (owner, method_name, instance_method, class_name) = self.instance_method(pwd, cd)?;
+        let instance = self.paths[owner].instance.as_ref().unwrap();
+        let class = self.class_for_instance(instance);
+
+        // parse the params into PolarValues
+        let params = self.parse_params(
+            &class.name, method_name, params, values, instance_method.param_types())?;
+        Ok((owner, method_name, instance_method, class_name, params))
+    }
+
+
+    /// Invokes the instance method stored at the specified path and casts the return value of the
+    /// instance method to the specified type.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
+    ///
+    /// #[derive(PartialEq, Debug)]
+    /// struct Foo { id: i32 }
+    /// impl Foo {
+    ///     fn new(id: i32) -> Foo { Foo { id } }
+    ///     fn add_to_id(&self, val: i32) -> i32 { self.id + val }
+    /// }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .add_method("add_to_id", Foo::add_to_id, vec!["int"], Some("add")).build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    ///
+    /// assert_eq!(registry.invoke_method_value::<i32>("/foo", "add", vec![15.to_polar()]).unwrap(),
+    ///            42 + 15)
+    /// ```
+    pub fn invoke_method_value<T: 'static + FromPolar>(&mut self,
+                                                       pwd: &str,
+                                                       cd: &str,
+                                                       params: Vec<PolarValue>)
+                                                       -> Result<T, RegistryError> {
+        let result = self.invoke_method(pwd, cd, params)?;
+        Self::cast::<T>(pwd, cd, "method", result)
+    }
+
+    /// Invokes the instance method stored at the specified path and returns the return value of the
+    /// instance method.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::Registry;
+    /// use rcore::command::oso::{Class, ClassBuilder, PolarValue, ToPolar};
+    ///
+    /// #[derive(PartialEq, Debug)]
+    /// struct Foo { id: i32 }
+    /// impl Foo {
+    ///     fn new(id: i32) -> Foo { Foo { id } }
+    ///     fn add_to_id(&self, val: i32) -> i32 { self.id + val }
+    /// }
+    ///
+    /// let class = ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+    ///         .add_method("add_to_id", Foo::add_to_id, vec!["int"], Some("add")).build();
+    /// let mut registry = Registry::default();
+    /// registry.cache_class(class).unwrap();
+    ///
+    /// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+    ///
+    /// assert_eq!(registry.invoke_method("/foo", "add", vec![15.to_polar()]).unwrap(),
+    ///            (42 + 15).to_polar())
+    /// ```
+    pub fn invoke_method(&mut self, pwd: &str, cd: &str, params: Vec<PolarValue>)
+                         -> Result<PolarValue, RegistryError> {
+        let (owner, method_name, instance_method, class_name) = self.instance_method(pwd, cd)?;
+
         // validate params of the instance method
-        self.validate_params(&params, &class.fq_name, method_name, instance_method.param_types())?;
-        self._invoke_method(pwd, cd, &class.fq_name, method_name, instance, instance_method, params)
+        self.validate_params(&params, &class_name, method_name, instance_method.param_types())?;
+        self._invoke_method(pwd, cd, &class_name, method_name, owner, &instance_method, params)
+    }
+
+    /// Invokes the instance method stored at the specified path like [Registry::invoke_method],
+    /// except the registry is only borrowed, so several threads can invoke methods at the same
+    /// time through a [SharedRegistry].
+    ///
+    /// Methods that take `&mut self` cannot change the instance, so they fail with an
+    /// [RegistryError::InvocationFailure] (see [Registry::is_mut_method]).
+    pub fn invoke_method_ref(&self, pwd: &str, cd: &str, params: Vec<PolarValue>)
+                             -> Result<PolarValue, RegistryError> {
+        let (owner, method_name, instance_method, class_name) = self.instance_method(pwd, cd)?;
+
+        self.validate_params(&params, &class_name, method_name, instance_method.param_types())?;
+        let instance = self.paths[owner].instance.as_ref().unwrap();
+        instance_method.invoke(instance, params, &self.host).map_err(|e| {
+            Registry::invocation_failure(pwd, cd, &class_name, method_name, e)
+        })
+    }
+
+    /// Returns true if the instance method stored at the specified path takes `&mut self`, so
+    /// invoking it changes the instance.
+    pub fn is_mut_method(&self, pwd: &str, cd: &str) -> Result<bool, RegistryError> {
+        Ok(self.instance_method(pwd, cd)?.2.is_mut())
+    }
+
+    /// Returns the id of the instance that owns the method stored at the specified path, the name
+    /// of the method, the method, and the fully qualified name of the instance's class.
+    fn instance_method(&self, pwd: &str, cd: &str)
+                       -> Result<(usize, &'static str, InstanceMethod, String), RegistryError> {
+        // check that we are an method node
+        let method_path = self.cd(pwd, cd)?;
+        let method_name = match method_path.method {
+            Some(name) => name,
+            None => return Err(RegistryError::MissingAtPath {
+                path: method_path.abs_path.to_owned(),
+                expected: "method",
+            })
+        };
+
+        // lookup the instance for the method and then the class
+        let owner = method_path.owner.unwrap();
+        let instance = self.paths[owner].instance.as_ref().unwrap();
+        let class = instance.class(&self.host).unwrap();
+        let instance_method = class.instance_methods.get(method_name).unwrap().clone();
+        Ok((owner, method_name, instance_method, class.fq_name.to_owned()))
+    }
+
+    /// Invokes the method on the instance of the specified path, which the registry owns, so
+    /// methods that take `&mut self` can change the instance.
+    fn _invoke_method(&mut self,
+                      pwd: &str,
+                      cd: &str,
+                      class_name: &str,
+                      method_name: &str,
+                      instance_id: usize,
+                      method: &InstanceMethod,
+                      params: Vec<PolarValue>) -> Result<PolarValue, RegistryError> {
+        let instance = self.paths[instance_id].instance.as_mut().unwrap();
+        method.invoke_mut(instance, params, &self.host).map_err(|e| {
+            Registry::invocation_failure(pwd, cd, class_name, method_name, e)
+        })
+    }
+
+    fn invocation_failure(pwd: &str, cd: &str, class_name: &str, method_name: &str, error: OsoError)
+                          -> RegistryError {
+        RegistryError::InvocationFailure {
+            pwd: pwd.to_owned(),
+            cd: cd.to_owned(),
+            class: class_name.to_owned(),
+            method: method_name.to_owned(),
+            invocation_type: "method",
+            reason: "method invocation failure",
+            error,
+        }
+    }
+}
+
+/// Returns the text of a value that is parsed back into the value by the shell, or `None` if the
+/// value cannot be written as text.
+fn dump_value(value: &PolarValue) -> Option<String> {
+    match value {
+        PolarValue::Integer(i) => Some(i.to_string()),
+        PolarValue::Float(f) => Some(f.to_string()),
+        PolarValue::Boolean(b) => Some(b.to_string()),
+        PolarValue::String(s) => Some(quote(s)),
+        _ => None
+    }
+}
+
+/// Quotes text so it is read by the shell as a single argument with the same value.
+fn quote(text: &str) -> String {
+    if !text.is_empty()
+        && text.chars().all(|c| c.is_alphanumeric() || "-_./:+,@%".contains(c)) {
+        text.to_owned()
+    } else if !text.contains('\'') && !text.chars().any(|c| c.is_control()) {
+        format!("'{}'", text)
+    } else {
+        let mut quoted = "\"".to_owned();
+        for c in text.chars() {
+            match c {
+                '\\' => quoted.push_str("\\\\"),
+                '"' => quoted.push_str("\\\""),
+                '$' => quoted.push_str("\\x24"),
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                '\r' => quoted.push_str("\\r"),
+                '\0' => quoted.push_str("\\0"),
+                c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                c => quoted.push(c)
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+/// The version of the format of the snapshots returned by [Registry::save].
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// A JSON snapshot of the paths of a registry.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    paths: Vec<PathSnapshot>,
+}
+
+/// A path that is re-created when a snapshot is loaded.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum PathSnapshot {
+    Dir {
+        path: String,
+    },
+    Instance {
+        path: String,
+        class: String,
+        args: Vec<Value>,
+        #[serde(default)]
+        attributes: BTreeMap<String, Value>,
+    },
+    Link {
+        path: String,
+        target: String,
+    },
+}
+
+/// Returns the path of a saved instance of the registry, like `{"instance": "/feeds/nyse"}`.
+fn saved_instance_path(json: &Value) -> Option<&str> {
+    match json {
+        Value::Object(values) if values.len() == 1 => values.get("instance")?.as_str(),
+        _ => None
+    }
+}
+
+/// Returns true if a parameter type is the name of a class, rather than a builtin type.
+fn is_class_type(param_type: &str) -> bool {
+    !matches!(param_type, "bool" | "boolean" | "int" | "integer" | "float" | "string"
+        | "list" | "vec" | "map" | "dict")
+}
+
+/// Returns the name of the type of a JSON value.
+fn json_type(json: &Value) -> &'static str {
+    match json {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// A handle to a [Registry] that is shared by several threads and [Shell](crate::command::Shell)s.
+///
+/// Cloning the handle shares the same registry.
+/// Any number of threads can read the registry at the same time (e.g., retrieve instances,
+/// retrieve the values of attributes, and invoke methods that take `&self`), while changes to the
+/// registry (e.g., creating directories and instances) are serialized and wait for the readers to
+/// finish.
+///
+/// The guards returned by [SharedRegistry::read] and [SharedRegistry::write] hold the lock until
+/// they are dropped, so they should not be held across an `.await` or while executing commands.
+///
+/// # Example
+/// ```
+/// use rcore::command::SharedRegistry;
+/// use rcore::command::oso::{ClassBuilder, PolarValue, ToPolar};
+///
+/// #[derive(Clone, PartialEq, Debug)]
+/// struct Foo { id: i32 }
+/// impl Foo {
+///     fn new(id: i32) -> Foo { Foo { id } }
+///     fn add_to_id(&self, val: i32) -> i32 { self.id + val }
+/// }
+///
+/// let registry = SharedRegistry::default();
+/// registry.cache_class(ClassBuilder::<Foo>::with_constructor(Foo::new, vec!["int"])
+///     .add_method("add_to_id", Foo::add_to_id, vec!["int"], Some("add")).build()).unwrap();
+/// registry.create_instance("/foo", ".", "Foo", vec![PolarValue::Integer(42)]).unwrap();
+///
+/// let worker_registry = registry.clone();
+/// let worker = std::thread::spawn(move || {
+///     worker_registry.invoke_method("/foo", "add", vec![15.to_polar()]).unwrap()
+/// });
+///
+/// assert_eq!((42 + 15).to_polar(), worker.join().unwrap());
+/// assert_eq!(&Foo { id: 42 }, registry.read().instance_value::<Foo>("/foo", ".").unwrap());
+/// ```
+#[derive(Clone, Default)]
+pub struct SharedRegistry {
+    registry: Arc<RwLock<Registry>>,
+}
+
+impl SharedRegistry {
+    /// Shares the specified registry.
+    pub fn new(registry: Registry) -> Self {
+        SharedRegistry {
+            registry: Arc::new(RwLock::new(registry)),
+        }
+    }
+
+    /// Locks the registry for reading, waiting for a writer to finish.
+    ///
+    /// A thread that panicked while holding the lock does not stop the registry from being read.
+    pub fn read(&self) -> RwLockReadGuard<'_, Registry> {
+        self.registry.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the registry for changes, waiting for the readers and other writers to finish.
+    pub fn write(&self) -> RwLockWriteGuard<'_, Registry> {
+        self.registry.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Caches a [Class] (see [Registry::cache_class]).
+    pub fn cache_class(&self, class: Class) -> Result<(), RegistryError> {
+        self.write().cache_class(class)
+    }
+
+    /// Creates a directory (see [Registry::mkdir]).
+    pub fn mkdir(&self, pwd: &str, cd: &str) -> Result<(), RegistryError> {
+        self.write().mkdir(pwd, cd)
+    }
+
+    /// Creates an instance of a class (see [Registry::create_instance]).
+    pub fn create_instance(&self, pwd: &str, cd: &str, class_name: &str, params: Vec<PolarValue>)
+                           -> Result<(), RegistryError> {
+        self.write().create_instance(pwd, cd, class_name, params)
+    }
+
+    /// Returns the value of an attribute (see [Registry::attr]).
+    pub fn attr(&self, pwd: &str, cd: &str) -> Result<PolarValue, RegistryError> {
+        self.read().attr(pwd, cd)
+    }
+
+    /// Returns the value of an attribute cast to the specified type (see [Registry::attr_value]).
+    pub fn attr_value<T: 'static + FromPolar>(&self, pwd: &str, cd: &str)
+                                              -> Result<T, RegistryError> {
+        self.read().attr_value(pwd, cd)
+    }
+
+    /// Invokes a method or returns the value of an attribute, choosing the lock like
+    /// [SharedRegistry::invoke_method].
+    pub(crate) fn parsed_invoke_with_values(&self,
+                                            pwd: &str,
+                                            cd: &str,
+                                            params: &Vec<&str>,
+                                            values: &HashMap<usize, PolarValue>)
+                                            -> Result<PolarValue, RegistryError> {
+        {
+            let registry = self.read();
+            if !registry.is_mut_method(pwd, cd).unwrap_or(false) {
+                return registry.parsed_invoke_ref_with_values(pwd, cd, params, values);
+            }
+        }
+        self.write().parsed_invoke_method_with_values(pwd, cd, params, values)
+    }
+
+    /// Invokes an instance method (see [Registry::invoke_method]).
+    ///
+    /// Methods that take `&self` are invoked while the registry is locked for reading, so they
+    /// run at the same time as other readers, while methods that take `&mut self` lock the
+    /// registry for changes.
+    pub fn invoke_method(&self, pwd: &str, cd: &str, params: Vec<PolarValue>)
+                         -> Result<PolarValue, RegistryError> {
+        {
+            let registry = self.read();
+            if !registry.is_mut_method(pwd, cd)? {
+                return registry.invoke_method_ref(pwd, cd, params);
+            }
+        }
+        self.write().invoke_method(pwd, cd, params)
+    }
+}
+
+/// Errors thrown when navigating the command tree.
+#[derive(Debug, Error)]
+pub enum RegistryError {
+    #[error("path does not contain an {expected} to retrieve: {path}")]
+    MissingAtPath {
+        path: String,
+        expected: &'static str,
+    },
+    #[error("cannot create child node at {pwd}/{child}")]
+    InvalidPathChildName {
+        pwd: String,
+        child: String,
+    },
+    #[error("cannot navigate to path: pwd={pwd}, cd={cd}, reason={reason}")]
+    IllegalPathNavigation {
+        pwd: String,
+        cd: String,
+        reason: &'static str,
+    },
+    #[error("cannot remove path: {path}, reason={reason}")]
+    IllegalRemove {
+        path: String,
+        reason: &'static str,
+    },
+    #[error("cannot move path: {path}, reason={reason}")]
+    IllegalMove {
+        path: String,
+        reason: &'static str,
+    },
+    #[error("cannot move or copy to path: {path}, reason={reason}")]
+    IllegalDestination {
+        path: String,
+        reason: &'static str,
+    },
+    #[error("cannot dump path: {path}, reason={reason}")]
+    IllegalDump {
+        path: String,
+        reason: &'static str,
+    },
+    #[error("instance passed to the constructor is no longer in the registry: path={path}, param_index={param_index}")]
+    MissingConstructorInstance {
+        path: String,
+        param_index: usize,
+    },
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("snapshot value has the wrong type: path={path}, name={name}, expected={expected}, got={got}")]
+    SnapshotTypeMismatch {
+        path: String,
+        name: String,
+        expected: String,
+        got: &'static str,
+    },
+    #[error("too many levels of links: {0}")]
+    LinkLoop(String),
+    #[error("duplicate path: {0}")]
+    DuplicatePath(String),
+    #[error("methods or attributes of class share the same name: class={class}, child={child}")]
+    ClassChildNameConflict {
+        class: String,
+        child: String,
+    },
+    #[error("attribute setter does not have a getter: class={class}, attr={attr}")]
+    SetterWithoutGetter {
+        class: String,
+        attr: String,
+    },
+    #[error("attribute cannot be set: {class}::{attr}")]
+    ReadOnlyAttribute {
+        class: String,
+        attr: String,
+    },
+    #[error("class has already been registered: {0}")]
+    DuplicateClass(String),
+    #[error("cannot make instance from class that is not registered: {0}")]
+    UnknownClass(String),
+    #[error("class does not have a constructor: {0}")]
+    NoConstructor(String),
+    #[error("invalid number of method parameters provided: {class}::{method} expects {expected} but received {received}")]
+    InvalidNumberOfMethodParameters {
+        class: String,
+        method: String,
+        expected: usize,
+        received: usize,
+    },
+    #[error("invalid method parameter type: {class}::{method} parameter {param_index} has type {param_type}: {reason}")]
+    InvalidMethodParameter {
+        class: String,
+        method: String,
+        param_index: usize,
+        param_type: &'static str,
+        reason: &'static str,
+    },
+    #[error("invalid {cast_type} cast: pwd={pwd}, cd={cd}, expected={expected}, got={got}")]
+    InvalidCast {
+        pwd: String,
+        cd: String,
+        cast_type: &'static str,
+        expected: String,
+        got: String,
+    },
+    #[error("an unhandled error from oso: reason={reason}, error={error}")]
+    InvocationFailure {
+        pwd: String,
+        cd: String,
+        class: String,
+        method: String,
+        invocation_type: &'static str,
+        reason: &'static str,
+        error: OsoError,
+    },
+}
+
+impl PartialEq for RegistryError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (RegistryError::MissingAtPath { path, expected },
+                RegistryError::MissingAtPath { path: path2, expected: expected2 }) =>
+                path == path2 && expected == expected2,
+            (RegistryError::InvalidPathChildName { pwd, child },
+                RegistryError::InvalidPathChildName { pwd: pwd2, child: child2 }) =>
+                pwd == pwd2 && child == child2,
+            (RegistryError::IllegalPathNavigation { pwd, cd, .. },
+                RegistryError::IllegalPathNavigation { pwd: pwd2, cd: cd2, .. }) =>
+                pwd == pwd2 && cd == cd2,
+            (RegistryError::IllegalRemove { path, reason },
+                RegistryError::IllegalRemove { path: path2, reason: reason2 }) =>
+                path == path2 && reason == reason2,
+            (RegistryError::IllegalMove { path, reason },
+                RegistryError::IllegalMove { path: path2, reason: reason2 }) =>
+                path == path2 && reason == reason2,
+            (RegistryError::IllegalDestination { path, reason },
+                RegistryError::IllegalDestination { path: path2, reason: reason2 }) =>
+                path == path2 && reason == reason2,
+            (RegistryError::IllegalDump { path, reason },
+                RegistryError::IllegalDump { path: path2, reason: reason2 }) =>
+                path == path2 && reason == reason2,
+            (RegistryError::MissingConstructorInstance { path, param_index },
+                RegistryError::MissingConstructorInstance { path: path2, param_index: param_index2 }) =>
+                path == path2 && param_index == param_index2,
+            (RegistryError::InvalidSnapshot(reason),
+                RegistryError::InvalidSnapshot(reason2)) =>
+                reason == reason2,
+            (RegistryError::SnapshotTypeMismatch { path, name, expected, got },
+                RegistryError::SnapshotTypeMismatch { path: path2, name: name2, expected: expected2, got: got2 }) =>
+                path == path2 && name == name2 && expected == expected2 && got == got2,
+            (RegistryError::LinkLoop(path),
+                RegistryError::LinkLoop(path2)) =>
+                path == path2,
+            (RegistryError::DuplicatePath(path),
+                RegistryError::DuplicatePath(path2)) =>
+                path == path2,
+            (RegistryError::DuplicateClass(class),
+                RegistryError::DuplicateClass(class2)) =>
+                class == class2,
+            (RegistryError::ClassChildNameConflict { class, child },
+                RegistryError::ClassChildNameConflict { class: class2, child: child2 }) =>
+                class == class2 && child == child2,
+            (RegistryError::SetterWithoutGetter { class, attr },
+                RegistryError::SetterWithoutGetter { class: class2, attr: attr2 }) =>
+                class == class2 && attr == attr2,
+            (RegistryError::ReadOnlyAttribute { class, attr },
+                RegistryError::ReadOnlyAttribute { class: class2, attr: attr2 }) =>
+                class == class2 && attr == attr2,
+            (RegistryError::UnknownClass(class),
+                RegistryError::UnknownClass(class2)) =>
+                class == class2,
+            (RegistryError::NoConstructor(class),
+                RegistryError::NoConstructor(class2)) =>
+                class == class2,
+            (RegistryError::InvalidNumberOfMethodParameters { class, method, expected, received },
+                RegistryError::InvalidNumberOfMethodParameters { class: class2, method: method2, expected: expected2, received: received2 }) =>
+                class == class2 && method == method2 && expected == expected2 && received == received2,
+            (RegistryError::InvalidMethodParameter { class, method, param_index, param_type, .. },
+                RegistryError::InvalidMethodParameter { class: class2, method: method2, param_index: param_index2, param_type: param_type2, .. }) =>
+                class == class2 && method == method2 && param_index == param_index2 && param_type == param_type2,
+            (RegistryError::InvalidCast { pwd, cd, cast_type, expected, got },
+                RegistryError::InvalidCast { pwd: pwd2, cd: cd2, cast_type: cast_type2, expected: expected2, got: got2 }) =>
+                pwd == pwd2 && cd == cd2 && cast_type == cast_type2 && expected == expected2 && got == got2,
+            (RegistryError::InvocationFailure { pwd, cd, class, method, invocation_type, .. },
+                RegistryError::InvocationFailure { pwd: pwd2, cd: cd2, class: class2, method: method2, invocation_type: invocation_type2, .. }) =>
+                pwd == pwd2 && cd == cd2 && class == class2 && method == method2 && invocation_type == invocation_type2,
+            _ => false
+        }
+    }
+
+    fn ne(&self, other: &Self) -> bool {
+        !eq(self, other)
+    }
+}
+
+#[cfg(test)]
+mod path_tests {
+    use std::collections::BTreeMap;
+    use crate::command::registry::{FindFilter, RegistryError};
+    use super::Registry;
+
+    #[test]
+    fn mkdir_absolute_directory() {
+        let mut registry = Registry::default();
+        registry.mkdir("/bar/me", "/foo").unwrap();
+
+        registry.mkdir("/foo", "/bar/soo").unwrap();
+
+        let node = registry.path("/bar/soo").unwrap();
+        assert_eq!("/bar/soo", node.abs_path);
+    }
+
+    #[test]
+    fn mkdir_creates_child_directory() {
+        let mut registry = Registry::default();
+
+        registry.mkdir("/", "foo").unwrap();
+
+        let node = registry.path("/foo").unwrap();
+        assert_eq!("foo", node.name);
+        assert_eq!("/foo", node.abs_path);
+        assert_eq!(0, node.children.len());
+        assert_eq!(true, node.instance.is_none());
+    }
+
+    #[test]
+    fn mkdir_creates_grandchild_directories() {
+        let mut registry = Registry::default();
+
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+
+        // then
+        let root = registry.path("/").unwrap();
+        let child = registry.path("/foo").unwrap();
+        let grandchild = registry.path("/foo/bar").unwrap();
+        let great_grandchild = registry.path("/foo/bar/soo").unwrap();
+
+        assert_eq!("soo", great_grandchild.name);
+        assert_eq!("/foo/bar/soo", great_grandchild.abs_path);
+        assert_eq!(Some(grandchild.id), great_grandchild.parent);
+        assert!(great_grandchild.children.is_empty());
+        assert!(great_grandchild.instance.is_none());
+
+        assert_eq!("bar", grandchild.name);
+        assert_eq!("/foo/bar", grandchild.abs_path);
+        assert_eq!(Some(child.id), grandchild.parent);
+        let mut children = BTreeMap::new();
+        children.insert("soo".to_owned(), great_grandchild.id);
+        assert_eq!(children, grandchild.children);
+
+        assert_eq!("foo", child.name);
+        assert_eq!("/foo", child.abs_path);
+        assert_eq!(Some(root.id), child.parent);
+        children.clear();
+        children.insert("bar".to_owned(), grandchild.id);
+        assert_eq!(children, child.children);
+
+        assert_eq!("", root.name);
+        assert_eq!("/", root.abs_path);
+        assert_eq!(None, root.parent);
+        children.clear();
+        children.insert("foo".to_owned(), child.id);
+        assert_eq!(children, root.children);
+    }
+
+    #[test]
+    fn mkdir_from_child() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "/foo/bar").unwrap();
+
+        registry.mkdir("/foo/bar", "../soo").unwrap();
+
+        let node = registry.path("/foo/soo").unwrap();
+        assert_eq!("/foo/soo", node.abs_path);
+    }
+
+    #[test]
+    fn mkdir_with_absolute_path_from_child_directory() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "/foo/bar").unwrap();
+
+        registry.mkdir("/foo/bar", "/soo").unwrap();
+
+        let node = registry.path("/soo").unwrap();
+        assert_eq!("/soo", node.abs_path);
+    }
+
+    #[test]
+    fn mkdir_with_current_directory() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "/foo/bar").unwrap();
+
+        registry.mkdir("/foo/bar", "./soo").unwrap();
+
+        let node = registry.path("/foo/bar/soo").unwrap();
+        assert_eq!("/foo/bar/soo", node.abs_path);
+    }
+
+    #[test]
+    fn mkdir_with_empty_directories() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "/foo/bar").unwrap();
+
+        registry.mkdir("/foo/bar", "soo///doo").unwrap();
+
+        let node = registry.path("/foo/bar/soo/doo").unwrap();
+        assert_eq!("/foo/bar/soo/doo", node.abs_path);
+    }
+
+    #[test]
+    fn remove_empty_directory() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar").unwrap();
+
+        registry.remove("/foo", "bar", false).unwrap();
+
+        assert!(registry.path("/foo/bar").is_err());
+        assert!(!registry.path("/foo").unwrap().has_children());
+    }
+
+    #[test]
+    fn remove_directory_with_children_is_error() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar").unwrap();
+
+        let result = registry.remove("/", "foo", false).err().unwrap();
+
+        assert_eq!(RegistryError::IllegalRemove {
+            path: "/foo".to_owned(),
+            reason: "directory is not empty",
+        }, result);
+        assert!(registry.path("/foo/bar").is_ok());
+    }
+
+    #[test]
+    fn remove_directory_recursively() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+        registry.mkdir("/", "foo/me").unwrap();
+        let size = registry.paths.len();
+
+        registry.remove("/", "foo", true).unwrap();
+
+        assert!(registry.path("/foo").is_err());
+        assert_eq!(size - 4, registry.paths.len());
+        registry.mkdir("/", "foo").unwrap();
+    }
+
+    #[test]
+    fn ids_are_allocated_in_order() {
+        let mut registry = Registry::default();
+
+        registry.mkdir("/", "foo/bar").unwrap();
+        registry.mkdir("/", "me").unwrap();
+
+        assert_eq!(0, registry.path("/").unwrap().id);
+        assert_eq!(1, registry.path("/foo").unwrap().id);
+        assert_eq!(2, registry.path("/foo/bar").unwrap().id);
+        assert_eq!(3, registry.path("/me").unwrap().id);
+    }
+
+    #[test]
+    fn ids_of_removed_paths_are_reused() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar").unwrap();
+        registry.mkdir("/", "me").unwrap();
+
+        registry.remove("/", "foo", true).unwrap();
+        registry.mkdir("/", "soo/doo").unwrap();
+        registry.mkdir("/", "boo").unwrap();
+
+        assert_eq!(2, registry.path("/soo").unwrap().id);
+        assert_eq!(1, registry.path("/soo/doo").unwrap().id);
+        assert_eq!(4, registry.path("/boo").unwrap().id);
+        assert_eq!(5, registry.paths.len());
+    }
+
+    #[test]
+    fn index_is_updated_on_remove() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+
+        registry.remove("/", "foo", true).unwrap();
+
+        assert_eq!(1, registry.index.len());
+        assert!(registry.path("/foo/bar/soo").is_err());
+    }
+
+    #[test]
+    fn find_with_max_depth() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+        registry.mkdir("/", "boo").unwrap();
+
+        let all = registry.find("/", ".", &FindFilter::default()).unwrap();
+        let depth = registry.find("/", ".", &FindFilter {
+            name: Some("*oo".to_owned()),
+            max_depth: Some(1),
+            ..FindFilter::default()
+        }).unwrap();
+
+        assert_eq!(vec!["/", "/boo", "/foo", "/foo/bar", "/foo/bar/soo"], all);
+        assert_eq!(vec!["/boo", "/foo"], depth);
+    }
+
+    #[test]
+    fn remove_root_is_error() {
+        let mut registry = Registry::default();
+
+        let result = registry.remove("/", ".", true).err().unwrap();
+
+        assert_eq!(RegistryError::IllegalRemove {
+            path: "/".to_owned(),
+            reason: "cannot remove the root directory",
+        }, result);
+    }
+
+    #[test]
+    fn move_path_updates_subtree() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+        registry.mkdir("/", "me").unwrap();
+
+        registry.move_path("/foo", "bar", "/me").unwrap();
+
+        let node = registry.path("/me/bar/soo").unwrap();
+        assert_eq!("/me/bar/soo", node.abs_path);
+        assert_eq!("/me/bar", registry.cd("/me/bar/soo", "..").unwrap().abs_path);
+        assert!(!registry.path("/foo").unwrap().has_children());
+        assert!(registry.path("/foo/bar/soo").is_err());
+        assert_eq!(registry.paths.len(), registry.index.len());
+    }
+
+    #[test]
+    fn move_root_is_error() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo").unwrap();
+
+        let result = registry.move_path("/", "/", "foo").err().unwrap();
+
+        assert_eq!(RegistryError::IllegalMove {
+            path: "/".to_owned(),
+            reason: "cannot move the root directory",
+        }, result);
+    }
+
+    #[test]
+    fn rename_path() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+
+        registry.rename("/foo", "bar", "me").unwrap();
+
+        assert_eq!("/foo/me/soo", registry.path("/foo/me/soo").unwrap().abs_path);
+        assert!(registry.path("/foo/bar").is_err());
+    }
+
+    #[test]
+    fn rename_to_existing_name_is_error() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar").unwrap();
+        registry.mkdir("/", "foo/me").unwrap();
+
+        let result = registry.rename("/foo", "bar", "me").err().unwrap();
+
+        assert_eq!(RegistryError::DuplicatePath("/foo/me".to_owned()), result);
+    }
+
+    #[test]
+    fn cd_follows_links() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+        registry.link("/", "me", "foo/bar", false).unwrap();
+        registry.link("/foo", "you", "../me/soo", false).unwrap();
+
+        assert_eq!("/foo/bar/soo", registry.cd("/me", "soo").unwrap().abs_path);
+        assert_eq!("/foo/bar/soo", registry.cd("/", "foo/you").unwrap().abs_path);
+        assert_eq!(Some("../me/soo"), registry.cd_no_follow("/", "foo/you").unwrap().link());
+    }
+
+    #[test]
+    fn mkdir_through_link() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo").unwrap();
+        registry.link("/", "me", "/foo", false).unwrap();
+
+        registry.mkdir("/me", "bar").unwrap();
+
+        assert_eq!("/foo/bar", registry.path("/me/bar").unwrap().abs_path);
+    }
+
+    #[test]
+    fn cd_link_loop_is_error() {
+        let mut registry = Registry::default();
+        registry.link("/", "me", "me", false).unwrap();
+
+        let result = registry.cd("/", "me").err().unwrap();
+
+        assert_eq!(RegistryError::LinkLoop("/me".to_owned()), result);
+    }
+
+    #[test]
+    fn cd_to_parent() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+
+        let grandchild = registry.cd("/foo/bar/soo", "..").unwrap();
+
+        assert_eq!("/foo/bar", grandchild.abs_path);
+    }
+
+    #[test]
+    fn cd_to_grandparent() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+
+        let grandchild = registry.cd("/foo/bar/soo", "../../").unwrap();
+
+        assert_eq!("/foo", grandchild.abs_path);
+    }
+
+    #[test]
+    fn cd_to_great_grandparent() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+
+        let grandchild = registry.cd("/foo/bar/soo", "../../../").unwrap();
+
+        assert_eq!("/", grandchild.abs_path);
+    }
+
+    #[test]
+    fn cd_beyond_root_is_error() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+
+        let result = registry.cd("/foo/bar/soo", "../../../..").err().unwrap();
+
+        assert_eq!(RegistryError::IllegalPathNavigation {
+            pwd: "/foo/bar/soo".to_owned(),
+            cd: "../../../..".to_owned(),
+            reason: "",
+        }, result);
+    }
+
+    #[test]
+    fn cd_to_unknown_directory_is_error() {
+        let mut registry = Registry::default();
+        registry.mkdir("/", "foo/bar/soo").unwrap();
+
+        let result = registry.cd("/foo/bar/soo", "doo").err().unwrap();
+
+        assert_eq!(RegistryError::IllegalPathNavigation {
+            pwd: "/foo/bar/soo".to_owned(),
+            cd: "doo".to_owned(),
+            reason: "",
+        }, result);
+    }
+}
+
+#[cfg(test)]
+mod registry_tests {
+    use crate::command::oso::{PolarClass, PolarValue};
+    use crate::command::registry::{FindFilter, NodeKind, RegistryError, Registry, SharedRegistry};
+    use serde_json::{json, Value};
+
+    #[derive(Clone, PolarClass, Default)]
+    struct User {
+        #[polar(attribute)]
+        pub username: String,
+    }
+
+    impl User {
+        fn new(username: String) -> User {
+            User { username }
+        }
+    }
+
+    #[derive(Clone, PolarClass, Default)]
+    struct User2 {
+        pub username: String,
+        #[polar(attribute)]
+        pub user_id: i32,
+    }
+
+    impl User2 {
+        fn new(username: String, user_id: i32) -> User2 {
+            User2 { username, user_id }
+        }
+
+        fn add_one(&self, num: i32) -> i32 {
+            num + 1
+        }
+    }
+
+    fn create_registry() -> Registry {
+        let mut registry = Registry::default();
+        registry.cache_class(User::get_polar_class_builder()
+            .set_constructor(User::new, vec!["string"])
+            .build()).unwrap();
+        registry.cache_class(User2::get_polar_class_builder()
+            .set_constructor(User2::new, vec!["string", "int"])
+            .add_method("add_one", User2::add_one, vec!["int"], None)
+            .build()).unwrap();
+        registry
+    }
+
+    #[test]
+    fn make_instance_1_param() {
+        let mut registry = create_registry();
+
+        registry.create_instance("/foo", ".", "User", vec![PolarValue::String("jim".to_owned())]).unwrap();
+
+        let user = registry.instance_value::<User>("/", "foo").unwrap();
+        assert_eq!(user.username, "jim");
+    }
+
+    #[test]
+    fn make_instance_2_params() {
+        let mut registry = create_registry();
+
+        registry.create_instance("/foo", ".", "User2", vec![PolarValue::String("jim".to_owned()), PolarValue::Integer(42)]).unwrap();
+
+        let user = registry.instance_value::<User2>("/foo", ".").unwrap();
+        assert_eq!(user.username, "jim");
+        assert_eq!(user.user_id, 42);
+    }
+
+    #[test]
+    fn make_instance_1_param_strings() {
+        let mut registry = create_registry();
+
+        registry.parsed_create_instance("/foo", ".", "User", &vec!["jim"]).unwrap();
+
+        let user = registry.instance_value::<User>("/foo", ".").unwrap();
+        assert_eq!(user.username, "jim");
+    }
+
+    #[test]
+    fn make_instance_2_params_strings() {
+        let mut registry = create_registry();
+
+        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+
+        let user = registry.instance_value::<User2>("/", "foo").unwrap();
+        assert_eq!(user.username, "jim");
+        assert_eq!(user.user_id, 42);
+    }
+
+    #[test]
+    fn make_instance_at_already_created_directory() {
+        let mut registry = create_registry();
+        registry.mkdir("/foo", ".").unwrap();
+
+        registry.create_instance("/foo", ".", "User", vec![PolarValue::String("jim".to_owned())]).unwrap();
+
+        let user = registry.instance_value::<User>("/foo", ".").unwrap();
+        assert_eq!(user.username, "jim");
+    }
+
+
+    #[test]
+    fn make_instance_at_same_directory_is_error() {
+        let mut registry = create_registry();
+        registry.create_instance("/foo", ".", "User", vec![PolarValue::String("jim".to_owned())]).unwrap();
+
+        let result = registry.create_instance("/foo", ".", "User", vec![PolarValue::String("greco".to_owned())]).err().unwrap();
+
+        assert_eq!(RegistryError::DuplicatePath("/foo".to_owned()), result);
+        let user = registry.instance_value::<User>("/foo", ".").unwrap();
+        assert_eq!(user.username, "jim");
+    }
+
+    #[test]
+    fn get_attribute() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+
+        let result: i32 = registry.attr_value("/foo", "user_id").unwrap();
+
+        assert_eq!(42, result);
+    }
+
+    #[test]
+    fn get_attribute_wrong_path_is_error() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+
+        let result = registry.attr_value::<i32>("/foo", ".").err().unwrap();
+
+        assert_eq!(RegistryError::MissingAtPath {
+            path: "/foo".to_owned(),
+            expected: "attribute",
+        }, result);
+    }
+
+    #[test]
+    fn get_attribute_wrong_type_is_error() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+
+        let result = registry.attr_value::<f64>("/foo/user_id", ".").err().unwrap();
+
+        assert_eq!(RegistryError::InvalidCast {
+            pwd: "/foo/user_id".to_owned(),
+            cd: ".".to_owned(),
+            cast_type: "attribute",
+            expected: "float".to_owned(),
+            got: "".to_string(),
+        }, result);
+    }
+
+    #[test]
+    fn get_all_attributes() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+
+        let result = registry.attrs("/", "foo", &[]).unwrap();
+
+        assert_eq!(vec![("user_id".to_owned(), PolarValue::Integer(42))], result);
+    }
+
+    #[test]
+    fn get_named_attributes() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+
+        let result = registry.attrs("/foo", ".", &["user_id", "user_id"]).unwrap();
+
+        assert_eq!(vec![("user_id".to_owned(), PolarValue::Integer(42)),
+                        ("user_id".to_owned(), PolarValue::Integer(42))], result);
+    }
+
+    #[test]
+    fn get_unknown_attribute_is_error() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+
+        let result = registry.attrs("/foo", ".", &["add_one"]).err().unwrap();
+
+        assert_eq!(RegistryError::MissingAtPath {
+            path: "/foo/add_one".to_owned(),
+            expected: "attribute",
+        }, result);
     }
 
-    fn _invoke_method(&self,
-                      pwd: &str,
-                      cd: &str,
-                      class_name: &str,
-                      method_name: &str,
-                      instance: &Instance,
-                      method: &InstanceMethod,
-                      params: Vec<PolarValue>) -> Result<PolarValue, RegistryError> {
-        method.invoke(instance, params, &self.host).map_err(|e| {
-            RegistryError::InvocationFailure {
-                pwd: pwd.to_owned(),
-                cd: cd.to_owned(),
-                class: class_name.to_owned(),
-                method: method_name.to_owned(),
-                invocation_type: "method",
-                reason: "method invocation failure",
-                error: e,
-            }
-        })
+    #[test]
+    fn get_attributes_of_directory_is_error() {
+        let mut registry = create_registry();
+        registry.mkdir("/", "foo").unwrap();
+
+        let result = registry.attrs("/foo", ".", &[]).err().unwrap();
+
+        assert_eq!(RegistryError::MissingAtPath {
+            path: "/foo".to_owned(),
+            expected: "instance",
+        }, result);
     }
-}
 
-/// Errors thrown when navigating the command tree.
-#[derive(Debug, Error)]
-pub enum RegistryError {
-    #[error("path does not contain an {expected} to retrieve: {path}")]
-    MissingAtPath {
-        path: String,
-        expected: &'static str,
-    },
-    #[error("cannot create child node at {pwd}/{child}")]
-    InvalidPathChildName {
-        pwd: String,
-        child: String,
-    },
-    #[error("cannot navigate to path: pwd={pwd}, cd={cd}, reason={reason}")]
-    IllegalPathNavigation {
-        pwd: String,
-        cd: String,
-        reason: &'static str,
-    },
-    #[error("duplicate path: {0}")]
-    DuplicatePath(String),
-    #[error("methods or attributes of class share the same name: class={class}, child={child}")]
-    ClassChildNameConflict {
-        class: String,
-        child: String,
-    },
-    #[error("class has already been registered: {0}")]
-    DuplicateClass(String),
-    #[error("cannot make instance from class that is not registered: {0}")]
-    UnknownClass(String),
-    #[error("class does not have a constructor: {0}")]
-    NoConstructor(String),
-    #[error("invalid number of method parameters provided: {class}::{method} expects {expected} but received {received}")]
-    InvalidNumberOfMethodParameters {
-        class: String,
-        method: String,
-        expected: usize,
-        received: usize,
-    },
-    #[error("invalid method parameter type: {class}::{method} parameter {param_index} has type {param_type}: {reason}")]
-    InvalidMethodParameter {
-        class: String,
-        method: String,
-        param_index: usize,
-        param_type: &'static str,
-        reason: &'static str,
-    },
-    #[error("invalid {cast_type} cast: pwd={pwd}, cd={cd}, expected={expected}, got={got}")]
-    InvalidCast {
-        pwd: String,
-        cd: String,
-        cast_type: &'static str,
-        expected: String,
-        got: String,
-    },
-    #[error("an unhandled error from oso: reason={reason}, error={error}")]
-    InvocationFailure {
-        pwd: String,
-        cd: String,
-        class: String,
-        method: String,
-        invocation_type: &'static str,
-        reason: &'static str,
-        error: OsoError,
-    },
-}
+    #[test]
+    fn parse_and_invoke_attribute() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
 
-impl PartialEq for RegistryError {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (RegistryError::MissingAtPath { path, expected },
-                RegistryError::MissingAtPath { path: path2, expected: expected2 }) =>
-                path == path2 && expected == expected2,
-            (RegistryError::InvalidPathChildName { pwd, child },
-                RegistryError::InvalidPathChildName { pwd: pwd2, child: child2 }) =>
-                pwd == pwd2 && child == child2,
-            (RegistryError::IllegalPathNavigation { pwd, cd, .. },
-                RegistryError::IllegalPathNavigation { pwd: pwd2, cd: cd2, .. }) =>
-                pwd == pwd2 && cd == cd2,
-            (RegistryError::DuplicatePath(path),
-                RegistryError::DuplicatePath(path2)) =>
-                path == path2,
-            (RegistryError::DuplicateClass(class),
-                RegistryError::DuplicateClass(class2)) =>
-                class == class2,
-            (RegistryError::ClassChildNameConflict { class, child },
-                RegistryError::ClassChildNameConflict { class: class2, child: child2 }) =>
-                class == class2 && child == child2,
-            (RegistryError::UnknownClass(class),
-                RegistryError::UnknownClass(class2)) =>
-                class == class2,
-            (RegistryError::NoConstructor(class),
-                RegistryError::NoConstructor(class2)) =>
-                class == class2,
-            (RegistryError::InvalidNumberOfMethodParameters { class, method, expected, received },
-                RegistryError::InvalidNumberOfMethodParameters { class: class2, method: method2, expected: expected2, received: received2 }) =>
-                class == class2 && method == method2 && expected == expected2 && received == received2,
-            (RegistryError::InvalidMethodParameter { class, method, param_index, param_type, .. },
-                RegistryError::InvalidMethodParameter { class: class2, method: method2, param_index: param_index2, param_type: param_type2, .. }) =>
-                class == class2 && method == method2 && param_index == param_index2 && param_type == param_type2,
-            (RegistryError::InvalidCast { pwd, cd, cast_type, expected, got },
-                RegistryError::InvalidCast { pwd: pwd2, cd: cd2, cast_type: cast_type2, expected: expected2, got: got2 }) =>
-                pwd == pwd2 && cd == cd2 && cast_type == cast_type2 && expected == expected2 && got == got2,
-            (RegistryError::InvocationFailure { pwd, cd, class, method, invocation_type, .. },
-                RegistryError::InvocationFailure { pwd: pwd2, cd: cd2, class: class2, method: method2, invocation_type: invocation_type2, .. }) =>
-                pwd == pwd2 && cd == cd2 && class == class2 && method == method2 && invocation_type == invocation_type2,
-            _ => false
-        }
+        let result = registry.parsed_invoke("/foo", "user_id", &vec![]).unwrap();
+
+        assert_eq!(PolarValue::Integer(42), result);
     }
 
-    fn ne(&self, other: &Self) -> bool {
-        !eq(self, other)
+    #[test]
+    fn parse_and_invoke_attribute_with_arguments_is_error() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+
+        let result = registry.parsed_invoke("/foo", "user_id", &vec!["1"]).err().unwrap();
+
+        assert_eq!(RegistryError::InvalidNumberOfMethodParameters {
+            class: "User2".to_owned(),
+            method: "user_id".to_owned(),
+            expected: 0,
+            received: 1,
+        }, result);
     }
-}
 
-#[cfg(test)]
-mod path_tests {
-    use std::collections::HashMap;
-    use crate::command::registry::RegistryError;
-    use super::Registry;
+    #[test]
+    fn parse_and_invoke_instance_is_error() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+
+        let result = registry.parsed_invoke("/foo", ".", &vec![]).err().unwrap();
+
+        assert_eq!(RegistryError::MissingAtPath {
+            path: "/foo".to_owned(),
+            expected: "method or attribute",
+        }, result);
+    }
 
     #[test]
-    fn mkdir_absolute_directory() {
-        let mut registry = Registry::default();
-        registry.mkdir("/bar/me", "/foo").unwrap();
+    fn call_instance_method() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance(
+            "/foo", ".", "User2", &vec!["jim", "10"]).unwrap();
 
-        registry.mkdir("/foo", "/bar/soo").unwrap();
+        let result = registry.invoke_method(
+            "/foo", "add_one", vec![PolarValue::Integer(42)]).unwrap();
 
-        let node = registry.path("/bar/soo").unwrap();
-        assert_eq!("/bar/soo", node.abs_path);
+        assert_eq!(PolarValue::Integer(43), result);
     }
 
     #[test]
-    fn mkdir_creates_child_directory() {
-        let mut registry = Registry::default();
+    fn call_instance_method_with_wrong_param_type_is_error() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance(
+            "/foo", ".", "User2", &vec!["jim", "10"]).unwrap();
 
-        registry.mkdir("/", "foo").unwrap();
+        let result = registry.invoke_method(
+            "/foo", "add_one", vec![PolarValue::Float(42.0)]).err().unwrap();
 
-        let node = registry.path("/foo").unwrap();
-        assert_eq!("foo", node.name);
-        assert_eq!("/foo", node.abs_path);
-        assert_eq!(0, node.children.len());
-        assert_eq!(true, node.instance.is_none());
+        assert_eq!(RegistryError::InvalidMethodParameter {
+            class: "rcore::command::registry::registry_tests::User2".to_string(),
+            method: "add_one".to_string(),
+            param_index: 0,
+            param_type: "int",
+            reason: "",
+        }, result);
     }
 
     #[test]
-    fn mkdir_creates_grandchild_directories() {
-        let mut registry = Registry::default();
+    fn call_instance_method_value() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance(
+            "/foo", ".", "User2", &vec!["jim", "10"]).unwrap();
 
-        registry.mkdir("/", "foo/bar/soo").unwrap();
+        let result: i32 = registry.invoke_method_value(
+            "/foo", "add_one", vec![PolarValue::Integer(42)]).unwrap();
 
-        // then
-        let root = registry.path("/").unwrap();
-        let child = registry.path("/foo").unwrap();
-        let grandchild = registry.path("/foo/bar").unwrap();
-        let great_grandchild = registry.path("/foo/bar/soo").unwrap();
+        assert_eq!(43, result);
+    }
 
-        assert_eq!("soo", great_grandchild.name);
-        assert_eq!("/foo/bar/soo", great_grandchild.abs_path);
-        assert_eq!(Some(grandchild.id), great_grandchild.parent);
-        assert!(great_grandchild.children.is_empty());
-        assert!(great_grandchild.instance.is_none());
+    #[test]
+    fn parse_and_call_instance_method() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance(
+            "/foo", ".", "User2", &vec!["jim", "10"]).unwrap();
 
-        assert_eq!("bar", grandchild.name);
-        assert_eq!("/foo/bar", grandchild.abs_path);
-        assert_eq!(Some(child.id), grandchild.parent);
-        let mut children = HashMap::new();
-        children.insert("soo".to_owned(), great_grandchild.id);
-        assert_eq!(children, grandchild.children);
+        let result = registry.parsed_invoke_method(
+            "/foo/add_one", ".", &vec!["42"]).unwrap();
+
+        assert_eq!(PolarValue::Integer(43), result);
+    }
+
+    #[test]
+    fn parse_and_call_instance_method_value() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance(
+            "/foo", ".", "User2", &vec!["jim", "10"]).unwrap();
+
+        let result: i32 = registry.parsed_invoke_method_value(
+            "/foo/add_one", ".", &vec!["42"]).unwrap();
+
+        assert_eq!(43, result);
+    }
+
+    #[derive(PolarClass, Clone, Default)]
+    struct Feed {
+        #[polar(attribute, writable)]
+        throttle: i32,
+        #[polar(attribute)]
+        name: String,
+    }
+
+    impl Feed {
+        fn set_throttle(&mut self, throttle: i32) -> i32 {
+            let previous = self.throttle;
+            self.throttle = throttle;
+            previous
+        }
+    }
+
+    fn cache_feed_class(registry: &mut Registry) {
+        registry.cache_class(Feed::get_polar_class_builder()
+            .set_constructor(|| Feed { throttle: 10, name: "feed".to_owned() }, vec![])
+            .add_mut_method("set_throttle", Feed::set_throttle, vec!["int"], None)
+            .build()).unwrap();
+    }
+
+    fn create_feed_registry() -> Registry {
+        let mut registry = Registry::default();
+        cache_feed_class(&mut registry);
+        registry.parsed_create_instance("/feed", ".", "Feed", &vec![]).unwrap();
+        registry
+    }
+
+    #[test]
+    fn call_mut_instance_method_changes_instance() {
+        let mut registry = create_feed_registry();
+
+        let result = registry.invoke_method(
+            "/feed", "set_throttle", vec![PolarValue::Integer(100)]).unwrap();
 
-        assert_eq!("foo", child.name);
-        assert_eq!("/foo", child.abs_path);
-        assert_eq!(Some(root.id), child.parent);
-        children.clear();
-        children.insert("bar".to_owned(), grandchild.id);
-        assert_eq!(children, child.children);
+        assert_eq!(PolarValue::Integer(10), result);
+        assert_eq!(100, registry.attr_value::<i32>("/feed", "throttle").unwrap());
+    }
 
-        assert_eq!("", root.name);
-        assert_eq!("/", root.abs_path);
-        assert_eq!(None, root.parent);
-        children.clear();
-        children.insert("foo".to_owned(), child.id);
-        assert_eq!(children, root.children);
+    #[test]
+    fn parse_and_call_mut_instance_method_changes_instance() {
+        let mut registry = create_feed_registry();
+
+        registry.parsed_invoke_method("/feed/set_throttle", ".", &vec!["100"]).unwrap();
+        registry.parsed_invoke_method("/feed/set_throttle", ".", &vec!["200"]).unwrap();
+
+        assert_eq!(200, registry.instance_value::<Feed>("/feed", ".").unwrap().throttle);
     }
 
     #[test]
-    fn mkdir_from_child() {
-        let mut registry = Registry::default();
-        registry.mkdir("/", "/foo/bar").unwrap();
+    fn call_mut_instance_method_through_shared_reference_is_error() {
+        let registry = create_feed_registry();
 
-        registry.mkdir("/foo/bar", "../soo").unwrap();
+        let result = registry.invoke_method_ref(
+            "/feed", "set_throttle", vec![PolarValue::Integer(100)]).err().unwrap();
 
-        let node = registry.path("/foo/soo").unwrap();
-        assert_eq!("/foo/soo", node.abs_path);
+        assert!(registry.is_mut_method("/feed", "set_throttle").unwrap());
+        assert!(matches!(result, RegistryError::InvocationFailure { .. }));
+        assert_eq!(10, registry.attr_value::<i32>("/feed", "throttle").unwrap());
     }
 
     #[test]
-    fn mkdir_with_absolute_path_from_child_directory() {
-        let mut registry = Registry::default();
-        registry.mkdir("/", "/foo/bar").unwrap();
+    fn registry_can_be_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Registry>();
+        assert_send_sync::<SharedRegistry>();
+    }
 
-        registry.mkdir("/foo/bar", "/soo").unwrap();
+    #[test]
+    fn shared_registry_invokes_mut_instance_method() {
+        let registry = SharedRegistry::new(create_feed_registry());
 
-        let node = registry.path("/soo").unwrap();
-        assert_eq!("/soo", node.abs_path);
+        registry.invoke_method("/feed", "set_throttle", vec![PolarValue::Integer(100)]).unwrap();
+
+        assert_eq!(100, registry.attr_value::<i32>("/feed", "throttle").unwrap());
     }
 
     #[test]
-    fn mkdir_with_current_directory() {
-        let mut registry = Registry::default();
-        registry.mkdir("/", "/foo/bar").unwrap();
+    fn shared_registry_reads_while_other_threads_create_instances() {
+        let registry = SharedRegistry::new(create_feed_registry());
+
+        let writers: Vec<_> = (0..4).map(|i| {
+            let registry = registry.clone();
+            std::thread::spawn(move || {
+                for j in 0..25 {
+                    registry.write().parsed_create_instance(
+                        "/feeds", &format!("feed{}_{}", i, j), "Feed", &vec![]).unwrap();
+                }
+            })
+        }).collect();
+        let readers: Vec<_> = (0..4).map(|_| {
+            let registry = registry.clone();
+            std::thread::spawn(move || {
+                for _ in 0..25 {
+                    assert_eq!(10, registry.attr_value::<i32>("/feed", "throttle").unwrap());
+                }
+            })
+        }).collect();
+        for thread in writers.into_iter().chain(readers) {
+            thread.join().unwrap();
+        }
 
-        registry.mkdir("/foo/bar", "./soo").unwrap();
+        assert_eq!(100, registry.read().path("/feeds").unwrap().children.len());
+    }
 
-        let node = registry.path("/foo/bar/soo").unwrap();
-        assert_eq!("/foo/bar/soo", node.abs_path);
+    #[derive(PolarClass, Clone)]
+    struct Session {
+        #[polar(attribute)]
+        throttle: i32,
+    }
+
+    fn cache_session_class(registry: &mut Registry) {
+        registry.cache_class(Session::get_polar_class_builder()
+            .set_constructor(|feed: Feed| Session { throttle: feed.throttle }, vec!["Feed"])
+            .build()).unwrap();
+    }
+
+    fn create_session_registry() -> Registry {
+        let mut registry = create_feed_registry();
+        cache_session_class(&mut registry);
+        registry.parsed_create_instance("/a", "session", "Session", &vec!["/feed"]).unwrap();
+        registry
     }
 
     #[test]
-    fn mkdir_with_empty_directories() {
-        let mut registry = Registry::default();
-        registry.mkdir("/", "/foo/bar").unwrap();
+    fn dump_creates_constructor_arguments_first() {
+        let mut registry = create_session_registry();
+        registry.move_path("/", "/feed", "/z").unwrap();
 
-        registry.mkdir("/foo/bar", "soo///doo").unwrap();
+        let commands = registry.dump("/", ".").unwrap();
 
-        let node = registry.path("/foo/bar/soo/doo").unwrap();
-        assert_eq!("/foo/bar/soo/doo", node.abs_path);
+        assert_eq!(vec![
+            "create /z Feed",
+            "/z/throttle = 10",
+            "create /a/session Session /z",
+        ], commands);
     }
 
     #[test]
-    fn cd_to_parent() {
-        let mut registry = Registry::default();
-        registry.mkdir("/", "foo/bar/soo").unwrap();
+    fn dump_writes_values_of_writable_attributes() {
+        let mut registry = create_feed_registry();
+        registry.parsed_set_attr("/feed/throttle", ".", "200").unwrap();
 
-        let grandchild = registry.cd("/foo/bar/soo", "..").unwrap();
+        let commands = registry.dump("/feed", ".").unwrap();
 
-        assert_eq!("/foo/bar", grandchild.abs_path);
+        assert_eq!(vec!["create /feed Feed", "/feed/throttle = 200"], commands);
     }
 
     #[test]
-    fn cd_to_grandparent() {
-        let mut registry = Registry::default();
-        registry.mkdir("/", "foo/bar/soo").unwrap();
+    fn dump_does_not_create_constructor_arguments_outside_directory() {
+        let registry = create_session_registry();
 
-        let grandchild = registry.cd("/foo/bar/soo", "../../").unwrap();
+        let commands = registry.dump("/a", ".").unwrap();
 
-        assert_eq!("/foo", grandchild.abs_path);
+        assert_eq!(vec!["create /a/session Session /feed"], commands);
     }
 
     #[test]
-    fn cd_to_great_grandparent() {
-        let mut registry = Registry::default();
-        registry.mkdir("/", "foo/bar/soo").unwrap();
+    fn dump_quotes_arguments() {
+        let mut registry = create_registry();
+        registry.parsed_create_instance("/users", "jim", "User", &vec!["jim greco"]).unwrap();
+        registry.parsed_create_instance("/users", "me", "User", &vec!["it's $me"]).unwrap();
+        registry.parsed_create_instance("/users", "you", "User", &vec![""]).unwrap();
 
-        let grandchild = registry.cd("/foo/bar/soo", "../../../").unwrap();
+        let commands = registry.dump("/users", ".").unwrap();
 
-        assert_eq!("/", grandchild.abs_path);
+        assert_eq!(vec![
+            "create /users/jim User 'jim greco'",
+            "create /users/me User \"it's \\x24me\"",
+            "create /users/you User ''",
+        ], commands);
     }
 
     #[test]
-    fn cd_beyond_root_is_error() {
-        let mut registry = Registry::default();
-        registry.mkdir("/", "foo/bar/soo").unwrap();
+    fn dump_with_removed_constructor_argument_is_error() {
+        let mut registry = create_session_registry();
+        registry.remove("/", "feed", false).unwrap();
 
-        let result = registry.cd("/foo/bar/soo", "../../../..").err().unwrap();
+        let result = registry.dump("/", ".").err().unwrap();
 
-        assert_eq!(RegistryError::IllegalPathNavigation {
-            pwd: "/foo/bar/soo".to_owned(),
-            cd: "../../../..".to_owned(),
-            reason: "",
+        assert_eq!(RegistryError::MissingConstructorInstance {
+            path: "/a/session".to_owned(),
+            param_index: 0,
         }, result);
     }
 
     #[test]
-    fn cd_to_unknown_directory_is_error() {
-        let mut registry = Registry::default();
-        registry.mkdir("/", "foo/bar/soo").unwrap();
+    fn constructor_argument_can_still_be_changed() {
+        let mut registry = create_session_registry();
 
-        let result = registry.cd("/foo/bar/soo", "doo").err().unwrap();
+        registry.invoke_method("/feed", "set_throttle", vec![PolarValue::Integer(100)]).unwrap();
 
-        assert_eq!(RegistryError::IllegalPathNavigation {
-            pwd: "/foo/bar/soo".to_owned(),
-            cd: "doo".to_owned(),
-            reason: "",
-        }, result);
+        assert_eq!(10, registry.attr_value::<i32>("/a/session", "throttle").unwrap());
     }
-}
 
-#[cfg(test)]
-mod registry_tests {
-    use crate::command::oso::{PolarClass, PolarValue};
-    use crate::command::registry::{RegistryError, Registry};
+    #[test]
+    fn copy_passes_moved_typed_constructor_argument() {
+        let mut registry = create_session_registry();
+        let feed = PolarValue::Instance(registry.instance("/feed", ".").unwrap().clone());
+        registry.create_instance("/", "typed", "Session", vec![feed]).unwrap();
+        registry.move_path("/", "/feed", "/z").unwrap();
 
-    #[derive(Clone, PolarClass, Default)]
-    struct User {
-        #[polar(attribute)]
-        pub username: String,
+        registry.copy("/", "/typed", "/b").unwrap();
+
+        assert_eq!(10, registry.attr_value::<i32>("/b", "throttle").unwrap());
+        assert_eq!("create /typed Session /z", registry.dump("/typed", ".").unwrap()[0]);
     }
 
-    impl User {
-        fn new(username: String) -> User {
-            User { username }
-        }
+    #[test]
+    fn save_and_load_recreates_registry() {
+        let mut registry = create_feed_registry();
+        registry.parsed_set_attr("/feed/throttle", ".", "200").unwrap();
+        cache_session_class(&mut registry);
+        registry.parsed_create_instance("/a", "session", "Session", &vec!["/feed"]).unwrap();
+        registry.mkdir("/", "empty").unwrap();
+        registry.link("/", "current", "/a/session", false).unwrap();
+        registry.move_path("/", "/feed", "/z").unwrap();
+        let snapshot = registry.save().unwrap();
+
+        let mut loaded = Registry::default();
+        cache_feed_class(&mut loaded);
+        cache_session_class(&mut loaded);
+        loaded.load(&snapshot).unwrap();
+
+        assert_eq!(200, loaded.attr_value::<i32>("/z", "throttle").unwrap());
+        assert_eq!(200, loaded.attr_value::<i32>("/current", "throttle").unwrap());
+        assert!(loaded.path("/empty").is_ok());
+        assert_eq!(snapshot, loaded.save().unwrap());
     }
 
-    #[derive(Clone, PolarClass, Default)]
-    struct User2 {
-        pub username: String,
-        #[polar(attribute)]
-        pub user_id: i32,
+    #[test]
+    fn save_writes_constructor_instances_as_paths() {
+        let mut registry = create_session_registry();
+        registry.move_path("/", "/feed", "/z").unwrap();
+
+        let snapshot: Value = serde_json::from_str(&registry.save().unwrap()).unwrap();
+
+        assert_eq!(json!({
+            "kind": "instance",
+            "path": "/a/session",
+            "class": "Session",
+            "args": [{ "instance": "/z" }],
+            "attributes": { "throttle": 10 },
+        }), snapshot["paths"][1]);
     }
 
-    impl User2 {
-        fn new(username: String, user_id: i32) -> User2 {
-            User2 { username, user_id }
-        }
+    #[test]
+    fn load_unknown_class_is_error() {
+        let snapshot = create_session_registry().save().unwrap();
+        let mut registry = Registry::default();
+        cache_feed_class(&mut registry);
 
-        fn add_one(&self, num: i32) -> i32 {
-            num + 1
-        }
+        let result = registry.load(&snapshot);
+
+        assert_eq!(Err(RegistryError::UnknownClass("Session".to_owned())), result);
+        assert!(registry.path("/feed").is_err());
     }
 
-    fn create_registry() -> Registry {
+    #[test]
+    fn load_value_of_wrong_type_is_error() {
+        let snapshot = json!({
+            "version": 1,
+            "paths": [{
+                "kind": "instance",
+                "path": "/feed",
+                "class": "Feed",
+                "args": [],
+                "attributes": { "throttle": "fast" },
+            }],
+        });
         let mut registry = Registry::default();
-        registry.cache_class(User::get_polar_class_builder()
-            .set_constructor(User::new, vec!["string"])
-            .build()).unwrap();
-        registry.cache_class(User2::get_polar_class_builder()
-            .set_constructor(User2::new, vec!["string", "int"])
-            .add_method("add_one", User2::add_one, vec!["int"], None)
-            .build()).unwrap();
-        registry
+        cache_feed_class(&mut registry);
+
+        let result = registry.load(&snapshot.to_string());
+
+        assert_eq!(Err(RegistryError::SnapshotTypeMismatch {
+            path: "/feed".to_owned(),
+            name: "throttle".to_owned(),
+            expected: "int".to_owned(),
+            got: "string",
+        }), result);
     }
 
     #[test]
-    fn make_instance_1_param() {
-        let mut registry = create_registry();
+    fn load_invalid_json_is_error() {
+        let result = Registry::default().load("{\"paths\": [");
 
-        registry.create_instance("/foo", ".", "User", vec![PolarValue::String("jim".to_owned())]).unwrap();
+        assert!(matches!(result, Err(RegistryError::InvalidSnapshot(_))));
+    }
 
-        let user = registry.instance_value::<User>("/", "foo").unwrap();
-        assert_eq!(user.username, "jim");
+    #[test]
+    fn find_methods_and_attributes_of_instances() {
+        let registry = create_feed_registry();
+
+        let methods = registry.find("/", ".", &FindFilter {
+            kind: Some(NodeKind::Method),
+            ..FindFilter::default()
+        }).unwrap();
+        let attributes = registry.find("/feed", ".", &FindFilter {
+            kind: Some(NodeKind::Attribute),
+            ..FindFilter::default()
+        }).unwrap();
+
+        assert_eq!(vec!["/feed/set_throttle"], methods);
+        assert_eq!(vec!["/feed/name", "/feed/throttle"], attributes);
     }
 
     #[test]
-    fn make_instance_2_params() {
-        let mut registry = create_registry();
+    fn find_does_not_search_targets_of_links() {
+        let mut registry = create_feed_registry();
+        registry.link("/", "links/feed", "/feed", false).unwrap();
+
+        let attributes = registry.find("/links", ".", &FindFilter {
+            name: Some("throttle".to_owned()),
+            ..FindFilter::default()
+        }).unwrap();
+        let instances = registry.find("/", ".", &FindFilter {
+            class: Some("Feed".to_owned()),
+            ..FindFilter::default()
+        }).unwrap();
+
+        assert!(attributes.is_empty());
+        assert_eq!(vec!["/feed"], instances);
+    }
 
-        registry.create_instance("/foo", ".", "User2", vec![PolarValue::String("jim".to_owned()), PolarValue::Integer(42)]).unwrap();
+    #[test]
+    fn remove_instance_removes_methods_and_attributes() {
+        let mut registry = create_feed_registry();
+        let size = registry.paths.len();
 
-        let user = registry.instance_value::<User2>("/foo", ".").unwrap();
-        assert_eq!(user.username, "jim");
-        assert_eq!(user.user_id, 42);
+        registry.remove("/", "feed", false).unwrap();
+
+        assert!(registry.path("/feed").is_err());
+        assert_eq!(size - 4, registry.paths.len());
+        registry.parsed_create_instance("/feed", ".", "Feed", &vec![]).unwrap();
     }
 
     #[test]
-    fn make_instance_1_param_strings() {
-        let mut registry = create_registry();
+    fn remove_attribute_of_instance_is_error() {
+        let mut registry = create_feed_registry();
 
-        registry.parsed_create_instance("/foo", ".", "User", &vec!["jim"]).unwrap();
+        let result = registry.remove("/feed", "throttle", true).err().unwrap();
 
-        let user = registry.instance_value::<User>("/foo", ".").unwrap();
-        assert_eq!(user.username, "jim");
+        assert_eq!(RegistryError::IllegalRemove {
+            path: "/feed/throttle".to_owned(),
+            reason: "cannot remove a method or attribute of an instance",
+        }, result);
+    }
+
+    fn create_ops_registry() -> Registry {
+        let mut registry = Registry::default();
+        registry.cache_class(Feed::get_polar_class_builder()
+            .set_constructor(|| Feed { throttle: 10, name: "feed".to_owned() }, vec![])
+            .add_method("get", |feed: &Feed| feed.throttle, vec![], Some("ops/get"))
+            .build()).unwrap();
+        registry.parsed_create_instance("/feed", ".", "Feed", &vec![]).unwrap();
+        registry
     }
 
     #[test]
-    fn make_instance_2_params_strings() {
-        let mut registry = create_registry();
+    fn remove_method_directory_of_instance_is_error() {
+        let mut registry = create_ops_registry();
 
-        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+        let result = registry.remove("/feed", "ops", true).err().unwrap();
 
-        let user = registry.instance_value::<User2>("/", "foo").unwrap();
-        assert_eq!(user.username, "jim");
-        assert_eq!(user.user_id, 42);
+        assert_eq!(RegistryError::IllegalRemove {
+            path: "/feed/ops".to_owned(),
+            reason: "cannot remove a method or attribute of an instance",
+        }, result);
+        assert_eq!(10, registry.parsed_invoke_method_value::<i32>("/feed/ops/get", ".", &vec![]).unwrap());
     }
 
     #[test]
-    fn make_instance_at_already_created_directory() {
-        let mut registry = create_registry();
-        registry.mkdir("/foo", ".").unwrap();
+    fn move_method_directory_of_instance_is_error() {
+        let mut registry = create_ops_registry();
 
-        registry.create_instance("/foo", ".", "User", vec![PolarValue::String("jim".to_owned())]).unwrap();
+        let result = registry.move_path("/", "/feed/ops", "/x").err().unwrap();
 
-        let user = registry.instance_value::<User>("/foo", ".").unwrap();
-        assert_eq!(user.username, "jim");
+        assert_eq!(RegistryError::IllegalMove {
+            path: "/feed/ops".to_owned(),
+            reason: "cannot move a method or attribute of an instance",
+        }, result);
     }
 
-
     #[test]
-    fn make_instance_at_same_directory_is_error() {
-        let mut registry = create_registry();
-        registry.create_instance("/foo", ".", "User", vec![PolarValue::String("jim".to_owned())]).unwrap();
+    fn move_into_method_directory_of_instance_is_error() {
+        let mut registry = create_ops_registry();
+        registry.mkdir("/", "x").unwrap();
+        registry.parsed_create_instance("/y", ".", "Feed", &vec![]).unwrap();
 
-        let result = registry.create_instance("/foo", ".", "User", vec![PolarValue::String("greco".to_owned())]).err().unwrap();
+        let into = registry.move_path("/", "/x", "/feed/ops").err().unwrap();
+        let to = registry.copy("/", "/y", "/feed/ops/y").err().unwrap();
 
-        assert_eq!(RegistryError::DuplicatePath("/foo".to_owned()), result);
-        let user = registry.instance_value::<User>("/foo", ".").unwrap();
-        assert_eq!(user.username, "jim");
+        let expected = RegistryError::IllegalDestination {
+            path: "/feed/ops".to_owned(),
+            reason: "cannot move or copy into a method or attribute of an instance",
+        };
+        assert_eq!(expected, into);
+        assert_eq!(expected, to);
+        assert_eq!(vec!["get"], registry.path("/feed/ops").unwrap().children.keys()
+            .collect::<Vec<&String>>());
     }
 
     #[test]
-    fn get_attribute() {
-        let mut registry = create_registry();
-        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+    fn remove_instance_with_child_instance_is_error() {
+        let mut registry = create_feed_registry();
+        registry.parsed_create_instance("/feed/backup", ".", "Feed", &vec![]).unwrap();
 
-        let result: i32 = registry.attr_value("/foo", "user_id").unwrap();
+        assert!(registry.remove("/", "feed", false).is_err());
+        registry.remove("/", "feed", true).unwrap();
 
-        assert_eq!(42, result);
+        assert!(registry.path("/feed/backup").is_err());
     }
 
     #[test]
-    fn get_attribute_wrong_path_is_error() {
-        let mut registry = create_registry();
-        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+    fn set_attribute_changes_instance() {
+        let mut registry = create_feed_registry();
 
-        let result = registry.attr_value::<i32>("/foo", ".").err().unwrap();
+        registry.set_attr("/feed", "throttle", PolarValue::Integer(100)).unwrap();
 
-        assert_eq!(RegistryError::MissingAtPath {
-            path: "/foo".to_owned(),
-            expected: "attribute",
-        }, result);
+        assert_eq!(100, registry.attr_value::<i32>("/feed", "throttle").unwrap());
     }
 
     #[test]
-    fn get_attribute_wrong_type_is_error() {
-        let mut registry = create_registry();
-        registry.parsed_create_instance("/foo", ".", "User2", &vec!["jim", "42"]).unwrap();
+    fn parse_and_set_attribute_changes_instance() {
+        let mut registry = create_feed_registry();
 
-        let result = registry.attr_value::<f64>("/foo/user_id", ".").err().unwrap();
+        registry.parsed_set_attr("/feed/throttle", ".", "200").unwrap();
 
-        assert_eq!(RegistryError::InvalidCast {
-            pwd: "/foo/user_id".to_owned(),
-            cd: ".".to_owned(),
-            cast_type: "attribute",
-            expected: "float".to_owned(),
-            got: "".to_string(),
-        }, result);
+        assert_eq!(200, registry.instance_value::<Feed>("/feed", ".").unwrap().throttle);
     }
 
     #[test]
-    fn call_instance_method() {
-        let mut registry = create_registry();
-        registry.parsed_create_instance(
-            "/foo", ".", "User2", &vec!["jim", "10"]).unwrap();
+    fn set_attribute_with_wrong_type_is_error() {
+        let mut registry = create_feed_registry();
 
-        let result = registry.invoke_method(
-            "/foo", "add_one", vec![PolarValue::Integer(42)]).unwrap();
+        let result = registry.set_attr(
+            "/feed", "throttle", PolarValue::String("fast".to_owned())).err().unwrap();
 
-        assert_eq!(PolarValue::Integer(43), result);
+        assert_eq!(RegistryError::InvalidMethodParameter {
+            class: "rcore::command::registry::registry_tests::Feed".to_owned(),
+            method: "throttle".to_owned(),
+            param_index: 0,
+            param_type: "int",
+            reason: "",
+        }, result);
+        assert_eq!(10, registry.attr_value::<i32>("/feed", "throttle").unwrap());
     }
 
     #[test]
-    fn call_instance_method_with_wrong_param_type_is_error() {
-        let mut registry = create_registry();
-        registry.parsed_create_instance(
-            "/foo", ".", "User2", &vec!["jim", "10"]).unwrap();
+    fn parse_and_set_attribute_with_unparsable_value_is_error() {
+        let mut registry = create_feed_registry();
 
-        let result = registry.invoke_method(
-            "/foo", "add_one", vec![PolarValue::Float(42.0)]).err().unwrap();
+        let result = registry.parsed_set_attr("/feed", "throttle", "fast").err().unwrap();
 
         assert_eq!(RegistryError::InvalidMethodParameter {
-            class: "rcore::command::registry::registry_tests::User2".to_string(),
-            method: "add_one".to_string(),
+            class: "Feed".to_owned(),
+            method: "throttle".to_owned(),
             param_index: 0,
             param_type: "int",
             reason: "",
@@ -1457,39 +4062,41 @@ mod registry_tests {
     }
 
     #[test]
-    fn call_instance_method_value() {
-        let mut registry = create_registry();
-        registry.parsed_create_instance(
-            "/foo", ".", "User2", &vec!["jim", "10"]).unwrap();
+    fn set_read_only_attribute_is_error() {
+        let mut registry = create_feed_registry();
 
-        let result: i32 = registry.invoke_method_value(
-            "/foo", "add_one", vec![PolarValue::Integer(42)]).unwrap();
+        let result = registry.parsed_set_attr("/feed", "name", "other").err().unwrap();
 
-        assert_eq!(43, result);
+        assert_eq!(RegistryError::ReadOnlyAttribute {
+            class: "Feed".to_owned(),
+            attr: "name".to_owned(),
+        }, result);
     }
 
     #[test]
-    fn parse_and_call_instance_method() {
-        let mut registry = create_registry();
-        registry.parsed_create_instance(
-            "/foo", ".", "User2", &vec!["jim", "10"]).unwrap();
+    fn set_method_is_error() {
+        let mut registry = create_feed_registry();
 
-        let result = registry.parsed_invoke_method(
-            "/foo/add_one", ".", &vec!["42"]).unwrap();
+        let result = registry.parsed_set_attr("/feed", "set_throttle", "1").err().unwrap();
 
-        assert_eq!(PolarValue::Integer(43), result);
+        assert_eq!(RegistryError::MissingAtPath {
+            path: "/feed/set_throttle".to_owned(),
+            expected: "attribute",
+        }, result);
     }
 
     #[test]
-    fn parse_and_call_instance_method_value() {
-        let mut registry = create_registry();
-        registry.parsed_create_instance(
-            "/foo", ".", "User2", &vec!["jim", "10"]).unwrap();
+    fn cache_class_with_setter_without_getter_is_error() {
+        let mut registry = Registry::default();
 
-        let result: i32 = registry.parsed_invoke_method_value(
-            "/foo/add_one", ".", &vec!["42"]).unwrap();
+        let result = registry.cache_class(Bar::get_polar_class_builder()
+            .add_attribute_setter("id", |_: &mut Bar, _: i32| {}, "int")
+            .build()).err().unwrap();
 
-        assert_eq!(43, result);
+        assert_eq!(RegistryError::SetterWithoutGetter {
+            class: "rcore::command::registry::registry_tests::Bar".to_owned(),
+            attr: "id".to_owned(),
+        }, result);
     }
 
     #[derive(PolarClass, Clone, Default, PartialEq, Debug)]
@@ -1,12 +1,16 @@
-use std::io;
-use log::{Level, debug};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::{fmt, io};
+use log::{Level, debug, error};
 use crate::command::context::{UserContext, IoContext, CommandContext};
+use crate::command::block::skip_block;
 use crate::command::lexer::{lex_command, LexerError, Tokens};
 
 use thiserror::Error;
-use crate::command::{CommandExecutionError, Registry, RegistryError, SourceInfo};
-use crate::command::commands::CommandValidationError;
-use crate::command::oso::Class;
+use crate::command::{CommandExecutionError, RegistryError, SharedRegistry, SourceInfo};
+use crate::command::commands::{
+    Command, CommandValidationError, ExecuteCommand, FunctionCommand, value_to_string};
+use crate::command::oso::{Class, PolarValue};
 
 /// The command shell is used to dynamically instantiate instances of structs, invoke methods on
 /// instances, and get the attribute values from instances at startup or run-time.
@@ -17,6 +21,7 @@ use crate::command::oso::Class;
 /// The shell supports variables for use in commands.
 ///
 /// - `<var> = <value>`: assigns a value to a variable
+/// - `<var> = <method> [arg ...]`: assigns the result of a method to a variable
 /// - `<var> := <value>`: assigns a value to a variable if it does not yet exist
 /// - `unset <var> [var ...]`: removes a variable from the shell
 /// - `echo [arg ...]`: writes back the provided arguments
@@ -24,6 +29,9 @@ use crate::command::oso::Class;
 /// Variables can only contain alphanumeric or underscore characters and must start with an
 /// alphabetic or underscore character.
 /// Variables can be accessed in other commands with the `$` sign.
+/// A variable assigned an instance, list, or map keeps the typed value, which is passed as is to
+/// methods and constructors when the variable is an argument by itself (e.g., `$config`, but not
+/// `"$config"`).
 ///
 /// ```
 /// let (result, _) = rcore::command::Shell::from_string(
@@ -37,14 +45,66 @@ use crate::command::oso::Class;
 /// assert_eq!("abc klm nop   qrs", &result);
 /// ```
 ///
+/// # Quoting
+/// Double quotes (`"..."`) allow for spaces in a value, and variables are still expanded within
+/// them.
+/// Within double quotes, `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\xNN` (an ASCII character), and
+/// `\u{NNNN}` (a unicode character) are escaped characters.
+/// Single quotes (`'...'`) are literal: nothing is expanded or escaped within them.
+///
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "price = 5
+///      echo \"cost:\\t$price\\u{20AC}\" 'cost: $price'").unwrap();
+///
+/// assert_eq!("cost:\t5\u{20AC} cost: $price", &result);
+/// ```
+///
+/// # Parameter Expansion
+/// Variables and arguments in curly brackets (e.g., `${var}` or `${1}`) can be modified:
+///
+/// - `${var:-word}`: the word if the variable is unset or empty
+/// - `${var:=word}`: the word if the variable is unset or empty, which is also assigned to the
+///   variable
+/// - `${var:?message}`: fails the command with the message if the variable is unset or empty
+/// - `${var:+word}`: the word if the variable is set and not empty, otherwise nothing
+/// - `${#var}`: the number of characters in the value
+/// - `${var#pattern}`, `${var##pattern}`: removes the shortest or longest prefix matching the
+///   pattern, where `*` matches any characters and `?` matches any single character
+/// - `${var%pattern}`, `${var%%pattern}`: removes the shortest or longest matching suffix
+/// - `${var^}`, `${var^^}`, `${var,}`, `${var,,}`: converts the first or all characters to
+///   uppercase or lowercase
+///
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "file = /feeds/nyse.json
+///      echo ${port:-8080} ${host:=localhost} $host ${#file} ${file##*/} ${file%.*} ${host^^}")
+///     .unwrap();
+///
+/// assert_eq!("8080 localhost localhost 16 nyse.json /feeds/nyse LOCALHOST", &result);
+/// ```
+///
 /// # Built-in Commands
 /// The following commands are built into the shell to facilitate the creation and navigation of the
 /// directory structure, echoing back arguments, and loading command files.
 ///
 /// - `cd <dir>`: changes the current working directory of the user
 /// - `ls [dir]`: lists the contents of the current user directory
+/// - `find [dir] [-name <pattern>] [-class <class>] [-type d|i|m|a|l] [-maxdepth <depth>]`:
+///   writes the paths in a directory, and everything in it, that match all of the conditions
+/// - `tree [dir] [-L <depth>] [-M] [-A] [-v]`: writes the contents of a directory, and everything
+///   in it, as a tree, optionally without methods (`-M`) or attributes (`-A`), or with the values of
+///   attributes (`-v`)
 /// - `pwd`: the current working directory of the user
 /// - `mkdir <dir>`: creates a new directory
+/// - `mv <path> <path>`: moves or renames a directory or instance
+/// - `ln -s|-sf <target> <link>`: creates, or re-points, a link to another path, which is followed
+///   when the link is navigated
+/// - `rmdir <dir>`: removes an empty directory
+/// - `rm [-r] <path> [path ...]`: removes instances and empty directories, or, with `-r`,
+///   directories and everything in them
+/// - `dump [dir]`: writes the commands that re-create a directory and everything in it, which can
+///   be saved with `dump > file` and executed with `source file`
 ///
 /// ```
 /// let (result, user_context) = rcore::command::Shell::from_string(
@@ -87,6 +147,163 @@ use crate::command::oso::Class;
 /// assert_eq!("/foo/bar/me", &result);
 /// ```
 ///
+/// # Command Substitution
+/// The output of a command can be used in another command with `$(...)`.
+/// The command is executed with a copy of the current variables and working directory, so it
+/// cannot change them.
+/// The results of methods and attributes are written as plain text, e.g., a string returned by a
+/// method without quotes, and trailing newlines are removed from the output.
+///
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "mkdir /foo
+///      cd /foo
+///      dir = $(pwd)
+///      cd /
+///      echo \"$dir != $(pwd)\"").unwrap();
+///
+/// assert_eq!("/foo != /", &result);
+/// ```
+///
+/// # Arithmetic Expansion
+/// Integer and float arithmetic can be used in a command with `$(( expr ))`.
+/// Expressions support parentheses, `+`, `-`, `*`, `/`, `%`, `**`, the comparison operators
+/// (`==`, `!=`, `<`, `<=`, `>`, `>=`), and `&&`, `||`, and `!`, which evaluate to 1 or 0.
+/// Variables can be referenced with or without a `$`.
+/// Division by zero and integer overflow are errors.
+///
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "base = 8000
+///      index = 3
+///      port = $((base + index))
+///      echo $port $(( $index / 2.0 ))").unwrap();
+///
+/// assert_eq!("8003 1.5", &result);
+/// ```
+///
+/// # Redirection
+/// The output of a command can be written to a file instead with `> file`, or appended to a file
+/// with `>> file`.
+/// The redirection must be at the end of the command.
+/// The output of a whole block is redirected at the end of the block, e.g., `done > file` or
+/// `fi >> file`.
+/// A quoted `">"` is not a redirection.
+///
+/// ```
+/// let path = std::env::temp_dir().join("rcore_shell_redirection_doc.txt");
+/// let (result, _) = rcore::command::Shell::from_string(&format!(
+///     "echo foo > \"{0}\"
+///      echo bar >> \"{0}\"
+///      echo \">\"", path.display())).unwrap();
+///
+/// assert_eq!(">", &result);
+/// assert_eq!("foobar", std::fs::read_to_string(&path).unwrap());
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+///
+/// # Pipelines
+/// Commands can be chained with `|`, e.g., `/feeds/list_symbols | /risk/check`.
+/// The result of each command is appended to the arguments of the next command:
+/// * the result of a method is one argument, except a list, which is one argument per element;
+///   instances, lists, and maps are passed as typed values, as with typed variables
+/// * the output of any other command is one argument per whitespace-separated word
+///
+/// Only the output of the last command is written, and it can be redirected.
+///
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "echo /foo | mkdir
+///      mkdir /bar
+///      ls | echo dirs:").unwrap();
+///
+/// assert_eq!("dirs: bar foo", &result);
+/// ```
+///
+/// # Conditional Blocks
+/// Commands can be executed conditionally with multi-line `if` blocks.
+/// Conditions can compare strings (`==`, `!=`) and numbers (`-eq`, `-ne`, `-lt`, `-le`, `-gt`,
+/// `-ge`), be combined with `!`, `&&`, and `||`, or invoke a method that returns a boolean.
+///
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "env = prod
+///      if $env == prod; then
+///          echo production
+///      else
+///          echo development
+///      fi").unwrap();
+///
+/// assert_eq!("production", &result);
+/// ```
+///
+/// # Loops
+/// Commands can be repeated with multi-line `for` and `while` loops.
+/// A `for` loop iterates over words, integer ranges (`{1..5}`), the result of a method that returns
+/// a list, or an iterable instance.
+/// A `while` loop takes the same conditions as an `if` block.
+///
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "for i in {1..3}; do
+///          echo $i
+///      done").unwrap();
+///
+/// assert_eq!("123", &result);
+/// ```
+///
+/// # Functions
+/// Commands can be grouped into functions with a multi-line `function` definition.
+/// Functions are called like any other command and their arguments are bound to the positional
+/// arguments (`$0`, `$1`, ...) and to the parameters named in the definition.
+///
+/// - `function <name> [param ...] {`: defines a function, which ends with a `}` line
+/// - `function`: lists the user-defined functions
+///
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "function greet first last {
+///          echo \"Hello, $first $1!\"
+///      }
+///      greet Montgomery Burns").unwrap();
+///
+/// assert_eq!("Hello, Montgomery Burns!", &result);
+/// ```
+///
+/// # Errors
+/// By default, the shell stops at the first failing command and returns its error (`set -e`).
+/// With `set +e`, failing commands are logged and counted, and the shell continues with the next
+/// command; a failing statement that starts a block also skips the block.
+/// The exit status of the last command, 0 if it succeeded and 1 if it failed, is available as `$?`.
+/// [Shell::execute_commands_with_report] returns every error with its [SourceInfo].
+///
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "set +e
+///      cd /missing
+///      if $? -ne 0; then
+///          echo failed
+///      fi").unwrap();
+///
+/// assert_eq!("failed", &result);
+/// ```
+///
+/// A multi-line `try { ... } catch [var] { ... }` block attempts commands and, at the first failing
+/// command, executes the commands of the catch block instead, with the error set to the variable.
+///
+/// ```
+/// let (result, _) = rcore::command::Shell::from_string(
+///     "try {
+///          cd /primary
+///      } catch err {
+///          mkdir /backup
+///          cd /backup
+///      }
+///      pwd").unwrap();
+///
+/// assert_eq!("/backup", &result);
+/// ```
+///
 /// # Instance Commands
 /// The primary usage of the command shell is to create instances of structs and invoke methods on
 /// or retrieve the value of attributes from those instances.
@@ -94,15 +311,60 @@ use crate::command::oso::Class;
 /// - `create <dir> <struct_name> [arg ...]`: instantiates an instance of a struct
 /// - `</path/to/method_or_attribute> [arg ...]`: invokes a method or retrieves the value of an
 ///    attribute
+/// - `get <dir> [attr ...]`: retrieves the values of several attributes of an instance
+/// - `cp <instance> <path>`: creates a copy of an instance with the same constructor arguments
+/// - `</path/to/attribute> = <value>|<method> [arg ...]`: sets the value of a writable attribute
+///
+/// Methods that take `&mut self`, which are added to a class with
+/// [add_mut_method](crate::command::oso::ClassBuilder::add_mut_method), change the instance that is
+/// stored in the directory, as do attribute setters, which are added to a class with
+/// [add_attribute_setter](crate::command::oso::ClassBuilder::add_attribute_setter) or with
+/// `#[polar(attribute, writable)]`.
 ///
 /// The user can configure the [CommandContext] with user-defined commands.
+///
+/// # Sharing the Registry
+/// Several shells, and the application's threads, can share one registry through a
+/// [SharedRegistry].
+/// Each command locks the registry while it executes, so commands that change the registry are
+/// serialized, while the application's threads read the registry between them.
+///
+/// ```
+/// use rcore::command::{CommandContext, IoContext, SharedRegistry, Shell, UserContext};
+///
+/// let registry = SharedRegistry::default();
+/// let mut admin = Shell::with_registry(registry.clone());
+/// let mut input = std::io::Cursor::new("mkdir /feeds/nyse".as_bytes());
+/// let mut output: Vec<u8> = Vec::new();
+/// admin.execute_commands(&mut UserContext::default(),
+///                        &mut IoContext::new("admin", &mut input, &mut output),
+///                        &CommandContext::default()).unwrap();
+///
+/// assert_eq!("/feeds/nyse", registry.read().path("/feeds/nyse").unwrap().abs_path());
+/// ```
 #[derive(Default)]
 pub struct Shell {
-    /// The command registry.
-    pub registry: Registry,
+    /// The command registry, which can be shared with other shells and threads.
+    pub registry: SharedRegistry,
+    error_count: usize,
+    report: Option<Vec<ShellError>>,
+    /// True while the command of a command substitution is executed, when the results of methods
+    /// and attributes are written as plain text, e.g., strings without quotes.
+    pub(crate) substituting: bool,
 }
 
 impl Shell {
+    /// Creates a shell that executes commands against the specified registry, which can be shared
+    /// with other shells.
+    pub fn with_registry(registry: SharedRegistry) -> Shell {
+        Shell {
+            registry,
+            error_count: 0,
+            report: None,
+            substituting: false,
+        }
+    }
+
     /// Caches a [Class] which describes a struct, a function to create instances ("constructor"),
     /// getters for the instance's attributes, and its instance functions ("instance methods").
     pub fn cache_class(&mut self, class: Class) -> Result<(), RegistryError> {
@@ -139,58 +401,305 @@ impl Shell {
                             command_context: &CommandContext) -> Result<(), ShellError> {
         loop {
             let line = io_context.line;
-            match lex_command(user_context, io_context) {
-                Some(result) => match result {
-                    Ok(tokens) => {
-                        if log::log_enabled!(Level::Debug) {
-                            debug!("{}:{}: {}",io_context.src,line,tokens.tokens_string());
-                        }
-
-                        let mut executed = false;
-
-                        for command in &command_context.builtin_commands {
-                            if tokens.len() > command.keyword_position()
-                                && tokens.get(command.keyword_position()) == command.keyword() {
-                                match command.validate(&tokens) {
-                                    Ok(_) => {
-                                        command.execute(
-                                            &tokens,
-                                            user_context,
-                                            io_context,
-                                            command_context,
-                                            self)?;
-                                        executed = true;
-                                        break;
-                                    },
-                                    Err(e) => return Err(ShellError::CommandValidationError {
-                                        src: io_context.to_source_info(),
-                                        tokens: tokens.clone(),
-                                        error: e,
-                                    })
-                                }
-                            }
-                        }
-
-                        if !executed {
-                            command_context.execute_command.execute(
-                                &tokens, user_context, io_context, command_context, self)?;
-                        }
+            let src = io_context.src;
+            let mut substitute = |command: &str, user_context: &UserContext|
+                self.substitute(command, user_context, src, command_context);
+            let result = match lex_command(user_context, io_context, &mut substitute) {
+                Some(Ok(tokens)) => {
+                    if log::log_enabled!(Level::Debug) {
+                        debug!("{}:{}: {}",io_context.src,line,tokens.tokens_string());
                     }
-                    Err(e) => return Err(match e {
-                        LexerError::IoError(e) => ShellError::IoError {
-                            src: io_context.to_source_info(),
-                            tokens: Tokens::new(vec![]),
-                            error: e,
-                        },
-                        e => ShellError::LexerError {
-                            src: io_context.to_source_info(),
-                            error: e,
-                        }
-                    })
+                    // the status is reset after `$?` is expanded, and a command that runs other
+                    // commands (e.g., `source`) may leave the status of the last one
+                    user_context.set_exit_status(0);
+                    self.execute_line(tokens, user_context, io_context, command_context)
+                }
+                // the input cannot be read, so there is nothing to continue with
+                Some(Err(LexerError::IoError(e))) =>
+                    return Err(ShellError::from_lexer_error(io_context, LexerError::IoError(e))),
+                Some(Err(e)) => {
+                    let error = ShellError::from_lexer_error(io_context, e);
+                    io_context.skip_line();
+                    Err(error)
                 }
                 None => return Ok(())
+            };
+
+            if let Err(e) = result {
+                user_context.set_exit_status(1);
+                if user_context.errexit() {
+                    return Err(e);
+                }
+
+                error!("{}", e);
+                if matches!(e, ShellError::LexerError { .. }
+                    | ShellError::CommandValidationError { .. }) {
+                    skip_block(&io_context.raw_command(), io_context);
+                }
+                self.error_count += 1;
+                if let Some(report) = &mut self.report {
+                    report.push(e);
+                }
+            }
+        }
+    }
+
+    /// Executes one or more commands through the shell, like [Shell::execute_commands], and
+    /// returns a report of the errors.
+    ///
+    /// With `set +e`, the report contains every failing command.
+    /// Otherwise, the report contains the error of the first failing command, which stopped the
+    /// execution.
+    ///
+    /// # Example
+    /// ```
+    /// use rcore::command::{CommandContext, IoContext, Shell, UserContext};
+    /// let mut input = std::io::Cursor::new(
+    ///         "set +e
+    ///          cd /missing
+    ///          echo ok
+    ///          $unknown".as_bytes());
+    /// let mut output: Vec<u8> = Vec::new();
+    /// let mut io_context = IoContext::new("test", &mut input, &mut output);
+    /// let mut user_context = UserContext::default();
+    /// let mut shell = Shell::default();
+    ///
+    /// let report = shell.execute_commands_with_report(
+    ///     &mut user_context, &mut io_context, &CommandContext::default());
+    ///
+    /// assert_eq!(2, report.len());
+    /// assert_eq!(2, report.errors()[0].src().line());
+    /// assert_eq!(4, report.errors()[1].src().line());
+    /// assert_eq!("ok", &String::from_utf8(output).unwrap());
+    /// ```
+    pub fn execute_commands_with_report(&mut self,
+                                        user_context: &mut UserContext,
+                                        io_context: &mut IoContext,
+                                        command_context: &CommandContext) -> ErrorReport {
+        let outer = self.report.replace(vec![]);
+        let result = self.execute_commands(user_context, io_context, command_context);
+        let mut errors = std::mem::replace(&mut self.report, outer).unwrap_or_default();
+        if let Err(e) = result {
+            errors.push(e);
+        }
+        ErrorReport { errors }
+    }
+
+    /// Returns the number of failing commands the shell has continued after (see `set +e`).
+    pub fn error_count(&self) -> usize {
+        self.error_count
+    }
+
+    /// Executes a lexed command, redirecting its output if the command ends with `> file` or
+    /// `>> file`.
+    fn execute_line(&mut self,
+                    tokens: Tokens,
+                    user_context: &mut UserContext,
+                    io_context: &mut IoContext,
+                    command_context: &CommandContext) -> Result<(), ShellError> {
+        match Shell::redirection(&tokens) {
+            // the output of a block is redirected at its terminator, e.g., "done > file"
+            Ok(Some(_)) if matches!(tokens.get(0), "if" | "for" | "while") =>
+                Err(ShellError::CommandValidationError {
+                    src: io_context.to_source_info(),
+                    tokens,
+                    error: CommandValidationError::InvalidRedirection,
+                }),
+            Ok(Some((tokens, file, append))) => {
+                let mut output = Shell::open_redirection(&file, append, &tokens, io_context)?;
+                let mut redirected = io_context.with_output(&mut output);
+                let result = self.execute_tokens(
+                    &tokens, user_context, &mut redirected, command_context);
+                let (line, col) = (redirected.line, redirected.col);
+                io_context.line = line;
+                io_context.col = col;
+                result
+            }
+            Ok(None) => self.execute_tokens(&tokens, user_context, io_context, command_context),
+            Err(e) => Err(ShellError::CommandValidationError {
+                src: io_context.to_source_info(),
+                tokens,
+                error: e,
+            })
+        }
+    }
+
+    /// Executes a single, lexed command, which may be a pipeline of commands.
+    ///
+    /// The result of each command in a pipeline is appended to the arguments of the next command.
+    fn execute_tokens(&mut self,
+                      tokens: &Tokens,
+                      user_context: &mut UserContext,
+                      io_context: &mut IoContext,
+                      command_context: &CommandContext) -> Result<(), ShellError> {
+        let stages = Shell::pipeline(tokens).map_err(|e| ShellError::CommandValidationError {
+            src: io_context.to_source_info(),
+            tokens: tokens.clone(),
+            error: e,
+        })?;
+
+        let last = stages.len() - 1;
+        let mut piped: Vec<PolarValue> = vec![];
+        for (i, mut stage) in stages.into_iter().enumerate() {
+            for value in piped.drain(..) {
+                let text = value_to_string(self, &value);
+                match value {
+                    PolarValue::Instance(_) | PolarValue::List(_) | PolarValue::Map(_) =>
+                        stage.push(text, Some(value)),
+                    _ => stage.push(text, None)
+                }
             }
+
+            if i == last {
+                return self.execute_stage(&stage, user_context, io_context, command_context);
+            }
+            piped = self.piped_values(&stage, user_context, io_context, command_context)?;
         }
+        Ok(())
+    }
+
+    /// Executes a command whose result is piped to the next command of a pipeline.
+    ///
+    /// The result of a method is returned as-is, with a list returned as its elements.
+    /// The output of any other command is returned as its whitespace-separated words.
+    fn piped_values(&mut self,
+                    tokens: &Tokens,
+                    user_context: &mut UserContext,
+                    io_context: &mut IoContext,
+                    command_context: &CommandContext) -> Result<Vec<PolarValue>, ShellError> {
+        if Shell::builtin_command(tokens, command_context).is_none()
+            && !user_context.has_function(tokens.get(0)) {
+            return Ok(match ExecuteCommand::invoke(tokens, user_context, io_context, self)? {
+                PolarValue::List(values) => values,
+                value => vec![value]
+            });
+        }
+
+        let mut output: Vec<u8> = Vec::new();
+        let mut captured = io_context.with_output(&mut output);
+        let result = self.execute_stage(tokens, user_context, &mut captured, command_context);
+        let (line, col) = (captured.line, captured.col);
+        io_context.line = line;
+        io_context.col = col;
+        result?;
+
+        Ok(String::from_utf8_lossy(&output)
+            .split_whitespace()
+            .map(|word| PolarValue::String(word.to_owned()))
+            .collect())
+    }
+
+    /// Executes a single command of a pipeline.
+    fn execute_stage(&mut self,
+                     tokens: &Tokens,
+                     user_context: &mut UserContext,
+                     io_context: &mut IoContext,
+                     command_context: &CommandContext) -> Result<(), ShellError> {
+        if let Some(command) = Shell::builtin_command(tokens, command_context) {
+            return match command.validate(tokens) {
+                Ok(_) => command.execute(tokens, user_context, io_context, command_context, self),
+                Err(e) => Err(ShellError::CommandValidationError {
+                    src: io_context.to_source_info(),
+                    tokens: tokens.clone(),
+                    error: e,
+                })
+            }
+        }
+
+        match user_context.function(tokens.get(0)).cloned() {
+            Some(function) => FunctionCommand::call(
+                &function, tokens, user_context, io_context, command_context, self),
+            None => command_context.execute_command.execute(
+                tokens, user_context, io_context, command_context, self)
+        }
+    }
+
+    /// Returns the built-in command for the tokens, if any.
+    fn builtin_command<'c>(tokens: &Tokens, command_context: &'c CommandContext)
+                           -> Option<&'c dyn Command> {
+        command_context.builtin_commands.iter()
+            .find(|command| tokens.len() > command.keyword_position()
+                && tokens.get(command.keyword_position()) == command.keyword())
+            .map(|command| command.as_ref())
+    }
+
+    /// Splits a pipeline (`cmd1 | cmd2`) into its commands.
+    fn pipeline(tokens: &Tokens) -> Result<Vec<Tokens>, CommandValidationError> {
+        let mut stages = vec![];
+        let mut start = 0;
+        for i in 0..=tokens.len() {
+            if i == tokens.len() || (tokens.is_operator(i) && tokens.get(i) == "|") {
+                if i == start {
+                    return Err(CommandValidationError::InvalidPipeline);
+                }
+                stages.push(tokens.slice(start, i));
+                start = i + 1;
+            }
+        }
+        Ok(stages)
+    }
+
+    /// Opens the file of an output redirection, which is appended to or truncated.
+    pub(crate) fn open_redirection(file: &str,
+                                   append: bool,
+                                   tokens: &Tokens,
+                                   io_context: &IoContext) -> Result<File, ShellError> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(file)
+            .map_err(|e| ShellError::IoError {
+                src: io_context.to_source_info(),
+                tokens: tokens.clone(),
+                error: e
+            })
+    }
+
+    /// Splits an output redirection (`> file` or `>> file`) from the end of the command.
+    ///
+    /// Returns the command's tokens, the file, and whether the file should be appended to, or
+    /// `None` if the output of the command is not redirected.
+    pub(crate) fn redirection(tokens: &Tokens)
+                   -> Result<Option<(Tokens, String, bool)>, CommandValidationError> {
+        let position = (0..tokens.len())
+            .find(|i| tokens.is_operator(*i) && (tokens.get(*i) == ">" || tokens.get(*i) == ">>"));
+        match position {
+            None => Ok(None),
+            Some(i) if i > 0 && i + 2 == tokens.len() && !tokens.is_operator(i + 1) => Ok(Some((
+                tokens.slice(0, i),
+                tokens.get(i + 1).to_owned(),
+                tokens.get(i) == ">>"))),
+            _ => Err(CommandValidationError::InvalidRedirection)
+        }
+    }
+
+    /// Executes the command of a command substitution, `$(...)`, and returns its output.
+    ///
+    /// The command is executed with a copy of the user context, so changes to variables and the
+    /// working directory are discarded.
+    /// The results of methods and attributes are written as plain text, e.g., a string returned by
+    /// a method without quotes, and trailing newlines are removed from the output.
+    pub(crate) fn substitute(&mut self,
+                             command: &str,
+                             user_context: &UserContext,
+                             src: &str,
+                             command_context: &CommandContext) -> Result<String, ShellError> {
+        debug!("[Substitute] executing command {}", command);
+        let mut user_context = user_context.clone();
+        // the first failing command fails the substitution, even with `set +e`, so the error is
+        // reported at the line of the substitution, like in a try block
+        user_context.set_errexit(true);
+        let mut input = io::Cursor::new(command.as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let mut io_context = IoContext::new(src, &mut input, &mut output);
+        let substituting = std::mem::replace(&mut self.substituting, true);
+        let result = self.execute_commands(&mut user_context, &mut io_context, command_context);
+        self.substituting = substituting;
+        result?;
+
+        Ok(String::from_utf8_lossy(&output).trim_end_matches('\n').to_owned())
     }
 
     /// This is a utility method to run commands from a string.
@@ -221,6 +730,39 @@ impl Shell {
     }
 }
 
+/// The errors of the commands executed by [Shell::execute_commands_with_report].
+#[derive(Debug, Default)]
+pub struct ErrorReport {
+    errors: Vec<ShellError>,
+}
+
+impl ErrorReport {
+    /// Returns the errors in the order they occurred.
+    pub fn errors(&self) -> &Vec<ShellError> {
+        &self.errors
+    }
+
+    /// The number of errors.
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Returns true if no commands failed.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error(s)", self.errors.len())?;
+        for error in &self.errors {
+            write!(f, "\n{}", error)?;
+        }
+        Ok(())
+    }
+}
+
 /// Errors thrown executing commands by the shell.
 #[derive(Debug, Error)]
 pub enum ShellError {
@@ -255,6 +797,70 @@ pub enum ShellError {
     },
 }
 
+impl ShellError {
+    /// Returns the source of the error.
+    pub fn src(&self) -> &SourceInfo {
+        match self {
+            ShellError::LexerError { src, .. } => src,
+            ShellError::IoError { src, .. } => src,
+            ShellError::RegistryError { src, .. } => src,
+            ShellError::CommandValidationError { src, .. } => src,
+            ShellError::CommandExecutionError { src, .. } => src,
+        }
+    }
+
+    /// Returns the tokens of the failing command, if the command was lexed.
+    pub fn tokens(&self) -> Option<&Tokens> {
+        match self {
+            ShellError::LexerError { .. } => None,
+            ShellError::IoError { tokens, .. } => Some(tokens),
+            ShellError::RegistryError { tokens, .. } => Some(tokens),
+            ShellError::CommandValidationError { tokens, .. } => Some(tokens),
+            ShellError::CommandExecutionError { tokens, .. } => Some(tokens),
+        }
+    }
+
+    /// Returns the message of the error, without its source and tokens.
+    pub fn message(&self) -> String {
+        match self {
+            ShellError::LexerError { error, .. } => error.to_string(),
+            ShellError::IoError { error, .. } => error.to_string(),
+            ShellError::RegistryError { error, .. } => error.to_string(),
+            ShellError::CommandValidationError { error, .. } => error.to_string(),
+            ShellError::CommandExecutionError { error, .. } => error.to_string(),
+        }
+    }
+
+    /// Returns the error as a map of its message, source, line, column, and tokens.
+    pub(crate) fn to_value(&self) -> PolarValue {
+        let src = self.src();
+        let tokens = self.tokens()
+            .map(|tokens| tokens.all().iter().map(|t| PolarValue::String(t.to_owned())).collect())
+            .unwrap_or_default();
+        PolarValue::Map(HashMap::from([
+            ("message".to_owned(), PolarValue::String(self.message())),
+            ("src".to_owned(), PolarValue::String(src.src.to_owned())),
+            ("line".to_owned(), PolarValue::Integer(src.line() as i64)),
+            ("col".to_owned(), PolarValue::Integer(src.col() as i64)),
+            ("tokens".to_owned(), PolarValue::List(tokens)),
+        ]))
+    }
+
+    pub(crate) fn from_lexer_error(io_context: &IoContext, error: LexerError) -> ShellError {
+        match error {
+            LexerError::IoError(e) => ShellError::IoError {
+                src: io_context.to_source_info(),
+                tokens: Tokens::new(vec![]),
+                error: e,
+            },
+            e => ShellError::LexerError {
+                src: io_context.to_source_info(),
+                error: e,
+            }
+        }
+    }
+}
+
 impl PartialEq for ShellError {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -286,10 +892,12 @@ impl PartialEq for ShellError {
 mod tests {
     use std::io;
     use std::io::Cursor;
-    use crate::command::commands::CommandValidationError;
+    use crate::command::commands::{CommandExecutionError, CommandValidationError};
     use crate::command::context::{UserContext, IoContext, CommandContext};
     use crate::command::lexer::{LexerError, Tokens};
-    use crate::command::shell::{Shell, ShellError};
+    use crate::command::shell::{ErrorReport, Shell, ShellError};
+    use crate::command::oso::PolarClass;
+    use crate::command::SharedRegistry;
 
     fn setup() -> (Shell, CommandContext, UserContext) {
         (Shell::default(), CommandContext::default(), UserContext::default())
@@ -355,4 +963,569 @@ do12 = goo".as_bytes());
             tokens: Tokens::new(vec!["12foo".to_owned(), "=".to_owned(), "soo".to_owned()]),
         }, result);
     }
+
+    #[test]
+    fn command_substitution_is_replaced_with_output() {
+        let (mut shell, commands, mut user_context) = setup();
+        let mut cursor = Cursor::new("mkdir /foo
+cd /foo
+foo = $(pwd)
+bar = \"dir=$(echo $(pwd) \"x\")\"".as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+        let result = shell.execute_commands(&mut user_context, &mut io_context, &commands).unwrap();
+
+        assert_eq!((), result);
+        assert_eq!("/foo", user_context.get_value("foo").unwrap());
+        assert_eq!("dir=/foo x", user_context.get_value("bar").unwrap());
+    }
+
+    #[test]
+    fn command_substitution_does_not_change_user_context() {
+        let (mut shell, commands, mut user_context) = setup();
+        let mut cursor = Cursor::new("mkdir /foo
+foo = $(cd /foo)
+bar = $(bar = 1)".as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+        shell.execute_commands(&mut user_context, &mut io_context, &commands).unwrap();
+
+        assert_eq!("", user_context.get_value("foo").unwrap());
+        assert_eq!("", user_context.get_value("bar").unwrap());
+        assert_eq!("/", user_context.pwd());
+    }
+
+    #[test]
+    fn command_substitution_error_points_at_outer_line() {
+        let (mut shell, commands, mut user_context) = setup();
+        let mut cursor = Cursor::new("foo = bar
+foo = $(echo $unknown)".as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+        let result = shell.execute_commands(&mut user_context, &mut io_context, &commands).err().unwrap();
+
+        match result {
+            ShellError::LexerError { src, error: LexerError::CommandSubstitutionFailure(inner) } => {
+                assert!(src.to_string().starts_with("test:2:"));
+                match *inner {
+                    ShellError::LexerError { error: LexerError::UnknownVariable(var), .. } =>
+                        assert_eq!("unknown", var),
+                    _ => panic!("unexpected error: {}", inner)
+                }
+            }
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
+
+    #[test]
+    fn command_substitution_fails_without_errexit() {
+        let (_, context, report, output) = execute_with_report("set +e
+echo $(cd /missing)
+echo $?");
+
+        assert_eq!("1", output);
+        assert_eq!(1, report.len());
+        assert_eq!(2, report.errors()[0].src().line());
+        assert!(matches!(report.errors()[0], ShellError::LexerError {
+            error: LexerError::CommandSubstitutionFailure(_), ..
+        }));
+        assert!(!context.errexit());
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Person {}
+
+    impl Person {
+        fn name(&self) -> String {
+            "Mr. Burns".to_owned()
+        }
+
+        fn age(&self) -> i32 {
+            104
+        }
+
+        fn quote(&self) -> String {
+            "say \"excellent\"".to_owned()
+        }
+    }
+
+    #[test]
+    fn command_substitution_of_method_result() {
+        let (mut shell, commands, mut user_context) = setup();
+        shell.cache_class(Person::get_polar_class_builder()
+            .set_constructor(|| Person {}, vec![])
+            .add_method("name", Person::name, vec![], None)
+            .add_method("age", Person::age, vec![], None)
+            .add_method("quote", Person::quote, vec![], None)
+            .build()).unwrap();
+        let mut cursor = Cursor::new("create /burns Person
+name = $(/burns/name)
+age = $(/burns/age)
+quote = $(/burns/quote)".as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+        shell.execute_commands(&mut user_context, &mut io_context, &commands).unwrap();
+
+        assert_eq!("Mr. Burns", user_context.get_value("name").unwrap());
+        assert_eq!("104", user_context.get_value("age").unwrap());
+        assert_eq!("say \"excellent\"", user_context.get_value("quote").unwrap());
+    }
+
+    #[test]
+    fn redirect_output_to_file() {
+        let (mut shell, commands, mut user_context) = setup();
+        let path = std::env::temp_dir().join("rcore_redirect_output_test.txt");
+        let text = format!("echo foo > \"{0}\"\necho bar > \"{0}\"\necho soo", path.display());
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut output);
+
+        shell.execute_commands(&mut user_context, &mut io_context, &commands).unwrap();
+
+        assert_eq!("soo", String::from_utf8(output).unwrap());
+        assert_eq!("bar", std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn redirect_output_appends_to_file() {
+        let (mut shell, commands, mut user_context) = setup();
+        let path = std::env::temp_dir().join("rcore_redirect_append_test.txt");
+        let text = format!("echo foo > \"{0}\"\necho bar >> \"{0}\"", path.display());
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+        shell.execute_commands(&mut user_context, &mut io_context, &commands).unwrap();
+
+        assert_eq!("foobar", std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn redirect_output_of_block() {
+        let (mut shell, commands, mut user_context) = setup();
+        let path = std::env::temp_dir().join("rcore_redirect_block_test.txt");
+        let text = format!("for i in 1 2; do\necho $i\ndone > \"{0}\"
+if true; then\necho 3\nfi >> \"{0}\"
+echo after", path.display());
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut output);
+
+        shell.execute_commands(&mut user_context, &mut io_context, &commands).unwrap();
+
+        assert_eq!("after", String::from_utf8(output).unwrap());
+        assert_eq!("123", std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn redirection_of_block_statement_is_error() {
+        let (mut shell, commands, mut user_context) = setup();
+        let mut cursor = Cursor::new("for i in 1 2; do > a.txt\necho $i\ndone".as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+        let result = shell.execute_commands(&mut user_context, &mut io_context, &commands)
+            .err().unwrap();
+
+        match result {
+            ShellError::CommandValidationError { error, .. } =>
+                assert_eq!(CommandValidationError::InvalidRedirection, error),
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
+
+    #[test]
+    fn invalid_redirection_of_block_terminator_is_error() {
+        for terminator in ["done a.txt", "done > a.txt b.txt"] {
+            let text = format!("while false; do\n{}", terminator);
+            let (mut shell, commands, mut user_context) = setup();
+            let mut cursor = Cursor::new(text.as_bytes());
+            let mut sink = io::sink();
+            let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+            let result = shell.execute_commands(&mut user_context, &mut io_context, &commands)
+                .err().unwrap();
+
+            match result {
+                ShellError::CommandExecutionError { error, .. } => assert_eq!(
+                    CommandExecutionError::UnexpectedBlockStatement(terminator.to_owned()), error),
+                _ => panic!("unexpected error: {}", result)
+            }
+        }
+    }
+
+    #[test]
+    fn semicolon_outside_block_statement_is_not_separated() {
+        let (mut shell, commands, mut user_context) = setup();
+        let mut cursor = Cursor::new("echo a;b\nif true; then\necho c; fi;\nfi".as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut output);
+
+        shell.execute_commands(&mut user_context, &mut io_context, &commands).unwrap();
+
+        assert_eq!("a;bc; fi;", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn quoted_redirection_is_not_redirected() {
+        let (mut shell, commands, mut user_context) = setup();
+        let mut cursor = Cursor::new("echo foo \">\" bar".as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut output);
+
+        shell.execute_commands(&mut user_context, &mut io_context, &commands).unwrap();
+
+        assert_eq!("foo > bar", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn redirection_without_single_file_is_error() {
+        for text in ["echo foo >", "echo foo > a b", "> a", "echo > > a"] {
+            let (mut shell, commands, mut user_context) = setup();
+            let mut cursor = Cursor::new(text.as_bytes());
+            let mut sink = io::sink();
+            let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+            let result = shell.execute_commands(&mut user_context, &mut io_context, &commands)
+                .err().unwrap();
+
+            match result {
+                ShellError::CommandValidationError { error, .. } =>
+                    assert_eq!(CommandValidationError::InvalidRedirection, error),
+                _ => panic!("unexpected error: {}", result)
+            }
+        }
+    }
+
+    #[test]
+    fn redirect_output_to_missing_directory_is_error() {
+        let (mut shell, commands, mut user_context) = setup();
+        let path = std::env::temp_dir().join("rcore_missing_directory").join("a.txt");
+        let text = format!("echo foo > \"{}\"", path.display());
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+        let result = shell.execute_commands(&mut user_context, &mut io_context, &commands)
+            .err().unwrap();
+
+        match result {
+            ShellError::IoError { tokens, .. } =>
+                assert_eq!(Tokens::new(vec!["echo".to_owned(), "foo".to_owned()]), tokens),
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Config {
+        port: i32,
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Desk {}
+
+    impl Desk {
+        fn symbols(&self) -> Vec<String> {
+            vec!["AAPL".to_owned(), "MSFT".to_owned()]
+        }
+
+        fn check(&self, first: String, second: String) -> String {
+            format!("{}+{}", first, second)
+        }
+
+        fn add(&self, a: i32, b: i32) -> i32 {
+            a + b
+        }
+
+        fn config(&self) -> Config {
+            Config { port: 80 }
+        }
+
+        fn port(&self, config: Config) -> i32 {
+            config.port
+        }
+    }
+
+    fn execute_pipeline(text: &str) -> Result<String, ShellError> {
+        let (mut shell, commands, mut user_context) = setup();
+        shell.cache_class(Config::get_polar_class_builder().build()).unwrap();
+        shell.cache_class(Desk::get_polar_class_builder()
+            .set_constructor(|| Desk {}, vec![])
+            .add_method("symbols", Desk::symbols, vec![], None)
+            .add_method("check", Desk::check, vec!["string", "string"], None)
+            .add_method("add", Desk::add, vec!["int", "int"], None)
+            .add_method("config", Desk::config, vec![], None)
+            .add_method("port", Desk::port, vec!["Config"], None)
+            .build()).unwrap();
+        shell.registry.write().parsed_create_instance("/desk", ".", "Desk", &vec![]).unwrap();
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut output);
+        shell.execute_commands(&mut user_context, &mut io_context, &commands)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn pipe_list_result_as_arguments() {
+        let result = execute_pipeline("/desk/symbols | /desk/check").unwrap();
+
+        assert_eq!("\"AAPL+MSFT\"", result);
+    }
+
+    #[test]
+    fn pipe_result_after_existing_arguments() {
+        let result = execute_pipeline("/desk/add 1 2 | /desk/add 3 | echo total").unwrap();
+
+        assert_eq!("total 6", result);
+    }
+
+    #[test]
+    fn pipe_instance_result_as_typed_argument() {
+        let result = execute_pipeline("/desk/config | /desk/port").unwrap();
+
+        assert_eq!("80", result);
+    }
+
+    #[test]
+    fn pipe_command_output_as_words() {
+        let result = execute_pipeline("echo 1 \"2\" | /desk/add").unwrap();
+
+        assert_eq!("3", result);
+    }
+
+    #[test]
+    fn pipe_output_of_last_command_is_redirected() {
+        let path = std::env::temp_dir().join("rcore_pipeline_redirect_test.txt");
+
+        let result = execute_pipeline(
+            &format!("/desk/symbols | echo > \"{}\"", path.display())).unwrap();
+
+        assert_eq!("", result);
+        assert_eq!("AAPL MSFT", std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn quoted_pipe_is_not_pipeline() {
+        let result = execute_pipeline("echo a \"|\" b").unwrap();
+
+        assert_eq!("a | b", result);
+    }
+
+    #[test]
+    fn pipeline_with_missing_command_is_error() {
+        for text in ["| echo", "echo |", "echo | | echo"] {
+            let result = execute_pipeline(text).err().unwrap();
+
+            match result {
+                ShellError::CommandValidationError { error, .. } =>
+                    assert_eq!(CommandValidationError::InvalidPipeline, error),
+                _ => panic!("unexpected error: {}", result)
+            }
+        }
+    }
+
+    #[test]
+    fn pipe_to_method_with_wrong_arguments_is_error() {
+        let result = execute_pipeline("/desk/symbols | /desk/add").err().unwrap();
+
+        assert!(matches!(result, ShellError::RegistryError { .. }));
+    }
+
+    #[test]
+    fn non_ascii_paths_and_messages() {
+        let (mut shell, commands, mut user_context) = setup();
+        let mut cursor = Cursor::new("mkdir /données
+cd /données
+if true; then
+    echo \"ça marche: $(pwd) ✓\"
+fi".as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut output);
+
+        shell.execute_commands(&mut user_context, &mut io_context, &commands).unwrap();
+
+        assert_eq!("ça marche: /données ✓", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn invalid_utf8_in_block_is_error() {
+        let (mut shell, commands, mut user_context) = setup();
+        let mut cursor = Cursor::new(b"if true; then\necho \xff\nfi".as_slice());
+        let mut sink = io::sink();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut sink);
+
+        let result = shell.execute_commands(&mut user_context, &mut io_context, &commands)
+            .err().unwrap();
+
+        match result {
+            ShellError::LexerError { error, .. } =>
+                assert_eq!(LexerError::InvalidUtf8(vec![0xff]), error),
+            _ => panic!("unexpected error: {}", result)
+        }
+    }
+
+    fn execute_with_report(text: &str) -> (Shell, UserContext, ErrorReport, String) {
+        let (mut shell, commands, mut user_context) = setup();
+        let mut cursor = Cursor::new(text.as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut output);
+
+        let report = shell.execute_commands_with_report(
+            &mut user_context, &mut io_context, &commands);
+
+        (shell, user_context, report, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn exit_status_of_last_command() {
+        let (_, context, report, output) = execute_with_report("set +e
+echo \"$? \"
+cd /missing
+echo \"$? \"
+echo $?
+cd /missing");
+
+        assert_eq!("0 1 0", output);
+        assert_eq!(1, context.exit_status());
+        assert_eq!(2, report.len());
+    }
+
+    #[test]
+    fn sourced_file_keeps_error_mode_and_exit_status() {
+        let path = std::env::temp_dir().join("rcore_errexit_source_test.txt");
+        std::fs::write(&path, "set +e\ncd /missing\n").unwrap();
+
+        let (_, context, report, output) = execute_with_report(&format!("source {}
+echo $?
+cd /missing
+source -s {}", path.display(), path.display()));
+
+        assert_eq!("1", output);
+        assert!(!context.errexit());
+        // the status of a subshell is not kept
+        assert_eq!(0, context.exit_status());
+        assert_eq!(3, report.len());
+    }
+
+    #[test]
+    fn failed_source_restores_level() {
+        let path = std::env::temp_dir().join("rcore_failed_source_test.txt");
+        std::fs::write(&path, "cd /missing\n").unwrap();
+
+        let (_, context, report, _) = execute_with_report(&format!("set +e
+source {}", path.display()));
+
+        assert_eq!(0, context.level);
+        assert_eq!(1, report.len());
+    }
+
+    #[test]
+    fn continue_after_failing_commands() {
+        let (mut shell, commands, mut user_context) = setup();
+        let mut cursor = Cursor::new("set +e
+cd /missing
+mkdir /foo
+/foo/missing
+echo done".as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let mut io_context = IoContext::new("test", &mut cursor, &mut output);
+
+        shell.execute_commands(&mut user_context, &mut io_context, &commands).unwrap();
+
+        assert_eq!("done", String::from_utf8(output).unwrap());
+        assert_eq!(2, shell.error_count());
+        assert!(!user_context.errexit());
+    }
+
+    #[test]
+    fn errexit_stops_at_first_failing_command() {
+        let (shell, context, report, output) = execute_with_report("set +e
+cd /missing
+set -e
+cd /missing
+echo unreachable");
+
+        assert_eq!("", output);
+        assert_eq!(2, report.len());
+        assert_eq!(4, report.errors()[1].src().line());
+        assert_eq!(1, shell.error_count());
+        assert!(context.errexit());
+    }
+
+    #[test]
+    fn lexer_error_skips_rest_of_line() {
+        let (_, _, report, output) = execute_with_report("set +e
+echo $unknown foo \"bar
+echo next");
+
+        assert_eq!("next", output);
+        assert_eq!(1, report.len());
+        match &report.errors()[0] {
+            ShellError::LexerError { error, src } => {
+                assert_eq!(LexerError::UnknownVariable("unknown".to_owned()), *error);
+                assert_eq!(2, src.line());
+            }
+            e => panic!("unexpected error: {}", e)
+        }
+    }
+
+    #[test]
+    fn failed_block_statement_skips_block() {
+        let (_, _, report, output) = execute_with_report("set +e
+for x; do
+    echo inside
+done
+if $unknown == 1; then
+    echo inside
+fi
+echo after");
+
+        assert_eq!("after", output);
+        assert_eq!(2, report.len());
+        assert_eq!(vec![2, 5], report.errors().iter().map(|e| e.src().line()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn continue_after_failing_commands_in_block() {
+        let (_, _, report, output) = execute_with_report("set +e
+for x in 1 2; do
+    cd /missing
+    echo $x
+done");
+
+        assert_eq!("12", output);
+        assert_eq!(2, report.len());
+        let lines: Vec<String> = report.to_string().lines().map(|l| l.to_owned()).collect();
+        assert_eq!(3, lines.len());
+        assert_eq!("2 error(s)", lines[0]);
+        assert!(lines[1].starts_with("test:3:"));
+        assert!(lines[2].starts_with("test:3:"));
+    }
+
+    #[test]
+    fn shells_share_registry() {
+        let registry = SharedRegistry::default();
+        let mut shell1 = Shell::with_registry(registry.clone());
+        let mut shell2 = Shell::with_registry(registry.clone());
+        let commands = CommandContext::default();
+        let mut output: Vec<u8> = Vec::new();
+
+        let mut cursor = Cursor::new("mkdir /foo/bar".as_bytes());
+        let mut io_context = IoContext::new("shell1", &mut cursor, &mut output);
+        shell1.execute_commands(&mut UserContext::default(), &mut io_context, &commands).unwrap();
+        let mut cursor = Cursor::new("cd /foo/bar\npwd".as_bytes());
+        let mut io_context = IoContext::new("shell2", &mut cursor, &mut output);
+        shell2.execute_commands(&mut UserContext::default(), &mut io_context, &commands).unwrap();
+
+        assert_eq!("/foo/bar", String::from_utf8(output).unwrap());
+        assert!(registry.read().path("/foo/bar").is_ok());
+    }
 }
\ No newline at end of file
@@ -1,13 +1,16 @@
 use proc_macro::TokenStream;
 use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
 use syn::{
     Attribute, Data, DataEnum, DataStruct, Fields, Lit, Meta, MetaNameValue, NestedMeta, Path,
+    Type,
 };
 
 #[derive(Debug, PartialEq)]
 enum OsoAttribute {
     ClassName { name: String },
     Attribute,
+    Writable,
 }
 
 fn get_single_segment(path: &Path) -> Option<String> {
@@ -32,6 +35,9 @@ fn get_nested_attr(nested: NestedMeta, oso_attrs: &mut Vec<OsoAttribute>) {
                         Some(ref seg) if seg == "attribute" => {
                             oso_attrs.push(OsoAttribute::Attribute);
                         }
+                        Some(ref seg) if seg == "writable" => {
+                            oso_attrs.push(OsoAttribute::Writable);
+                        }
                         _ => (),
                     };
                 }
@@ -59,6 +65,31 @@ fn get_nested_attr(nested: NestedMeta, oso_attrs: &mut Vec<OsoAttribute>) {
     }
 }
 
+/// Returns the parameter type of a writable attribute, which is used to parse and validate the
+/// values assigned to the attribute (e.g., "int" or a class name), or `None` if values of the
+/// field's type cannot be parsed from the shell.
+fn get_param_type(ty: &Type) -> Option<String> {
+    let segment = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+    // generic types (e.g., Option<T> or Vec<T>) cannot be parsed
+    if !segment.arguments.is_empty() {
+        return None;
+    }
+    let ident = segment.ident.to_string();
+    match ident.as_str() {
+        // "int" values are parsed as i32, so wider integers would be truncated
+        "i8" | "u8" | "i16" | "u16" | "i32" => Some("int".to_owned()),
+        "f64" => Some("float".to_owned()),
+        "bool" => Some("bool".to_owned()),
+        "String" => Some("string".to_owned()),
+        "u32" | "i64" | "u64" | "i128" | "u128" | "isize" | "usize" | "f32" | "char" | "str" => None,
+        // a class, whose instances are parsed from their paths
+        _ => Some(ident),
+    }
+}
+
 fn get_oso_attrs(attr: Attribute, oso_attrs: &mut Vec<OsoAttribute>) {
     let meta = attr.parse_meta().unwrap();
     if let Meta::List(list) = meta {
@@ -108,6 +139,24 @@ pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
                         getters.push(quote! {
                             .add_attribute_getter(#name, |recv: &#type_name| recv.#attr.clone())
                         });
+                        if oso_attrs.contains(&OsoAttribute::Writable) {
+                            let ty = field.ty;
+                            let param_type = match get_param_type(&ty) {
+                                Some(param_type) => param_type,
+                                None => {
+                                    return quote_spanned! { ty.span() => compile_error!("#[polar(writable)] is only supported on i8-i32, u8-u16, f64, bool, String, and class fields."); }.into();
+                                }
+                            };
+                            getters.push(quote! {
+                                .add_attribute_setter(
+                                    #name,
+                                    |recv: &mut #type_name, value: #ty| recv.#attr = value,
+                                    #param_type)
+                            });
+                        }
+                    } else if oso_attrs.contains(&OsoAttribute::Writable) {
+                        let span = field.ident.unwrap().span();
+                        return quote_spanned! { span => compile_error!("#[polar(writable)] requires #[polar(attribute)]."); }.into();
                     }
                 }
             }